@@ -0,0 +1,669 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Admin API served by a running launcher, and the observer CLI (`status`,
+//! `history`, `events`, `check-now`, `approve`) that reads it locally or from
+//! a remote host. Read tokens only see status with secrets masked; operator
+//! tokens can also see secret config values, trigger an update check, and
+//! approve a held update. Destructive commands stay local to the host.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::deployment::DeploymentRecord;
+use crate::resolve_vmm_url;
+use crate::timefmt::DisplayTimezone;
+use crate::{
+    audit, capture, compose_hash, events, guest_metrics, history, state, unix_now, PlatformConfig,
+    ValidatorUpdater,
+};
+
+/// Events kept in memory for `/events`
+const EVENT_HISTORY: usize = 200;
+/// Environment variable holding the admin API token when `--token-file` is not given
+pub(crate) const TOKEN_ENV: &str = "LAUNCHER_ADMIN_TOKEN";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdminApiConfig {
+    /// Address to listen on, e.g. `0.0.0.0:9850`
+    pub listen: String,
+    /// Bearer token for monitoring: status, history, events, masked config
+    pub read_token: String,
+    /// Bearer token that additionally reads secrets and triggers update checks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operator_token: Option<String>,
+    /// Serve a read-only HTML status page at `/`, without a token
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub status_page: bool,
+}
+
+/// What a bearer token may do; each role includes the ones before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AdminRole {
+    Read,
+    Operator,
+}
+
+/// Result of the most recent update check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckOutcome {
+    pub at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Stable code of the error, e.g. `E_VMM_UNREACHABLE`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusReport {
+    pub version: String,
+    pub vm_id: Option<String>,
+    pub compose_hash: Option<String>,
+    #[serde(default)]
+    pub pinned_image: Option<state::PinnedImage>,
+    #[serde(default)]
+    pub owned_vm_ids: Vec<String>,
+    #[serde(default)]
+    pub recent_failures: usize,
+    /// Update step a launcher died in the middle of, resumed on its next start
+    #[serde(default)]
+    pub pending_update: Option<String>,
+    /// When an operator stopped the VM with `stop`
+    #[serde(default)]
+    pub stopped_by_operator: Option<u64>,
+    /// When an operator paused auto-updates with `pause`
+    #[serde(default)]
+    pub paused: Option<u64>,
+    /// Only known when reported by a running launcher
+    #[serde(default)]
+    pub observe: Option<bool>,
+    #[serde(default)]
+    pub last_check: Option<CheckOutcome>,
+    /// Containers inside the managed VM, when the guest agent is reachable
+    #[serde(default)]
+    pub guest: Option<guest_metrics::GuestMetrics>,
+    /// Compose hash of an update held until approved (`require_update_approval`)
+    #[serde(default)]
+    pub awaiting_approval: Option<String>,
+    /// Changelog the API published with the update awaiting approval
+    #[serde(default)]
+    pub approval_changelog: Option<String>,
+    /// The latest config's compose hash as computed by the launcher, the API, and the VMM
+    #[serde(default)]
+    pub hash_check: Option<compose_hash::HashComparison>,
+    /// Operator notes on the current deployment
+    #[serde(default)]
+    pub annotations: Vec<state::Annotation>,
+}
+
+impl StatusReport {
+    fn from_state(state: state::UpdaterState) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            vm_id: state.vm_id,
+            compose_hash: state.current_hash,
+            pinned_image: state.pinned_image,
+            owned_vm_ids: state.owned_vm_ids,
+            recent_failures: state.recent_failures.len(),
+            pending_update: state.pending_update.map(|intent| intent.to_string()),
+            stopped_by_operator: state.stopped_by_operator,
+            paused: state.paused,
+            annotations: state.annotations,
+            observe: None,
+            last_check: None,
+            guest: None,
+            awaiting_approval: None,
+            approval_changelog: None,
+            hash_check: None,
+        }
+    }
+
+    fn print(&self, tz: DisplayTimezone) {
+        let or_none = |value: Option<&str>| value.unwrap_or("(none)").to_string();
+        println!("Launcher status (v{}):", self.version);
+        println!("  Managed VM: {}", or_none(self.vm_id.as_deref()));
+        println!("  Compose hash: {}", or_none(self.compose_hash.as_deref()));
+        match &self.pinned_image {
+            Some(pin) => println!("  Pinned image: {} ({})", pin.name, pin.digest),
+            None => println!("  Pinned image: (none)"),
+        }
+        if !self.owned_vm_ids.is_empty() {
+            println!("  Owned VMs: {}", self.owned_vm_ids.join(", "));
+        }
+        println!("  Recent VM failures: {}", self.recent_failures);
+        if let Some(pending) = &self.pending_update {
+            println!("  Update in progress: {}", pending);
+        }
+        if let Some(at) = self.stopped_by_operator {
+            println!(
+                "  Stopped by operator: since {} (until `start`, or `recreate` if removed)",
+                tz.format(at)
+            );
+        }
+        if let Some(at) = self.paused {
+            println!(
+                "  Auto-updates paused: since {} (until `resume`)",
+                tz.format(at)
+            );
+        }
+        if let Some(hash) = &self.awaiting_approval {
+            println!("  Awaiting approval: update to {}", hash);
+            if let Some(changelog) = &self.approval_changelog {
+                for line in changelog.lines() {
+                    println!("    {}", line);
+                }
+            }
+        }
+        if !self.annotations.is_empty() {
+            println!("  Annotations:");
+            for annotation in &self.annotations {
+                println!("    {}  {}", tz.format(annotation.at), annotation.text);
+            }
+        }
+        if let Some(observe) = self.observe {
+            println!("  Mode: {}", if observe { "observe" } else { "active" });
+        }
+        if let Some(hashes) = &self.hash_check {
+            println!("  Compose hashes: {}", hashes);
+        }
+        match &self.last_check {
+            Some(CheckOutcome {
+                at, error: None, ..
+            }) => {
+                println!("  Last check: {} ok", tz.format(*at))
+            }
+            Some(CheckOutcome {
+                at,
+                error: Some(error),
+                error_code,
+            }) => println!(
+                "  Last check: {} failed{}: {}",
+                tz.format(*at),
+                error_code
+                    .as_ref()
+                    .map(|code| format!(" [{}]", code))
+                    .unwrap_or_default(),
+                error
+            ),
+            None => {}
+        }
+        match &self.guest {
+            Some(guest) => {
+                println!(
+                    "  Guest: {} (as of {})",
+                    guest,
+                    tz.format(guest.collected_at)
+                );
+                for container in &guest.containers {
+                    println!(
+                        "    {} [{}] {} restarts={} ({})",
+                        container.name,
+                        container.state,
+                        container.status,
+                        container.restarts,
+                        container.image
+                    );
+                }
+            }
+            None if self.vm_id.is_some() => println!("  Guest: (guest agent unreachable)"),
+            None => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventRecord {
+    pub at: u64,
+    pub event: Value,
+}
+
+/// Live daemon state shared with the admin API
+#[derive(Default)]
+#[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+pub(crate) struct AdminShared {
+    observe: Mutex<bool>,
+    last_check: Mutex<Option<CheckOutcome>>,
+    events: Mutex<VecDeque<EventRecord>>,
+    guest: Mutex<Option<guest_metrics::GuestMetrics>>,
+    awaiting_approval: Mutex<Option<String>>,
+    approval_changelog: Mutex<Option<String>>,
+    hash_check: Mutex<Option<compose_hash::HashComparison>>,
+    /// Compose hash an operator approved; only kept in memory
+    approved: Mutex<Option<String>>,
+    check_requested: tokio::sync::Notify,
+}
+
+#[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+impl AdminShared {
+    pub(crate) fn set_observe(&self, observe: bool) {
+        *self.observe.lock().unwrap() = observe;
+    }
+
+    pub(crate) fn record_check(&self, result: &Result<()>) {
+        *self.last_check.lock().unwrap() = Some(CheckOutcome {
+            at: unix_now(),
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+            error_code: result
+                .as_ref()
+                .err()
+                .map(|e| crate::classify_error(e).to_string()),
+        });
+    }
+
+    pub(crate) fn record_guest(&self, metrics: Option<guest_metrics::GuestMetrics>) {
+        *self.guest.lock().unwrap() = metrics;
+    }
+
+    pub(crate) fn guest(&self) -> Option<guest_metrics::GuestMetrics> {
+        self.guest.lock().unwrap().clone()
+    }
+
+    pub(crate) fn record_event(&self, event: &events::Event) {
+        let mut events = self.events.lock().unwrap();
+        if events.len() == EVENT_HISTORY {
+            events.pop_front();
+        }
+        events.push_back(EventRecord {
+            at: unix_now(),
+            event: serde_json::to_value(event).unwrap_or(Value::Null),
+        });
+    }
+
+    pub(crate) fn set_awaiting_approval(
+        &self,
+        compose_hash: Option<String>,
+        changelog: Option<&str>,
+    ) {
+        *self.approval_changelog.lock().unwrap() = changelog
+            .filter(|_| compose_hash.is_some())
+            .map(String::from);
+        *self.awaiting_approval.lock().unwrap() = compose_hash;
+    }
+
+    pub(crate) fn awaiting_approval(&self) -> Option<String> {
+        self.awaiting_approval.lock().unwrap().clone()
+    }
+
+    pub(crate) fn record_hash_check(&self, comparison: compose_hash::HashComparison) {
+        *self.hash_check.lock().unwrap() = Some(comparison);
+    }
+
+    pub(crate) fn hash_check(&self) -> Option<compose_hash::HashComparison> {
+        self.hash_check.lock().unwrap().clone()
+    }
+
+    pub(crate) fn approve(&self, compose_hash: &str) {
+        *self.approved.lock().unwrap() = Some(compose_hash.to_string());
+    }
+
+    pub(crate) fn is_approved(&self, compose_hash: &str) -> bool {
+        self.approved.lock().unwrap().as_deref() == Some(compose_hash)
+    }
+
+    pub(crate) fn request_check(&self) {
+        self.check_requested.notify_one();
+    }
+
+    /// Resolves when an operator asks for an immediate update check
+    pub(crate) async fn check_requested(&self) {
+        self.check_requested.notified().await
+    }
+
+    pub(crate) fn status(&self) -> StatusReport {
+        let mut report = StatusReport::from_state(state::UpdaterState::load().unwrap_or_default());
+        report.observe = Some(*self.observe.lock().unwrap());
+        report.last_check = self.last_check.lock().unwrap().clone();
+        report.guest = self.guest();
+        report.awaiting_approval = self.awaiting_approval();
+        report.approval_changelog = self.approval_changelog.lock().unwrap().clone();
+        report.hash_check = self.hash_check();
+        report
+    }
+
+    pub(crate) fn recent_events(&self, tail: usize) -> Vec<EventRecord> {
+        let events = self.events.lock().unwrap();
+        events
+            .iter()
+            .skip(events.len().saturating_sub(tail))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Where observer commands read from: this host's files, or a launcher's admin API
+#[derive(clap::Args)]
+pub struct RemoteArgs {
+    /// Admin API of a remote launcher, e.g. `10.0.0.5:9850`
+    #[arg(long)]
+    pub host: Option<String>,
+    /// File holding the bearer token for the admin API (defaults to $LAUNCHER_ADMIN_TOKEN)
+    #[arg(long)]
+    pub token_file: Option<PathBuf>,
+}
+
+impl RemoteArgs {
+    async fn get<T: serde::de::DeserializeOwned>(&self, host: &str, path: &str) -> Result<T> {
+        request(reqwest::Method::GET, host, &self.remote_token()?, path).await
+    }
+
+    fn remote_token(&self) -> Result<String> {
+        read_token(self.token_file.as_deref())
+    }
+}
+
+/// The admin API token from `token_file`, or else `$LAUNCHER_ADMIN_TOKEN`.
+/// Never taken as an argument, where `ps` and shell history would show it.
+pub(crate) fn read_token(token_file: Option<&Path>) -> Result<String> {
+    let token = match token_file {
+        Some(path) => {
+            std::fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?
+        }
+        None => std::env::var(TOKEN_ENV)
+            .ok()
+            .context(format!("--token-file or {} is required", TOKEN_ENV))?,
+    };
+    let token = token.trim();
+    if token.is_empty() {
+        anyhow::bail!("The admin API token is empty");
+    }
+    Ok(token.to_string())
+}
+
+pub(crate) async fn request<T: serde::de::DeserializeOwned>(
+    method: reqwest::Method,
+    host: &str,
+    token: &str,
+    path: &str,
+) -> Result<T> {
+    let base = if host.contains("://") {
+        host.trim_end_matches('/').to_string()
+    } else {
+        format!("http://{}", host)
+    };
+    let url = format!("{}{}", base, path);
+    let response = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()?
+        .request(method, &url)
+        .bearer_auth(token)
+        .send()
+        .await
+        .context(format!("Failed to reach admin API at {}", base))?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = capture::error_body(response).await;
+        anyhow::bail!("Admin API returned {}: {}", status, body);
+    }
+    response.json().await.context("Invalid admin API response")
+}
+
+fn display_timezone() -> DisplayTimezone {
+    DisplayTimezone::from_config(&PlatformConfig::load().unwrap_or_default())
+}
+
+/// Print the deployments `rollback` can restore, newest first
+pub fn run_rollback_list_command() -> Result<()> {
+    let kept = DeploymentRecord::kept()?;
+    if kept.is_empty() {
+        println!("No deployments kept");
+        return Ok(());
+    }
+    let current = state::UpdaterState::load()
+        .ok()
+        .and_then(|state| state.current_hash);
+    let tz = display_timezone();
+    for record in &kept {
+        let marker = if Some(&record.compose_hash) == current.as_ref() {
+            " (current)"
+        } else {
+            ""
+        };
+        let source = record
+            .api_url
+            .as_deref()
+            .map(|url| format!("  from {}", url))
+            .unwrap_or_default();
+        println!(
+            "{}  {}  image {}  VM {}{}{}",
+            tz.format(record.deployed_at),
+            compose_hash::app_id_from_hash(&record.compose_hash),
+            record.image,
+            record.vm_id,
+            source,
+            marker
+        );
+    }
+    Ok(())
+}
+
+pub async fn run_status_command(remote: RemoteArgs) -> Result<()> {
+    let report = match &remote.host {
+        Some(host) => remote.get(host, "/status").await?,
+        None => {
+            let mut report = StatusReport::from_state(state::UpdaterState::load()?);
+            if let Some(vm_id) = &report.vm_id {
+                report.guest = local_guest_metrics(vm_id).await;
+            }
+            report
+        }
+    };
+    report.print(display_timezone());
+    if remote.host.is_none() {
+        print_live_status().await;
+    }
+    Ok(())
+}
+
+/// Ask the VMM and the platform API directly, so `status` is accurate even
+/// when no launcher is running
+async fn print_live_status() {
+    let platform_config = PlatformConfig::load().unwrap_or_default();
+    let vmm_url = resolve_vmm_url(&platform_config).url;
+    println!("Live (VMM at {}):", vmm_url);
+    let live = match ValidatorUpdater::new(vmm_url, &platform_config).await {
+        Ok(mut updater) => updater.live_status().await,
+        Err(e) => Err(e),
+    };
+    let live = match live {
+        Ok(live) => live,
+        Err(e) => {
+            println!("  VMM unreachable: {:#}", e);
+            return;
+        }
+    };
+    match &live.vm {
+        Some(vm) => {
+            println!(
+                "  VM: {} ({})",
+                vm.id,
+                vm.name.as_deref().unwrap_or("unnamed")
+            );
+            println!("  State: {}", vm.state);
+            println!("  app_id: {}", vm.app_id.as_deref().unwrap_or("(unknown)"));
+        }
+        None => println!("  VM: (no validator VM)"),
+    }
+    match (&live.latest_hash, &live.api_error) {
+        (Some(hash), _) => println!(
+            "  Latest compose hash: {} (app_id {})",
+            hash,
+            compose_hash::app_id_from_hash(hash)
+        ),
+        (None, Some(error)) => println!("  Latest compose hash: unavailable: {}", error),
+        (None, None) => {}
+    }
+    if let Some(hashes) = &live.hashes {
+        println!("  Compose hashes: {}", hashes);
+    }
+    match &live.pending_update {
+        Some(action) => println!("  Update pending: {}", action),
+        None if live.api_error.is_none() => println!("  Update pending: no, up to date"),
+        None => println!("  Update pending: unknown"),
+    }
+}
+
+/// Query the guest agent directly; restart counts are only tracked by a running launcher
+#[cfg(feature = "metrics")]
+async fn local_guest_metrics(vm_id: &str) -> Option<guest_metrics::GuestMetrics> {
+    let vmm_url = resolve_vmm_url(&PlatformConfig::load().unwrap_or_default()).url;
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .danger_accept_invalid_certs(true)
+        .build()
+        .ok()?;
+    guest_metrics::collect(&client, &vmm_url, vm_id, unix_now())
+        .await
+        .ok()
+}
+
+#[cfg(not(feature = "metrics"))]
+async fn local_guest_metrics(_vm_id: &str) -> Option<guest_metrics::GuestMetrics> {
+    None
+}
+
+async fn history_entries(remote: &RemoteArgs, tail: usize) -> Result<Vec<audit::AuditEntry>> {
+    match &remote.host {
+        Some(host) => remote.get(host, &format!("/history?tail={}", tail)).await,
+        None => {
+            let entries = audit::read_entries()?;
+            Ok(entries.into_iter().rev().take(tail).rev().collect())
+        }
+    }
+}
+
+pub async fn run_history_command(remote: RemoteArgs, tail: usize, deployments: bool) -> Result<()> {
+    if deployments {
+        let entries = history_entries(&remote, usize::MAX).await?;
+        let deployments = history::deployments(&entries);
+        if deployments.is_empty() {
+            println!("No deployments recorded");
+        }
+        let tz = display_timezone();
+        for deployment in deployments
+            .iter()
+            .skip(deployments.len().saturating_sub(tail))
+        {
+            history::print_deployment(deployment, tz);
+        }
+        return Ok(());
+    }
+    let entries = history_entries(&remote, tail).await?;
+    if entries.is_empty() {
+        println!("No launcher actions recorded");
+    }
+    let tz = display_timezone();
+    for entry in &entries {
+        audit::print_entry(entry, tz);
+    }
+    Ok(())
+}
+
+/// The whole deployment history, from this host's audit log or a remote one
+pub async fn run_history_export_command(
+    remote: RemoteArgs,
+    format: history::ExportFormat,
+    since: Option<u64>,
+) -> Result<()> {
+    let entries = history_entries(&remote, usize::MAX).await?;
+    print!("{}", history::export(&entries, format, since)?);
+    Ok(())
+}
+
+/// Events only exist in a running launcher, so without `--host` this asks the
+/// local launcher's admin API
+pub async fn run_events_command(remote: RemoteArgs, tail: usize) -> Result<()> {
+    let path = format!("/events?tail={}", tail);
+    let events: Vec<EventRecord> = match &remote.host {
+        Some(host) => remote.get(host, &path).await?,
+        None => {
+            let config = local_admin_api()?;
+            let local = config.listen.replace("0.0.0.0", "127.0.0.1");
+            request(reqwest::Method::GET, &local, &config.read_token, &path).await?
+        }
+    };
+    if events.is_empty() {
+        println!("No events since the launcher started");
+    }
+    let tz = display_timezone();
+    for record in &events {
+        let kind = record
+            .event
+            .get("type")
+            .and_then(|t| t.as_str())
+            .unwrap_or("Unknown");
+        let mut details = record.event.clone();
+        if let Some(fields) = details.as_object_mut() {
+            fields.remove("type");
+        }
+        println!("{} {} {}", tz.format(record.at), kind, details);
+    }
+    Ok(())
+}
+
+fn local_admin_api() -> Result<AdminApiConfig> {
+    PlatformConfig::load()
+        .unwrap_or_default()
+        .admin_api
+        .context("This needs a running launcher; enable the admin API or pass --host")
+}
+
+/// Admin API address and operator token: the remote one, or the local launcher's
+fn operator_target(remote: &RemoteArgs) -> Result<(String, String)> {
+    match &remote.host {
+        Some(host) => Ok((host.clone(), remote.remote_token()?)),
+        None => {
+            let config = local_admin_api()?;
+            let token = config
+                .operator_token
+                .context("The local admin API has no operator_token configured")?;
+            Ok((config.listen.replace("0.0.0.0", "127.0.0.1"), token))
+        }
+    }
+}
+
+/// Ask a launcher to run an update check now instead of at its next poll
+pub async fn run_check_now_command(remote: RemoteArgs) -> Result<()> {
+    let (host, token) = operator_target(&remote)?;
+    let _: Value = request(reqwest::Method::POST, &host, &token, "/check").await?;
+    println!("✓ Update check requested");
+    Ok(())
+}
+
+/// Let a launcher with `require_update_approval` apply the update to `compose_hash`
+pub async fn run_approve_command(remote: RemoteArgs, compose_hash: &str) -> Result<()> {
+    let (host, token) = operator_target(&remote)?;
+    let report: StatusReport = request(reqwest::Method::GET, &host, &token, "/status").await?;
+    if report.awaiting_approval.as_deref() == Some(compose_hash) {
+        match &report.approval_changelog {
+            Some(changelog) => {
+                println!("Changes in this update:");
+                for line in changelog.lines() {
+                    println!("  {}", line);
+                }
+            }
+            None => println!("The API published no changelog for this update"),
+        }
+    } else {
+        println!(
+            "The launcher is not holding compose hash {} yet; it will apply it once it does",
+            compose_hash
+        );
+    }
+    approve(&host, &token, compose_hash).await?;
+    println!("✓ Update to compose hash {} approved", compose_hash);
+    Ok(())
+}
+
+pub(crate) async fn approve(host: &str, token: &str, compose_hash: &str) -> Result<()> {
+    let path = format!("/approve?compose_hash={}", compose_hash);
+    let _: Value = request(reqwest::Method::POST, host, token, &path).await?;
+    Ok(())
+}
@@ -0,0 +1,490 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! HTTP listener for the admin API; only built with the `metrics` feature so
+//! minimal builds expose no network service.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{info, warn};
+
+use crate::admin_api::{AdminApiConfig, AdminRole, AdminShared};
+use crate::timefmt::DisplayTimezone;
+use crate::{audit, deployment, events, status_page, unix_now, PlatformConfig};
+
+/// Upper bound on a request head; the API has no request bodies
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Events listed on the status page
+const STATUS_PAGE_EVENTS: usize = 20;
+
+impl AdminApiConfig {
+    /// Role granted to a presented token, if any
+    fn role_for(&self, presented: &str) -> Option<AdminRole> {
+        if self
+            .operator_token
+            .as_deref()
+            .is_some_and(|token| constant_time_eq(presented, token))
+        {
+            Some(AdminRole::Operator)
+        } else if constant_time_eq(presented, &self.read_token) {
+            Some(AdminRole::Read)
+        } else {
+            None
+        }
+    }
+}
+
+/// Bind the admin API and serve it in the background
+pub(crate) async fn serve(
+    config: &AdminApiConfig,
+    shared: Arc<AdminShared>,
+    bus: &events::EventBus,
+) -> Result<()> {
+    if config.read_token.is_empty() || config.operator_token.as_deref() == Some("") {
+        anyhow::bail!("Admin API tokens must not be empty");
+    }
+    if config.operator_token.as_deref() == Some(config.read_token.as_str()) {
+        anyhow::bail!("Admin API operator_token must differ from read_token");
+    }
+    let listener = TcpListener::bind(&config.listen)
+        .await
+        .context(format!("Failed to bind admin API on {}", config.listen))?;
+    info!("Admin API listening on {}", config.listen);
+
+    let mut receiver = bus.subscribe();
+    let collector = shared.clone();
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => collector.record_event(&event),
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+
+    let config = Arc::new(config.clone());
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    warn!("Admin API accept failed: {}", e);
+                    continue;
+                }
+            };
+            let (shared, config) = (shared.clone(), config.clone());
+            tokio::spawn(async move {
+                if let Err(e) = handle(stream, &shared, &config).await {
+                    warn!("Admin API request from {} failed: {:#}", peer, e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    shared: &AdminShared,
+    config: &AdminApiConfig,
+) -> Result<()> {
+    let head = tokio::time::timeout(REQUEST_TIMEOUT, read_head(&mut stream))
+        .await
+        .context("Timed out reading request")??;
+
+    let mut lines = head.lines();
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let role = lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if !name.trim().eq_ignore_ascii_case("authorization") {
+            return None;
+        }
+        let presented = value.trim().strip_prefix("Bearer ")?;
+        config.role_for(presented.trim())
+    });
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let tail = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("tail="))
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(20);
+    let compose_hash = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("compose_hash="))
+        .filter(|hash| !hash.is_empty() && hash.chars().all(|c| c.is_ascii_hexdigit()));
+
+    if config.status_page && method == "GET" && path == "/" {
+        let deployed_at = deployment::DeploymentRecord::load()
+            .ok()
+            .flatten()
+            .map(|record| record.deployed_at);
+        let page = status_page::render(
+            &shared.status(),
+            &shared.recent_events(STATUS_PAGE_EVENTS),
+            deployed_at,
+            unix_now(),
+            DisplayTimezone::from_config(&PlatformConfig::load().unwrap_or_default()),
+        );
+        return respond(&mut stream, "200 OK", "text/html; charset=utf-8", &page).await;
+    }
+
+    let required = match (method, path) {
+        ("GET", "/status" | "/history" | "/events" | "/config") => Some(AdminRole::Read),
+        ("POST", "/check" | "/approve") => Some(AdminRole::Operator),
+        _ => None,
+    };
+
+    let (status, body) = match (required, role) {
+        (None, _) => ("404 Not Found", json_error("unknown endpoint")),
+        (Some(_), None) => (
+            "401 Unauthorized",
+            json_error("missing or invalid bearer token"),
+        ),
+        (Some(required), Some(role)) if role < required => (
+            "403 Forbidden",
+            json_error("this endpoint requires an operator token"),
+        ),
+        (Some(_), Some(role)) => match path {
+            "/status" => ("200 OK", serde_json::to_string(&shared.status())?),
+            "/history" => {
+                let entries = audit::read_entries()?;
+                let tail: Vec<_> = entries
+                    .iter()
+                    .skip(entries.len().saturating_sub(tail))
+                    .collect();
+                ("200 OK", serde_json::to_string(&tail)?)
+            }
+            "/events" => (
+                "200 OK",
+                serde_json::to_string(&shared.recent_events(tail))?,
+            ),
+            "/config" => ("200 OK", config_for_role(role)?.to_string()),
+            "/check" => {
+                info!("Update check requested via admin API");
+                shared.request_check();
+                (
+                    "202 Accepted",
+                    serde_json::json!({ "status": "check requested" }).to_string(),
+                )
+            }
+            "/approve" => match compose_hash {
+                Some(compose_hash) => {
+                    info!(
+                        "Update to compose hash {} approved via admin API",
+                        compose_hash
+                    );
+                    shared.approve(compose_hash);
+                    shared.request_check();
+                    (
+                        "202 Accepted",
+                        serde_json::json!({ "status": "approved", "compose_hash": compose_hash })
+                            .to_string(),
+                    )
+                }
+                None => (
+                    "400 Bad Request",
+                    json_error("compose_hash=<hex hash> is required"),
+                ),
+            },
+            _ => unreachable!(),
+        },
+    };
+
+    respond(&mut stream, status, "application/json", &body).await
+}
+
+async fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await.ok();
+    Ok(())
+}
+
+async fn read_head(stream: &mut TcpStream) -> Result<String> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if buf.len() > MAX_REQUEST_BYTES {
+            anyhow::bail!("Request head exceeds {} bytes", MAX_REQUEST_BYTES);
+        }
+    }
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+const MASK: &str = "********";
+
+/// Settings a read token sees, as paths into the serialized platform config
+/// (`*` for every element of a list). Anything not listed is left out, so a
+/// setting added later stays hidden from read tokens until it is listed here.
+const READ_VISIBLE: &[&str] = &[
+    "dstack_vmm_url",
+    "vmm_url",
+    "network",
+    "api_url",
+    "api_mirrors",
+    "poll_interval_secs",
+    "poll_jitter_percent",
+    "vm_recheck_secs",
+    "push_updates",
+    "api_transport",
+    "timezone",
+    "api_tls.client_cert",
+    "api_tls.ca_cert",
+    "http.user_agent",
+    "http.max_bytes_per_sec",
+    "resource_limits",
+    "drift_policy",
+    "recovery",
+    "admin_api.listen",
+    "admin_api.status_page",
+    "retention",
+    "endpoint_integrations.*.type",
+    "endpoint_integrations.*.method",
+    "endpoint_integrations.*.record_name",
+    "endpoint_integrations.*.ttl",
+    "public_ip",
+    "require_image_digest",
+    "image_support",
+    "health_checks.*.name",
+    "health_checks.*.interval_secs",
+    "health_checks.*.timeout_secs",
+    "canary.env_flag",
+    "canary.port_offset",
+    "canary.timeout_secs",
+    "canary.probes.*.name",
+    "canary.probes.*.interval_secs",
+    "canary.probes.*.timeout_secs",
+    "require_update_approval",
+    "remove_orphans",
+    "warm_standby",
+    "allowed_envs",
+    "missing_env",
+    "boot_timeout",
+    "disclosure",
+    "power",
+    "embedded_vmm.ready_timeout_secs",
+    "clock_check",
+    "notifications.mqtt.topic_prefix",
+    "notifications.opsgenie.eu",
+];
+
+/// Settings a read token sees only as present: the keys of a map, the
+/// length of a list, or that a value is set
+const READ_MASKED: &[&str] = &[
+    "env",
+    "user_config",
+    "http.headers",
+    "notifications.webhooks",
+    "notifications.mqtt.broker_url",
+    "notifications.pagerduty.routing_key",
+    "notifications.opsgenie.api_key",
+];
+
+/// Masked for every role: secrets the config view never needs to return
+const ALWAYS_MASKED: &[&str] = &[
+    "notifications.webhooks",
+    "notifications.mqtt.password",
+    "notifications.pagerduty.routing_key",
+    "notifications.opsgenie.api_key",
+    "admin_api.read_token",
+    "admin_api.operator_token",
+];
+
+/// The platform config as seen by `role`. Read tokens get only the
+/// settings in [`READ_VISIBLE`] and [`READ_MASKED`]; admin API tokens and
+/// alerting credentials are never returned.
+fn config_for_role(role: AdminRole) -> Result<Value> {
+    let config = serde_json::to_value(PlatformConfig::load()?)?;
+    Ok(config_view(&config, role))
+}
+
+fn config_view(config: &Value, role: AdminRole) -> Value {
+    if role < AdminRole::Operator {
+        let mut view = Value::Object(Default::default());
+        for path in READ_VISIBLE {
+            project(
+                config,
+                &path.split('.').collect::<Vec<_>>(),
+                &mut view,
+                false,
+            );
+        }
+        for path in READ_MASKED {
+            project(
+                config,
+                &path.split('.').collect::<Vec<_>>(),
+                &mut view,
+                true,
+            );
+        }
+        return view;
+    }
+    let mut view = config.clone();
+    for path in ALWAYS_MASKED {
+        project(
+            config,
+            &path.split('.').collect::<Vec<_>>(),
+            &mut view,
+            true,
+        );
+    }
+    view
+}
+
+/// Copy what `path` selects in `source` to the same place in `view`, masked
+/// with `mask`
+fn project(source: &Value, path: &[&str], view: &mut Value, mask: bool) {
+    let Some((first, rest)) = path.split_first() else {
+        *view = if mask { masked(source) } else { source.clone() };
+        return;
+    };
+    match (*first, source) {
+        ("*", Value::Array(items)) => {
+            if !matches!(view, Value::Array(existing) if existing.len() == items.len()) {
+                *view = Value::Array(vec![Value::Null; items.len()]);
+            }
+            if let Value::Array(views) = view {
+                for (item, view) in items.iter().zip(views) {
+                    project(item, rest, view, mask);
+                }
+            }
+        }
+        (key, Value::Object(fields)) => {
+            let Some(field) = fields.get(key).filter(|v| !v.is_null()) else {
+                return;
+            };
+            if !view.is_object() {
+                *view = Value::Object(Default::default());
+            }
+            if let Value::Object(views) = view {
+                let view = views.entry(key.to_string()).or_insert(Value::Null);
+                project(field, rest, view, mask);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn masked(value: &Value) -> Value {
+    match value {
+        Value::Object(fields) => fields
+            .keys()
+            .map(|key| (key.clone(), Value::String(MASK.to_string())))
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        Value::Array(items) => Value::Array(items.iter().map(masked).collect()),
+        Value::Null => Value::Null,
+        _ => Value::String(MASK.to_string()),
+    }
+}
+
+fn json_error(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Value {
+        serde_json::json!({
+            "vmm_url": "http://127.0.0.1:10300",
+            "env": { "HOTKEY_PASSPHRASE": "hunter2" },
+            "user_config": "secret",
+            "http": { "user_agent": "ua", "headers": { "X-Tenant": "t-1" } },
+            "notifications": {
+                "webhooks": ["https://hooks.slack.com/services/T0/B0/secret"],
+                "mqtt": { "broker_url": "mqtt://broker", "password": "pw", "topic_prefix": "p" },
+                "pagerduty": { "routing_key": "rk" },
+            },
+            "admin_api": { "listen": "0.0.0.0:9850", "read_token": "r", "operator_token": "o" },
+            "health_checks": [{ "name": "disk", "command": "check --token abc", "args": [], "interval_secs": 30, "timeout_secs": 5 }],
+            "endpoint_integrations": [{ "type": "http", "url": "https://lb/hook?key=k", "bearer_token": "b" }],
+            "some_future_secret": "s3cr3t",
+        })
+    }
+
+    #[test]
+    fn read_view_holds_only_allowlisted_settings() {
+        let view = config_view(&config(), AdminRole::Read);
+        let text = view.to_string();
+        for secret in [
+            "hunter2",
+            "hooks.slack.com",
+            "t-1",
+            "\"pw\"",
+            "\"rk\"",
+            "\"r\"",
+            "\"o\"",
+            "--token",
+            "key=k",
+            "\"b\"",
+            "s3cr3t",
+            "mqtt://broker",
+        ] {
+            assert!(!text.contains(secret), "{} leaked in {}", secret, text);
+        }
+        assert_eq!(view["vmm_url"], "http://127.0.0.1:10300");
+        assert_eq!(view["env"]["HOTKEY_PASSPHRASE"], MASK);
+        assert_eq!(view["http"]["user_agent"], "ua");
+        assert_eq!(view["http"]["headers"]["X-Tenant"], MASK);
+        assert_eq!(view["notifications"]["webhooks"][0], MASK);
+        assert_eq!(view["notifications"]["mqtt"]["topic_prefix"], "p");
+        assert_eq!(view["admin_api"]["listen"], "0.0.0.0:9850");
+        assert_eq!(view["health_checks"][0]["name"], "disk");
+        assert_eq!(view["endpoint_integrations"][0]["type"], "http");
+        assert!(view.get("some_future_secret").is_none());
+    }
+
+    #[test]
+    fn operator_view_masks_credentials() {
+        let view = config_view(&config(), AdminRole::Operator);
+        assert_eq!(view["env"]["HOTKEY_PASSPHRASE"], "hunter2");
+        assert_eq!(view["notifications"]["webhooks"][0], MASK);
+        assert_eq!(view["notifications"]["mqtt"]["password"], MASK);
+        assert_eq!(view["notifications"]["pagerduty"]["routing_key"], MASK);
+        assert_eq!(view["admin_api"]["read_token"], MASK);
+        assert_eq!(view["admin_api"]["operator_token"], MASK);
+    }
+
+    #[test]
+    fn unset_settings_stay_out() {
+        let view = config_view(&serde_json::json!({ "vmm_url": null }), AdminRole::Read);
+        assert_eq!(view, serde_json::json!({}));
+    }
+}
@@ -0,0 +1,455 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Incidents in PagerDuty and Opsgenie. Each failure condition (see
+//! [`crate::notify::conditions`]) opens one alert, deduplicated per host and
+//! condition, which is resolved when the condition clears. Each service
+//! gets one task delivering requests in order, and the conditions it has
+//! open are kept in the store, so a restart still resolves them.
+
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "notifications")]
+use {
+    crate::notify::{NotificationSink, TransitionEvent},
+    crate::{capture, http_config, store},
+    anyhow::{Context, Result},
+    serde_json::{json, Value},
+    std::collections::{BTreeMap, BTreeSet},
+    std::time::Duration,
+    tokio::sync::mpsc,
+    tracing::warn,
+};
+
+/// Conditions with an open incident, per service
+pub(crate) const OPEN_KEY: &str = "alerts.json";
+
+#[cfg(feature = "notifications")]
+const PAGERDUTY_EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+/// Opsgenie truncates alert messages beyond this
+#[cfg(feature = "notifications")]
+const OPSGENIE_MESSAGE_MAX: usize = 130;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PagerDutyConfig {
+    /// Integration key of an Events API v2 integration
+    pub routing_key: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpsgenieConfig {
+    /// API key of an Opsgenie API integration
+    pub api_key: String,
+    /// Account hosted in the EU instance (api.eu.opsgenie.com)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub eu: bool,
+}
+
+#[cfg(feature = "notifications")]
+impl OpsgenieConfig {
+    fn alerts_url(&self) -> &'static str {
+        if self.eu {
+            "https://api.eu.opsgenie.com/v2/alerts"
+        } else {
+            "https://api.opsgenie.com/v2/alerts"
+        }
+    }
+}
+
+/// Alert dedup key: stable for a condition on this host, so repeated
+/// triggers land on the same incident and several validators never share
+/// one. URL-safe, since Opsgenie closes alerts by alias in the path.
+#[cfg(feature = "notifications")]
+fn dedup_key(condition: &str) -> String {
+    format!(
+        "validator-launcher-{}-{}",
+        http_config::host_id(),
+        condition
+    )
+}
+
+/// What an incident sink should do for an event
+#[cfg(feature = "notifications")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Action {
+    Trigger { condition: String, summary: String },
+    Resolve { condition: String },
+}
+
+/// Work for a service's delivery task
+#[cfg(feature = "notifications")]
+enum Job {
+    Actions(Vec<Action>),
+    /// Resolve every open incident
+    AllClear,
+}
+
+/// Incident actions for an event: resolve what cleared, then trigger what
+/// began. Update events are not incidents.
+#[cfg(feature = "notifications")]
+fn actions(event: &TransitionEvent) -> Vec<Action> {
+    match event {
+        TransitionEvent::Failing {
+            condition,
+            replaces,
+            ..
+        } => {
+            let mut actions = Vec::new();
+            if let Some(previous) = replaces {
+                actions.push(Action::Resolve {
+                    condition: previous.to_string(),
+                });
+            }
+            actions.push(Action::Trigger {
+                condition: condition.to_string(),
+                summary: event.message(),
+            });
+            actions
+        }
+        TransitionEvent::Recovered { condition, .. } => vec![Action::Resolve {
+            condition: condition.to_string(),
+        }],
+        TransitionEvent::UpdateApplied { .. } | TransitionEvent::UpdatePending { .. } => Vec::new(),
+    }
+}
+
+#[cfg(feature = "notifications")]
+fn client() -> Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .context("Failed to create alerting client")
+}
+
+/// An incident service: how an action becomes a request
+#[cfg(feature = "notifications")]
+trait Service: Send + 'static {
+    const NAME: &'static str;
+    fn request(&self, action: &Action) -> reqwest::RequestBuilder;
+}
+
+/// Conditions `service` had open when the launcher last ran
+#[cfg(feature = "notifications")]
+fn load_open(service: &str) -> BTreeSet<String> {
+    let open = store::open()
+        .and_then(|store| store::get_json::<BTreeMap<String, BTreeSet<String>>>(&store, OPEN_KEY));
+    match open {
+        Ok(open) => open
+            .and_then(|mut open| open.remove(service))
+            .unwrap_or_default(),
+        Err(e) => {
+            warn!("Failed to load open {} incidents: {:#}", service, e);
+            BTreeSet::new()
+        }
+    }
+}
+
+#[cfg(feature = "notifications")]
+fn save_open(service: &str, conditions: &BTreeSet<String>) {
+    let result = store::open().and_then(|store| {
+        store::update_json(
+            &store,
+            OPEN_KEY,
+            |open: &mut BTreeMap<String, BTreeSet<String>>| {
+                if conditions.is_empty() {
+                    open.remove(service);
+                } else {
+                    open.insert(service.to_string(), conditions.clone());
+                }
+            },
+        )
+    });
+    if let Err(e) = result {
+        warn!("Failed to record open {} incidents: {:#}", service, e);
+    }
+}
+
+/// The actions a job comes to given the open conditions. Only one condition
+/// fails at a time, so a trigger also resolves any other left open, e.g. by
+/// a run that stopped before its condition cleared.
+#[cfg(feature = "notifications")]
+fn plan(job: Job, open: &BTreeSet<String>) -> Vec<Action> {
+    let resolve_other = |keep: Option<&str>| {
+        open.iter()
+            .filter(|condition| Some(condition.as_str()) != keep)
+            .map(|condition| Action::Resolve {
+                condition: condition.clone(),
+            })
+            .collect::<Vec<_>>()
+    };
+    match job {
+        Job::AllClear => resolve_other(None),
+        Job::Actions(actions) => {
+            let mut planned: Vec<Action> = Vec::new();
+            for action in actions {
+                if let Action::Trigger { condition, .. } = &action {
+                    for resolve in resolve_other(Some(condition)) {
+                        if !planned.contains(&resolve) {
+                            planned.push(resolve);
+                        }
+                    }
+                }
+                if !planned.contains(&action) {
+                    planned.push(action);
+                }
+            }
+            planned
+        }
+    }
+}
+
+/// Deliver jobs one at a time, in order (a resolve must land before the
+/// next trigger), tracking which conditions are open
+#[cfg(feature = "notifications")]
+async fn deliver<S: Service>(service: S, mut jobs: mpsc::UnboundedReceiver<Job>) {
+    let mut open = load_open(S::NAME);
+    while let Some(job) = jobs.recv().await {
+        let before = open.clone();
+        for action in plan(job, &open) {
+            let delivered = match service.request(&action).send().await {
+                Ok(response) if response.status().is_success() => true,
+                Ok(response) => {
+                    let status = response.status();
+                    let body = capture::error_body(response).await;
+                    warn!("{} returned status {}: {}", S::NAME, status, body);
+                    false
+                }
+                Err(e) => {
+                    warn!("Failed to deliver alert to {}: {}", S::NAME, e);
+                    false
+                }
+            };
+            match action {
+                // Counted as open even if undelivered, so it is resolved later
+                Action::Trigger { condition, .. } => {
+                    open.insert(condition);
+                }
+                // Kept open if undelivered, to be retried on the next all-clear
+                Action::Resolve { condition } if delivered => {
+                    open.remove(&condition);
+                }
+                Action::Resolve { .. } => {}
+            }
+        }
+        if open != before {
+            save_open(S::NAME, &open);
+        }
+    }
+}
+
+/// Spawn the delivery task for `service`
+#[cfg(feature = "notifications")]
+fn spawn<S: Service>(service: S) -> mpsc::UnboundedSender<Job> {
+    let (jobs, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(deliver(service, receiver));
+    jobs
+}
+
+/// Queue the incident actions for an event
+#[cfg(feature = "notifications")]
+fn enqueue(jobs: &mpsc::UnboundedSender<Job>, event: &TransitionEvent) {
+    let actions = actions(event);
+    if !actions.is_empty() {
+        let _ = jobs.send(Job::Actions(actions));
+    }
+}
+
+/// Events API v2 trigger and resolve, with the dedup key per condition
+#[cfg(feature = "notifications")]
+pub struct PagerDutySink {
+    jobs: mpsc::UnboundedSender<Job>,
+}
+
+#[cfg(feature = "notifications")]
+struct PagerDuty {
+    config: PagerDutyConfig,
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "notifications")]
+impl PagerDutySink {
+    pub fn new(config: PagerDutyConfig) -> Result<Self> {
+        Ok(Self {
+            jobs: spawn(PagerDuty {
+                config,
+                client: client()?,
+            }),
+        })
+    }
+}
+
+#[cfg(feature = "notifications")]
+impl PagerDuty {
+    fn body(&self, action: &Action) -> Value {
+        match action {
+            Action::Trigger { condition, summary } => json!({
+                "routing_key": self.config.routing_key,
+                "event_action": "trigger",
+                "dedup_key": dedup_key(condition),
+                "payload": {
+                    "summary": summary,
+                    "source": http_config::host_id(),
+                    "severity": "critical",
+                    "component": "validator-launcher",
+                    "class": condition,
+                },
+            }),
+            Action::Resolve { condition } => json!({
+                "routing_key": self.config.routing_key,
+                "event_action": "resolve",
+                "dedup_key": dedup_key(condition),
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "notifications")]
+impl Service for PagerDuty {
+    const NAME: &'static str = "PagerDuty";
+
+    fn request(&self, action: &Action) -> reqwest::RequestBuilder {
+        self.client
+            .post(PAGERDUTY_EVENTS_URL)
+            .json(&self.body(action))
+    }
+}
+
+#[cfg(feature = "notifications")]
+impl NotificationSink for PagerDutySink {
+    fn notify(&self, event: &TransitionEvent) {
+        enqueue(&self.jobs, event);
+    }
+
+    fn all_clear(&self) {
+        let _ = self.jobs.send(Job::AllClear);
+    }
+}
+
+/// Alert API create and close, with the alias per condition
+#[cfg(feature = "notifications")]
+pub struct OpsgenieSink {
+    jobs: mpsc::UnboundedSender<Job>,
+}
+
+#[cfg(feature = "notifications")]
+struct Opsgenie {
+    config: OpsgenieConfig,
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "notifications")]
+impl OpsgenieSink {
+    pub fn new(config: OpsgenieConfig) -> Result<Self> {
+        Ok(Self {
+            jobs: spawn(Opsgenie {
+                config,
+                client: client()?,
+            }),
+        })
+    }
+}
+
+#[cfg(feature = "notifications")]
+impl Service for Opsgenie {
+    const NAME: &'static str = "Opsgenie";
+
+    fn request(&self, action: &Action) -> reqwest::RequestBuilder {
+        let base = self.config.alerts_url();
+        let request = match action {
+            Action::Trigger { condition, summary } => {
+                let message: String = summary.chars().take(OPSGENIE_MESSAGE_MAX).collect();
+                self.client.post(base).json(&json!({
+                    "message": message,
+                    "alias": dedup_key(condition),
+                    "description": summary,
+                    "source": http_config::host_id(),
+                    "entity": "validator-launcher",
+                    "tags": [condition],
+                    "priority": "P1",
+                }))
+            }
+            Action::Resolve { condition } => self
+                .client
+                .post(format!("{}/{}/close", base, dedup_key(condition)))
+                .query(&[("identifierType", "alias")])
+                .json(&json!({ "source": http_config::host_id() })),
+        };
+        request.header(
+            reqwest::header::AUTHORIZATION,
+            format!("GenieKey {}", self.config.api_key),
+        )
+    }
+}
+
+#[cfg(feature = "notifications")]
+impl NotificationSink for OpsgenieSink {
+    fn notify(&self, event: &TransitionEvent) {
+        enqueue(&self.jobs, event);
+    }
+
+    fn all_clear(&self) {
+        let _ = self.jobs.send(Job::AllClear);
+    }
+}
+
+#[cfg(all(test, feature = "notifications"))]
+mod tests {
+    use super::*;
+
+    fn trigger(condition: &str) -> Action {
+        Action::Trigger {
+            condition: condition.to_string(),
+            summary: "failing".to_string(),
+        }
+    }
+
+    fn resolve(condition: &str) -> Action {
+        Action::Resolve {
+            condition: condition.to_string(),
+        }
+    }
+
+    fn open(conditions: &[&str]) -> BTreeSet<String> {
+        conditions.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn trigger_resolves_conditions_left_open() {
+        let planned = plan(Job::Actions(vec![trigger("b")]), &open(&["a", "b"]));
+        assert_eq!(planned, vec![resolve("a"), trigger("b")]);
+    }
+
+    #[test]
+    fn replaced_condition_is_resolved_once() {
+        let event = TransitionEvent::Failing {
+            error: "boom".to_string(),
+            condition: "b",
+            code: "E",
+            replaces: Some("a"),
+            annotations: Vec::new(),
+        };
+        let planned = plan(Job::Actions(actions(&event)), &open(&["a"]));
+        assert_eq!(planned.len(), 2);
+        assert_eq!(planned[0], resolve("a"));
+        assert!(matches!(&planned[1], Action::Trigger { condition, .. } if condition == "b"));
+    }
+
+    #[test]
+    fn all_clear_resolves_everything_open() {
+        assert_eq!(
+            plan(Job::AllClear, &open(&["a", "b"])),
+            vec![resolve("a"), resolve("b")]
+        );
+        assert!(plan(Job::AllClear, &open(&[])).is_empty());
+    }
+
+    #[test]
+    fn updates_are_not_incidents() {
+        let event = TransitionEvent::UpdateApplied {
+            vm_id: "vm".to_string(),
+            compose_hash: "hash".to_string(),
+            changelog: None,
+        };
+        assert!(actions(&event).is_empty());
+    }
+}
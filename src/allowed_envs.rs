@@ -0,0 +1,186 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! The `allowed_envs` list of the app manifest. It is part of the compose
+//! hash, so every path that builds a manifest resolves it here: the keys the
+//! API provisions, the keys every validator gets, the API's `required_env`,
+//! then the local override, sorted and deduplicated.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{defaults, ComposeConfig};
+
+/// Local changes to the resolved list. Any change gives the VM an app_id of
+/// its own, different from what the platform computes for the same config.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AllowedEnvsOverride {
+    /// Keys allowed in addition to the resolved ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub add: Vec<String>,
+    /// Resolved keys to leave out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remove: Vec<String>,
+}
+
+impl AllowedEnvsOverride {
+    pub fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.remove.is_empty()
+    }
+}
+
+impl fmt::Display for AllowedEnvsOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |keys: &[String]| {
+            if keys.is_empty() {
+                "(none)".to_string()
+            } else {
+                keys.join(", ")
+            }
+        };
+        write!(f, "add {}; remove {}", list(&self.add), list(&self.remove))
+    }
+}
+
+/// Where a key in the resolved list came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnvSource {
+    /// `provisioning.env_keys` of the compose config
+    Provisioning,
+    /// Keys every validator VM receives ([`defaults::ENV_KEYS`])
+    Default,
+    /// `required_env` of the compose config
+    RequiredEnv,
+    /// `allowed_envs.add` of the platform config
+    Override,
+}
+
+impl fmt::Display for EnvSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EnvSource::Provisioning => "provisioning.env_keys",
+            EnvSource::Default => "launcher default",
+            EnvSource::RequiredEnv => "required_env",
+            EnvSource::Override => "local override",
+        })
+    }
+}
+
+/// The resolved list, with the sources of every key
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AllowedEnvs {
+    /// Keys in manifest order, each with every source that listed it
+    pub keys: BTreeMap<String, Vec<EnvSource>>,
+    /// Keys the sources listed but the local override removed
+    pub removed: BTreeMap<String, Vec<EnvSource>>,
+}
+
+impl AllowedEnvs {
+    /// Resolve the list for `config`
+    pub fn resolve(config: &ComposeConfig, overrides: Option<&AllowedEnvsOverride>) -> Self {
+        let mut resolved = Self::default();
+        let sources = config
+            .provisioning
+            .env_keys
+            .iter()
+            .map(|key| (key.as_str(), EnvSource::Provisioning))
+            .chain(
+                defaults::ENV_KEYS
+                    .iter()
+                    .map(|key| (*key, EnvSource::Default)),
+            )
+            .chain(
+                config
+                    .required_env
+                    .iter()
+                    .map(|key| (key.as_str(), EnvSource::RequiredEnv)),
+            );
+        for (key, source) in sources {
+            resolved.insert(key, source);
+        }
+
+        if let Some(overrides) = overrides {
+            for key in &overrides.remove {
+                if let Some(sources) = resolved.keys.remove(key) {
+                    resolved.removed.insert(key.clone(), sources);
+                }
+            }
+            for key in &overrides.add {
+                resolved.removed.remove(key);
+                resolved.insert(key, EnvSource::Override);
+            }
+        }
+
+        let added = resolved
+            .keys
+            .values()
+            .any(|sources| sources == &[EnvSource::Override]);
+        if added || !resolved.removed.is_empty() {
+            info!(
+                "allowed_envs overridden locally ({}); the app_id differs from the platform's",
+                overrides.map(|o| o.to_string()).unwrap_or_default()
+            );
+        }
+        info!("Allowed environment variables: {:?}", resolved.list());
+        resolved
+    }
+
+    fn insert(&mut self, key: &str, source: EnvSource) {
+        let sources = self.keys.entry(key.to_string()).or_default();
+        if !sources.contains(&source) {
+            sources.push(source);
+        }
+    }
+
+    /// Sorted, deduplicated keys as they go into the manifest
+    pub fn list(&self) -> Vec<String> {
+        self.keys.keys().cloned().collect()
+    }
+}
+
+impl fmt::Display for AllowedEnvs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let describe = |sources: &[EnvSource]| {
+            sources
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let width = self
+            .keys
+            .keys()
+            .chain(self.removed.keys())
+            .map(|key| key.len())
+            .max()
+            .unwrap_or(0);
+        write!(f, "allowed_envs ({}):", self.keys.len())?;
+        for (key, sources) in &self.keys {
+            write!(
+                f,
+                "\n  {:width$}  {}",
+                key,
+                describe(sources),
+                width = width
+            )?;
+        }
+        if !self.removed.is_empty() {
+            write!(f, "\nRemoved by local override:")?;
+            for (key, sources) in &self.removed {
+                write!(
+                    f,
+                    "\n  {:width$}  (from {})",
+                    key,
+                    describe(sources),
+                    width = width
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
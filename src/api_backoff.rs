@@ -0,0 +1,99 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! How long the daemon waits before the next update check while the
+//! platform API fails. Network errors and 5xx responses usually pass, so
+//! checks back off exponentially from the poll interval. A 4xx (other than
+//! 408 and 429) will not go away until someone changes the request or the
+//! API, so checks drop straight to the cap. A `Retry-After` on 429 and 503
+//! is honoured up to the cap.
+
+use std::time::Duration;
+
+use crate::defaults;
+
+/// Why the platform API could not be used, as far as retrying goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiFailure {
+    /// No response: DNS, connect, TLS, or timeout
+    Network,
+    /// 5xx, 408, or a response the launcher cannot use
+    Server,
+    /// 429
+    RateLimited,
+    /// Any other 4xx
+    Client,
+}
+
+impl ApiFailure {
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            408 => ApiFailure::Server,
+            429 => ApiFailure::RateLimited,
+            400..=499 => ApiFailure::Client,
+            _ => ApiFailure::Server,
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            ApiFailure::Network => "unreachable",
+            ApiFailure::Server => "failing",
+            ApiFailure::RateLimited => "rate-limiting",
+            ApiFailure::Client => "rejecting requests",
+        }
+    }
+}
+
+/// Consecutive API failures and the wait they call for
+#[derive(Debug, Default)]
+pub struct ApiBackoff {
+    failures: u32,
+    delay: Option<Duration>,
+}
+
+impl ApiBackoff {
+    /// The wait before the next check, or None to poll as usual
+    pub fn delay(&self) -> Option<Duration> {
+        self.delay
+    }
+
+    /// Count a failed check and return the wait before the next one
+    pub fn failed(
+        &mut self,
+        failure: ApiFailure,
+        retry_after: Option<Duration>,
+        poll_interval: Duration,
+    ) -> Duration {
+        let max = Duration::from_secs(defaults::API_BACKOFF_MAX_SECS).max(poll_interval);
+        self.failures = self.failures.saturating_add(1);
+        let delay = match failure {
+            ApiFailure::Client => max,
+            ApiFailure::Network | ApiFailure::Server | ApiFailure::RateLimited => retry_after
+                .unwrap_or_else(|| poll_interval.saturating_mul(1 << (self.failures - 1).min(16)))
+                .clamp(poll_interval, max),
+        };
+        if self.delay != Some(delay) {
+            tracing::info!(
+                "Platform API {} ({} failed check(s) in a row), next check in {}s",
+                failure.describe(),
+                self.failures,
+                delay.as_secs()
+            );
+        }
+        self.delay = Some(delay);
+        delay
+    }
+
+    /// Reset after a check got through to the API
+    pub fn succeeded(&mut self) {
+        if self.failures > 0 {
+            tracing::info!(
+                "Platform API answering again after {} failed check(s)",
+                self.failures
+            );
+        }
+        *self = Self::default();
+    }
+}
@@ -0,0 +1,65 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Compose config schema versioning and feature negotiation with the platform API.
+
+use anyhow::Result;
+
+use crate::error_code::ErrorCode;
+
+/// Highest compose config schema version this launcher understands
+pub const SUPPORTED_SCHEMA_VERSION: u32 = 1;
+
+/// Request header advertising the schema version this launcher understands
+pub const SCHEMA_VERSION_HEADER: &str = "X-Launcher-Schema-Version";
+
+/// Request header advertising the optional features this launcher implements
+pub const FEATURES_HEADER: &str = "X-Launcher-Features";
+
+/// Optional API features implemented by this build
+pub const IMPLEMENTED_FEATURES: &[&str] = &[
+    "compose_patch",
+    "env_metadata",
+    "hash_version",
+    "provisioning",
+];
+
+/// Resolve the schema version of a response; absent means version 1.
+/// A newer schema may change the meaning of fields, so it is refused outright.
+pub fn check_schema_version(version: Option<u32>) -> Result<u32> {
+    let version = version.unwrap_or(1);
+    if version > SUPPORTED_SCHEMA_VERSION {
+        return Err(ErrorCode::ApiSchemaUnsupported.error(format!(
+            "API uses compose config schema version {}, but this launcher only understands up to {}; upgrade the launcher",
+            version, SUPPORTED_SCHEMA_VERSION
+        )));
+    }
+    Ok(version)
+}
+
+/// Advertised features this build does not implement
+pub fn unsupported_features(features: &[String]) -> Vec<String> {
+    features
+        .iter()
+        .filter(|f| !IMPLEMENTED_FEATURES.contains(&f.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// What an unimplemented feature means for this launcher, for features known to exist
+pub fn impact(feature: &str) -> Option<&'static str> {
+    match feature {
+        "signatures" => {
+            Some("compose configs are signed, but this launcher does not verify signatures")
+        }
+        "multi_vm" => {
+            Some("the API describes several VMs, but this launcher only manages the validator VM")
+        }
+        _ => None,
+    }
+}
+
+pub fn features_header_value() -> String {
+    IMPLEMENTED_FEATURES.join(",")
+}
@@ -0,0 +1,748 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::timefmt::DisplayTimezone;
+use crate::{paths, unix_now, PlatformConfig};
+
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Action of the entry that starts signing with a key, naming its public
+/// key. It is signed by that key, and every entry after it must be too, so
+/// stripping signatures and re-chaining the log cannot go unnoticed.
+pub const SIGNING_ENABLED: &str = "AuditSigningEnabled";
+
+fn audit_log_path() -> PathBuf {
+    paths::state_dir().join("audit.log")
+}
+
+/// Where the chain resumes after old entries were pruned
+fn anchor_path() -> PathBuf {
+    paths::state_dir().join("audit-anchor.json")
+}
+
+fn signing_key_path() -> PathBuf {
+    paths::state_dir().join("audit-signing.key")
+}
+
+fn verifying_key_path() -> PathBuf {
+    paths::state_dir().join("audit-signing.pub")
+}
+
+/// One line of the audit log. Each entry commits to its predecessor through
+/// `prev_hash`, and is signed when an audit signing key is present.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub timestamp: u64,
+    pub action: String,
+    pub details: Value,
+    pub prev_hash: String,
+    pub hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// Sequence number and previous hash the chain starts from once the entries
+/// before it have been pruned, with the signing key in effect at that point
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChainAnchor {
+    seq: u64,
+    prev_hash: String,
+    /// Public key whose `AuditSigningEnabled` entry was pruned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signing_key: Option<String>,
+}
+
+impl Default for ChainAnchor {
+    fn default() -> Self {
+        Self {
+            seq: 0,
+            prev_hash: GENESIS_HASH.to_string(),
+            signing_key: None,
+        }
+    }
+}
+
+fn load_anchor() -> Result<ChainAnchor> {
+    let path = anchor_path();
+    match std::fs::read_to_string(&path) {
+        Ok(content) => {
+            serde_json::from_str(&content).context(format!("Failed to parse {}", path.display()))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(ChainAnchor::default()),
+        Err(e) => Err(e).context(format!("Failed to read {}", path.display())),
+    }
+}
+
+/// The part of an entry covered by its hash
+#[derive(Serialize)]
+struct HashedFields<'a> {
+    seq: u64,
+    timestamp: u64,
+    action: &'a str,
+    details: &'a Value,
+    prev_hash: &'a str,
+}
+
+impl AuditEntry {
+    /// The entry following `seq`/`prev_hash`, signed with `key` if given
+    fn chained(
+        seq: u64,
+        prev_hash: String,
+        action: &str,
+        details: Value,
+        key: Option<&SigningKey>,
+    ) -> Result<Self> {
+        let mut entry = AuditEntry {
+            seq,
+            timestamp: unix_now(),
+            action: action.to_string(),
+            details,
+            prev_hash,
+            hash: String::new(),
+            signature: None,
+        };
+        entry.hash = entry.compute_hash();
+        if let Some(key) = key {
+            let hash_bytes = hex::decode(&entry.hash).context("Invalid audit entry hash")?;
+            entry.signature = Some(hex::encode(key.sign(&hash_bytes).to_bytes()));
+        }
+        Ok(entry)
+    }
+
+    /// Public key an `AuditSigningEnabled` entry starts signing with
+    fn enabled_key(&self) -> Option<&str> {
+        (self.action == SIGNING_ENABLED)
+            .then(|| self.details.get("public_key")?.as_str())
+            .flatten()
+    }
+
+    fn compute_hash(&self) -> String {
+        let fields = HashedFields {
+            seq: self.seq,
+            timestamp: self.timestamp,
+            action: &self.action,
+            details: &self.details,
+            prev_hash: &self.prev_hash,
+        };
+        let canonical = serde_json::to_vec(&fields).unwrap_or_default();
+        hex::encode(Sha256::digest(canonical))
+    }
+}
+
+/// Append an entry to the audit log, chaining it to the previous one. The
+/// user the launcher runs as is added to object details as `user`.
+pub fn record(action: &str, mut details: Value) -> Result<AuditEntry> {
+    if let Some(fields) = details.as_object_mut() {
+        fields
+            .entry("user")
+            .or_insert_with(|| Value::String(current_user()));
+    }
+    let _lock = lock_log()?;
+    append_entry(action, details)
+}
+
+/// Who is running the launcher: the invoking user under sudo, otherwise the
+/// login name or uid
+fn current_user() -> String {
+    let env = |name| std::env::var(name).ok().filter(|value| !value.is_empty());
+    if let Some(sudo_user) = env("SUDO_USER") {
+        return format!("{} (sudo)", sudo_user);
+    }
+    if let Some(user) = env("USER").or_else(|| env("LOGNAME")) {
+        return user;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if let Ok(meta) = std::fs::metadata("/proc/self") {
+            return format!("uid {}", meta.uid());
+        }
+    }
+    "unknown".to_string()
+}
+
+/// Serialize writers (the daemon and `prune`) so no entry is lost while the
+/// log is rewritten. Released when the returned file is dropped.
+fn lock_log() -> Result<std::fs::File> {
+    use fs2::FileExt;
+
+    paths::ensure_state_dir()?;
+    let lock_path = audit_log_path().with_extension("lock");
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .context(format!("Failed to open {}", lock_path.display()))?;
+    file.lock_exclusive()
+        .context(format!("Failed to lock {}", lock_path.display()))?;
+    Ok(file)
+}
+
+fn append_entry(action: &str, details: Value) -> Result<AuditEntry> {
+    let entries = read_entries()?;
+    let anchor = load_anchor()?;
+    let key = load_signing_key()?;
+    let mut lines = Vec::new();
+    let (mut seq, mut prev_hash) = match entries.last() {
+        Some(last) => (last.seq + 1, last.hash.clone()),
+        None => (anchor.seq, anchor.prev_hash.clone()),
+    };
+
+    // A key with no entry enabling it (new, or from before enabling entries
+    // were written) is enabled first, so verification knows where signing starts
+    if let Some(key) = &key {
+        let public_key = hex::encode(key.verifying_key().to_bytes());
+        if action != SIGNING_ENABLED && signing_key_at_end(&entries, &anchor) != Some(&public_key) {
+            let enabled = AuditEntry::chained(
+                seq,
+                prev_hash,
+                SIGNING_ENABLED,
+                serde_json::json!({ "public_key": public_key }),
+                Some(key),
+            )?;
+            seq += 1;
+            prev_hash = enabled.hash.clone();
+            lines.push(enabled);
+        }
+    }
+    let entry = AuditEntry::chained(seq, prev_hash, action, details, key.as_ref())?;
+    lines.push(entry.clone());
+
+    let path = audit_log_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .context(format!("Failed to create {}", parent.display()))?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .context(format!("Failed to open {}", path.display()))?;
+    for entry in &lines {
+        let line = serde_json::to_string(entry).context("Failed to serialize audit entry")?;
+        writeln!(file, "{}", line).context(format!("Failed to write {}", path.display()))?;
+    }
+    file.sync_data().ok();
+
+    Ok(entry)
+}
+
+/// Public key the last `AuditSigningEnabled` entry (or the anchor, if it was
+/// pruned) started signing with
+fn signing_key_at_end<'a>(entries: &'a [AuditEntry], anchor: &'a ChainAnchor) -> Option<&'a str> {
+    entries
+        .iter()
+        .rev()
+        .find_map(AuditEntry::enabled_key)
+        .or(anchor.signing_key.as_deref())
+}
+
+/// Start signing new entries with the local key, recording its public key
+pub fn enable_signing() -> Result<AuditEntry> {
+    let key = load_signing_key()?.context("No audit signing key")?;
+    let _lock = lock_log()?;
+    append_entry(
+        SIGNING_ENABLED,
+        serde_json::json!({
+            "public_key": hex::encode(key.verifying_key().to_bytes()),
+            "user": current_user(),
+        }),
+    )
+}
+
+pub fn read_entries() -> Result<Vec<AuditEntry>> {
+    let path = audit_log_path();
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context(format!("Failed to read {}", path.display())),
+    };
+
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| {
+            serde_json::from_str(line).context(format!("Malformed audit entry on line {}", i + 1))
+        })
+        .collect()
+}
+
+fn load_signing_key() -> Result<Option<SigningKey>> {
+    let path = signing_key_path();
+    if !path.exists() {
+        return Ok(None);
+    }
+    let hex_seed = crate::read_key_material(&path.to_string_lossy())?;
+    let seed = hex::decode(String::from_utf8_lossy(&hex_seed).trim())
+        .context("Audit signing key is not valid hex")?;
+    let seed: [u8; 32] = seed
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Audit signing key must be 32 bytes"))?;
+    Ok(Some(SigningKey::from_bytes(&seed)))
+}
+
+fn load_verifying_key(pubkey_hex: Option<&str>) -> Result<Option<VerifyingKey>> {
+    let hex_key = match pubkey_hex {
+        Some(key) => key.to_string(),
+        None => match std::fs::read_to_string(verifying_key_path()) {
+            Ok(key) => key,
+            Err(_) => return Ok(None),
+        },
+    };
+    let bytes = hex::decode(hex_key.trim()).context("Audit public key is not valid hex")?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Audit public key must be 32 bytes"))?;
+    Ok(Some(
+        VerifyingKey::from_bytes(&bytes).context("Invalid audit public key")?,
+    ))
+}
+
+/// Check the hash chain and, when a public key is available, every signature
+pub fn verify(pubkey_hex: Option<&str>) -> Result<usize> {
+    let entries = read_entries()?;
+    let verifying_key = load_verifying_key(pubkey_hex)?;
+    verify_entries(&entries, &load_anchor()?, verifying_key.as_ref())
+}
+
+/// Check `entries` against the chain `anchor`. With `key`, the log must have
+/// started signing with it (an `AuditSigningEnabled` entry naming it, or an
+/// anchor that pruned one), and every entry from there on must carry a valid
+/// signature by it. Entries before that point are covered by the hash chain.
+fn verify_entries(
+    entries: &[AuditEntry],
+    anchor: &ChainAnchor,
+    key: Option<&VerifyingKey>,
+) -> Result<usize> {
+    let key_hex = key.map(|key| hex::encode(key.to_bytes()));
+    let mut expected_prev = anchor.prev_hash.clone();
+    let mut signing = key_hex.is_some() && anchor.signing_key == key_hex;
+
+    // Entries before the anchor may linger if pruning was interrupted
+    let entries: Vec<&AuditEntry> = entries
+        .iter()
+        .skip_while(|entry| entry.seq < anchor.seq)
+        .collect();
+    for (i, entry) in entries.iter().enumerate() {
+        let expected_seq = anchor.seq + i as u64;
+        if entry.seq != expected_seq {
+            anyhow::bail!(
+                "Entry {}: sequence gap (found seq {})",
+                expected_seq,
+                entry.seq
+            );
+        }
+        if entry.prev_hash != expected_prev {
+            anyhow::bail!(
+                "Entry {}: chain broken, prev_hash does not match",
+                entry.seq
+            );
+        }
+        if entry.compute_hash() != entry.hash {
+            anyhow::bail!("Entry {}: content does not match its hash", entry.seq);
+        }
+        if let (Some(enabled), Some(key_hex)) = (entry.enabled_key(), &key_hex) {
+            if enabled == key_hex {
+                signing = true;
+            } else if signing {
+                anyhow::bail!(
+                    "Entry {}: signing key replaced by {}; verify with that key",
+                    entry.seq,
+                    enabled
+                );
+            }
+        }
+        if let (true, Some(key)) = (signing, key) {
+            let signature = entry
+                .signature
+                .as_deref()
+                .context(format!("Entry {}: missing signature", entry.seq))?;
+            let signature = hex::decode(signature)
+                .ok()
+                .and_then(|bytes| Signature::from_slice(&bytes).ok())
+                .context(format!("Entry {}: malformed signature", entry.seq))?;
+            let hash_bytes = hex::decode(&entry.hash).context("Invalid audit entry hash")?;
+            key.verify(&hash_bytes, &signature)
+                .map_err(|_| anyhow::anyhow!("Entry {}: invalid signature", entry.seq))?;
+        }
+        expected_prev = entry.hash.clone();
+    }
+    if key.is_some() && !signing {
+        anyhow::bail!(
+            "No {} entry for this public key: the log was never signed with it, or its signed entries were removed",
+            SIGNING_ENABLED
+        );
+    }
+
+    Ok(entries.len())
+}
+
+/// Drop the oldest `count` entries. The chain is re-anchored at the first
+/// kept entry and the pruning itself is recorded as a new entry, so `verify`
+/// keeps working and the gap is accounted for. Returns the entries removed.
+pub fn prune_oldest(count: usize) -> Result<usize> {
+    let _lock = lock_log()?;
+    let entries = read_entries()?;
+    let count = count.min(entries.len());
+    let Some(last_pruned) = count.checked_sub(1).map(|i| &entries[i]) else {
+        return Ok(0);
+    };
+
+    let previous = load_anchor()?;
+    let anchor = ChainAnchor {
+        seq: last_pruned.seq + 1,
+        prev_hash: last_pruned.hash.clone(),
+        signing_key: signing_key_at_end(&entries[..count], &previous).map(str::to_string),
+    };
+    let anchor_json =
+        serde_json::to_string_pretty(&anchor).context("Failed to serialize anchor")?;
+    write_replacing(&anchor_path(), &anchor_json)?;
+
+    let mut kept = String::new();
+    for entry in &entries[count..] {
+        kept.push_str(&serde_json::to_string(entry).context("Failed to serialize audit entry")?);
+        kept.push('\n');
+    }
+    write_replacing(&audit_log_path(), &kept)?;
+
+    append_entry(
+        "AuditPruned",
+        serde_json::json!({ "entries": count, "through_seq": last_pruned.seq }),
+    )?;
+    Ok(count)
+}
+
+/// Size in bytes each entry occupies in the log file, oldest first
+pub fn entry_sizes() -> Result<Vec<(u64, u64)>> {
+    Ok(read_entries()?
+        .iter()
+        .map(|entry| {
+            let size = serde_json::to_string(entry)
+                .map(|l| l.len() + 1)
+                .unwrap_or(0);
+            (entry.timestamp, size as u64)
+        })
+        .collect())
+}
+
+fn write_replacing(path: &std::path::Path, content: &str) -> Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, content)
+        .context(format!("Failed to write {}", tmp_path.display()))?;
+    std::fs::rename(&tmp_path, path).context(format!("Failed to replace {}", path.display()))
+}
+
+#[derive(Subcommand)]
+pub enum AuditCommands {
+    /// Show the most recent audit log entries
+    Show {
+        /// Number of entries to show
+        #[arg(long, default_value_t = 20)]
+        tail: usize,
+    },
+    /// Verify the hash chain and signatures of the audit log
+    Verify {
+        /// Hex-encoded public key to verify against (defaults to the local one)
+        #[arg(long)]
+        pubkey: Option<String>,
+    },
+    /// Generate a local key used to sign new audit entries
+    InitKey {
+        /// Replace an existing key
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+pub(crate) fn print_entry(entry: &AuditEntry, tz: DisplayTimezone) {
+    println!(
+        "#{} {} {} {}{}",
+        entry.seq,
+        tz.format(entry.timestamp),
+        entry.action,
+        entry.details,
+        if entry.signature.is_some() {
+            " [signed]"
+        } else {
+            ""
+        }
+    );
+}
+
+pub fn run_audit_command(cmd: AuditCommands) -> Result<()> {
+    match cmd {
+        AuditCommands::Show { tail } => {
+            let entries = read_entries()?;
+            let tz = DisplayTimezone::from_config(&PlatformConfig::load().unwrap_or_default());
+            if entries.is_empty() {
+                println!("Audit log is empty");
+            }
+            for entry in entries.iter().skip(entries.len().saturating_sub(tail)) {
+                print_entry(entry, tz);
+            }
+        }
+        AuditCommands::Verify { pubkey } => {
+            let count = verify(pubkey.as_deref())?;
+            println!("✓ Audit log verified: {} entries intact", count);
+            if pubkey.is_none() && !verifying_key_path().exists() {
+                println!("  (no public key available, signatures were not checked)");
+            }
+        }
+        AuditCommands::InitKey { force } => {
+            let key_path = signing_key_path();
+            if key_path.exists() && !force {
+                anyhow::bail!(
+                    "Audit signing key already exists at {} (use --force to replace)",
+                    key_path.display()
+                );
+            }
+            paths::ensure_state_dir()?;
+
+            let key = SigningKey::generate(&mut rand::rngs::OsRng);
+            write_private_file(&key_path, &hex::encode(key.to_bytes()))?;
+            let pubkey = hex::encode(key.verifying_key().to_bytes());
+            std::fs::write(verifying_key_path(), &pubkey)
+                .context("Failed to write audit public key")?;
+            enable_signing()?;
+
+            println!("✓ Audit signing key created at {}", key_path.display());
+            println!("  Public key: {}", pubkey);
+            println!("  Store the public key off-host to verify the log after an incident.");
+        }
+    }
+    Ok(())
+}
+
+fn write_private_file(path: &std::path::Path, content: &str) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .context(format!("Failed to create {}", path.display()))?;
+    file.write_all(content.as_bytes())
+        .context(format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    fn public(key: &SigningKey) -> String {
+        hex::encode(key.verifying_key().to_bytes())
+    }
+
+    fn push(log: &mut Vec<AuditEntry>, action: &str, details: Value, key: Option<&SigningKey>) {
+        let (seq, prev_hash) = match log.last() {
+            Some(last) => (last.seq + 1, last.hash.clone()),
+            None => (0, GENESIS_HASH.to_string()),
+        };
+        log.push(AuditEntry::chained(seq, prev_hash, action, details, key).unwrap());
+    }
+
+    /// An unsigned entry, then signing enabled with `key` and two signed entries
+    fn signed_log(key: &SigningKey) -> Vec<AuditEntry> {
+        let mut log = Vec::new();
+        push(&mut log, "CreateVm", serde_json::json!({ "vm": "a" }), None);
+        push(
+            &mut log,
+            SIGNING_ENABLED,
+            serde_json::json!({ "public_key": public(key) }),
+            Some(key),
+        );
+        push(
+            &mut log,
+            "StopVm",
+            serde_json::json!({ "vm": "a" }),
+            Some(key),
+        );
+        push(
+            &mut log,
+            "RemoveVm",
+            serde_json::json!({ "vm": "a" }),
+            Some(key),
+        );
+        log
+    }
+
+    /// Recompute the hashes after an edit, as an attacker without the key would
+    fn rechain(log: &mut [AuditEntry]) {
+        let mut prev = GENESIS_HASH.to_string();
+        for entry in log {
+            entry.prev_hash = prev;
+            entry.hash = entry.compute_hash();
+            prev = entry.hash.clone();
+        }
+    }
+
+    fn verify_with(log: &[AuditEntry], key: Option<&SigningKey>) -> Result<usize> {
+        let verifying = key.map(SigningKey::verifying_key);
+        verify_entries(log, &ChainAnchor::default(), verifying.as_ref())
+    }
+
+    #[test]
+    fn unsigned_log_verifies_without_key() {
+        let mut log = Vec::new();
+        push(&mut log, "CreateVm", serde_json::json!({}), None);
+        push(&mut log, "StopVm", serde_json::json!({}), None);
+        assert_eq!(verify_with(&log, None).unwrap(), 2);
+    }
+
+    #[test]
+    fn unsigned_log_fails_with_key() {
+        let mut log = Vec::new();
+        push(&mut log, "CreateVm", serde_json::json!({}), None);
+        let err = verify_with(&log, Some(&key(1))).unwrap_err();
+        assert!(err.to_string().contains(SIGNING_ENABLED), "{:#}", err);
+    }
+
+    #[test]
+    fn signed_log_verifies() {
+        let key = key(1);
+        let log = signed_log(&key);
+        assert_eq!(verify_with(&log, Some(&key)).unwrap(), 4);
+        assert_eq!(verify_with(&log, None).unwrap(), 4);
+    }
+
+    #[test]
+    fn tampered_entry_fails() {
+        let key = key(1);
+        let mut log = signed_log(&key);
+        log[2].details = serde_json::json!({ "vm": "b" });
+        let err = verify_with(&log, Some(&key)).unwrap_err();
+        assert!(
+            err.to_string().contains("does not match its hash"),
+            "{:#}",
+            err
+        );
+    }
+
+    #[test]
+    fn tampered_and_rechained_fails() {
+        let key = key(1);
+        let mut log = signed_log(&key);
+        log[2].details = serde_json::json!({ "vm": "b" });
+        rechain(&mut log);
+        let err = verify_with(&log, Some(&key)).unwrap_err();
+        assert!(err.to_string().contains("invalid signature"), "{:#}", err);
+    }
+
+    #[test]
+    fn tampered_before_signing_fails() {
+        let key = key(1);
+        let mut log = signed_log(&key);
+        log[0].details = serde_json::json!({ "vm": "b" });
+        rechain(&mut log);
+        assert!(verify_with(&log, Some(&key)).is_err());
+    }
+
+    #[test]
+    fn stripped_signature_fails() {
+        let key = key(1);
+        let mut log = signed_log(&key);
+        log[3].signature = None;
+        let err = verify_with(&log, Some(&key)).unwrap_err();
+        assert!(err.to_string().contains("missing signature"), "{:#}", err);
+    }
+
+    #[test]
+    fn stripped_and_rechained_fails() {
+        let key = key(1);
+        let mut log = signed_log(&key);
+        for entry in &mut log {
+            entry.signature = None;
+        }
+        rechain(&mut log);
+        assert!(verify_with(&log, Some(&key)).is_err());
+
+        // Dropping the entry that enabled signing as well
+        log.remove(1);
+        for (seq, entry) in log.iter_mut().enumerate() {
+            entry.seq = seq as u64;
+        }
+        rechain(&mut log);
+        let err = verify_with(&log, Some(&key)).unwrap_err();
+        assert!(err.to_string().contains(SIGNING_ENABLED), "{:#}", err);
+    }
+
+    #[test]
+    fn forged_signing_key_fails() {
+        let key = key(1);
+        let forged = self::key(2);
+        let log = signed_log(&forged);
+        assert!(verify_with(&log, Some(&forged)).is_ok());
+        assert!(verify_with(&log, Some(&key)).is_err());
+    }
+
+    #[test]
+    fn replaced_key_needs_the_new_key() {
+        let old = key(1);
+        let new = key(2);
+        let mut log = signed_log(&old);
+        push(
+            &mut log,
+            SIGNING_ENABLED,
+            serde_json::json!({ "public_key": public(&new) }),
+            Some(&new),
+        );
+        push(&mut log, "CreateVm", serde_json::json!({}), Some(&new));
+        assert_eq!(verify_with(&log, Some(&new)).unwrap(), 6);
+        let err = verify_with(&log, Some(&old)).unwrap_err();
+        assert!(err.to_string().contains("replaced"), "{:#}", err);
+    }
+
+    #[test]
+    fn anchor_keeps_pruned_signing_key() {
+        let key = key(1);
+        let log = signed_log(&key);
+        let anchor = ChainAnchor {
+            seq: 2,
+            prev_hash: log[1].hash.clone(),
+            signing_key: signing_key_at_end(&log[..2], &ChainAnchor::default()).map(str::to_string),
+        };
+        let verifying = key.verifying_key();
+        assert_eq!(
+            verify_entries(&log[2..], &anchor, Some(&verifying)).unwrap(),
+            2
+        );
+
+        let mut stripped = log[2..].to_vec();
+        stripped[1].signature = None;
+        assert!(verify_entries(&stripped, &anchor, Some(&verifying)).is_err());
+
+        let unanchored = ChainAnchor {
+            signing_key: None,
+            ..anchor
+        };
+        assert!(verify_entries(&log[2..], &unanchored, Some(&verifying)).is_err());
+    }
+
+    #[test]
+    fn sequence_gap_fails() {
+        let mut log = signed_log(&key(1));
+        log.remove(2);
+        assert!(verify_with(&log, None).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Rate-capped, resumable reading of platform API responses. On a
+//! constrained link a multi-megabyte compose config is read no faster than
+//! the configured cap, so it does not starve the validator's own traffic, and
+//! a connection dropped midway is resumed with a ranged request instead of
+//! starting over, where the server supports ranges.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::StatusCode;
+use tracing::warn;
+
+/// Ranged requests made for one response before giving up
+const RESUME_ATTEMPTS: u32 = 5;
+
+/// Sleeps as needed to keep the bytes read at or below `bytes_per_sec`
+struct Throttle {
+    bytes_per_sec: Option<u64>,
+    started: Instant,
+    bytes: u64,
+}
+
+impl Throttle {
+    fn new(bytes_per_sec: Option<u64>) -> Self {
+        Self {
+            bytes_per_sec: bytes_per_sec.filter(|rate| *rate > 0),
+            started: Instant::now(),
+            bytes: 0,
+        }
+    }
+
+    async fn consume(&mut self, bytes: usize) {
+        let Some(rate) = self.bytes_per_sec else {
+            return;
+        };
+        self.bytes += bytes as u64;
+        let due = Duration::from_secs_f64(self.bytes as f64 / rate as f64);
+        if let Some(wait) = due.checked_sub(self.started.elapsed()) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// How long a request may take to deliver `max_bytes` at `bytes_per_sec`,
+/// on top of `base`
+pub fn request_timeout(base: Duration, bytes_per_sec: Option<u64>, max_bytes: usize) -> Duration {
+    match bytes_per_sec.filter(|rate| *rate > 0) {
+        Some(rate) => base + Duration::from_secs((max_bytes as u64).div_ceil(rate)),
+        None => base,
+    }
+}
+
+/// Read the body of `response`, at most `max_bytes`, at no more than
+/// `bytes_per_sec`. When the connection drops and the response named a
+/// validator (ETag or Last-Modified) and `Accept-Ranges: bytes`, the rest is
+/// fetched with `Range` and `If-Range` headers added to the request from
+/// `resume`; a full response in reply means the content changed, and
+/// reading starts over.
+pub async fn read_body(
+    mut response: reqwest::Response,
+    bytes_per_sec: Option<u64>,
+    max_bytes: usize,
+    resume: impl Fn(HeaderMap) -> reqwest::RequestBuilder,
+) -> Result<Vec<u8>> {
+    let validator = resume_validator(response.headers());
+    let mut throttle = Throttle::new(bytes_per_sec);
+    let mut body = Vec::new();
+    let mut attempts = 0;
+    loop {
+        let interrupted = loop {
+            match response.chunk().await {
+                Ok(Some(chunk)) => {
+                    if body.len() + chunk.len() > max_bytes {
+                        anyhow::bail!("API response exceeded {} bytes, aborting", max_bytes);
+                    }
+                    body.extend_from_slice(&chunk);
+                    throttle.consume(chunk.len()).await;
+                }
+                Ok(None) => return Ok(body),
+                Err(e) => break e,
+            }
+        };
+        let Some(validator) = validator.as_ref().filter(|_| attempts < RESUME_ATTEMPTS) else {
+            return Err(interrupted).context("Failed to read response body");
+        };
+        attempts += 1;
+        warn!(
+            "API response interrupted after {} bytes, resuming ({}/{}): {}",
+            body.len(),
+            attempts,
+            RESUME_ATTEMPTS,
+            interrupted
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::RANGE,
+            HeaderValue::from_str(&format!("bytes={}-", body.len()))?,
+        );
+        headers.insert(header::IF_RANGE, validator.clone());
+        response = resume(headers)
+            .send()
+            .await
+            .context("Failed to resume reading the API response")?;
+        match response.status() {
+            StatusCode::PARTIAL_CONTENT => {
+                let start = response
+                    .headers()
+                    .get(header::CONTENT_RANGE)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(content_range_start);
+                if start != Some(body.len() as u64) {
+                    anyhow::bail!("API resumed the response at the wrong offset");
+                }
+            }
+            StatusCode::OK => body.clear(),
+            status => anyhow::bail!("API returned status {} when resuming", status),
+        }
+    }
+}
+
+/// The validator a ranged request can be made against, if the server
+/// supports ranges and sent one
+fn resume_validator(headers: &HeaderMap) -> Option<HeaderValue> {
+    let ranges = headers
+        .get(header::ACCEPT_RANGES)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("bytes"));
+    // A weak ETag cannot be used with If-Range
+    let etag = headers
+        .get(header::ETAG)
+        .filter(|v| !v.as_bytes().starts_with(b"W/"));
+    ranges
+        .then(|| etag.or_else(|| headers.get(header::LAST_MODIFIED)).cloned())
+        .flatten()
+}
+
+/// First byte of `bytes START-END/TOTAL`
+fn content_range_start(value: &str) -> Option<u64> {
+    value
+        .strip_prefix("bytes ")?
+        .split('-')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
@@ -0,0 +1,256 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Optional canary step before an update: the new deployment is first
+//! started next to the running validator, with its host ports shifted and
+//! without signing secrets, and operator probes must pass against it. The
+//! verified VM, which has the validator's app_id, then replaces the old one.
+
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::env_metadata::{self, EnvMetadata};
+use crate::health::{self, HealthCheckConfig};
+use crate::{defaults, VmParameters};
+
+/// Env var carrying the canary VM's id to probes
+pub const PROBE_VM_ID_ENV: &str = "CANARY_VM_ID";
+/// Env var carrying the host port offset to probes
+pub const PROBE_PORT_OFFSET_ENV: &str = "CANARY_PORT_OFFSET";
+
+fn default_env_flag() -> String {
+    defaults::CANARY_ENV_FLAG.to_string()
+}
+
+fn default_port_offset() -> u16 {
+    defaults::CANARY_PORT_OFFSET
+}
+
+fn default_timeout_secs() -> u64 {
+    defaults::CANARY_TIMEOUT_SECS
+}
+
+fn default_withheld_env() -> Vec<String> {
+    defaults::CANARY_WITHHELD_ENV
+        .iter()
+        .map(|key| key.to_string())
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CanaryConfig {
+    /// Env var set to `1` in the canary so the validator runs in test mode;
+    /// only delivered if the app's allowed envs already list it
+    #[serde(default = "default_env_flag")]
+    pub env_flag: String,
+    /// Added to every host port of the canary so it does not clash with the running VM
+    #[serde(default = "default_port_offset")]
+    pub port_offset: u16,
+    /// How long the probes may take to pass, counted from the canary running
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Commands that must all exit 0; `interval_secs` is the retry interval
+    #[serde(default)]
+    pub probes: Vec<HealthCheckConfig>,
+    /// Env vars never given to the canary, on top of those the API marks secret
+    #[serde(default = "default_withheld_env")]
+    pub withheld_env: Vec<String>,
+}
+
+impl Default for CanaryConfig {
+    fn default() -> Self {
+        Self {
+            env_flag: default_env_flag(),
+            port_offset: default_port_offset(),
+            timeout_secs: default_timeout_secs(),
+            probes: Vec::new(),
+            withheld_env: default_withheld_env(),
+        }
+    }
+}
+
+impl fmt::Display for CanaryConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}=1, ports +{}, {} probe(s) within {}s",
+            self.env_flag,
+            self.port_offset,
+            self.probes.len(),
+            self.timeout_secs
+        )?;
+        if !self.withheld_env.is_empty() {
+            write!(f, ", withholding {}", self.withheld_env.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parameters of the canary: the new VM's, running, with host ports
+/// shifted by the offset. The name is kept, as it is part of the app_id.
+pub fn vm_params(params: &VmParameters, config: &CanaryConfig) -> Result<VmParameters> {
+    let mut canary = params.clone();
+    canary.stopped = false;
+    for port in &mut canary.ports {
+        port.host_port = port
+            .host_port
+            .checked_add(config.port_offset)
+            .context(format!(
+                "Canary port offset {} pushes host port {} past 65535",
+                config.port_offset, port.host_port
+            ))?;
+    }
+    Ok(canary)
+}
+
+/// Env of the canary: the validator's without withheld or secret keys, plus
+/// the test-mode flag if `allowed_envs` lists it. Adding the flag to the
+/// allowed envs would change the app_id, and with it the VM that is promoted.
+pub fn env(
+    env_vars: Vec<Value>,
+    config: &CanaryConfig,
+    metadata: &EnvMetadata,
+    allowed_envs: &[String],
+) -> Vec<Value> {
+    let mut env: Vec<Value> = env_vars
+        .into_iter()
+        .filter(|var| {
+            let key = var.get("key").and_then(|k| k.as_str()).unwrap_or_default();
+            let withheld = config.withheld_env.iter().any(|withheld| withheld == key)
+                || env_metadata::is_secret(key, metadata);
+            if withheld {
+                info!("Withholding {} from the canary", key);
+            }
+            !withheld && key != config.env_flag
+        })
+        .collect();
+    if allowed_envs.contains(&config.env_flag) {
+        env.push(json!({ "key": config.env_flag, "value": "1" }));
+    } else {
+        warn!(
+            "{} is not in the app's allowed envs; the canary runs without it",
+            config.env_flag
+        );
+    }
+    env
+}
+
+/// Run the probes until all pass in the same round, or fail once the timeout
+/// has passed
+pub async fn verify(config: &CanaryConfig, vm_id: &str) -> Result<()> {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(config.timeout_secs);
+    let env = [
+        (PROBE_VM_ID_ENV, vm_id.to_string()),
+        (PROBE_PORT_OFFSET_ENV, config.port_offset.to_string()),
+    ];
+    loop {
+        let mut failures = Vec::new();
+        for probe in &config.probes {
+            if let Err(e) = health::run_check(probe, &env).await {
+                failures.push(format!("{} ({:#})", probe.name, e));
+            }
+        }
+        if failures.is_empty() {
+            info!("Canary {} passed {} probe(s)", vm_id, config.probes.len());
+            return Ok(());
+        }
+        if tokio::time::Instant::now() >= deadline {
+            anyhow::bail!(
+                "Canary probes still failing after {}s: {}",
+                config.timeout_secs,
+                failures.join("; ")
+            );
+        }
+        warn!("Canary probes failing, retrying: {}", failures.join("; "));
+        let retry = config
+            .probes
+            .iter()
+            .map(|p| p.interval_secs)
+            .min()
+            .unwrap_or(defaults::HEALTH_CHECK_INTERVAL_SECS);
+        tokio::time::sleep(Duration::from_secs(retry)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env_metadata::EnvKeyMetadata;
+    use crate::PortMapping;
+
+    fn var(key: &str) -> Value {
+        json!({ "key": key, "value": "x" })
+    }
+
+    fn keys(env: &[Value]) -> Vec<&str> {
+        env.iter().map(|var| var["key"].as_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn env_withholds_signing_and_secret_keys() {
+        let metadata = EnvMetadata::from([(
+            "API_TOKEN".to_string(),
+            EnvKeyMetadata {
+                secret: true,
+                ..Default::default()
+            },
+        )]);
+        let env = env(
+            vec![var("HOTKEY_PASSPHRASE"), var("API_TOKEN"), var("NETUID")],
+            &CanaryConfig::default(),
+            &metadata,
+            &[],
+        );
+        assert_eq!(keys(&env), ["NETUID"]);
+    }
+
+    #[test]
+    fn env_flag_only_when_allowed() {
+        let config = CanaryConfig::default();
+        let allowed = vec![config.env_flag.clone()];
+        let env_with = env(vec![var("NETUID")], &config, &EnvMetadata::new(), &allowed);
+        assert_eq!(keys(&env_with), ["NETUID", config.env_flag.as_str()]);
+        assert_eq!(env_with[1]["value"], "1");
+
+        // A flag set by the operator is not passed on either
+        let env_without = env(
+            vec![var("NETUID"), var(&config.env_flag)],
+            &config,
+            &EnvMetadata::new(),
+            &[],
+        );
+        assert_eq!(keys(&env_without), ["NETUID"]);
+    }
+
+    #[test]
+    fn vm_params_keep_name_and_shift_ports() {
+        let params = VmParameters {
+            stopped: true,
+            ports: vec![PortMapping {
+                host_port: 8080,
+                ..Default::default()
+            }],
+            ..VmParameters::default()
+        };
+        let config = CanaryConfig {
+            port_offset: 100,
+            ..Default::default()
+        };
+        let canary = vm_params(&params, &config).unwrap();
+        assert_eq!(canary.name, params.name);
+        assert!(!canary.stopped);
+        assert_eq!(canary.ports[0].host_port, 8180);
+
+        let too_far = CanaryConfig {
+            port_offset: u16::MAX,
+            ..Default::default()
+        };
+        assert!(vm_params(&params, &too_far).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Payloads too large to log in full, such as a compose config that fails to
+//! parse. The log line keeps the start of the payload and points at a copy of
+//! the whole of it under `captures/` in the state directory, so journald stays
+//! usable and nothing is lost for debugging.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::{paths, unix_now};
+
+/// Captured payloads kept; older ones are removed as new ones are written
+const MAX_CAPTURES: usize = 20;
+/// Error response bodies are read only this far, for a log line
+pub(crate) const MAX_LOGGED_BODY_BYTES: usize = 2048;
+
+pub(crate) fn captures_dir() -> PathBuf {
+    paths::state_dir().join("captures")
+}
+
+/// Shorten a payload for logging, keeping it on a char boundary
+fn truncate_for_log(text: &str, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text.to_string();
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!(
+        "{}... [truncated, {} bytes total]",
+        &text[..end],
+        text.len()
+    )
+}
+
+/// `text` as it should appear in a log line: in full if it fits in
+/// `max_bytes`, otherwise truncated with the path of a full copy. `kind`
+/// names the payload in the file name, e.g. `compose-config`.
+pub fn for_log(kind: &str, text: &str, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text.to_string();
+    }
+    let truncated = truncate_for_log(text, max_bytes);
+    match capture(kind, text) {
+        Ok(path) => format!("{} (full payload in {})", truncated, path.display()),
+        Err(e) => format!("{} (not captured: {:#})", truncated, e),
+    }
+}
+
+/// The start of an error response's body, for a log line. At most
+/// [`MAX_LOGGED_BODY_BYTES`] are read; the rest is never downloaded.
+pub(crate) async fn error_body(mut response: reqwest::Response) -> String {
+    let mut body = Vec::new();
+    let truncated = loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                body.extend_from_slice(&chunk);
+                if body.len() > MAX_LOGGED_BODY_BYTES {
+                    break true;
+                }
+            }
+            Ok(None) => break false,
+            Err(e) if body.is_empty() => return format!("(body unreadable: {})", e),
+            Err(_) => break false,
+        }
+    };
+    log_text(body, truncated)
+}
+
+fn log_text(mut body: Vec<u8>, truncated: bool) -> String {
+    body.truncate(MAX_LOGGED_BODY_BYTES);
+    let text = String::from_utf8_lossy(&body);
+    let text = text.trim();
+    if truncated {
+        format!("{}... [truncated]", text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn capture(kind: &str, text: &str) -> Result<PathBuf> {
+    let dir = captures_dir();
+    std::fs::create_dir_all(&dir).context(format!("Failed to create {}", dir.display()))?;
+    let stamp = unix_now();
+    let mut path = dir.join(format!("{}-{}.txt", kind, stamp));
+    let mut n = 1;
+    while path.exists() {
+        path = dir.join(format!("{}-{}-{}.txt", kind, stamp, n));
+        n += 1;
+    }
+    std::fs::write(&path, text).context(format!("Failed to write {}", path.display()))?;
+    prune(&dir);
+    Ok(path)
+}
+
+/// Remove all but the newest [`MAX_CAPTURES`] captures
+fn prune(dir: &Path) {
+    let captures = list_in(dir);
+    if captures.len() <= MAX_CAPTURES {
+        return;
+    }
+    for (path, _) in &captures[..captures.len() - MAX_CAPTURES] {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Captured payloads with their sizes in bytes, oldest first
+pub(crate) fn list() -> Vec<(PathBuf, u64)> {
+    list_in(&captures_dir())
+}
+
+fn list_in(dir: &Path) -> Vec<(PathBuf, u64)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut captures: Vec<_> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok()?;
+            Some((meta.modified().ok()?, entry.path(), meta.len()))
+        })
+        .collect();
+    captures.sort();
+    captures
+        .into_iter()
+        .map(|(_, path, size)| (path, size))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_text_is_bounded() {
+        assert_eq!(log_text(b" not found\n".to_vec(), false), "not found");
+        let long = vec![b'x'; MAX_LOGGED_BODY_BYTES + 100];
+        let text = log_text(long, true);
+        assert!(text.ends_with("... [truncated]"));
+        assert_eq!(text.len(), MAX_LOGGED_BODY_BYTES + "... [truncated]".len());
+        // A multi-byte char cut in half is replaced, not an error
+        let mut split = vec![b'x'; MAX_LOGGED_BODY_BYTES - 1];
+        split.extend_from_slice("é".as_bytes());
+        assert!(log_text(split, true).contains('\u{fffd}'));
+    }
+}
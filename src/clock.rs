@@ -0,0 +1,150 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Host clock drift, measured before creating a VM. A VM attests to the KMS
+//! as it boots, and both the TDX quote verification and the TLS connections
+//! involved check validity periods against the clock; on a drifted host they
+//! fail with errors that say nothing about time. The offset is measured
+//! against an SNTP server if one is configured, and otherwise against the
+//! `Date` header of the last platform API response.
+
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::defaults;
+
+/// Seconds between the NTP epoch (1900) and the Unix epoch
+const NTP_UNIX_OFFSET: f64 = 2_208_988_800.0;
+
+const NTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClockPolicy {
+    /// SNTP server to measure against, e.g. `time.cloudflare.com:123`;
+    /// without one, the platform API's `Date` header is used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ntp_server: Option<String>,
+    /// Largest tolerated offset in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_drift_secs: Option<u64>,
+    /// Alert but still create the VM when the clock is off
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub warn_only: bool,
+}
+
+impl ClockPolicy {
+    pub fn max_drift_secs(&self) -> u64 {
+        self.max_drift_secs
+            .unwrap_or(defaults::MAX_CLOCK_DRIFT_SECS)
+    }
+
+    pub fn exceeded(&self, offset_secs: f64) -> bool {
+        offset_secs.abs() > self.max_drift_secs() as f64
+    }
+
+    /// Offset of a trusted clock from the host's, in seconds (positive when
+    /// the host is behind), and what it was measured against.
+    /// `api_offset` is the offset seen in the last platform API response.
+    pub async fn measure(&self, api_offset: Option<f64>) -> Result<(f64, String)> {
+        match &self.ntp_server {
+            Some(server) => Ok((ntp_offset(server).await?, format!("NTP server {}", server))),
+            None => Ok((
+                api_offset.context("No platform API response with a Date header yet")?,
+                "the platform API".to_string(),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ClockPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "at most {}s against {}{}",
+            self.max_drift_secs(),
+            self.ntp_server.as_deref().unwrap_or("the platform API"),
+            if self.warn_only { ", alert only" } else { "" }
+        )
+    }
+}
+
+/// `offset_secs` as seen from the host, e.g. "12.3s behind"
+pub fn describe(offset_secs: f64) -> String {
+    format!(
+        "{:.1}s {}",
+        offset_secs.abs(),
+        if offset_secs > 0.0 { "behind" } else { "ahead" }
+    )
+}
+
+fn unix_secs(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/// Offset from an HTTP `Date` header of a response to a request sent at
+/// `sent` and received at `received`. The header has whole seconds, so the
+/// server's time is taken as the middle of its second.
+pub fn offset_from_date(date: &str, sent: SystemTime, received: SystemTime) -> Option<f64> {
+    let server = chrono::DateTime::parse_from_rfc2822(date).ok()?.timestamp() as f64 + 0.5;
+    Some(server - (unix_secs(sent) + unix_secs(received)) / 2.0)
+}
+
+/// Offset from one SNTP exchange with `server` (RFC 4330)
+async fn ntp_offset(server: &str) -> Result<f64> {
+    let addr = tokio::net::lookup_host(server)
+        .await
+        .context(format!("Failed to resolve NTP server {}", server))?
+        .next()
+        .context(format!("NTP server {} has no address", server))?;
+    let bind = if addr.is_ipv6() {
+        "[::]:0"
+    } else {
+        "0.0.0.0:0"
+    };
+    let socket = tokio::net::UdpSocket::bind(bind).await?;
+    socket.connect(addr).await?;
+
+    // LI 0, version 4, mode 3 (client); the transmit timestamp comes back
+    // as the originate timestamp, tying the answer to this request
+    let mut request = [0u8; 48];
+    request[0] = 0x23;
+    let sent = SystemTime::now();
+    request[40..48].copy_from_slice(&ntp_timestamp(sent));
+    socket.send(&request).await?;
+
+    let mut response = [0u8; 48];
+    let len = tokio::time::timeout(NTP_TIMEOUT, socket.recv(&mut response))
+        .await
+        .context(format!("NTP server {} did not answer", server))??;
+    let received = SystemTime::now();
+    if len < 48 || response[0] & 0x07 != 4 || response[24..32] != request[40..48] {
+        anyhow::bail!("Invalid answer from NTP server {}", server);
+    }
+    if response[1] == 0 {
+        anyhow::bail!("NTP server {} refused the request", server);
+    }
+
+    let server_received = from_ntp_timestamp(&response[32..40]);
+    let server_sent = from_ntp_timestamp(&response[40..48]);
+    Ok(((server_received - unix_secs(sent)) + (server_sent - unix_secs(received))) / 2.0)
+}
+
+fn ntp_timestamp(time: SystemTime) -> [u8; 8] {
+    let secs = unix_secs(time) + NTP_UNIX_OFFSET;
+    let mut bytes = [0u8; 8];
+    bytes[..4].copy_from_slice(&(secs.trunc() as u32).to_be_bytes());
+    bytes[4..].copy_from_slice(&((secs.fract() * 4_294_967_296.0) as u32).to_be_bytes());
+    bytes
+}
+
+fn from_ntp_timestamp(bytes: &[u8]) -> f64 {
+    let secs = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64;
+    let fraction = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as f64;
+    secs - NTP_UNIX_OFFSET + fraction / 4_294_967_296.0
+}
@@ -0,0 +1,202 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Compose hash and app_id derivation.
+//!
+//! The hash algorithm is versioned so it can change without every launcher
+//! instantly disagreeing with every VMM: the platform API names the version
+//! in the compose config (`hash_version`), and the launcher advertises the
+//! versions it implements in the [`SUPPORTED_VERSIONS_HEADER`] request header.
+//!
+//! Version 1: `sha256(normalized_manifest || 0x00 || image)`, where the
+//! manifest is the app-compose JSON passed to the VMM, re-serialized with all
+//! object keys sorted recursively. The app_id is the first [`APP_ID_LEN`] hex
+//! characters of the compose hash. An existing version must never change.
+
+use std::fmt;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Number of hex characters of the compose hash that form the app_id
+pub const APP_ID_LEN: usize = 40;
+
+/// Request header listing the hash versions this launcher implements
+pub const SUPPORTED_VERSIONS_HEADER: &str = "X-Compose-Hash-Versions";
+
+/// A compose hash algorithm version
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "u32", into = "u32")]
+pub enum HashVersion {
+    /// Key-sorted manifest, a NUL separator, then the image
+    #[default]
+    V1,
+}
+
+impl HashVersion {
+    /// Every version this build can compute, oldest first
+    pub const SUPPORTED: &'static [HashVersion] = &[HashVersion::V1];
+
+    /// Resolve the version requested by the API; absent means version 1
+    pub fn negotiate(requested: Option<u32>) -> Result<Self> {
+        requested.map_or(Ok(Self::V1), Self::try_from)
+    }
+
+    pub fn number(self) -> u32 {
+        match self {
+            HashVersion::V1 => 1,
+        }
+    }
+
+    /// Human-readable description, recorded in exported deployments
+    pub fn algorithm(self) -> &'static str {
+        match self {
+            HashVersion::V1 => "sha256(key-sorted app-compose.json || 0x00 || image)",
+        }
+    }
+
+    /// Full hex-encoded compose hash of an app manifest and image.
+    ///
+    /// A manifest that is not valid JSON is hashed as-is, matching the launcher.
+    pub fn compose_hash(self, manifest: &str, image: &str) -> String {
+        match self {
+            HashVersion::V1 => {
+                let normalized =
+                    normalize_manifest(manifest).unwrap_or_else(|_| manifest.to_string());
+                let mut hasher = Sha256::new();
+                hasher.update(normalized.as_bytes());
+                hasher.update(b"\0");
+                hasher.update(image.as_bytes());
+                hex::encode(hasher.finalize())
+            }
+        }
+    }
+
+    /// Value for [`SUPPORTED_VERSIONS_HEADER`], e.g. `1`
+    pub fn supported_header_value() -> String {
+        Self::SUPPORTED
+            .iter()
+            .map(|v| v.number().to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+impl TryFrom<u32> for HashVersion {
+    type Error = anyhow::Error;
+
+    fn try_from(version: u32) -> Result<Self> {
+        match version {
+            1 => Ok(HashVersion::V1),
+            other => anyhow::bail!(
+                "Unsupported compose hash version {} (this launcher supports {}); upgrade the launcher",
+                other,
+                Self::supported_header_value()
+            ),
+        }
+    }
+}
+
+impl From<HashVersion> for u32 {
+    fn from(version: HashVersion) -> u32 {
+        version.number()
+    }
+}
+
+impl fmt::Display for HashVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}", self.number())
+    }
+}
+
+/// Version 1 compose hash of an app manifest and image
+pub fn compose_hash(manifest: &str, image: &str) -> String {
+    HashVersion::V1.compose_hash(manifest, image)
+}
+
+/// The version 1 app_id of a VM created from this manifest and image
+pub fn app_id(manifest: &str, image: &str) -> String {
+    app_id_from_hash(&compose_hash(manifest, image)).to_string()
+}
+
+/// Truncate a compose hash (or an app_id reported by the VMM) to app_id length
+pub fn app_id_from_hash(hash: &str) -> &str {
+    &hash[..APP_ID_LEN.min(hash.len())]
+}
+
+/// The compose hash of one config as the launcher, the platform API, and the
+/// VMM compute it. The launcher matches the VM's app_id against its own hash,
+/// so if the VMM disagrees, every VM it creates looks outdated and is
+/// recreated on the next check.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashComparison {
+    pub launcher: String,
+    /// Published by the API with the config, if it publishes one
+    #[serde(default)]
+    pub api: Option<String>,
+    /// From the VMM's `GetComposeHash`, if it answered
+    #[serde(default)]
+    pub vmm: Option<String>,
+}
+
+impl HashComparison {
+    /// Components whose hash differs from the launcher's, by app_id
+    pub fn disagreeing(&self) -> Vec<&'static str> {
+        let app_id = |hash: &str| {
+            let hash = hash.trim();
+            app_id_from_hash(hash.strip_prefix("0x").unwrap_or(hash)).to_ascii_lowercase()
+        };
+        let ours = app_id(&self.launcher);
+        [("API", &self.api), ("VMM", &self.vmm)]
+            .into_iter()
+            .filter(|(_, hash)| hash.as_deref().is_some_and(|hash| app_id(hash) != ours))
+            .map(|(name, _)| name)
+            .collect()
+    }
+}
+
+impl fmt::Display for HashComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let app_id = |hash: &Option<String>, missing: &str| {
+            hash.as_deref()
+                .map(|hash| app_id_from_hash(hash).to_string())
+                .unwrap_or_else(|| missing.to_string())
+        };
+        write!(
+            f,
+            "launcher {}, API {}, VMM {}",
+            app_id_from_hash(&self.launcher),
+            app_id(&self.api, "(not published)"),
+            app_id(&self.vmm, "(unavailable)")
+        )?;
+        match self.disagreeing().as_slice() {
+            [] => write!(f, " (agree)"),
+            names => write!(f, " (MISMATCH: {})", names.join(", ")),
+        }
+    }
+}
+
+/// Parse and re-serialize JSON with all object keys sorted recursively
+pub fn normalize_manifest(manifest: &str) -> Result<String> {
+    let value: Value =
+        serde_json::from_str(manifest).context("Failed to parse JSON for normalization")?;
+    serde_json::to_string(&sort_json_keys(&value)).context("Failed to serialize normalized JSON")
+}
+
+/// Recursively sort all object keys in a JSON value
+pub fn sort_json_keys(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let sorted: std::collections::BTreeMap<String, Value> = map
+                .iter()
+                .map(|(k, v)| (k.clone(), sort_json_keys(v)))
+                .collect();
+            Value::Object(sorted.into_iter().collect())
+        }
+        Value::Array(arr) => Value::Array(arr.iter().map(sort_json_keys).collect()),
+        _ => value.clone(),
+    }
+}
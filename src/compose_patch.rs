@@ -0,0 +1,110 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Incremental compose updates: the launcher offers the hash of the compose
+//! content it already has, and the API may answer with a line patch against
+//! it instead of the full content.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::store::{self, Store};
+
+/// Request header carrying the sha256 of the cached base compose content
+pub const BASE_SHA256_HEADER: &str = "X-Compose-Base-Sha256";
+
+/// Line-based patch from a base compose content to the current one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComposePatch {
+    /// sha256 of the content the patch applies to
+    pub base_sha256: String,
+    /// sha256 of the full content after applying the patch
+    pub sha256: String,
+    /// Sorted, non-overlapping edits; line numbers refer to the base
+    pub hunks: Vec<Hunk>,
+}
+
+/// Replace `delete` lines starting at 0-based line `start` with `insert`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hunk {
+    pub start: usize,
+    #[serde(default)]
+    pub delete: usize,
+    #[serde(default)]
+    pub insert: Vec<String>,
+}
+
+pub fn content_sha256(content: &str) -> String {
+    hex::encode(Sha256::digest(content.as_bytes()))
+}
+
+impl ComposePatch {
+    /// Apply the patch to `base`, verifying both the base and the result hash
+    pub fn apply(&self, base: &str) -> Result<String> {
+        let base_sha256 = content_sha256(base);
+        if base_sha256 != self.base_sha256 {
+            anyhow::bail!(
+                "Patch base {} does not match cached compose {}",
+                self.base_sha256,
+                base_sha256
+            );
+        }
+
+        let lines: Vec<&str> = base.split('\n').collect();
+        let mut patched: Vec<&str> = Vec::with_capacity(lines.len());
+        let mut cursor = 0;
+        for hunk in &self.hunks {
+            let end = hunk.start + hunk.delete;
+            if hunk.start < cursor || end > lines.len() {
+                anyhow::bail!(
+                    "Patch hunk at line {} (-{}) is out of order or beyond the base ({} lines)",
+                    hunk.start,
+                    hunk.delete,
+                    lines.len()
+                );
+            }
+            patched.extend_from_slice(&lines[cursor..hunk.start]);
+            patched.extend(hunk.insert.iter().map(String::as_str));
+            cursor = end;
+        }
+        patched.extend_from_slice(&lines[cursor..]);
+        let content = patched.join("\n");
+
+        let sha256 = content_sha256(&content);
+        if sha256 != self.sha256 {
+            anyhow::bail!(
+                "Patched compose hash {} does not match expected {}",
+                sha256,
+                self.sha256
+            );
+        }
+        Ok(content)
+    }
+}
+
+pub(crate) const BASE_KEY: &str = "compose-base.yml";
+
+/// Compose content from the last successful fetch, used as the patch base
+pub fn load_base() -> Option<String> {
+    let content = store::open().and_then(|store| store.get(BASE_KEY)).ok()??;
+    String::from_utf8(content).ok()
+}
+
+/// Remember `content` as the next patch base; failures are only logged
+pub fn update_base(content: &str) {
+    if load_base().as_deref() == Some(content) {
+        return;
+    }
+    if let Err(e) = save_base(content) {
+        tracing::warn!("Failed to cache compose content: {:#}", e);
+    }
+}
+
+fn save_base(content: &str) -> Result<()> {
+    store::open()?.commit(vec![(
+        BASE_KEY.to_string(),
+        Some(content.as_bytes().to_vec()),
+    )])
+}
@@ -0,0 +1,49 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Conditional requests for the compose config. The `ETag` and
+//! `Last-Modified` of the last full response are sent back as
+//! `If-None-Match` and `If-Modified-Since`; an API that supports them
+//! answers 304 Not Modified while nothing changed, and the cached config is
+//! used without downloading, parsing, or hashing it again.
+
+use reqwest::header::{self, HeaderMap, HeaderValue};
+
+/// What identifies a response to the server, for revalidating it
+#[derive(Debug, Clone)]
+pub struct Validators {
+    etag: Option<HeaderValue>,
+    last_modified: Option<HeaderValue>,
+}
+
+impl Validators {
+    /// The validators of a response, if it has any
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let validators = Self {
+            etag: headers.get(header::ETAG).cloned(),
+            last_modified: headers.get(header::LAST_MODIFIED).cloned(),
+        };
+        (validators.etag.is_some() || validators.last_modified.is_some()).then_some(validators)
+    }
+
+    /// Headers making a request conditional on the response having changed
+    pub fn request_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(etag) = &self.etag {
+            headers.insert(header::IF_NONE_MATCH, etag.clone());
+        }
+        // If-None-Match takes precedence where both are understood
+        if let Some(last_modified) = &self.last_modified {
+            headers.insert(header::IF_MODIFIED_SINCE, last_modified.clone());
+        }
+        headers
+    }
+}
+
+/// A response body kept for conditional requests
+#[derive(Debug, Clone)]
+pub struct Cached<T> {
+    pub validators: Validators,
+    pub value: T,
+}
@@ -0,0 +1,696 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! JSON Schemas for the platform config and the compose config the API
+//! serves, and a linter checking files against them, so configuration
+//! management can validate templates before they reach a host.
+//!
+//! The schemas are written by hand and must be kept in step with the serde
+//! definitions; `lint` also deserializes the file, so a mismatch surfaces as
+//! a parse error rather than a silently accepted config.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde_json::{json, Map, Value};
+
+use crate::http_config;
+use crate::timefmt::DisplayTimezone;
+use crate::validation::{self, ImageSupport, ValidationIssue};
+use crate::{ComposeConfig, PlatformConfig};
+
+const SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Which document a schema or lint applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigKind {
+    /// The launcher's own config file
+    Platform,
+    /// A compose config as returned by the platform API
+    Compose,
+}
+
+fn string(description: &str) -> Value {
+    json!({ "type": "string", "description": description })
+}
+
+fn nullable_string(description: &str) -> Value {
+    json!({ "type": ["string", "null"], "description": description })
+}
+
+fn boolean(description: &str, default: bool) -> Value {
+    json!({ "type": "boolean", "description": description, "default": default })
+}
+
+fn integer(max: u64, description: &str) -> Value {
+    json!({ "type": "integer", "minimum": 0, "maximum": max, "description": description })
+}
+
+fn nullable_integer(max: u64, description: &str) -> Value {
+    json!({ "type": ["integer", "null"], "minimum": 0, "maximum": max, "description": description })
+}
+
+fn string_array(description: &str) -> Value {
+    json!({ "type": "array", "items": { "type": "string" }, "description": description })
+}
+
+fn object(properties: Value, required: &[&str]) -> Value {
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+/// Optional section: absent, `null`, or the object
+fn nullable(mut schema: Value, description: &str) -> Value {
+    schema["type"] = json!(["object", "null"]);
+    schema["description"] = json!(description);
+    schema
+}
+
+const U16: u64 = u16::MAX as u64;
+const U32: u64 = u32::MAX as u64;
+const U64: u64 = u64::MAX;
+
+/// JSON Schema for the platform config file
+pub fn platform_config_schema() -> Value {
+    let api_tls = object(
+        json!({
+            "client_cert": string("Path to the PEM-encoded client certificate (chain)"),
+            "client_key": string("Path to the PEM-encoded PKCS#8 private key"),
+            "ca_cert": nullable_string("PEM CA bundle used to verify the platform API server"),
+        }),
+        &["client_cert", "client_key"],
+    );
+    let http = object(
+        json!({
+            "user_agent": nullable_string("Replaces the default `validator-auto-updater/<version> (host <id>)`"),
+            "headers": {
+                "type": "object",
+                "additionalProperties": { "type": "string" },
+                "description": "Added to every platform API request",
+            },
+            "max_bytes_per_sec": nullable_integer(U64, "Read API responses at no more than this many bytes per second"),
+        }),
+        &[],
+    );
+    let resource_limits = object(
+        json!({
+            "max_vcpu": nullable_integer(U32, "Maximum number of vCPUs"),
+            "max_memory_mb": nullable_integer(U32, "Maximum memory in MB"),
+            "max_disk_gb": nullable_integer(U32, "Maximum disk size in GB"),
+            "policy": {
+                "enum": ["reject", "clamp"],
+                "default": "reject",
+                "description": "What to do when the API asks for more than the caps",
+            },
+        }),
+        &[],
+    );
+    let mqtt = object(
+        json!({
+            "broker_url": string("`mqtt://host[:1883]`, or `mqtts://host[:8883]` for TLS"),
+            "topic_prefix": nullable_string("Topic prefix, `platform-validator/<host id>` unless set"),
+            "username": nullable_string("Broker username"),
+            "password": nullable_string("Broker password"),
+            "ca_cert": nullable_string("PEM CA bundle for a broker with a private CA"),
+        }),
+        &["broker_url"],
+    );
+    let pagerduty = object(
+        json!({
+            "routing_key": string("Integration key of an Events API v2 integration"),
+        }),
+        &["routing_key"],
+    );
+    let opsgenie = object(
+        json!({
+            "api_key": string("API key of an Opsgenie API integration"),
+            "eu": boolean("Account hosted in the EU instance", false),
+        }),
+        &["api_key"],
+    );
+    let notifications = object(
+        json!({
+            "webhooks": string_array("URLs that receive a JSON POST for every transition"),
+            "mqtt": nullable(mqtt, "Broker that receives transitions and the launcher status"),
+            "pagerduty": nullable(pagerduty, "PagerDuty incidents, one per failure condition"),
+            "opsgenie": nullable(opsgenie, "Opsgenie alerts, one per failure condition"),
+        }),
+        &[],
+    );
+    let recovery = object(
+        json!({
+            "start_stopped": boolean("Start a cleanly stopped VM whose config is current instead of recreating it", true),
+            "crashloop_threshold": integer(U32, "Failures within the window after which recreation is held off"),
+            "crashloop_window_secs": integer(U64, "Crashloop window in seconds"),
+            "verify_on_start": boolean("Check that a VM found at startup is booted and running its app before keeping it", false),
+            "max_recreations": integer(U32, "Automatic recreations within the window after which the launcher is degraded; 0 disables the limit"),
+            "recreation_window_secs": integer(U64, "Recreation window in seconds"),
+            "recreate_backoff_secs": integer(U64, "Seconds before the second recreation within the window, doubled for each one after"),
+        }),
+        &[],
+    );
+    let admin_api = object(
+        json!({
+            "listen": string("Address to listen on, e.g. `0.0.0.0:9850`"),
+            "read_token": string("Bearer token for monitoring: status, history, events, masked config"),
+            "operator_token": nullable_string("Bearer token that additionally reads secrets and triggers update checks"),
+            "status_page": boolean("Serve a read-only HTML status page at `/`, without a token", false),
+        }),
+        &["listen", "read_token"],
+    );
+    let boot_timeout = object(
+        json!({
+            "base_secs": integer(U64, "Seconds for a VM whose disk already exists"),
+            "secs_per_disk_gb": integer(U64, "Seconds added per GB of disk when a new VM boots for the first time"),
+            "fixed_secs": nullable_integer(U64, "One timeout for every boot, replacing the computed ones"),
+        }),
+        &[],
+    );
+    let disclosure = object(
+        json!({
+            "suppress_sysinfo": boolean("Keep `public_sysinfo` off even when the API asks for it", false),
+            "suppress_tcbinfo": boolean("Keep `public_tcbinfo` off even when the API asks for it", false),
+        }),
+        &[],
+    );
+    let power = object(
+        json!({
+            "inhibit_updates": boolean("Block shutdown and suspend while the VM is being recreated or resized", true),
+            "stop_vm_on_shutdown": boolean("Stop the VM through the VMM before the host shuts down", false),
+        }),
+        &[],
+    );
+    let clock_check = object(
+        json!({
+            "ntp_server": nullable_string("SNTP server to measure against, e.g. `time.cloudflare.com:123`; without one, the platform API's `Date` header is used"),
+            "max_drift_secs": nullable_integer(U64, "Largest tolerated offset in seconds"),
+            "warn_only": boolean("Alert but still create the VM when the clock is off", false),
+        }),
+        &[],
+    );
+    let embedded_vmm = object(
+        json!({
+            "binary": string("Path of the VMM binary (not interpreted by a shell)"),
+            "args": string_array("Arguments passed to the VMM"),
+            "ready_timeout_secs": integer(U64, "How long the first update check waits for the VMM to answer RPCs"),
+        }),
+        &["binary"],
+    );
+    let retention = object(
+        json!({
+            "audit_max_entries": nullable_integer(U64, "Keep at most this many audit log entries"),
+            "audit_max_age_days": nullable_integer(U64, "Drop audit log entries older than this"),
+            "max_disk_mb": integer(U64, "Upper bound on the total size of the state directory"),
+        }),
+        &[],
+    );
+    let http_endpoint = object(
+        json!({
+            "type": { "const": "http" },
+            "url": string("URL that receives the binding as JSON"),
+            "method": nullable_string("HTTP method, PUT unless set"),
+            "bearer_token": nullable_string("Bearer token sent with the request"),
+        }),
+        &["type", "url"],
+    );
+    let cloudflare_dns = object(
+        json!({
+            "type": { "const": "cloudflare_dns" },
+            "zone_id": string("Cloudflare zone ID"),
+            "record_name": string("Fully qualified record name"),
+            "api_token": string("API token with DNS edit permission for the zone"),
+            "ttl": integer(U32, "Record TTL in seconds"),
+        }),
+        &["type", "zone_id", "record_name", "api_token"],
+    );
+    let image_support = object(
+        json!({
+            "runners": string_array("Runners the image can execute"),
+            "max_manifest_version": integer(U32, "Highest app manifest version the image can parse"),
+        }),
+        &[],
+    );
+    let health_check = object(
+        json!({
+            "name": string("Unique name for the check"),
+            "command": string("Executable to run (not interpreted by a shell)"),
+            "args": string_array("Arguments passed to the command"),
+            "interval_secs": integer(U64, "Seconds between runs"),
+            "timeout_secs": integer(U64, "Seconds before the command is killed and the check fails"),
+        }),
+        &["name", "command"],
+    );
+
+    let canary = object(
+        json!({
+            "env_flag": string("Env var set to `1` in the canary so the validator runs in test mode"),
+            "port_offset": integer(U16, "Added to every host port of the canary"),
+            "timeout_secs": integer(U64, "How long the probes may take to pass"),
+            "probes": {
+                "type": "array",
+                "items": health_check.clone(),
+                "description": "Commands that must all exit 0; `interval_secs` is the retry interval",
+            },
+            "withheld_env": {
+                "type": "array",
+                "items": { "type": "string" },
+                "description": "Env vars never given to the canary, on top of those the API marks secret",
+            },
+        }),
+        &[],
+    );
+
+    let allowed_envs = object(
+        json!({
+            "add": string_array("Keys allowed in addition to the resolved ones"),
+            "remove": string_array("Resolved keys to leave out"),
+        }),
+        &[],
+    );
+    let missing_env_action = json!({
+        "enum": ["warn", "hold", "fail", null],
+        "description": "`warn` deploys without the value, `hold` leaves the VM as it is with an alert, `fail` fails the check (default)",
+    });
+    let missing_env = object(
+        json!({
+            "secret": missing_env_action,
+            "required": missing_env_action,
+            "provisioning": missing_env_action,
+        }),
+        &[],
+    );
+
+    let mut schema = object(
+        json!({
+            "dstack_vmm_url": nullable_string("VMM URL passed to the VM as DSTACK_VMM_URL (guest side)"),
+            "env": {
+                "type": ["object", "null"],
+                "additionalProperties": { "type": "string" },
+                "description": "Environment variables passed to the VM",
+            },
+            "vmm_url": nullable_string("VMM RPC endpoint used by the launcher itself (host side)"),
+            "network": {
+                "enum": ["mainnet", "testnet", "devnet", null],
+                "description": "Platform network whose endpoints are used",
+            },
+            "api_url": nullable_string("Compose config endpoint of the platform API, overriding the network's"),
+            "api_mirrors": string_array("Compose config endpoints tried in order while the API URL is unreachable or answers 5xx"),
+            "poll_interval_secs": {
+                "type": ["integer", "null"],
+                "minimum": 1,
+                "maximum": U64,
+                "description": "Seconds between update checks",
+            },
+            "poll_jitter_percent": {
+                "type": ["integer", "null"],
+                "minimum": 0,
+                "maximum": 50,
+                "description": "Random spread of each poll interval, in percent either way",
+            },
+            "push_updates": boolean("Keep an event stream open to the platform API and check for an update as soon as it announces one", false),
+            "api_transport": {
+                "enum": ["http", "websocket", null],
+                "description": "How the compose config is fetched: polled over HTTP, or pushed over a WebSocket subscription",
+            },
+            "vm_recheck_secs": nullable_integer(U64, "While the API answers 304 Not Modified, seconds between full checks of an up-to-date VM (0 checks it on every poll)"),
+            "timezone": nullable_string("Timezone for displayed timestamps (`local` or an IANA name)"),
+            "api_tls": nullable(api_tls, "Client identity presented to the platform API for mutual TLS"),
+            "http": nullable(http, "User agent and extra headers for platform API requests"),
+            "resource_limits": nullable(resource_limits, "Hard caps on the resources requested for the VM"),
+            "drift_policy": {
+                "enum": ["ignore", "alert", "reconcile", null],
+                "description": "How drift between the VM's actual and desired parameters is handled",
+            },
+            "notifications": nullable(notifications, "Transition webhooks"),
+            "recovery": nullable(recovery, "How stopped and failed VMs are brought back"),
+            "admin_api": nullable(admin_api, "Read-only admin API for remote observers"),
+            "retention": nullable(retention, "Bounds on local data under the state directory"),
+            "endpoint_integrations": {
+                "type": "array",
+                "items": { "oneOf": [http_endpoint, cloudflare_dns] },
+                "description": "External records (load balancers, DNS) updated after the VM is recreated",
+            },
+            "public_ip": nullable_string("Public IP announced to endpoint integrations"),
+            "require_image_digest": boolean("Refuse to trust a VM whose image digest differs from the one pinned when it was deployed", false),
+            "user_config": nullable_string("Local user_config, merged over the API-provided value"),
+            "image_support": {
+                "type": "object",
+                "additionalProperties": image_support,
+                "description": "Runners and manifest versions each guest image supports, by image name",
+            },
+            "health_checks": {
+                "type": "array",
+                "items": health_check,
+                "description": "Operator-defined checks that feed into the launcher's health",
+            },
+            "canary": nullable(canary, "Verify the new deployment in a canary VM before replacing the running one"),
+            "require_update_approval": boolean("Hold updates of a running VM until approved through the admin API", false),
+            "remove_orphans": boolean("Remove VMs the launcher created but no longer manages, found by the hourly orphan scan", false),
+            "warm_standby": boolean("Keep a stopped VM with the current config ready to replace a failed one", false),
+            "allowed_envs": nullable(allowed_envs, "Local additions to and removals from the resolved allowed_envs"),
+            "missing_env": nullable(missing_env, "What an update check does when a required env key has no value, per key class"),
+            "boot_timeout": nullable(boot_timeout, "How long a VM may take to boot, scaled by disk size for a new VM"),
+            "disclosure": nullable(disclosure, "Manifest disclosure flags kept off regardless of the API's manifest defaults"),
+            "power": nullable(power, "Shutdown and suspend handling through systemd-logind"),
+            "clock_check": nullable(clock_check, "Host clock drift tolerated before creating a VM, which attests as it boots"),
+            "embedded_vmm": nullable(embedded_vmm, "VMM process the launcher starts and restarts itself (all-in-one mode)"),
+        }),
+        &[],
+    );
+    schema["$schema"] = json!(SCHEMA_DIALECT);
+    schema["title"] = json!("Platform validator launcher config");
+    schema
+}
+
+/// JSON Schema for the compose config served by the platform API. Unknown
+/// top-level fields are allowed, since the launcher ignores them.
+pub fn compose_config_schema() -> Value {
+    let manifest_defaults = object(
+        json!({
+            "manifest_version": integer(U32, "App manifest version"),
+            "name": nullable_string("App name"),
+            "runner": string("Runner, e.g. `docker-compose`"),
+            "kms_enabled": boolean("Use the KMS", false),
+            "gateway_enabled": boolean("Register with the gateway", false),
+            "local_key_provider_enabled": boolean("Use the local key provider", false),
+            "key_provider_id": string("Key provider ID"),
+            "public_logs": boolean("Expose container logs", false),
+            "public_sysinfo": boolean("Expose system information", false),
+            "public_tcbinfo": boolean("Expose TCB information", false),
+            "no_instance_id": boolean("Do not derive an instance ID", false),
+            "secure_time": boolean("Require secure time", false),
+        }),
+        &["manifest_version", "runner"],
+    );
+    let port = object(
+        json!({
+            "protocol": { "enum": ["tcp", "udp"] },
+            "host_port": integer(U16, "Port on the host"),
+            "vm_port": integer(U16, "Port inside the VM"),
+            "host_address": nullable_string("Host address to bind, all addresses if unset"),
+        }),
+        &["protocol", "host_port", "vm_port"],
+    );
+    let vm_parameters = object(
+        json!({
+            "name": nullable_string("VM name"),
+            "image": string("Guest image name, e.g. `dstack-0.5.2`"),
+            "vcpu": integer(U32, "Number of vCPUs"),
+            "memory": integer(U32, "Memory in MB"),
+            "disk_size": integer(U32, "Disk size in GB"),
+            "user_config": string("user_config passed to the VM"),
+            "ports": { "type": "array", "items": port },
+            "hugepages": boolean("Back memory with hugepages", false),
+            "pin_numa": boolean("Pin the VM to a NUMA node", false),
+            "stopped": boolean("Create the VM without starting it", false),
+        }),
+        &["image", "vcpu", "memory", "disk_size"],
+    );
+    let provisioning = object(
+        json!({
+            "env_keys": string_array("Environment keys forwarded from the platform config"),
+            "manifest_defaults": manifest_defaults,
+            "vm_parameters": vm_parameters,
+        }),
+        &[],
+    );
+    let env_key = object(
+        json!({
+            "description": nullable_string("What the key is for"),
+            "example": nullable_string("Example value"),
+            "secret": boolean("Values must not be echoed back", false),
+        }),
+        &[],
+    );
+    let hunk = object(
+        json!({
+            "start": integer(U64, "First base line replaced"),
+            "delete": integer(U64, "Number of base lines removed"),
+            "insert": string_array("Lines inserted in their place"),
+        }),
+        &["start"],
+    );
+    let compose_patch = object(
+        json!({
+            "base_sha256": string("sha256 of the content the patch applies to"),
+            "sha256": string("sha256 of the full content after applying the patch"),
+            "hunks": { "type": "array", "items": hunk },
+        }),
+        &["base_sha256", "sha256", "hunks"],
+    );
+
+    let mut schema = object(
+        json!({
+            "vm_type": string("VM type"),
+            "compose_content": string("Full compose content; empty when `compose_patch` is sent instead"),
+            "description": nullable_string("Human-readable description of the release"),
+            "updated_at": string("When the config was last changed"),
+            "required_env": string_array("Environment keys that must be set on the host"),
+            "provisioning": provisioning,
+            "env_metadata": {
+                "type": "object",
+                "additionalProperties": env_key,
+                "description": "Per-key descriptions, examples, and secret flags for env keys",
+            },
+            "hash_version": nullable_integer(U32, "Compose hash algorithm version (absent means 1)"),
+            "schema_version": nullable_integer(U32, "Compose config schema version (absent means 1)"),
+            "features": string_array("Optional features the API is using in this response"),
+            "compose_patch": nullable(compose_patch, "Patch against the compose content the launcher offered as its base"),
+        }),
+        &["vm_type", "updated_at"],
+    );
+    schema["additionalProperties"] = json!(true);
+    schema["$schema"] = json!(SCHEMA_DIALECT);
+    schema["title"] = json!("Platform validator compose config");
+    schema
+}
+
+pub fn schema_for(kind: ConfigKind) -> Value {
+    match kind {
+        ConfigKind::Platform => platform_config_schema(),
+        ConfigKind::Compose => compose_config_schema(),
+    }
+}
+
+/// Check `value` against `schema`, supporting the subset of JSON Schema the
+/// schemas above use
+pub fn check(value: &Value, schema: &Value) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+    check_at(value, schema, "", &mut issues);
+    issues
+}
+
+fn push(issues: &mut Vec<ValidationIssue>, path: &str, message: impl Into<String>) {
+    issues.push(ValidationIssue {
+        field: if path.is_empty() {
+            "(top level)".to_string()
+        } else {
+            path.to_string()
+        },
+        message: message.into(),
+    });
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_u64() || n.is_i64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn check_at(value: &Value, schema: &Value, path: &str, issues: &mut Vec<ValidationIssue>) {
+    if let Some(expected) = schema.get("const") {
+        if value != expected {
+            push(issues, path, format!("must be {}", expected));
+        }
+        return;
+    }
+    if let Some(allowed) = schema.get("enum").and_then(|e| e.as_array()) {
+        if !allowed.contains(value) {
+            let names: Vec<String> = allowed.iter().map(|v| v.to_string()).collect();
+            push(issues, path, format!("must be one of {}", names.join(", ")));
+        }
+        return;
+    }
+    if let Some(branches) = schema.get("oneOf").and_then(|b| b.as_array()) {
+        check_one_of(value, branches, path, issues);
+        return;
+    }
+
+    if let Some(types) = schema.get("type") {
+        let actual = type_name(value);
+        let matches = |t: &Value| t == actual || (t == "number" && actual == "integer");
+        let ok = match types {
+            Value::Array(types) => types.iter().any(matches),
+            t => matches(t),
+        };
+        if !ok {
+            let expected = match types {
+                Value::Array(types) => types
+                    .iter()
+                    .filter_map(|t| t.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" or "),
+                t => t.as_str().unwrap_or_default().to_string(),
+            };
+            push(
+                issues,
+                path,
+                format!("expected {}, found {}", expected, actual),
+            );
+            return;
+        }
+    }
+
+    match value {
+        Value::Number(n) => {
+            let min = schema.get("minimum").and_then(|m| m.as_u64());
+            let max = schema.get("maximum").and_then(|m| m.as_u64());
+            match n.as_u64() {
+                Some(n) if min.is_some_and(|min| n < min) => push(
+                    issues,
+                    path,
+                    format!("must be at least {}", min.unwrap_or_default()),
+                ),
+                Some(n) if max.is_some_and(|max| n > max) => push(
+                    issues,
+                    path,
+                    format!("must be at most {}", max.unwrap_or_default()),
+                ),
+                None if min.is_some() => push(issues, path, "must not be negative"),
+                _ => {}
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check_at(item, item_schema, &format!("{}[{}]", path, i), issues);
+                }
+            }
+        }
+        Value::Object(map) => check_object(map, schema, path, issues),
+        _ => {}
+    }
+}
+
+fn check_object(
+    map: &Map<String, Value>,
+    schema: &Value,
+    path: &str,
+    issues: &mut Vec<ValidationIssue>,
+) {
+    let child = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+    let properties = schema.get("properties").and_then(|p| p.as_object());
+    for key in schema
+        .get("required")
+        .and_then(|r| r.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|k| k.as_str())
+    {
+        if !map.contains_key(key) {
+            push(issues, &child(key), "required field is missing");
+        }
+    }
+    for (key, value) in map {
+        match properties.and_then(|p| p.get(key)) {
+            Some(property) => check_at(value, property, &child(key), issues),
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => push(issues, &child(key), "unknown field"),
+                Some(extra @ Value::Object(_)) => check_at(value, extra, &child(key), issues),
+                _ => {}
+            },
+        }
+    }
+}
+
+/// Report the closest branch when none matches, which for the tagged enums
+/// used here is the one with the right `type`
+fn check_one_of(value: &Value, branches: &[Value], path: &str, issues: &mut Vec<ValidationIssue>) {
+    let results: Vec<Vec<ValidationIssue>> = branches
+        .iter()
+        .map(|branch| {
+            let mut branch_issues = Vec::new();
+            check_at(value, branch, path, &mut branch_issues);
+            branch_issues
+        })
+        .collect();
+    match results.iter().filter(|r| r.is_empty()).count() {
+        1 => {}
+        0 => {
+            if let Some(closest) = results.into_iter().min_by_key(|r| r.len()) {
+                issues.extend(closest);
+            }
+        }
+        _ => push(issues, path, "matches more than one alternative"),
+    }
+}
+
+/// Checks beyond the schema that need the launcher's own parsing
+fn semantic_issues(value: &Value, kind: ConfigKind) -> Result<Vec<ValidationIssue>> {
+    let mut issues = Vec::new();
+    match kind {
+        ConfigKind::Platform => {
+            let config: PlatformConfig = serde_json::from_value(value.clone())
+                .context("Launcher cannot load this config")?;
+            if let Some(timezone) = &config.timezone {
+                if let Err(e) = DisplayTimezone::parse(timezone) {
+                    push(&mut issues, "timezone", format!("{:#}", e));
+                }
+            }
+            for (name, value) in config.http.iter().flat_map(|h| &h.headers) {
+                if let Err(e) = http_config::parse_header(name, value) {
+                    push(
+                        &mut issues,
+                        &format!("http.headers.{}", name),
+                        format!("{:#}", e),
+                    );
+                }
+            }
+            if let Some(ip) = &config.public_ip {
+                if ip.parse::<std::net::IpAddr>().is_err() {
+                    push(&mut issues, "public_ip", "not an IP address");
+                }
+            }
+        }
+        ConfigKind::Compose => {
+            let config: ComposeConfig = serde_json::from_value(value.clone())
+                .context("Launcher cannot load this compose config")?;
+            // Without a host's image_support, judge against the built-in defaults
+            let report = validation::validate_compose_config(&config, &ImageSupport::default());
+            issues.extend(report.issues);
+        }
+    }
+    Ok(issues)
+}
+
+/// Validate a file against the schema for `kind`, then against the
+/// launcher's own parsing. Returns every issue found; an unparseable file
+/// is an error.
+pub fn lint_file(path: &Path, kind: ConfigKind) -> Result<Vec<ValidationIssue>> {
+    let content =
+        std::fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+    let value: Value =
+        serde_json::from_str(&content).context(format!("{} is not valid JSON", path.display()))?;
+    let issues = check(&value, &schema_for(kind));
+    if !issues.is_empty() {
+        return Ok(issues);
+    }
+    semantic_issues(&value, kind)
+}
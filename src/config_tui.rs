@@ -2,20 +2,101 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Subcommand;
 
-use crate::PlatformConfig;
+use crate::admin_api::AdminApiConfig;
+use crate::alerting::{OpsgenieConfig, PagerDutyConfig};
+use crate::config_schema::{self, ConfigKind};
+use crate::drift::DriftPolicy;
+use crate::endpoints::EndpointIntegration;
+use crate::env_metadata;
+use crate::health::HealthCheckConfig;
+use crate::http_config::{self, HttpConfig};
+use crate::limits::{LimitPolicy, ResourceLimits};
+use crate::missing_env::{KeyClass, MissingEnvAction};
+use crate::mqtt::{self, MqttConfig};
+use crate::network::Network;
+use crate::subscribe::ApiTransport;
+use crate::timefmt::DisplayTimezone;
+use crate::validation::ImageSupport;
+use crate::vmm_supervisor::EmbeddedVmmConfig;
+use crate::{
+    defaults, resolve_api_url, resolve_guest_vmm_url, resolve_poll_interval, resolve_vmm_url,
+    ApiTlsConfig, PlatformConfig,
+};
 
 #[derive(Subcommand)]
 pub enum ConfigCommands {
     /// Show current configuration
-    Show,
-    /// Set VMM URL
+    Show {
+        /// Show the default of every setting instead
+        #[arg(long)]
+        defaults: bool,
+    },
+    /// Restore settings to their defaults (all but `env` unless --key is given)
+    Reset {
+        /// Setting to reset, as named in the config file (e.g., poll_interval_secs)
+        #[arg(long)]
+        key: Option<String>,
+    },
+    /// Set the VMM URL passed to the VM as DSTACK_VMM_URL (guest side)
     SetVmmUrl {
-        /// VMM URL (e.g., http://10.0.2.2:16850/)
+        /// VMM URL (e.g., http://10.0.2.2:10300/)
+        url: String,
+    },
+    /// Set the VMM RPC URL used by the launcher itself (host side)
+    SetVmmRpcUrl {
+        /// VMM RPC URL (e.g., http://localhost:10300)
+        url: String,
+    },
+    /// Fetch the compose config from this URL instead of the network's (e.g. a staging or mock API)
+    SetApiUrl {
+        /// Compose config endpoint (e.g., https://api.staging.example/config/compose/validator_vm)
         url: String,
     },
+    /// Fall back to this compose config endpoint while the API URL and earlier mirrors fail
+    AddApiMirror {
+        /// Compose config endpoint of the mirror
+        url: String,
+    },
+    /// Stop using a mirror of the platform API
+    RemoveApiMirror { url: String },
+    /// Use the endpoints of a platform network
+    SetNetwork {
+        #[arg(value_enum)]
+        network: Network,
+    },
+    /// Set the interval between update checks
+    SetPollInterval {
+        /// Interval in seconds
+        seconds: u64,
+    },
+    /// Set how much each poll interval is randomly spread, so a fleet does not poll in lockstep
+    SetPollJitter {
+        /// Percent either way (0 disables, at most 50)
+        percent: u8,
+    },
+    /// Check for an update as soon as the platform API announces one over an event stream
+    SetPushUpdates {
+        #[arg(action = clap::ArgAction::Set)]
+        enabled: bool,
+    },
+    /// Fetch the compose config by polling over HTTP, or over a WebSocket subscription
+    SetApiTransport {
+        #[arg(value_enum)]
+        transport: ApiTransport,
+    },
+    /// Set how often an up-to-date VM is still checked while the API reports no change
+    SetVmRecheck {
+        /// Seconds between full checks (0 checks on every poll)
+        seconds: u64,
+    },
+    /// Set the timezone used to display timestamps
+    SetTimezone {
+        /// `local` (default) or an IANA name such as `UTC` or `Europe/Berlin`
+        timezone: String,
+    },
     /// Set an environment variable
     SetEnv {
         /// Environment variable key
@@ -30,44 +111,774 @@ pub enum ConfigCommands {
     },
     /// List all environment variables
     ListEnv,
+    /// Print an env file template of the keys the platform expects, with help text
+    EnvTemplate,
     /// Get a specific environment variable value
     GetEnv {
         /// Environment variable key
         key: String,
     },
+    /// Configure a client certificate for mutual TLS toward the platform API
+    SetApiTls {
+        /// Path to the PEM-encoded client certificate
+        #[arg(long)]
+        cert: String,
+        /// Path to the PEM-encoded PKCS#8 private key (must be mode 600)
+        #[arg(long)]
+        key: String,
+        /// PEM CA bundle used to verify the platform API (defaults to the system roots)
+        #[arg(long)]
+        ca: Option<String>,
+    },
+    /// Remove the mutual TLS client certificate configuration
+    ClearApiTls,
+    /// Set the User-Agent sent to the platform API (omit to restore the default)
+    SetUserAgent { user_agent: Option<String> },
+    /// Add or replace a header sent with every platform API request
+    SetHeader {
+        /// Header name (e.g., X-Tenant-Id)
+        name: String,
+        value: String,
+    },
+    /// Stop sending a custom header
+    RemoveHeader { name: String },
+    /// Cap the rate platform API responses are read at, in bytes per second (omit to remove the cap)
+    SetApiBandwidth { bytes_per_sec: Option<u64> },
+    /// Set hard caps on the resources the launcher may request for the VM
+    SetLimits {
+        /// Maximum number of vCPUs
+        #[arg(long)]
+        max_vcpu: Option<u32>,
+        /// Maximum memory in MB
+        #[arg(long)]
+        max_memory_mb: Option<u32>,
+        /// Maximum disk size in GB
+        #[arg(long)]
+        max_disk_gb: Option<u32>,
+        /// What to do when the API asks for more than the caps
+        #[arg(long, value_enum, default_value_t = LimitPolicy::Reject)]
+        policy: LimitPolicy,
+    },
+    /// Remove all local resource caps
+    ClearLimits,
+    /// Set how drift between the VM's actual and desired parameters is handled
+    SetDriftPolicy {
+        #[arg(value_enum)]
+        policy: DriftPolicy,
+    },
+    /// Set what an update check does when a required env key has no value
+    SetMissingEnvPolicy {
+        #[arg(value_enum)]
+        action: MissingEnvAction,
+        /// Only for this class of keys (default: every class)
+        #[arg(long, value_enum)]
+        class: Option<KeyClass>,
+    },
+    /// Require the VMM-reported image digest to match the one pinned at deployment
+    SetRequireImageDigest {
+        #[arg(action = clap::ArgAction::Set)]
+        enabled: bool,
+    },
+    /// Set how stopped and failed VMs are brought back (unset options keep their value)
+    SetRecovery {
+        /// Start a cleanly stopped VM with current config instead of recreating it
+        #[arg(long, action = clap::ArgAction::Set)]
+        start_stopped: Option<bool>,
+        /// Failures within the window after which recreation is held off (0 disables)
+        #[arg(long)]
+        crashloop_threshold: Option<u32>,
+        /// Crashloop window in seconds
+        #[arg(long)]
+        crashloop_window: Option<u64>,
+        /// Check that a VM found at startup is booted and running its app before keeping it
+        #[arg(long, action = clap::ArgAction::Set)]
+        verify_on_start: Option<bool>,
+        /// Automatic recreations within the window after which the launcher is degraded (0 disables)
+        #[arg(long)]
+        max_recreations: Option<u32>,
+        /// Recreation window in seconds
+        #[arg(long)]
+        recreation_window: Option<u64>,
+        /// Seconds before the second recreation within the window, doubled for each one after
+        #[arg(long)]
+        recreate_backoff: Option<u64>,
+    },
+    /// Set how long a VM may take to boot (unset options keep their value)
+    SetBootTimeout {
+        /// Seconds for a VM whose disk already exists
+        #[arg(long)]
+        base: Option<u64>,
+        /// Seconds added per GB of disk when a new VM boots for the first time
+        #[arg(long)]
+        per_disk_gb: Option<u64>,
+        /// Seconds for every boot, replacing the computed timeouts (0 goes back to computing them)
+        #[arg(long)]
+        fixed: Option<u64>,
+    },
+    /// Serve the read-only admin API (status, history, events) for remote observers
+    SetAdminApi {
+        /// Address to listen on
+        #[arg(long, default_value = defaults::ADMIN_API_LISTEN)]
+        listen: String,
+        /// Read-only bearer token (a random one is generated if omitted)
+        #[arg(long)]
+        token: Option<String>,
+        /// Also issue an operator token, which can read secrets and trigger update checks
+        #[arg(long)]
+        operator: bool,
+        /// Also serve a read-only HTML status page at `/`, which needs no token
+        #[arg(long)]
+        status_page: bool,
+    },
+    /// Stop serving the admin API
+    DisableAdminApi,
+    /// Start and supervise the dstack VMM from the launcher (all-in-one mode)
+    SetEmbeddedVmm {
+        /// Seconds the first update check waits for the VMM to answer
+        #[arg(long, default_value_t = defaults::EMBEDDED_VMM_READY_TIMEOUT_SECS)]
+        ready_timeout: u64,
+        /// VMM binary, followed by its arguments
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Leave the VMM to its own service
+    DisableEmbeddedVmm,
+    /// Keep manifest disclosure flags off even when the platform API asks for them
+    /// (unset options keep their value)
+    SetDisclosure {
+        /// Keep `public_sysinfo` off
+        #[arg(long, action = clap::ArgAction::Set)]
+        suppress_sysinfo: Option<bool>,
+        /// Keep `public_tcbinfo` off
+        #[arg(long, action = clap::ArgAction::Set)]
+        suppress_tcbinfo: Option<bool>,
+    },
+    /// Set how the launcher reacts to host shutdown and suspend (unset options keep their value)
+    SetPowerPolicy {
+        /// Block shutdown and suspend while the VM is being recreated or resized
+        #[arg(long, action = clap::ArgAction::Set)]
+        inhibit_updates: Option<bool>,
+        /// Stop the VM through the VMM before the host shuts down
+        #[arg(long, action = clap::ArgAction::Set)]
+        stop_vm_on_shutdown: Option<bool>,
+    },
+    /// Set how far the host clock may drift before VM creation is refused (unset options keep their value)
+    SetClockCheck {
+        /// SNTP server to measure against, e.g. time.cloudflare.com:123 (empty to use the platform API's Date header)
+        #[arg(long)]
+        ntp_server: Option<String>,
+        /// Largest tolerated offset in seconds
+        #[arg(long)]
+        max_drift: Option<u64>,
+        /// Alert but still create the VM when the clock is off
+        #[arg(long, action = clap::ArgAction::Set)]
+        warn_only: Option<bool>,
+    },
+    /// Set retention limits for local data (unset options keep their value, 0 removes a limit)
+    SetRetention {
+        /// Keep at most this many audit log entries
+        #[arg(long)]
+        audit_max_entries: Option<usize>,
+        /// Drop audit log entries older than this many days
+        #[arg(long)]
+        audit_max_age_days: Option<u64>,
+        /// Upper bound on the state directory size in MB
+        #[arg(long)]
+        max_disk_mb: Option<u64>,
+    },
+    /// Set the local user_config, merged over the value provided by the API
+    SetUserConfig {
+        /// user_config content (usually a JSON object)
+        #[arg(conflicts_with = "file", required_unless_present = "file")]
+        value: Option<String>,
+        /// Read the user_config from a file
+        #[arg(long)]
+        file: Option<std::path::PathBuf>,
+    },
+    /// Remove the local user_config, using the API value as-is
+    ClearUserConfig,
+    /// Declare the runners and manifest version a guest image supports
+    SetImageSupport {
+        /// Image name as used in vm_parameters (e.g., dstack-0.5.2)
+        image: String,
+        /// Supported runner (repeatable)
+        #[arg(long = "runner", required = true)]
+        runners: Vec<String>,
+        /// Highest app manifest version the image can parse
+        #[arg(long)]
+        max_manifest_version: u32,
+    },
+    /// Remove an image's support declaration, falling back to the built-in defaults
+    ClearImageSupport {
+        /// Image name
+        image: String,
+    },
+    /// Add a webhook notified on state transitions (failing, recovered, update applied)
+    AddWebhook {
+        /// URL that receives a JSON POST per transition
+        url: String,
+    },
+    /// Add an external health check; it passes when the command exits with status 0
+    AddHealthCheck {
+        /// Unique name for the check
+        #[arg(long)]
+        name: String,
+        /// Seconds between runs
+        #[arg(long, default_value_t = defaults::HEALTH_CHECK_INTERVAL_SECS)]
+        interval: u64,
+        /// Seconds before the command is killed and the check fails
+        #[arg(long, default_value_t = defaults::HEALTH_CHECK_TIMEOUT_SECS)]
+        timeout: u64,
+        /// Command to run, followed by its arguments
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Verify updates in a canary VM before replacing the running one (unset options keep their value)
+    SetCanary {
+        /// Env var set to 1 in the canary VM
+        #[arg(long)]
+        env_flag: Option<String>,
+        /// Added to the canary's host ports so they don't clash with the running VM
+        #[arg(long)]
+        port_offset: Option<u16>,
+        /// Seconds the probes may take to pass
+        #[arg(long)]
+        timeout: Option<u64>,
+        /// Env var never given to the canary (repeatable; replaces the list)
+        #[arg(long = "withhold-env", value_name = "KEY")]
+        withheld_env: Vec<String>,
+    },
+    /// Add a canary probe; it passes when the command exits with status 0
+    AddCanaryProbe {
+        /// Unique name for the probe
+        #[arg(long)]
+        name: String,
+        /// Seconds between retries while the probe fails
+        #[arg(long, default_value_t = defaults::CANARY_PROBE_INTERVAL_SECS)]
+        interval: u64,
+        /// Seconds before the command is killed and the attempt fails
+        #[arg(long, default_value_t = defaults::HEALTH_CHECK_TIMEOUT_SECS)]
+        timeout: u64,
+        /// Command to run, followed by its arguments (CANARY_VM_ID and CANARY_PORT_OFFSET are set)
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Remove a canary probe
+    RemoveCanaryProbe {
+        /// Name of the probe to remove
+        name: String,
+    },
+    /// Update without a canary step
+    DisableCanary,
+    /// Allow an env key in the manifest in addition to those resolved from the API (changes the app_id)
+    AddAllowedEnv { key: String },
+    /// Leave a resolved key out of the manifest's allowed_envs (changes the app_id)
+    ExcludeAllowedEnv { key: String },
+    /// Use allowed_envs as resolved from the API
+    ClearAllowedEnvs,
+    /// Hold updates of the running VM until approved (by `rollout` or `approve`)
+    SetRequireUpdateApproval {
+        #[arg(action = clap::ArgAction::Set)]
+        enabled: bool,
+    },
+    /// Remove VMs the launcher created but no longer manages at the hourly orphan scan
+    SetRemoveOrphans {
+        #[arg(action = clap::ArgAction::Set)]
+        enabled: bool,
+    },
+    /// Keep a stopped VM with the current config ready to replace a failed one
+    SetWarmStandby {
+        #[arg(action = clap::ArgAction::Set)]
+        enabled: bool,
+    },
+    /// Remove an external health check
+    RemoveHealthCheck {
+        /// Name of the check to remove
+        name: String,
+    },
+    /// Publish status and transitions to an MQTT broker
+    SetMqtt {
+        /// Broker URL (e.g., mqtt://broker.lan:1883 or mqtts://broker.example.com)
+        broker_url: String,
+        /// Topic prefix (defaults to platform-validator/<host id>)
+        #[arg(long)]
+        topic_prefix: Option<String>,
+        #[arg(long)]
+        username: Option<String>,
+        #[arg(long)]
+        password: Option<String>,
+        /// PEM CA bundle for a broker with a private CA
+        #[arg(long)]
+        ca_cert: Option<String>,
+    },
+    /// Stop publishing to MQTT
+    DisableMqtt,
+    /// Open a PagerDuty incident per failure condition, resolved when it clears
+    SetPagerduty {
+        /// Integration key of an Events API v2 integration
+        routing_key: String,
+    },
+    /// Stop alerting PagerDuty
+    DisablePagerduty,
+    /// Open an Opsgenie alert per failure condition, closed when it clears
+    SetOpsgenie {
+        /// API key of an Opsgenie API integration
+        api_key: String,
+        /// The account is hosted in Opsgenie's EU instance
+        #[arg(long)]
+        eu: bool,
+    },
+    /// Stop alerting Opsgenie
+    DisableOpsgenie,
+    /// Remove a transition webhook
+    RemoveWebhook {
+        /// Webhook URL to remove
+        url: String,
+    },
+    /// Send the VM's new binding to an HTTP endpoint (e.g., a load balancer) after each recreation
+    AddHttpEndpoint {
+        /// URL that receives the binding as JSON
+        url: String,
+        /// HTTP method to use
+        #[arg(long, default_value = defaults::ENDPOINT_HTTP_METHOD)]
+        method: String,
+        /// Bearer token sent with the request
+        #[arg(long)]
+        token: Option<String>,
+    },
+    /// Point a Cloudflare DNS record at the host's public IP after each recreation
+    AddCloudflareDns {
+        /// Cloudflare zone ID
+        #[arg(long)]
+        zone_id: String,
+        /// Fully qualified record name (e.g., validator.example.com)
+        #[arg(long)]
+        record: String,
+        /// API token with DNS edit permission for the zone
+        #[arg(long)]
+        api_token: String,
+        /// Record TTL in seconds
+        #[arg(long, default_value_t = defaults::DNS_TTL_SECS)]
+        ttl: u32,
+    },
+    /// Remove an endpoint integration by its number in `config show`
+    RemoveEndpoint {
+        /// 1-based index as listed by `config show`
+        index: usize,
+    },
+    /// Set the public IP announced to endpoint integrations (omit to derive it from port bindings)
+    SetPublicIp { ip: Option<String> },
+    /// Print the JSON Schema of the platform config (or of the API's compose config)
+    Schema {
+        /// Schema for the compose config served by the platform API instead
+        #[arg(long)]
+        compose: bool,
+    },
+    /// Check a config file against the schema without installing it
+    Lint {
+        /// File to check
+        file: std::path::PathBuf,
+        /// The file is a compose config as served by the platform API
+        #[arg(long)]
+        compose: bool,
+    },
+}
+
+fn schema_kind(compose: bool) -> ConfigKind {
+    if compose {
+        ConfigKind::Compose
+    } else {
+        ConfigKind::Platform
+    }
+}
+
+/// Commands that never read or write the installed config
+fn run_offline_command(cmd: &ConfigCommands) -> Option<Result<()>> {
+    match cmd {
+        ConfigCommands::Schema { compose } => {
+            let schema = config_schema::schema_for(schema_kind(*compose));
+            Some(
+                serde_json::to_string_pretty(&schema)
+                    .map(|json| println!("{}", json))
+                    .map_err(Into::into),
+            )
+        }
+        ConfigCommands::Lint { file, compose } => Some(lint(file, schema_kind(*compose))),
+        _ => None,
+    }
+}
+
+fn lint(file: &std::path::Path, kind: ConfigKind) -> Result<()> {
+    let issues = config_schema::lint_file(file, kind)?;
+    if issues.is_empty() {
+        println!("✓ {} is valid", file.display());
+        return Ok(());
+    }
+    for issue in &issues {
+        println!("  - {}: {}", issue.field, issue.message);
+    }
+    anyhow::bail!("{} has {} problem(s)", file.display(), issues.len())
 }
 
 pub fn run_config_command(cmd: ConfigCommands) -> Result<()> {
-    let mut config = PlatformConfig::load().unwrap_or_else(|_| PlatformConfig {
-        dstack_vmm_url: Some("http://10.0.2.2:16850/".to_string()),
-        env: None,
-    });
+    if let Some(result) = run_offline_command(&cmd) {
+        return result;
+    }
+
+    // Hold the lock across load-modify-save so concurrent invocations can't lose updates
+    let _lock = PlatformConfig::lock()?;
+
+    let mut config = match PlatformConfig::load() {
+        Ok(config) => config,
+        Err(_) if !crate::paths::config_path().exists() => PlatformConfig::default(),
+        // Never overwrite a config we could not parse with defaults
+        Err(e) => {
+            return Err(e.context(format!(
+                "{} is corrupted; fix or remove it before changing settings",
+                crate::paths::config_path().display()
+            )))
+        }
+    };
 
     match cmd {
-        ConfigCommands::Show => {
+        ConfigCommands::Show { defaults: true } => {
+            println!("Default Platform Configuration:");
+            for (key, default) in defaults::settings() {
+                println!("  {}: {}", key, default);
+            }
+        }
+        ConfigCommands::Show { defaults: false } => {
             println!("Current Platform Configuration:");
+            println!("  Config file: {}", crate::paths::config_path().display());
+            println!("  State dir: {}", crate::paths::state_dir().display());
+            println!("  VMM URL (guest): {}", resolve_guest_vmm_url(&config));
+            println!("  VMM RPC URL (host): {}", resolve_vmm_url(&config));
+            println!("  Network: {}", config.network.unwrap_or_default());
+            println!("  API URL: {}", resolve_api_url(&config));
+            if config.api_mirrors.is_empty() {
+                println!("  API mirrors: (none)");
+            } else {
+                println!("  API mirrors: {}", config.api_mirrors.join(", "));
+            }
+            println!("  Poll interval: {}", resolve_poll_interval(&config, None));
+            println!(
+                "  Poll jitter: {}",
+                config
+                    .poll_jitter_percent
+                    .map(|p| format!("±{}%", p))
+                    .unwrap_or_else(|| "(default)".to_string())
+            );
+            println!("  Push updates: {}", config.push_updates);
             println!(
-                "  VMM URL: {}",
-                config.dstack_vmm_url.as_deref().unwrap_or("(not set)")
+                "  API transport: {}",
+                config.api_transport.unwrap_or_default()
             );
+            println!(
+                "  VM recheck: {}",
+                config
+                    .vm_recheck_secs
+                    .map(|secs| format!("{}s", secs))
+                    .unwrap_or_else(|| "(default)".to_string())
+            );
+            println!("  Timezone: {}", DisplayTimezone::from_config(&config));
             println!("  Environment Variables:");
             if let Some(env) = &config.env {
                 if env.is_empty() {
                     println!("    (none)");
                 } else {
+                    let metadata = env_metadata::load_cached();
                     for (key, value) in env {
+                        let value = if env_metadata::is_secret(key, &metadata) {
+                            "********"
+                        } else {
+                            value.as_str()
+                        };
                         println!("    {} = {}", key, value);
                     }
                 }
             } else {
                 println!("    (none)");
             }
+            match &config.api_tls {
+                Some(tls) => {
+                    println!("  API mTLS:");
+                    println!("    Client certificate: {}", tls.client_cert);
+                    println!("    Client key: {}", tls.client_key);
+                    println!(
+                        "    CA bundle: {}",
+                        tls.ca_cert.as_deref().unwrap_or("(not set)")
+                    );
+                }
+                None => println!("  API mTLS: (disabled)"),
+            }
+            let http = config.http.clone().unwrap_or_default();
+            println!("  API User-Agent: {}", http.user_agent());
+            if http.headers.is_empty() {
+                println!("  API headers: (none)");
+            } else {
+                println!("  API headers:");
+                for (name, value) in &http.headers {
+                    println!("    {}: {}", name, value);
+                }
+            }
+            match http.max_bytes_per_sec {
+                Some(rate) => println!("  API bandwidth cap: {} bytes/s", rate),
+                None => println!("  API bandwidth cap: (none)"),
+            }
+            match &config.resource_limits {
+                Some(limits) => {
+                    let fmt_cap = |cap: Option<u32>| {
+                        cap.map(|c| c.to_string())
+                            .unwrap_or_else(|| "(none)".to_string())
+                    };
+                    println!("  Resource Limits ({:?}):", limits.policy);
+                    println!("    Max vCPU: {}", fmt_cap(limits.max_vcpu));
+                    println!("    Max memory (MB): {}", fmt_cap(limits.max_memory_mb));
+                    println!("    Max disk (GB): {}", fmt_cap(limits.max_disk_gb));
+                }
+                None => println!("  Resource Limits: (none)"),
+            }
+            println!(
+                "  Drift Policy: {:?}",
+                config.drift_policy.unwrap_or_default()
+            );
+            println!(
+                "  Missing Env Policy: {}",
+                config.missing_env.clone().unwrap_or_default()
+            );
+            println!(
+                "  Require image digest match: {}",
+                config.require_image_digest
+            );
+            println!(
+                "  Recovery: {}",
+                config.recovery.clone().unwrap_or_default()
+            );
+            println!(
+                "  Retention: {}",
+                config.retention.clone().unwrap_or_default()
+            );
+            match &config.admin_api {
+                Some(admin_api) => println!(
+                    "  Admin API: {} (read token{}{})",
+                    admin_api.listen,
+                    if admin_api.operator_token.is_some() {
+                        ", operator token"
+                    } else {
+                        ""
+                    },
+                    if admin_api.status_page {
+                        ", status page"
+                    } else {
+                        ""
+                    }
+                ),
+                None => println!("  Admin API: (disabled)"),
+            }
+            println!(
+                "  Boot timeout: {}",
+                config.boot_timeout.clone().unwrap_or_default()
+            );
+            println!(
+                "  Disclosure: {}",
+                config.disclosure.clone().unwrap_or_default()
+            );
+            println!("  Power: {}", config.power.clone().unwrap_or_default());
+            println!(
+                "  Clock check: {}",
+                config.clock_check.clone().unwrap_or_default()
+            );
+            println!(
+                "  Embedded VMM: {}",
+                config
+                    .embedded_vmm
+                    .as_ref()
+                    .map(|vmm| vmm.to_string())
+                    .unwrap_or_else(|| "(none)".to_string())
+            );
+            println!(
+                "  Public IP: {}",
+                config
+                    .public_ip
+                    .as_deref()
+                    .unwrap_or("(from port bindings)")
+            );
+            if config.endpoint_integrations.is_empty() {
+                println!("  Endpoints: (none)");
+            } else {
+                println!("  Endpoints:");
+                for (i, integration) in config.endpoint_integrations.iter().enumerate() {
+                    println!("    {}. {}", i + 1, integration.describe());
+                }
+            }
+            match config
+                .notifications
+                .as_ref()
+                .filter(|n| !n.webhooks.is_empty())
+            {
+                Some(notifications) => {
+                    println!("  Webhooks:");
+                    for url in &notifications.webhooks {
+                        println!("    {}", url);
+                    }
+                }
+                None => println!("  Webhooks: (none)"),
+            }
+            match config.notifications.as_ref().and_then(|n| n.mqtt.as_ref()) {
+                Some(mqtt) => println!("  MQTT: {}", mqtt.describe()),
+                None => println!("  MQTT: (disabled)"),
+            }
+            match config.notifications.as_ref() {
+                Some(n) if n.pagerduty.is_some() => println!("  PagerDuty: enabled"),
+                _ => println!("  PagerDuty: (disabled)"),
+            }
+            match config
+                .notifications
+                .as_ref()
+                .and_then(|n| n.opsgenie.as_ref())
+            {
+                Some(opsgenie) if opsgenie.eu => println!("  Opsgenie: enabled (EU)"),
+                Some(_) => println!("  Opsgenie: enabled"),
+                None => println!("  Opsgenie: (disabled)"),
+            }
+            if config.health_checks.is_empty() {
+                println!("  Health Checks: (none)");
+            } else {
+                println!("  Health Checks:");
+                for check in &config.health_checks {
+                    println!(
+                        "    {}: {} {} (every {}s, timeout {}s)",
+                        check.name,
+                        check.command,
+                        check.args.join(" "),
+                        check.interval_secs,
+                        check.timeout_secs
+                    );
+                }
+            }
+            match &config.canary {
+                Some(canary) => {
+                    println!("  Canary: {}", canary);
+                    for probe in &canary.probes {
+                        println!(
+                            "    {}: {} {} (retry every {}s, timeout {}s)",
+                            probe.name,
+                            probe.command,
+                            probe.args.join(" "),
+                            probe.interval_secs,
+                            probe.timeout_secs
+                        );
+                    }
+                }
+                None => println!("  Canary: (disabled)"),
+            }
+            println!(
+                "  Require update approval: {}",
+                config.require_update_approval
+            );
+            println!("  Remove orphans: {}", config.remove_orphans);
+            println!("  Warm standby: {}", config.warm_standby);
+            match config.allowed_envs.as_ref().filter(|o| !o.is_empty()) {
+                Some(overrides) => println!("  Allowed envs override: {}", overrides),
+                None => println!("  Allowed envs override: (none)"),
+            }
         }
         ConfigCommands::SetVmmUrl { url } => {
             config.dstack_vmm_url = Some(url.clone());
             config.save()?;
             println!("✓ VMM URL set to: {}", url);
         }
+        ConfigCommands::SetVmmRpcUrl { url } => {
+            config.vmm_url = Some(url.clone());
+            config.save()?;
+            println!("✓ VMM RPC URL set to: {}", url);
+        }
+        ConfigCommands::SetApiUrl { url } => {
+            crate::check_api_url(&url)?;
+            config.api_url = Some(url.clone());
+            config.save()?;
+            println!("✓ API URL set to: {}", url);
+        }
+        ConfigCommands::AddApiMirror { url } => {
+            crate::check_api_url(&url)?;
+            if config.api_mirrors.contains(&url) {
+                anyhow::bail!("API mirror {} is already configured", url);
+            }
+            config.api_mirrors.push(url.clone());
+            config.save()?;
+            println!(
+                "✓ API mirror added: {} ({} mirror(s) configured)",
+                url,
+                config.api_mirrors.len()
+            );
+        }
+        ConfigCommands::RemoveApiMirror { url } => {
+            let before = config.api_mirrors.len();
+            config.api_mirrors.retain(|mirror| *mirror != url);
+            if config.api_mirrors.len() == before {
+                anyhow::bail!("API mirror {} not found", url);
+            }
+            config.save()?;
+            println!("✓ API mirror removed: {}", url);
+        }
+        ConfigCommands::SetNetwork { network } => {
+            config.network = Some(network);
+            config.save()?;
+            println!("✓ Network set to: {} (API {})", network, network.api_url());
+            let resolved = resolve_api_url(&config);
+            if resolved.url != network.api_url() {
+                println!("  Note: the API URL {} takes precedence", resolved);
+            }
+        }
+        ConfigCommands::SetPollInterval { seconds } => {
+            if seconds == 0 {
+                anyhow::bail!("Poll interval must be at least 1 second");
+            }
+            config.poll_interval_secs = Some(seconds);
+            config.save()?;
+            println!("✓ Poll interval set to: {}s", seconds);
+        }
+        ConfigCommands::SetPollJitter { percent } => {
+            if percent > crate::defaults::POLL_JITTER_MAX_PERCENT {
+                anyhow::bail!(
+                    "Poll jitter must be at most {}%",
+                    crate::defaults::POLL_JITTER_MAX_PERCENT
+                );
+            }
+            config.poll_jitter_percent = Some(percent);
+            config.save()?;
+            println!("✓ Poll jitter set to: ±{}%", percent);
+        }
+        ConfigCommands::SetPushUpdates { enabled } => {
+            config.push_updates = enabled;
+            config.save()?;
+            println!("✓ Push updates: {}", enabled);
+        }
+        ConfigCommands::SetApiTransport { transport } => {
+            config.api_transport = Some(transport);
+            config.save()?;
+            match transport {
+                ApiTransport::Http => println!("✓ API transport set to: http"),
+                ApiTransport::Websocket => println!(
+                    "✓ API transport set to: websocket ({})",
+                    crate::subscribe::subscribe_url(&resolve_api_url(&config).url)?
+                ),
+            }
+        }
+        ConfigCommands::SetVmRecheck { seconds } => {
+            config.vm_recheck_secs = Some(seconds);
+            config.save()?;
+            println!(
+                "✓ VM recheck set to: {}s while the API reports no change",
+                seconds
+            );
+        }
+        ConfigCommands::SetTimezone { timezone } => {
+            let tz = DisplayTimezone::parse(&timezone)?;
+            config.timezone = Some(tz.to_string());
+            config.save()?;
+            println!("✓ Timezone set to: {}", tz);
+        }
         ConfigCommands::SetEnv { key, value } => {
             config.ensure_env_map();
             config
@@ -95,15 +906,56 @@ pub fn run_config_command(cmd: ConfigCommands) -> Result<()> {
                 if env.is_empty() {
                     println!("No environment variables configured");
                 } else {
+                    let metadata = env_metadata::load_cached();
                     println!("Environment Variables:");
                     for (key, value) in env {
+                        let value = if env_metadata::is_secret(key, &metadata) {
+                            "********"
+                        } else {
+                            value.as_str()
+                        };
                         println!("  {} = {}", key, value);
+                        if let Some(description) =
+                            metadata.get(key).and_then(|m| m.description.as_deref())
+                        {
+                            println!("      {}", description);
+                        }
                     }
                 }
             } else {
                 println!("No environment variables configured");
             }
         }
+        ConfigCommands::EnvTemplate => {
+            let metadata = env_metadata::load_cached();
+            let mut keys: Vec<&str> = defaults::ENV_KEYS.to_vec();
+            keys.extend(metadata.keys().map(String::as_str));
+            keys.sort_unstable();
+            keys.dedup();
+
+            let env = config.env.clone().unwrap_or_default();
+            for key in keys {
+                let meta = metadata.get(key).cloned().unwrap_or_default();
+                if let Some(description) = &meta.description {
+                    println!("# {}", description);
+                }
+                if let Some(example) = meta.example.as_ref().filter(|_| !meta.secret) {
+                    println!("# Example: {}", example);
+                }
+                if meta.secret {
+                    println!("# Secret: value is not shown");
+                }
+                let value = match env.get(key) {
+                    Some(value) if !meta.secret => value.as_str(),
+                    _ => "",
+                };
+                println!("{}={}", key, value);
+                println!();
+            }
+            if metadata.is_empty() {
+                println!("# No key metadata cached yet; it is fetched from the platform API by the running launcher.");
+            }
+        }
         ConfigCommands::GetEnv { key } => {
             if let Some(env) = &config.env {
                 if let Some(value) = env.get(&key) {
@@ -115,6 +967,711 @@ pub fn run_config_command(cmd: ConfigCommands) -> Result<()> {
                 anyhow::bail!("Environment variable '{}' not found", key);
             }
         }
+        ConfigCommands::SetApiTls { cert, key, ca } => {
+            let tls = ApiTlsConfig {
+                client_cert: cert,
+                client_key: key,
+                ca_cert: ca,
+            };
+            // Fail now rather than at the next daemon start
+            tls.load_identity()?;
+            tls.load_ca_cert()?;
+            config.api_tls = Some(tls);
+            config.save()?;
+            println!("✓ API mTLS client certificate configured");
+        }
+        ConfigCommands::ClearApiTls => {
+            config.api_tls = None;
+            config.save()?;
+            println!("✓ API mTLS client certificate removed");
+        }
+        ConfigCommands::SetUserAgent { user_agent } => {
+            if let Some(user_agent) = &user_agent {
+                reqwest::header::HeaderValue::from_str(user_agent)
+                    .context(format!("Invalid user agent '{}'", user_agent))?;
+            }
+            let http = config.http.get_or_insert_with(Default::default);
+            http.user_agent = user_agent;
+            let effective = http.user_agent();
+            if *http == HttpConfig::default() {
+                config.http = None;
+            }
+            config.save()?;
+            println!("✓ User-Agent: {}", effective);
+        }
+        ConfigCommands::SetApiBandwidth { bytes_per_sec } => {
+            if bytes_per_sec == Some(0) {
+                anyhow::bail!("The bandwidth cap must be at least 1 byte per second");
+            }
+            let http = config.http.get_or_insert_with(Default::default);
+            http.max_bytes_per_sec = bytes_per_sec;
+            if *http == HttpConfig::default() {
+                config.http = None;
+            }
+            config.save()?;
+            match bytes_per_sec {
+                Some(rate) => println!("✓ API responses read at up to {} bytes/s", rate),
+                None => println!("✓ API bandwidth cap removed"),
+            }
+        }
+        ConfigCommands::SetHeader { name, value } => {
+            http_config::parse_header(&name, &value)?;
+            let header = name.to_ascii_lowercase();
+            if header == "user-agent" || header == "authorization" || header == "host" {
+                anyhow::bail!(
+                    "'{}' cannot be set as a custom header{}",
+                    name,
+                    if header == "user-agent" {
+                        "; use `config set-user-agent`"
+                    } else {
+                        ""
+                    }
+                );
+            }
+            let http = config.http.get_or_insert_with(Default::default);
+            // Header names are case-insensitive; keep a single entry per header
+            http.headers
+                .retain(|existing, _| !existing.eq_ignore_ascii_case(&name));
+            http.headers.insert(name.clone(), value);
+            config.save()?;
+            println!("✓ Header {} set (applied on the next poll)", name);
+        }
+        ConfigCommands::RemoveHeader { name } => {
+            let http = config.http.get_or_insert_with(Default::default);
+            let before = http.headers.len();
+            http.headers
+                .retain(|existing, _| !existing.eq_ignore_ascii_case(&name));
+            if http.headers.len() == before {
+                anyhow::bail!("Header '{}' is not set", name);
+            }
+            if *http == HttpConfig::default() {
+                config.http = None;
+            }
+            config.save()?;
+            println!("✓ Header {} removed", name);
+        }
+        ConfigCommands::SetLimits {
+            max_vcpu,
+            max_memory_mb,
+            max_disk_gb,
+            policy,
+        } => {
+            let limits = ResourceLimits {
+                max_vcpu,
+                max_memory_mb,
+                max_disk_gb,
+                policy,
+            };
+            if limits.is_empty() {
+                anyhow::bail!("Specify at least one of --max-vcpu, --max-memory-mb, --max-disk-gb");
+            }
+            config.resource_limits = Some(limits);
+            config.save()?;
+            println!("✓ Resource limits updated");
+        }
+        ConfigCommands::ClearLimits => {
+            config.resource_limits = None;
+            config.save()?;
+            println!("✓ Resource limits removed");
+        }
+        ConfigCommands::SetDriftPolicy { policy } => {
+            config.drift_policy = Some(policy);
+            config.save()?;
+            println!("✓ Drift policy set to: {:?}", policy);
+        }
+        ConfigCommands::SetMissingEnvPolicy { action, class } => {
+            let policy = config.missing_env.get_or_insert_with(Default::default);
+            for class in class.map_or(KeyClass::ALL.to_vec(), |class| vec![class]) {
+                policy.set(class, action);
+            }
+            let policy = policy.to_string();
+            config.save()?;
+            println!("✓ Missing env policy: {}", policy);
+        }
+        ConfigCommands::SetRequireImageDigest { enabled } => {
+            config.require_image_digest = enabled;
+            config.save()?;
+            println!("✓ Require image digest match: {}", enabled);
+        }
+        ConfigCommands::SetRecovery {
+            start_stopped,
+            crashloop_threshold,
+            crashloop_window,
+            verify_on_start,
+            max_recreations,
+            recreation_window,
+            recreate_backoff,
+        } => {
+            if crashloop_window == Some(0) {
+                anyhow::bail!("Crashloop window must be at least 1 second");
+            }
+            if recreation_window == Some(0) {
+                anyhow::bail!("Recreation window must be at least 1 second");
+            }
+            let recovery = config.recovery.get_or_insert_with(Default::default);
+            if let Some(start_stopped) = start_stopped {
+                recovery.start_stopped = start_stopped;
+            }
+            if let Some(threshold) = crashloop_threshold {
+                recovery.crashloop_threshold = threshold;
+            }
+            if let Some(window) = crashloop_window {
+                recovery.crashloop_window_secs = window;
+            }
+            if let Some(verify_on_start) = verify_on_start {
+                recovery.verify_on_start = verify_on_start;
+            }
+            if let Some(max_recreations) = max_recreations {
+                recovery.max_recreations = max_recreations;
+            }
+            if let Some(window) = recreation_window {
+                recovery.recreation_window_secs = window;
+            }
+            if let Some(backoff) = recreate_backoff {
+                recovery.recreate_backoff_secs = backoff;
+            }
+            let summary = recovery.to_string();
+            config.save()?;
+            println!("✓ Recovery policy: {}", summary);
+        }
+        ConfigCommands::SetBootTimeout {
+            base,
+            per_disk_gb,
+            fixed,
+        } => {
+            if base == Some(0) {
+                anyhow::bail!("Boot timeout must be at least 1 second");
+            }
+            let boot_timeout = config.boot_timeout.get_or_insert_with(Default::default);
+            if let Some(base) = base {
+                boot_timeout.base_secs = base;
+            }
+            if let Some(per_disk_gb) = per_disk_gb {
+                boot_timeout.secs_per_disk_gb = per_disk_gb;
+            }
+            if let Some(fixed) = fixed {
+                boot_timeout.fixed_secs = (fixed > 0).then_some(fixed);
+            }
+            let summary = boot_timeout.to_string();
+            config.save()?;
+            println!("✓ Boot timeout: {}", summary);
+        }
+        ConfigCommands::SetAdminApi {
+            listen,
+            token,
+            operator,
+            status_page,
+        } => {
+            listen
+                .parse::<std::net::SocketAddr>()
+                .context(format!("Invalid listen address '{}'", listen))?;
+            let random_token = || {
+                let mut bytes = [0u8; 32];
+                rand::RngCore::fill_bytes(&mut rand::rngs::OsRng, &mut bytes);
+                hex::encode(bytes)
+            };
+            let generated = token.is_none();
+            let read_token = token.unwrap_or_else(random_token);
+            let operator_token = operator.then(random_token);
+            if read_token.is_empty() {
+                anyhow::bail!("Admin API token must not be empty");
+            }
+            config.admin_api = Some(AdminApiConfig {
+                listen: listen.clone(),
+                read_token: read_token.clone(),
+                operator_token: operator_token.clone(),
+                status_page,
+            });
+            config.save()?;
+            println!(
+                "✓ Admin API will listen on {} (restart the service to apply)",
+                listen
+            );
+            if generated {
+                println!("  Read token: {}", read_token);
+            }
+            if let Some(operator_token) = operator_token {
+                println!("  Operator token: {}", operator_token);
+            }
+            if status_page {
+                println!("  Status page: http://{}/ (no token required)", listen);
+            }
+        }
+        ConfigCommands::DisableAdminApi => {
+            if config.admin_api.take().is_none() {
+                println!("Admin API is not enabled");
+            } else {
+                config.save()?;
+                println!("✓ Admin API disabled (restart the service to apply)");
+            }
+        }
+        ConfigCommands::SetEmbeddedVmm {
+            ready_timeout,
+            command,
+        } => {
+            let mut command = command.into_iter();
+            let binary = command.next().context("Missing VMM binary")?;
+            let vmm = EmbeddedVmmConfig {
+                binary,
+                args: command.collect(),
+                ready_timeout_secs: ready_timeout,
+            };
+            let summary = vmm.to_string();
+            config.embedded_vmm = Some(vmm);
+            config.save()?;
+            println!("✓ Embedded VMM: {} (restart the service to apply)", summary);
+            println!(
+                "  The VMM RPC URL ({}) must match the address it listens on",
+                resolve_vmm_url(&config).url
+            );
+        }
+        ConfigCommands::DisableEmbeddedVmm => {
+            if config.embedded_vmm.take().is_none() {
+                println!("Embedded VMM is not enabled");
+            } else {
+                config.save()?;
+                println!("✓ Embedded VMM disabled (restart the service to apply)");
+            }
+        }
+        ConfigCommands::SetDisclosure {
+            suppress_sysinfo,
+            suppress_tcbinfo,
+        } => {
+            let previous = config.disclosure.clone().unwrap_or_default();
+            let disclosure = config.disclosure.get_or_insert_with(Default::default);
+            if let Some(suppress_sysinfo) = suppress_sysinfo {
+                disclosure.suppress_sysinfo = suppress_sysinfo;
+            }
+            if let Some(suppress_tcbinfo) = suppress_tcbinfo {
+                disclosure.suppress_tcbinfo = suppress_tcbinfo;
+            }
+            let changed = *disclosure != previous;
+            let summary = disclosure.to_string();
+            config.save()?;
+            println!("✓ Disclosure: {}", summary);
+            if changed {
+                println!("  The app manifest changes with it: the compose hash and app_id change,");
+                println!("  so the VM is recreated at the next update check, keys the KMS derives");
+                println!(
+                    "  from the app_id change, and the app_id no longer matches hosts running"
+                );
+                println!("  the API's manifest unchanged. `update --dry-run` shows the plan.");
+            }
+        }
+        ConfigCommands::SetPowerPolicy {
+            inhibit_updates,
+            stop_vm_on_shutdown,
+        } => {
+            let power = config.power.get_or_insert_with(Default::default);
+            if let Some(inhibit_updates) = inhibit_updates {
+                power.inhibit_updates = inhibit_updates;
+            }
+            if let Some(stop_vm_on_shutdown) = stop_vm_on_shutdown {
+                power.stop_vm_on_shutdown = stop_vm_on_shutdown;
+            }
+            let summary = power.to_string();
+            config.save()?;
+            println!("✓ Power: {}", summary);
+        }
+        ConfigCommands::SetClockCheck {
+            ntp_server,
+            max_drift,
+            warn_only,
+        } => {
+            let clock_check = config.clock_check.get_or_insert_with(Default::default);
+            if let Some(ntp_server) = ntp_server {
+                clock_check.ntp_server = Some(ntp_server).filter(|s| !s.is_empty());
+            }
+            if let Some(max_drift) = max_drift {
+                clock_check.max_drift_secs = Some(max_drift);
+            }
+            if let Some(warn_only) = warn_only {
+                clock_check.warn_only = warn_only;
+            }
+            let summary = clock_check.to_string();
+            config.save()?;
+            println!("✓ Clock check: {}", summary);
+        }
+        ConfigCommands::SetRetention {
+            audit_max_entries,
+            audit_max_age_days,
+            max_disk_mb,
+        } => {
+            if max_disk_mb == Some(0) {
+                anyhow::bail!("State dir size limit must be at least 1 MB");
+            }
+            let retention = config.retention.get_or_insert_with(Default::default);
+            if let Some(entries) = audit_max_entries {
+                retention.audit_max_entries = (entries > 0).then_some(entries);
+            }
+            if let Some(days) = audit_max_age_days {
+                retention.audit_max_age_days = (days > 0).then_some(days);
+            }
+            if let Some(mb) = max_disk_mb {
+                retention.max_disk_mb = mb;
+            }
+            let summary = retention.to_string();
+            config.save()?;
+            println!("✓ Retention: {}", summary);
+        }
+        ConfigCommands::SetUserConfig { value, file } => {
+            let content = match file {
+                Some(path) => std::fs::read_to_string(&path)
+                    .context(format!("Failed to read {}", path.display()))?,
+                None => value.unwrap_or_default(),
+            };
+            if !content.trim_start().starts_with('{')
+                || serde_json::from_str::<serde_json::Value>(&content).is_err()
+            {
+                println!("Note: user_config is not a JSON object; it will replace the API value instead of being merged");
+            }
+            config.user_config = Some(content);
+            config.save()?;
+            println!("✓ user_config set (applied on the next update check)");
+        }
+        ConfigCommands::ClearUserConfig => {
+            config.user_config = None;
+            config.save()?;
+            println!("✓ user_config cleared");
+        }
+        ConfigCommands::SetImageSupport {
+            image,
+            runners,
+            max_manifest_version,
+        } => {
+            if max_manifest_version == 0 {
+                anyhow::bail!("Max manifest version must be > 0");
+            }
+            config.image_support.insert(
+                image.clone(),
+                ImageSupport {
+                    runners,
+                    max_manifest_version,
+                },
+            );
+            config.save()?;
+            println!("✓ Image support for '{}' updated", image);
+        }
+        ConfigCommands::ClearImageSupport { image } => {
+            if config.image_support.remove(&image).is_none() {
+                anyhow::bail!("No image support configured for '{}'", image);
+            }
+            config.save()?;
+            println!("✓ Image support for '{}' cleared", image);
+        }
+        ConfigCommands::AddWebhook { url } => {
+            reqwest::Url::parse(&url).context(format!("Invalid webhook URL '{}'", url))?;
+            let notifications = config.notifications.get_or_insert_with(Default::default);
+            if notifications.webhooks.contains(&url) {
+                println!("Webhook already configured: {}", url);
+            } else {
+                notifications.webhooks.push(url.clone());
+                config.save()?;
+                println!("✓ Webhook added: {}", url);
+            }
+        }
+        ConfigCommands::AddHealthCheck {
+            name,
+            interval,
+            timeout,
+            command,
+        } => {
+            if config.health_checks.iter().any(|check| check.name == name) {
+                anyhow::bail!("Health check '{}' already exists", name);
+            }
+            if interval == 0 || timeout == 0 {
+                anyhow::bail!("Interval and timeout must be at least 1 second");
+            }
+            let mut command = command.into_iter();
+            let program = command.next().context("Missing health check command")?;
+            config.health_checks.push(HealthCheckConfig {
+                name: name.clone(),
+                command: program,
+                args: command.collect(),
+                interval_secs: interval,
+                timeout_secs: timeout,
+            });
+            config.save()?;
+            println!("✓ Health check '{}' added", name);
+        }
+        ConfigCommands::RemoveHealthCheck { name } => {
+            let before = config.health_checks.len();
+            config.health_checks.retain(|check| check.name != name);
+            if config.health_checks.len() == before {
+                anyhow::bail!("Health check '{}' not found", name);
+            }
+            config.save()?;
+            println!("✓ Health check '{}' removed", name);
+        }
+        ConfigCommands::SetCanary {
+            env_flag,
+            port_offset,
+            timeout,
+            withheld_env,
+        } => {
+            let canary = config.canary.get_or_insert_with(Default::default);
+            if let Some(env_flag) = env_flag {
+                if env_flag.is_empty() {
+                    anyhow::bail!("Canary env flag must not be empty");
+                }
+                canary.env_flag = env_flag;
+            }
+            if let Some(port_offset) = port_offset {
+                canary.port_offset = port_offset;
+            }
+            if let Some(timeout) = timeout {
+                canary.timeout_secs = timeout;
+            }
+            if !withheld_env.is_empty() {
+                canary.withheld_env = withheld_env;
+            }
+            let summary = canary.to_string();
+            config.save()?;
+            println!("✓ Canary enabled: {}", summary);
+        }
+        ConfigCommands::AddCanaryProbe {
+            name,
+            interval,
+            timeout,
+            command,
+        } => {
+            let canary = config.canary.get_or_insert_with(Default::default);
+            if canary.probes.iter().any(|probe| probe.name == name) {
+                anyhow::bail!("Canary probe '{}' already exists", name);
+            }
+            if interval == 0 || timeout == 0 {
+                anyhow::bail!("Interval and timeout must be at least 1 second");
+            }
+            let mut command = command.into_iter();
+            let program = command.next().context("Missing canary probe command")?;
+            canary.probes.push(HealthCheckConfig {
+                name: name.clone(),
+                command: program,
+                args: command.collect(),
+                interval_secs: interval,
+                timeout_secs: timeout,
+            });
+            config.save()?;
+            println!("✓ Canary probe '{}' added", name);
+        }
+        ConfigCommands::RemoveCanaryProbe { name } => {
+            let canary = config.canary.as_mut().context("Canary is not enabled")?;
+            let before = canary.probes.len();
+            canary.probes.retain(|probe| probe.name != name);
+            if canary.probes.len() == before {
+                anyhow::bail!("Canary probe '{}' not found", name);
+            }
+            config.save()?;
+            println!("✓ Canary probe '{}' removed", name);
+        }
+        ConfigCommands::DisableCanary => {
+            config.canary = None;
+            config.save()?;
+            println!("✓ Canary disabled");
+        }
+        ConfigCommands::AddAllowedEnv { key } => {
+            let overrides = config.allowed_envs.get_or_insert_with(Default::default);
+            overrides.remove.retain(|k| *k != key);
+            if !overrides.add.contains(&key) {
+                overrides.add.push(key.clone());
+            }
+            config.save()?;
+            println!("✓ {} added to allowed_envs", key);
+            println!("  Run `env resolve` to see the resulting list");
+        }
+        ConfigCommands::ExcludeAllowedEnv { key } => {
+            let overrides = config.allowed_envs.get_or_insert_with(Default::default);
+            overrides.add.retain(|k| *k != key);
+            if !overrides.remove.contains(&key) {
+                overrides.remove.push(key.clone());
+            }
+            config.save()?;
+            println!("✓ {} excluded from allowed_envs", key);
+            println!("  Run `env resolve` to see the resulting list");
+        }
+        ConfigCommands::ClearAllowedEnvs => {
+            config.allowed_envs = None;
+            config.save()?;
+            println!("✓ allowed_envs override cleared");
+        }
+        ConfigCommands::SetRemoveOrphans { enabled } => {
+            config.remove_orphans = enabled;
+            config.save()?;
+            println!("✓ Remove orphans: {}", enabled);
+        }
+        ConfigCommands::SetWarmStandby { enabled } => {
+            config.warm_standby = enabled;
+            config.save()?;
+            println!("✓ Warm standby: {}", enabled);
+        }
+        ConfigCommands::SetRequireUpdateApproval { enabled } => {
+            config.require_update_approval = enabled;
+            config.save()?;
+            println!("✓ Require update approval: {}", enabled);
+            if enabled
+                && config
+                    .admin_api
+                    .as_ref()
+                    .is_none_or(|api| api.operator_token.is_none())
+            {
+                println!("  Approvals arrive through the admin API; enable it with set-admin-api --operator");
+            }
+        }
+        ConfigCommands::SetMqtt {
+            broker_url,
+            topic_prefix,
+            username,
+            password,
+            ca_cert,
+        } => {
+            let mqtt = MqttConfig {
+                broker_url,
+                topic_prefix,
+                username,
+                password,
+                ca_cert,
+            };
+            mqtt::validate(&mqtt)?;
+            let description = mqtt.describe();
+            config
+                .notifications
+                .get_or_insert_with(Default::default)
+                .mqtt = Some(mqtt);
+            config.save()?;
+            println!("✓ Publishing to MQTT broker {}", description);
+        }
+        ConfigCommands::DisableMqtt => {
+            if let Some(notifications) = config.notifications.as_mut() {
+                notifications.mqtt = None;
+            }
+            config.save()?;
+            println!("✓ MQTT publishing disabled");
+        }
+        ConfigCommands::SetPagerduty { routing_key } => {
+            if routing_key.trim().is_empty() {
+                anyhow::bail!("Routing key must not be empty");
+            }
+            config
+                .notifications
+                .get_or_insert_with(Default::default)
+                .pagerduty = Some(PagerDutyConfig { routing_key });
+            config.save()?;
+            println!("✓ PagerDuty alerting enabled");
+        }
+        ConfigCommands::DisablePagerduty => {
+            if let Some(notifications) = config.notifications.as_mut() {
+                notifications.pagerduty = None;
+            }
+            config.save()?;
+            println!("✓ PagerDuty alerting disabled");
+        }
+        ConfigCommands::SetOpsgenie { api_key, eu } => {
+            if api_key.trim().is_empty() {
+                anyhow::bail!("API key must not be empty");
+            }
+            config
+                .notifications
+                .get_or_insert_with(Default::default)
+                .opsgenie = Some(OpsgenieConfig { api_key, eu });
+            config.save()?;
+            println!("✓ Opsgenie alerting enabled");
+        }
+        ConfigCommands::DisableOpsgenie => {
+            if let Some(notifications) = config.notifications.as_mut() {
+                notifications.opsgenie = None;
+            }
+            config.save()?;
+            println!("✓ Opsgenie alerting disabled");
+        }
+        ConfigCommands::RemoveWebhook { url } => {
+            let notifications = config.notifications.get_or_insert_with(Default::default);
+            if !notifications.webhooks.contains(&url) {
+                anyhow::bail!("Webhook '{}' not found", url);
+            }
+            notifications.webhooks.retain(|existing| *existing != url);
+            if notifications.webhooks.is_empty() {
+                config.notifications = None;
+            }
+            config.save()?;
+            println!("✓ Webhook removed: {}", url);
+        }
+        ConfigCommands::AddHttpEndpoint { url, method, token } => {
+            reqwest::Url::parse(&url).context(format!("Invalid endpoint URL '{}'", url))?;
+            reqwest::Method::from_bytes(method.to_uppercase().as_bytes())
+                .context(format!("Invalid HTTP method '{}'", method))?;
+            let integration = EndpointIntegration::Http {
+                url,
+                method: Some(method.to_uppercase()),
+                bearer_token: token,
+            };
+            let description = integration.describe();
+            config.endpoint_integrations.push(integration);
+            config.save()?;
+            println!("✓ Endpoint added: {}", description);
+        }
+        ConfigCommands::AddCloudflareDns {
+            zone_id,
+            record,
+            api_token,
+            ttl,
+        } => {
+            let integration = EndpointIntegration::CloudflareDns {
+                zone_id,
+                record_name: record,
+                api_token,
+                ttl,
+            };
+            let description = integration.describe();
+            config.endpoint_integrations.push(integration);
+            config.save()?;
+            println!("✓ Endpoint added: {}", description);
+            if config.public_ip.is_none() {
+                println!("  Note: without `config set-public-ip`, the IP is taken from a port binding with a specific host address");
+            }
+        }
+        ConfigCommands::RemoveEndpoint { index } => {
+            if index == 0 || index > config.endpoint_integrations.len() {
+                anyhow::bail!(
+                    "No endpoint #{} ({} configured)",
+                    index,
+                    config.endpoint_integrations.len()
+                );
+            }
+            let removed = config.endpoint_integrations.remove(index - 1);
+            config.save()?;
+            println!("✓ Endpoint removed: {}", removed.describe());
+        }
+        ConfigCommands::SetPublicIp { ip } => {
+            if let Some(ip) = &ip {
+                ip.parse::<std::net::IpAddr>()
+                    .context(format!("Invalid IP address '{}'", ip))?;
+            }
+            config.public_ip = ip;
+            config.save()?;
+            match &config.public_ip {
+                Some(ip) => println!("✓ Public IP set to {}", ip),
+                None => println!("✓ Public IP cleared (derived from port bindings)"),
+            }
+        }
+        ConfigCommands::Reset { key } => {
+            defaults::reset(&mut config, key.as_deref())?;
+            let path = crate::paths::config_path();
+            if path.exists() {
+                let backup = path.with_extension("json.bak");
+                std::fs::copy(&path, &backup)
+                    .context(format!("Failed to back up {}", path.display()))?;
+                println!("Previous config saved to {}", backup.display());
+            }
+            config.save()?;
+            match key {
+                Some(key) => println!("✓ {} reset to its default", key),
+                None => println!(
+                    "✓ All settings reset to their defaults, except {} (reset each with --key)",
+                    defaults::KEPT_ON_FULL_RESET.join(", ")
+                ),
+            }
+        }
+        ConfigCommands::Schema { .. } | ConfigCommands::Lint { .. } => unreachable!(),
     }
 
     Ok(())
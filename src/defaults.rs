@@ -0,0 +1,275 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Default value of every platform config setting. Serde defaults, CLI
+//! argument defaults and `config show --defaults` all read from here, and
+//! `config reset` restores settings to them.
+
+use anyhow::Result;
+
+use crate::clock::ClockPolicy;
+use crate::disclosure::DisclosurePolicy;
+use crate::drift::DriftPolicy;
+use crate::missing_env::MissingEnvPolicy;
+use crate::network::Network;
+use crate::power::PowerPolicy;
+use crate::provisioning::BootTimeout;
+use crate::recovery::RecoveryPolicy;
+use crate::retention::RetentionPolicy;
+use crate::subscribe::ApiTransport;
+use crate::validation::ImageSupport;
+use crate::PlatformConfig;
+
+/// VMM RPC endpoint as seen from the host, used when nothing else is configured
+pub const VMM_RPC_URL: &str = "http://localhost:10300";
+/// The same VMM as seen from inside the VM (QEMU user-mode gateway)
+pub const GUEST_VMM_URL: &str = "http://10.0.2.2:10300/";
+/// Env keys every validator VM receives, whether or not the API lists them
+pub const ENV_KEYS: &[&str] = &["DSTACK_VMM_URL", "HOTKEY_PASSPHRASE", "VALIDATOR_BASE_URL"];
+pub const POLL_INTERVAL_SECS: u64 = 5;
+pub const POLL_JITTER_PERCENT: u8 = 20;
+/// Jitter never shortens an interval below half
+pub const POLL_JITTER_MAX_PERCENT: u8 = 50;
+/// Seconds between full checks of an up-to-date VM while the API answers 304
+pub const VM_RECHECK_SECS: u64 = 60;
+/// Seconds between update checks while the push event stream is connected
+pub const PUSH_POLL_SECS: u64 = 300;
+/// Longest wait between update checks while the platform API fails
+pub const API_BACKOFF_MAX_SECS: u64 = 300;
+/// Host clock offset tolerated before creating a VM
+pub const MAX_CLOCK_DRIFT_SECS: u64 = 5;
+pub const TIMEZONE: &str = "local";
+/// Loopback only, so enabling the admin API never exposes it by accident
+pub const ADMIN_API_LISTEN: &str = "127.0.0.1:9850";
+pub const ENDPOINT_HTTP_METHOD: &str = "PUT";
+pub const DNS_TTL_SECS: u32 = 60;
+pub const HEALTH_CHECK_INTERVAL_SECS: u64 = 60;
+pub const HEALTH_CHECK_TIMEOUT_SECS: u64 = 10;
+pub const RECOVERY_START_STOPPED: bool = true;
+pub const CRASHLOOP_THRESHOLD: u32 = 3;
+pub const CRASHLOOP_WINDOW_SECS: u64 = 3600;
+pub const MAX_RECREATIONS: u32 = 6;
+pub const RECREATION_WINDOW_SECS: u64 = 3600;
+pub const RECREATE_BACKOFF_SECS: u64 = 30;
+/// Backoff between automatic recreations never grows beyond this
+pub const RECREATE_BACKOFF_MAX_SECS: u64 = 1800;
+pub const RETENTION_MAX_DISK_MB: u64 = 256;
+pub const CANARY_ENV_FLAG: &str = "VALIDATOR_CANARY";
+/// Host ports of a canary VM are shifted by this much
+pub const CANARY_PORT_OFFSET: u16 = 10000;
+pub const CANARY_TIMEOUT_SECS: u64 = 600;
+pub const CANARY_PROBE_INTERVAL_SECS: u64 = 15;
+/// Env vars never given to a canary VM: it shares the validator's app_id,
+/// but must not sign anything
+pub const CANARY_WITHHELD_ENV: &[&str] = &["HOTKEY_PASSPHRASE"];
+/// First topic level of MQTT topics, followed by the host id unless a prefix is set
+pub const MQTT_TOPIC_ROOT: &str = "platform-validator";
+/// Runners a guest image is assumed to support when it has no `image_support` entry
+pub const IMAGE_RUNNERS: &[&str] = &["docker-compose", "bash"];
+/// Highest app manifest version understood by current dstack guest images
+pub const MAX_MANIFEST_VERSION: u32 = 2;
+/// Boot timeout of a VM whose disk already exists
+pub const BOOT_TIMEOUT_SECS: u64 = 300;
+/// Added to the boot timeout of a new VM per GB of disk it provisions
+pub const BOOT_TIMEOUT_SECS_PER_DISK_GB: u64 = 1;
+/// How long the first update check waits for an embedded VMM to come up
+pub const EMBEDDED_VMM_READY_TIMEOUT_SECS: u64 = 120;
+
+/// Settings `config reset` keeps when no key is given, as resetting them would
+/// repoint the validator at another network or drop its secrets and
+/// credentials; each is only reset when named explicitly
+pub const KEPT_ON_FULL_RESET: &[&str] = &["env", "network", "api_url", "api_tls", "admin_api"];
+
+/// Every top-level platform config setting with a description of its default
+pub fn settings() -> Vec<(&'static str, String)> {
+    vec![
+        ("dstack_vmm_url", GUEST_VMM_URL.to_string()),
+        (
+            "vmm_url",
+            format!("{} (the VMM_URL env var takes precedence)", VMM_RPC_URL),
+        ),
+        ("network", Network::default().to_string()),
+        (
+            "api_url",
+            format!(
+                "{} (the network's; --api-url and the PLATFORM_API_URL env var take precedence)",
+                Network::default().api_url()
+            ),
+        ),
+        ("api_mirrors", "(none)".to_string()),
+        ("poll_interval_secs", format!("{}s", POLL_INTERVAL_SECS)),
+        ("poll_jitter_percent", format!("{}%", POLL_JITTER_PERCENT)),
+        ("vm_recheck_secs", format!("{}s", VM_RECHECK_SECS)),
+        ("push_updates", false.to_string()),
+        ("api_transport", ApiTransport::default().to_string()),
+        ("timezone", TIMEZONE.to_string()),
+        (
+            "env",
+            "(none; only reset when named with --key)".to_string(),
+        ),
+        ("api_tls", "(disabled)".to_string()),
+        (
+            "http",
+            format!(
+                "User-Agent {}, no extra headers, no bandwidth cap",
+                crate::http_config::default_user_agent()
+            ),
+        ),
+        ("resource_limits", "(none)".to_string()),
+        ("drift_policy", format!("{:?}", DriftPolicy::default())),
+        (
+            "notifications",
+            "(no webhooks, MQTT or alerting)".to_string(),
+        ),
+        ("recovery", RecoveryPolicy::default().to_string()),
+        ("admin_api", "(disabled)".to_string()),
+        ("retention", RetentionPolicy::default().to_string()),
+        ("endpoint_integrations", "(none)".to_string()),
+        ("public_ip", "(from port bindings)".to_string()),
+        ("require_image_digest", false.to_string()),
+        ("user_config", "(API value as-is)".to_string()),
+        (
+            "image_support",
+            format!(
+                "runners {}, manifest version up to {}",
+                ImageSupport::default().runners.join(", "),
+                MAX_MANIFEST_VERSION
+            ),
+        ),
+        ("health_checks", "(none)".to_string()),
+        ("canary", "(disabled)".to_string()),
+        ("require_update_approval", false.to_string()),
+        ("remove_orphans", false.to_string()),
+        ("warm_standby", false.to_string()),
+        ("allowed_envs", "(as resolved from the API)".to_string()),
+        ("missing_env", MissingEnvPolicy::default().to_string()),
+        ("boot_timeout", BootTimeout::default().to_string()),
+        ("disclosure", DisclosurePolicy::default().to_string()),
+        ("power", PowerPolicy::default().to_string()),
+        ("clock_check", ClockPolicy::default().to_string()),
+        (
+            "embedded_vmm",
+            "(none; the VMM runs as its own service)".to_string(),
+        ),
+    ]
+}
+
+/// Restore `key` to its default, or every setting except those in
+/// `KEPT_ON_FULL_RESET` when `key` is None
+pub fn reset(config: &mut PlatformConfig, key: Option<&str>) -> Result<()> {
+    let Some(key) = key else {
+        let mut fresh = PlatformConfig::default();
+        for key in KEPT_ON_FULL_RESET {
+            reset_one(&mut fresh, config, key)?;
+        }
+        *config = fresh;
+        return Ok(());
+    };
+    reset_one(config, &PlatformConfig::default(), key)
+}
+
+/// Copy setting `key` from `source` into `config`
+fn reset_one(config: &mut PlatformConfig, source: &PlatformConfig, key: &str) -> Result<()> {
+    let source = source.clone();
+    match key {
+        "dstack_vmm_url" => config.dstack_vmm_url = source.dstack_vmm_url,
+        "vmm_url" => config.vmm_url = source.vmm_url,
+        "network" => config.network = source.network,
+        "api_url" => config.api_url = source.api_url,
+        "api_mirrors" => config.api_mirrors = source.api_mirrors,
+        "poll_interval_secs" => config.poll_interval_secs = source.poll_interval_secs,
+        "poll_jitter_percent" => config.poll_jitter_percent = source.poll_jitter_percent,
+        "vm_recheck_secs" => config.vm_recheck_secs = source.vm_recheck_secs,
+        "push_updates" => config.push_updates = source.push_updates,
+        "api_transport" => config.api_transport = source.api_transport,
+        "timezone" => config.timezone = source.timezone,
+        "env" => config.env = source.env,
+        "api_tls" => config.api_tls = source.api_tls,
+        "http" => config.http = source.http,
+        "resource_limits" => config.resource_limits = source.resource_limits,
+        "drift_policy" => config.drift_policy = source.drift_policy,
+        "notifications" => config.notifications = source.notifications,
+        "recovery" => config.recovery = source.recovery,
+        "admin_api" => config.admin_api = source.admin_api,
+        "retention" => config.retention = source.retention,
+        "endpoint_integrations" => config.endpoint_integrations = source.endpoint_integrations,
+        "public_ip" => config.public_ip = source.public_ip,
+        "require_image_digest" => config.require_image_digest = source.require_image_digest,
+        "user_config" => config.user_config = source.user_config,
+        "image_support" => config.image_support = source.image_support,
+        "health_checks" => config.health_checks = source.health_checks,
+        "canary" => config.canary = source.canary,
+        "require_update_approval" => {
+            config.require_update_approval = source.require_update_approval
+        }
+        "remove_orphans" => config.remove_orphans = source.remove_orphans,
+        "warm_standby" => config.warm_standby = source.warm_standby,
+        "allowed_envs" => config.allowed_envs = source.allowed_envs,
+        "missing_env" => config.missing_env = source.missing_env,
+        "boot_timeout" => config.boot_timeout = source.boot_timeout,
+        "disclosure" => config.disclosure = source.disclosure,
+        "power" => config.power = source.power,
+        "clock_check" => config.clock_check = source.clock_check,
+        "embedded_vmm" => config.embedded_vmm = source.embedded_vmm,
+        _ => {
+            let keys: Vec<&str> = settings().into_iter().map(|(key, _)| key).collect();
+            anyhow::bail!(
+                "Unknown setting '{}'; expected one of: {}",
+                key,
+                keys.join(", ")
+            )
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configured() -> PlatformConfig {
+        PlatformConfig {
+            network: Some(Network::Testnet),
+            api_url: Some("https://api.example.net".to_string()),
+            admin_api: Some(crate::admin_api::AdminApiConfig {
+                listen: "0.0.0.0:9850".to_string(),
+                read_token: "read".to_string(),
+                operator_token: Some("operator".to_string()),
+                status_page: false,
+            }),
+            poll_interval_secs: Some(30),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn full_reset_keeps_network_and_credentials() {
+        let mut config = configured();
+        reset(&mut config, None).unwrap();
+        assert_eq!(config.network, Some(Network::Testnet));
+        assert_eq!(config.api_url.as_deref(), Some("https://api.example.net"));
+        let admin_api = config.admin_api.unwrap();
+        assert_eq!(admin_api.operator_token.as_deref(), Some("operator"));
+        assert_eq!(config.poll_interval_secs, None);
+    }
+
+    #[test]
+    fn named_reset_clears_kept_settings() {
+        let mut config = configured();
+        reset(&mut config, Some("network")).unwrap();
+        reset(&mut config, Some("admin_api")).unwrap();
+        assert_eq!(config.network, None);
+        assert!(config.admin_api.is_none());
+        assert_eq!(config.api_url.as_deref(), Some("https://api.example.net"));
+        assert!(reset(&mut config, Some("nope")).is_err());
+    }
+
+    #[test]
+    fn kept_settings_are_known() {
+        let keys: Vec<&str> = settings().into_iter().map(|(key, _)| key).collect();
+        for key in KEPT_ON_FULL_RESET {
+            assert!(keys.contains(key), "{}", key);
+        }
+    }
+}
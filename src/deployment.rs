@@ -0,0 +1,179 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::store::{self, Store};
+use crate::{compose_hash, ComposeConfig, VmParameters};
+
+pub const COMPOSE_CONFIG_FILE: &str = "compose-config.json";
+pub const DOCKER_COMPOSE_FILE: &str = "docker-compose.yml";
+pub const APP_COMPOSE_FILE: &str = "app-compose.json";
+pub const VM_PARAMETERS_FILE: &str = "vm-parameters.json";
+pub const ALLOWED_ENVS_FILE: &str = "allowed-envs.json";
+pub const DEPLOYMENT_FILE: &str = "deployment.json";
+
+pub(crate) const RECORD_KEY: &str = "deployment.json";
+/// The most recent distinct deployments, newest first, for `rollback`
+pub(crate) const PREVIOUS_KEY: &str = "deployments.json";
+/// Deployments kept under [`PREVIOUS_KEY`], the current one included
+const KEPT_DEPLOYMENTS: usize = 5;
+
+/// The exact inputs of the currently deployed VM, kept so it can be exported
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentRecord {
+    pub vm_id: String,
+    pub compose_config: ComposeConfig,
+    /// Effective parameters (after local caps)
+    pub vm_params: VmParameters,
+    pub allowed_envs: Vec<String>,
+    /// The app manifest exactly as passed to the VMM as `compose_file`
+    pub compose_file: String,
+    pub image: String,
+    pub compose_hash: String,
+    /// Records written before hash versioning used version 1
+    #[serde(default)]
+    pub hash_version: compose_hash::HashVersion,
+    pub deployed_at: u64,
+    /// Platform API endpoint the compose config came from; None when it was
+    /// applied from a file or recorded before endpoints were
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+}
+
+/// Summary written alongside the exported artifacts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentManifest {
+    pub vm_id: String,
+    pub image: String,
+    pub compose_hash: String,
+    pub app_id: String,
+    #[serde(default)]
+    pub hash_version: compose_hash::HashVersion,
+    pub hash_algorithm: String,
+    pub deployed_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+}
+
+impl DeploymentRecord {
+    pub fn load() -> Result<Option<Self>> {
+        store::get_json(&store::open()?, RECORD_KEY)
+    }
+
+    /// Save as the current deployment and at the head of the kept ones,
+    /// replacing an older record of the same compose hash
+    pub fn save(&self) -> Result<()> {
+        let store = store::open()?;
+        let mut kept = Self::kept_in(&store)?;
+        kept.retain(|record| record.compose_hash != self.compose_hash);
+        kept.insert(0, self.clone());
+        kept.truncate(KEPT_DEPLOYMENTS);
+        let record = serde_json::to_vec_pretty(self).context("Failed to serialize deployment")?;
+        let kept = serde_json::to_vec_pretty(&kept).context("Failed to serialize deployments")?;
+        // One transaction, so the current record is always among the kept ones
+        store.commit(vec![
+            (RECORD_KEY.to_string(), Some(record)),
+            (PREVIOUS_KEY.to_string(), Some(kept)),
+        ])
+    }
+
+    /// The kept deployments, newest first
+    pub fn kept() -> Result<Vec<Self>> {
+        Self::kept_in(&store::open()?)
+    }
+
+    /// Serialized size in bytes of each kept deployment but the current
+    /// one, oldest first
+    pub(crate) fn previous_sizes() -> Result<Vec<u64>> {
+        Ok(Self::kept()?
+            .iter()
+            .skip(1)
+            .rev()
+            .map(|record| serde_json::to_vec_pretty(record).map_or(0, |json| json.len() as u64))
+            .collect())
+    }
+
+    /// Forget the `count` oldest kept deployments, never the current one
+    pub(crate) fn drop_oldest_previous(count: usize) -> Result<()> {
+        store::update_json(&store::open()?, PREVIOUS_KEY, |kept: &mut Vec<Self>| {
+            kept.truncate(kept.len().saturating_sub(count).max(1));
+        })
+    }
+
+    fn kept_in(store: &dyn store::Store) -> Result<Vec<Self>> {
+        Ok(store::get_json(store, PREVIOUS_KEY)?.unwrap_or_default())
+    }
+
+    /// Write the artifacts needed to audit or byte-exactly redeploy this VM
+    pub fn export(&self, dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dir).context(format!("Failed to create {}", dir.display()))?;
+
+        write_json(dir, COMPOSE_CONFIG_FILE, &self.compose_config)?;
+        write_file(
+            dir,
+            DOCKER_COMPOSE_FILE,
+            &self.compose_config.compose_content,
+        )?;
+        // Byte-exact: this is the string the compose hash is computed over
+        write_file(dir, APP_COMPOSE_FILE, &self.compose_file)?;
+        write_json(dir, VM_PARAMETERS_FILE, &self.vm_params)?;
+        write_json(dir, ALLOWED_ENVS_FILE, &self.allowed_envs)?;
+
+        let manifest = DeploymentManifest {
+            vm_id: self.vm_id.clone(),
+            image: self.image.clone(),
+            compose_hash: self.compose_hash.clone(),
+            app_id: compose_hash::app_id_from_hash(&self.compose_hash).to_string(),
+            hash_version: self.hash_version,
+            hash_algorithm: self.hash_version.algorithm().to_string(),
+            deployed_at: self.deployed_at,
+            api_url: self.api_url.clone(),
+        };
+        write_json(dir, DEPLOYMENT_FILE, &manifest)
+    }
+}
+
+fn write_file(dir: &Path, name: &str, content: &str) -> Result<()> {
+    let path = dir.join(name);
+    std::fs::write(&path, content).context(format!("Failed to write {}", path.display()))
+}
+
+fn write_json<T: Serialize>(dir: &Path, name: &str, value: &T) -> Result<()> {
+    let json = serde_json::to_string_pretty(value).context("Failed to serialize artifact")?;
+    write_file(dir, name, &json)
+}
+
+/// Artifacts read back from an export directory
+pub struct ExportedDeployment {
+    pub manifest: DeploymentManifest,
+    pub compose_config: ComposeConfig,
+    pub vm_params: VmParameters,
+    pub allowed_envs: Vec<String>,
+    pub compose_file: String,
+}
+
+impl ExportedDeployment {
+    pub fn read(dir: &Path) -> Result<Self> {
+        let compose_file_path = dir.join(APP_COMPOSE_FILE);
+        Ok(Self {
+            manifest: read_json(dir, DEPLOYMENT_FILE)?,
+            compose_config: read_json(dir, COMPOSE_CONFIG_FILE)?,
+            vm_params: read_json(dir, VM_PARAMETERS_FILE)?,
+            allowed_envs: read_json(dir, ALLOWED_ENVS_FILE)?,
+            compose_file: std::fs::read_to_string(&compose_file_path)
+                .context(format!("Failed to read {}", compose_file_path.display()))?,
+        })
+    }
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(dir: &Path, name: &str) -> Result<T> {
+    let path = dir.join(name);
+    let content =
+        std::fs::read_to_string(&path).context(format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&content).context(format!("Failed to parse {}", path.display()))
+}
@@ -0,0 +1,72 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Local say over what the VM discloses publicly. The platform API's
+//! manifest defaults may ask for `public_sysinfo` and `public_tcbinfo`; an
+//! operator can suppress either one here, but never enable one the API left
+//! off. Both flags are part of the app manifest, so suppressing one changes
+//! the compose hash and the app_id.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ManifestDefaults;
+
+/// Manifest flags the operator keeps off regardless of the API
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DisclosurePolicy {
+    /// Keep `public_sysinfo` off (host and guest system information)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub suppress_sysinfo: bool,
+    /// Keep `public_tcbinfo` off (TCB measurements and event log)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub suppress_tcbinfo: bool,
+}
+
+impl DisclosurePolicy {
+    /// `defaults` with the suppressed flags cleared, and the names of the
+    /// flags the API asked for that were cleared
+    pub fn apply(&self, defaults: &ManifestDefaults) -> (ManifestDefaults, Vec<&'static str>) {
+        let mut defaults = defaults.clone();
+        let mut suppressed = Vec::new();
+        let flags = [
+            (
+                "public_sysinfo",
+                &mut defaults.public_sysinfo,
+                self.suppress_sysinfo,
+            ),
+            (
+                "public_tcbinfo",
+                &mut defaults.public_tcbinfo,
+                self.suppress_tcbinfo,
+            ),
+        ];
+        for (name, flag, suppress) in flags {
+            if suppress && *flag {
+                *flag = false;
+                suppressed.push(name);
+            }
+        }
+        (defaults, suppressed)
+    }
+}
+
+impl fmt::Display for DisclosurePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let describe = |suppress: bool| {
+            if suppress {
+                "suppressed"
+            } else {
+                "as requested"
+            }
+        };
+        write!(
+            f,
+            "sysinfo {}, tcbinfo {}",
+            describe(self.suppress_sysinfo),
+            describe(self.suppress_tcbinfo)
+        )
+    }
+}
@@ -0,0 +1,141 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::VmParameters;
+
+/// How to react when the running VM's parameters differ from the desired ones
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum DriftPolicy {
+    /// Do not compare parameters at all
+    Ignore,
+    /// Log an alert whenever the drift changes
+    #[default]
+    Alert,
+    /// Recreate the VM with the desired parameters
+    Reconcile,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParameterDrift {
+    pub field: &'static str,
+    pub desired: String,
+    pub actual: String,
+}
+
+impl fmt::Display for ParameterDrift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: desired={} actual={}",
+            self.field, self.desired, self.actual
+        )
+    }
+}
+
+/// Compare desired parameters against the VMM-reported `configuration` object.
+/// Fields the VMM does not report are skipped rather than treated as drift.
+pub(crate) fn detect_drift(desired: &VmParameters, actual: &Value) -> Vec<ParameterDrift> {
+    let mut drift = Vec::new();
+
+    let mut compare = |field: &'static str, desired: String, actual: Option<String>| {
+        if let Some(actual) = actual {
+            if actual != desired {
+                drift.push(ParameterDrift {
+                    field,
+                    desired,
+                    actual,
+                });
+            }
+        }
+    };
+
+    let as_u64 = |key: &str| {
+        actual
+            .get(key)
+            .and_then(|v| v.as_u64())
+            .map(|v| v.to_string())
+    };
+    let as_bool = |key: &str| {
+        actual
+            .get(key)
+            .and_then(|v| v.as_bool())
+            .map(|v| v.to_string())
+    };
+
+    compare(
+        "image",
+        desired.image.clone(),
+        actual
+            .get("image")
+            .and_then(|v| v.as_str())
+            .map(String::from),
+    );
+    compare("vcpu", desired.vcpu.to_string(), as_u64("vcpu"));
+    compare("memory", desired.memory.to_string(), as_u64("memory"));
+    compare(
+        "disk_size",
+        desired.disk_size.to_string(),
+        as_u64("disk_size"),
+    );
+    compare(
+        "hugepages",
+        desired.hugepages.to_string(),
+        as_bool("hugepages"),
+    );
+    compare(
+        "pin_numa",
+        desired.pin_numa.to_string(),
+        as_bool("pin_numa"),
+    );
+
+    let actual_ports = actual.get("ports").and_then(|v| v.as_array()).map(|ports| {
+        let mut ports: Vec<String> = ports
+            .iter()
+            .map(|p| {
+                format_port(
+                    p.get("protocol").and_then(|v| v.as_str()).unwrap_or("tcp"),
+                    p.get("host_address").and_then(|v| v.as_str()),
+                    p.get("host_port").and_then(|v| v.as_u64()).unwrap_or(0),
+                    p.get("vm_port").and_then(|v| v.as_u64()).unwrap_or(0),
+                )
+            })
+            .collect();
+        ports.sort();
+        ports.join(",")
+    });
+    let mut desired_ports: Vec<String> = desired
+        .ports
+        .iter()
+        .map(|p| {
+            format_port(
+                &p.protocol,
+                p.host_address.as_deref(),
+                p.host_port as u64,
+                p.vm_port as u64,
+            )
+        })
+        .collect();
+    desired_ports.sort();
+    compare("ports", desired_ports.join(","), actual_ports);
+
+    drift
+}
+
+pub(crate) fn format_port(
+    protocol: &str,
+    host_address: Option<&str>,
+    host_port: u64,
+    vm_port: u64,
+) -> String {
+    match host_address.filter(|a| !a.is_empty() && *a != "0.0.0.0") {
+        Some(addr) => format!("{}:{}:{}/{}", addr, host_port, vm_port, protocol),
+        None => format!("{}:{}/{}", host_port, vm_port, protocol),
+    }
+}
@@ -0,0 +1,236 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Keeps external records (load balancer targets, DNS) pointing at the
+//! validator after it is recreated, since its public binding may change.
+
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+#[cfg(feature = "notifications")]
+use {
+    anyhow::{Context, Result},
+    serde_json::{json, Value},
+    std::time::Duration,
+    tracing::info,
+};
+
+use crate::{defaults, PortMapping};
+
+#[cfg(feature = "notifications")]
+const CLOUDFLARE_API: &str = "https://api.cloudflare.com/client/v4";
+#[cfg(feature = "notifications")]
+const ATTEMPTS: u32 = 3;
+
+/// An external record updated whenever a new VM comes up
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EndpointIntegration {
+    /// Send the new binding as JSON to an HTTP endpoint
+    Http {
+        url: String,
+        /// HTTP method, PUT unless set
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        method: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bearer_token: Option<String>,
+    },
+    /// Point a Cloudflare DNS A/AAAA record at the validator's public IP
+    CloudflareDns {
+        zone_id: String,
+        record_name: String,
+        api_token: String,
+        #[serde(default = "default_ttl")]
+        ttl: u32,
+    },
+}
+
+fn default_ttl() -> u32 {
+    defaults::DNS_TTL_SECS
+}
+
+impl EndpointIntegration {
+    /// Short description without credentials
+    pub fn describe(&self) -> String {
+        match self {
+            EndpointIntegration::Http { url, method, .. } => {
+                format!(
+                    "{} {}",
+                    method.as_deref().unwrap_or(defaults::ENDPOINT_HTTP_METHOD),
+                    url
+                )
+            }
+            EndpointIntegration::CloudflareDns {
+                record_name, ttl, ..
+            } => format!("Cloudflare DNS {} (ttl {}s)", record_name, ttl),
+        }
+    }
+}
+
+/// Where the validator can be reached after a (re)creation
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointBinding {
+    pub vm_id: String,
+    pub app_id: String,
+    /// Public IP of the host, if known
+    pub public_ip: Option<IpAddr>,
+    pub ports: Vec<PortMapping>,
+}
+
+impl EndpointBinding {
+    /// `public_ip` wins; otherwise the first port mapping bound to a specific address
+    pub fn new(vm_id: &str, app_id: &str, ports: &[PortMapping], public_ip: Option<&str>) -> Self {
+        let public_ip = public_ip.and_then(|ip| ip.parse().ok()).or_else(|| {
+            ports
+                .iter()
+                .filter_map(|p| p.host_address.as_deref()?.parse::<IpAddr>().ok())
+                .find(|ip| !ip.is_unspecified() && !ip.is_loopback())
+        });
+        Self {
+            vm_id: vm_id.to_string(),
+            app_id: app_id.to_string(),
+            public_ip,
+            ports: ports.to_vec(),
+        }
+    }
+}
+
+/// Update every integration, retrying each a few times. Failures are logged
+/// and never fail the update that triggered them.
+#[cfg(feature = "notifications")]
+pub async fn update_all(integrations: &[EndpointIntegration], binding: &EndpointBinding) {
+    if integrations.is_empty() {
+        return;
+    }
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Failed to create endpoint integration client: {}", e);
+            return;
+        }
+    };
+
+    for integration in integrations {
+        let mut attempt = 1;
+        loop {
+            match update_one(&client, integration, binding).await {
+                Ok(()) => {
+                    info!("Updated endpoint {}", integration.describe());
+                    break;
+                }
+                Err(e) if attempt < ATTEMPTS => {
+                    warn!(
+                        "Failed to update endpoint {} (attempt {}/{}): {:#}",
+                        integration.describe(),
+                        attempt,
+                        ATTEMPTS,
+                        e
+                    );
+                    tokio::time::sleep(Duration::from_secs(2u64.pow(attempt))).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    warn!(
+                        "ALERT: endpoint {} still points at the old binding: {:#}",
+                        integration.describe(),
+                        e
+                    );
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "notifications"))]
+pub async fn update_all(integrations: &[EndpointIntegration], _binding: &EndpointBinding) {
+    for integration in integrations {
+        warn!(
+            "Not updating endpoint {}: this build lacks the `notifications` feature",
+            integration.describe()
+        );
+    }
+}
+
+#[cfg(feature = "notifications")]
+async fn update_one(
+    client: &reqwest::Client,
+    integration: &EndpointIntegration,
+    binding: &EndpointBinding,
+) -> Result<()> {
+    match integration {
+        EndpointIntegration::Http {
+            url,
+            method,
+            bearer_token,
+        } => {
+            let method = reqwest::Method::from_bytes(
+                method
+                    .as_deref()
+                    .unwrap_or(defaults::ENDPOINT_HTTP_METHOD)
+                    .as_bytes(),
+            )
+            .context("Invalid HTTP method")?;
+            let mut request = client.request(method, url).json(binding);
+            if let Some(token) = bearer_token {
+                request = request.bearer_auth(token);
+            }
+            let response = request.send().await?;
+            if !response.status().is_success() {
+                anyhow::bail!("endpoint returned {}", response.status());
+            }
+            Ok(())
+        }
+        EndpointIntegration::CloudflareDns {
+            zone_id,
+            record_name,
+            api_token,
+            ttl,
+        } => {
+            let ip = binding
+                .public_ip
+                .context("public IP unknown; set public_ip or bind a port to a specific address")?;
+            let record_type = if ip.is_ipv4() { "A" } else { "AAAA" };
+            let records_url = format!("{}/zones/{}/dns_records", CLOUDFLARE_API, zone_id);
+
+            let existing: Value = client
+                .get(&records_url)
+                .bearer_auth(api_token)
+                .query(&[("name", record_name.as_str()), ("type", record_type)])
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            let record_id = existing
+                .get("result")
+                .and_then(|r| r.as_array())
+                .and_then(|records| records.first())
+                .and_then(|record| record.get("id"))
+                .and_then(|id| id.as_str());
+
+            let body = json!({
+                "type": record_type,
+                "name": record_name,
+                "content": ip.to_string(),
+                "ttl": ttl,
+            });
+            let request = match record_id {
+                Some(id) => client.put(format!("{}/{}", records_url, id)),
+                None => client.post(&records_url),
+            };
+            request
+                .bearer_auth(api_token)
+                .json(&body)
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok(())
+        }
+    }
+}
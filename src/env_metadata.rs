@@ -0,0 +1,74 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::store;
+
+/// Operator-facing help for an environment key, as provided by the API
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvKeyMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub example: Option<String>,
+    /// Values must not be echoed back (e.g. passphrases)
+    #[serde(default)]
+    pub secret: bool,
+}
+
+pub type EnvMetadata = BTreeMap<String, EnvKeyMetadata>;
+
+pub(crate) const CACHE_KEY: &str = "env-metadata.json";
+
+/// Metadata from the last API response, so offline `config` commands can use it.
+/// A missing or unreadable cache is treated as empty.
+pub fn load_cached() -> EnvMetadata {
+    store::open()
+        .and_then(|store| store::get_json(&store, CACHE_KEY))
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
+/// Refresh the cache when the API's metadata changed; failures are only logged
+pub fn update_cache(metadata: &EnvMetadata) {
+    if metadata.is_empty() || *metadata == load_cached() {
+        return;
+    }
+    if let Err(e) = save_cached(metadata) {
+        tracing::warn!("Failed to cache env key metadata: {:#}", e);
+    }
+}
+
+fn save_cached(metadata: &EnvMetadata) -> Result<()> {
+    store::put_json(&store::open()?, CACHE_KEY, metadata)
+}
+
+/// `KEY (description, e.g. example)`, or just `KEY` without metadata
+pub fn describe_key(key: &str, metadata: &EnvMetadata) -> String {
+    let Some(meta) = metadata.get(key) else {
+        return key.to_string();
+    };
+    let mut help = Vec::new();
+    if let Some(description) = &meta.description {
+        help.push(description.clone());
+    }
+    if let Some(example) = meta.example.as_ref().filter(|_| !meta.secret) {
+        help.push(format!("e.g. {}", example));
+    }
+    if help.is_empty() {
+        key.to_string()
+    } else {
+        format!("{} ({})", key, help.join(", "))
+    }
+}
+
+/// Whether a key's value should be masked when displayed
+pub fn is_secret(key: &str, metadata: &EnvMetadata) -> bool {
+    metadata.get(key).is_some_and(|meta| meta.secret)
+}
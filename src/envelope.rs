@@ -0,0 +1,387 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! The `encrypted_env` envelope sent to the VMM, and the `env` developer
+//! commands that resolve allowed_envs and build and take apart envelopes
+//! without deploying a VM.
+//!
+//! Layout: ephemeral X25519 public key (32 bytes), AES-256-GCM nonce
+//! (12 bytes), then the ciphertext with its 16-byte tag. The X25519 shared
+//! secret with the app's public key is the AES key as-is, and there is no
+//! AAD. The plaintext is `{"env":[{"key":..,"value":..},..]}`.
+
+use std::fmt;
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
+use anyhow::{Context, Result};
+use rand::RngCore;
+use serde_json::{json, Value};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::{resolve_vmm_url, PlatformConfig, ValidatorUpdater};
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const MASK: &str = "********";
+
+/// Encrypt `env_vars` for the app public key with a fresh ephemeral key and nonce
+pub(crate) fn seal(env_vars: &[Value], pubkey_hex: &str) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    seal_with(
+        &plaintext(env_vars),
+        pubkey_hex,
+        StaticSecret::random_from_rng(rand::thread_rng()),
+        nonce,
+    )
+}
+
+fn plaintext(env_vars: &[Value]) -> String {
+    json!({ "env": env_vars }).to_string()
+}
+
+fn seal_with(
+    plaintext: &str,
+    pubkey_hex: &str,
+    ephemeral_secret: StaticSecret,
+    nonce: [u8; NONCE_LEN],
+) -> Result<Vec<u8>> {
+    let remote_pubkey = PublicKey::from(decode_key(pubkey_hex, "public key")?);
+    let ephemeral_public = PublicKey::from(&ephemeral_secret);
+    let shared_secret = ephemeral_secret.diffie_hellman(&remote_pubkey);
+
+    let cipher = Aes256Gcm::new(shared_secret.as_bytes().into());
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_bytes())
+        .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
+
+    let mut envelope = Vec::with_capacity(KEY_LEN + NONCE_LEN + ciphertext.len());
+    envelope.extend_from_slice(ephemeral_public.as_bytes());
+    envelope.extend_from_slice(&nonce);
+    envelope.extend_from_slice(&ciphertext);
+    Ok(envelope)
+}
+
+/// Decode a 32-byte hex key, with or without a `0x` prefix
+fn decode_key(key_hex: &str, what: &str) -> Result<[u8; KEY_LEN]> {
+    let key_hex = key_hex.trim();
+    let bytes = hex::decode(key_hex.strip_prefix("0x").unwrap_or(key_hex))
+        .context(format!("Failed to decode {} hex", what))?;
+    let len = bytes.len();
+    bytes.try_into().map_err(|_| {
+        anyhow::anyhow!(
+            "Invalid {} length: expected {} bytes, got {}",
+            what,
+            KEY_LEN,
+            len
+        )
+    })
+}
+
+/// The parts of an envelope, as the decrypting side reads them
+pub struct EnvelopeLayout {
+    pub ephemeral_public_key: [u8; KEY_LEN],
+    pub nonce: [u8; NONCE_LEN],
+    /// Ciphertext including the tag
+    pub ciphertext: Vec<u8>,
+}
+
+impl EnvelopeLayout {
+    pub fn parse(envelope: &[u8]) -> Result<Self> {
+        if envelope.len() < KEY_LEN + NONCE_LEN + TAG_LEN {
+            anyhow::bail!(
+                "Envelope is {} bytes; at least {} (key + nonce + tag) are needed",
+                envelope.len(),
+                KEY_LEN + NONCE_LEN + TAG_LEN
+            );
+        }
+        let (key, rest) = envelope.split_at(KEY_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        Ok(Self {
+            ephemeral_public_key: key.try_into()?,
+            nonce: nonce.try_into()?,
+            ciphertext: ciphertext.to_vec(),
+        })
+    }
+
+    fn len(&self) -> usize {
+        KEY_LEN + NONCE_LEN + self.ciphertext.len()
+    }
+
+    /// Decrypt with the app's X25519 secret key, as the KMS side does
+    pub fn open(&self, secret_key_hex: &str) -> Result<String> {
+        let secret = StaticSecret::from(decode_key(secret_key_hex, "secret key")?);
+        let shared_secret = secret.diffie_hellman(&PublicKey::from(self.ephemeral_public_key));
+        let cipher = Aes256Gcm::new(shared_secret.as_bytes().into());
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&self.nonce), self.ciphertext.as_slice())
+            .map_err(|_| {
+                anyhow::anyhow!("Decryption failed: wrong secret key or corrupted envelope")
+            })?;
+        String::from_utf8(plaintext).context("Decrypted plaintext is not UTF-8")
+    }
+}
+
+impl fmt::Display for EnvelopeLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let end = self.len();
+        let data_end = KEY_LEN + NONCE_LEN;
+        writeln!(f, "Envelope: {} bytes", end)?;
+        writeln!(
+            f,
+            "  [0..{}) ephemeral X25519 public key: {}",
+            KEY_LEN,
+            hex::encode(self.ephemeral_public_key)
+        )?;
+        writeln!(
+            f,
+            "  [{}..{}) AES-256-GCM nonce: {}",
+            KEY_LEN,
+            data_end,
+            hex::encode(self.nonce)
+        )?;
+        writeln!(
+            f,
+            "  [{}..{}) ciphertext: {} bytes ({} bytes of plaintext + {}-byte tag)",
+            data_end,
+            end,
+            self.ciphertext.len(),
+            self.ciphertext.len() - TAG_LEN,
+            TAG_LEN
+        )?;
+        writeln!(f, "  AAD: none")?;
+        write!(f, "  AES key: X25519 shared secret, used directly (no KDF)")
+    }
+}
+
+/// The plaintext with every value replaced by the mask, unless `show_values`
+fn describe_plaintext(plaintext: &str, show_values: bool) -> String {
+    let Ok(mut parsed) = serde_json::from_str::<Value>(plaintext) else {
+        return format!("(not JSON) {}", plaintext);
+    };
+    if !show_values {
+        for entry in parsed
+            .get_mut("env")
+            .and_then(|env| env.as_array_mut())
+            .into_iter()
+            .flatten()
+        {
+            if let Some(value) = entry.get_mut("value") {
+                *value = Value::String(MASK.to_string());
+            }
+        }
+    }
+    serde_json::to_string_pretty(&parsed).unwrap_or_else(|_| plaintext.to_string())
+}
+
+#[derive(clap::Subcommand)]
+pub enum EnvCommands {
+    /// Print the allowed_envs list a VM would get and where each key comes from
+    Resolve {
+        /// Compose config JSON as served by the API (defaults to the one it serves now)
+        #[arg(long)]
+        compose: Option<std::path::PathBuf>,
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Encrypt an env set for an app public key the way the launcher does (nothing is deployed)
+    Encrypt {
+        /// App env encryption public key (hex), as returned by GetAppEnvEncryptPubKey
+        #[arg(long)]
+        pubkey: String,
+        /// Variable to include (repeatable); defaults to the env from the platform config
+        #[arg(long = "env", value_name = "KEY=VALUE")]
+        env: Vec<String>,
+        /// Print the envelope layout and the plaintext (values masked), not just the envelope
+        #[arg(long)]
+        dry_run: bool,
+        /// Ephemeral X25519 secret (hex) instead of a random one, for reproducible test vectors
+        #[arg(long)]
+        ephemeral_secret: Option<String>,
+        /// Nonce (hex, 12 bytes) instead of a random one
+        #[arg(long)]
+        nonce: Option<String>,
+    },
+    /// Print the layout of an encrypted_env envelope, and decrypt it given the app's secret key
+    InspectEnvelope {
+        /// Hex-encoded envelope, or @PATH of a file containing it
+        envelope: String,
+        /// App X25519 secret key (hex), to decrypt as the KMS would
+        #[arg(long)]
+        secret_key: Option<String>,
+        /// Print decrypted values instead of masking them
+        #[arg(long, requires = "secret_key")]
+        show_values: bool,
+    },
+}
+
+fn parse_env_args(args: &[String]) -> Result<Vec<Value>> {
+    args.iter()
+        .map(|arg| {
+            let (key, value) = arg
+                .split_once('=')
+                .context(format!("Invalid --env '{}': expected KEY=VALUE", arg))?;
+            Ok(json!({ "key": key, "value": value }))
+        })
+        .collect()
+}
+
+pub async fn run_env_command(cmd: EnvCommands) -> Result<()> {
+    match cmd {
+        EnvCommands::Resolve { compose, json } => {
+            let payload = compose
+                .map(|path| {
+                    std::fs::read_to_string(&path)
+                        .context(format!("Failed to read {}", path.display()))
+                })
+                .transpose()?;
+            let platform_config = PlatformConfig::load().unwrap_or_default();
+            let updater =
+                ValidatorUpdater::new(resolve_vmm_url(&platform_config).url, &platform_config)
+                    .await?;
+            let resolved = updater.resolve_allowed_envs(payload.as_deref()).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&resolved)?);
+            } else {
+                println!("{}", resolved);
+            }
+        }
+        EnvCommands::Encrypt {
+            pubkey,
+            env,
+            dry_run,
+            ephemeral_secret,
+            nonce,
+        } => {
+            let env_vars = if env.is_empty() {
+                ValidatorUpdater::build_env_vars(&PlatformConfig::load().unwrap_or_default())
+            } else {
+                parse_env_args(&env)?
+            };
+            let ephemeral_secret = match ephemeral_secret {
+                Some(secret) => StaticSecret::from(decode_key(&secret, "ephemeral secret")?),
+                None => StaticSecret::random_from_rng(rand::thread_rng()),
+            };
+            let nonce = match nonce {
+                Some(nonce) => {
+                    let bytes = hex::decode(nonce.trim()).context("Failed to decode nonce hex")?;
+                    let len = bytes.len();
+                    bytes.try_into().map_err(|_| {
+                        anyhow::anyhow!(
+                            "Invalid nonce length: expected {} bytes, got {}",
+                            NONCE_LEN,
+                            len
+                        )
+                    })?
+                }
+                None => {
+                    let mut nonce = [0u8; NONCE_LEN];
+                    rand::thread_rng().fill_bytes(&mut nonce);
+                    nonce
+                }
+            };
+            let plaintext = plaintext(&env_vars);
+            let envelope = seal_with(&plaintext, &pubkey, ephemeral_secret, nonce)?;
+            if dry_run {
+                println!("{}", EnvelopeLayout::parse(&envelope)?);
+                println!("Plaintext ({} bytes):", plaintext.len());
+                println!("{}", describe_plaintext(&plaintext, false));
+                println!("encrypted_env:");
+            }
+            println!("{}", hex::encode(envelope));
+        }
+        EnvCommands::InspectEnvelope {
+            envelope,
+            secret_key,
+            show_values,
+        } => {
+            let envelope = match envelope.strip_prefix('@') {
+                Some(path) => {
+                    std::fs::read_to_string(path).context(format!("Failed to read {}", path))?
+                }
+                None => envelope,
+            };
+            let envelope = envelope.trim();
+            let bytes = hex::decode(envelope.strip_prefix("0x").unwrap_or(envelope))
+                .context("Envelope is not valid hex")?;
+            let layout = EnvelopeLayout::parse(&bytes)?;
+            println!("{}", layout);
+            if let Some(secret_key) = secret_key {
+                let plaintext = layout.open(&secret_key)?;
+                println!("✓ Decrypted ({} bytes):", plaintext.len());
+                println!("{}", describe_plaintext(&plaintext, show_values));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Independently computed with Python's `cryptography` (X25519 + AES-256-GCM)
+    const APP_PUBLIC: &str = "79a631eede1bf9c98f12032cdeadd0e7a079398fc786b88cc846ec89af85a51a";
+    const APP_SECRET: &str = "404142434445464748494a4b4c4d4e4f505152535455565758595a5b5c5d5e5f";
+    const EPHEMERAL_PUBLIC: &str =
+        "07a37cbc142093c8b755dc1b10e86cb426374ad16aa853ed0bdfc0b2b86d1c7c";
+    const ENVELOPE: &str = "07a37cbc142093c8b755dc1b10e86cb426374ad16aa853ed0bdfc0b2b86d1c7c\
+                            a0a1a2a3a4a5a6a7a8a9aaab\
+                            ec7483927118ef4cdf315ab4e06bd774715bd0428d6fa729accbbdc9fc0be149\
+                            f89c52f89ee55790156e4a013b5b6121a6aad487eb";
+    const PLAINTEXT: &str = r#"{"env":[{"key":"FOO","value":"bar"}]}"#;
+
+    fn ephemeral_secret() -> StaticSecret {
+        StaticSecret::from(std::array::from_fn::<u8, KEY_LEN, _>(|i| i as u8 + 1))
+    }
+
+    fn nonce() -> [u8; NONCE_LEN] {
+        std::array::from_fn(|i| 0xa0 + i as u8)
+    }
+
+    #[test]
+    fn seal_matches_vector() {
+        let env = [json!({ "key": "FOO", "value": "bar" })];
+        assert_eq!(plaintext(&env), PLAINTEXT);
+        let envelope = seal_with(PLAINTEXT, APP_PUBLIC, ephemeral_secret(), nonce()).unwrap();
+        assert_eq!(hex::encode(envelope), ENVELOPE);
+        // The prefix is accepted too
+        let prefixed = format!("0x{}", APP_PUBLIC);
+        let envelope = seal_with(PLAINTEXT, &prefixed, ephemeral_secret(), nonce()).unwrap();
+        assert_eq!(hex::encode(envelope), ENVELOPE);
+    }
+
+    #[test]
+    fn parse_and_open_vector() {
+        let layout = EnvelopeLayout::parse(&hex::decode(ENVELOPE).unwrap()).unwrap();
+        assert_eq!(hex::encode(layout.ephemeral_public_key), EPHEMERAL_PUBLIC);
+        assert_eq!(layout.nonce, nonce());
+        assert_eq!(layout.ciphertext.len(), PLAINTEXT.len() + TAG_LEN);
+        assert_eq!(layout.len(), ENVELOPE.len() / 2);
+        assert_eq!(layout.open(APP_SECRET).unwrap(), PLAINTEXT);
+    }
+
+    #[test]
+    fn rejects_tampered_or_short_envelopes() {
+        let mut envelope = hex::decode(ENVELOPE).unwrap();
+        *envelope.last_mut().unwrap() ^= 1;
+        let layout = EnvelopeLayout::parse(&envelope).unwrap();
+        assert!(layout.open(APP_SECRET).is_err());
+        assert!(EnvelopeLayout::parse(&envelope[..KEY_LEN + NONCE_LEN + TAG_LEN - 1]).is_err());
+        assert!(seal_with(PLAINTEXT, &APP_PUBLIC[2..], ephemeral_secret(), nonce()).is_err());
+    }
+
+    #[test]
+    fn random_seal_round_trips() {
+        let env = [json!({ "key": "FOO", "value": "bar" })];
+        let envelope = seal(&env, APP_PUBLIC).unwrap();
+        let layout = EnvelopeLayout::parse(&envelope).unwrap();
+        assert_eq!(layout.open(APP_SECRET).unwrap(), PLAINTEXT);
+    }
+}
@@ -0,0 +1,213 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Stable codes for failures, emitted next to the (English) messages in
+//! logs, JSON output, notifications, and exit statuses, so dashboards and
+//! runbooks can key off them. A code, once published, keeps its meaning and
+//! exit status.
+
+use std::fmt;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum ErrorCode {
+    #[serde(rename = "E_VMM_UNREACHABLE")]
+    VmmUnreachable,
+    #[serde(rename = "E_API_UNREACHABLE")]
+    ApiUnreachable,
+    #[serde(rename = "E_API_ERROR")]
+    ApiError,
+    #[serde(rename = "E_API_SCHEMA_UNSUPPORTED")]
+    ApiSchemaUnsupported,
+    #[serde(rename = "E_CONFIG_INVALID")]
+    ConfigInvalid,
+    #[serde(rename = "E_ENV_MISSING")]
+    EnvMissing,
+    #[serde(rename = "E_HASH_MISMATCH")]
+    HashMismatch,
+    #[serde(rename = "E_IMAGE_DIGEST_MISMATCH")]
+    ImageDigestMismatch,
+    #[serde(rename = "E_VM_CRASHLOOP")]
+    VmCrashloop,
+    #[serde(rename = "E_VM_FLAPPING")]
+    VmFlapping,
+    #[serde(rename = "E_CLOCK_DRIFT")]
+    ClockDrift,
+    #[serde(rename = "E_VM_BOOT_TIMEOUT")]
+    VmBootTimeout,
+    #[serde(rename = "E_VM_NOT_OWNED")]
+    VmNotOwned,
+    #[serde(rename = "E_INSTANCE_LOCKED")]
+    InstanceLocked,
+    #[serde(rename = "E_HEALTH_CHECK_FAILING")]
+    HealthCheckFailing,
+    #[serde(rename = "E_UNKNOWN")]
+    Unknown,
+}
+
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 16] = [
+        ErrorCode::VmmUnreachable,
+        ErrorCode::ApiUnreachable,
+        ErrorCode::ApiError,
+        ErrorCode::ApiSchemaUnsupported,
+        ErrorCode::ConfigInvalid,
+        ErrorCode::EnvMissing,
+        ErrorCode::HashMismatch,
+        ErrorCode::ImageDigestMismatch,
+        ErrorCode::VmCrashloop,
+        ErrorCode::VmFlapping,
+        ErrorCode::ClockDrift,
+        ErrorCode::VmBootTimeout,
+        ErrorCode::VmNotOwned,
+        ErrorCode::InstanceLocked,
+        ErrorCode::HealthCheckFailing,
+        ErrorCode::Unknown,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::VmmUnreachable => "E_VMM_UNREACHABLE",
+            ErrorCode::ApiUnreachable => "E_API_UNREACHABLE",
+            ErrorCode::ApiError => "E_API_ERROR",
+            ErrorCode::ApiSchemaUnsupported => "E_API_SCHEMA_UNSUPPORTED",
+            ErrorCode::ConfigInvalid => "E_CONFIG_INVALID",
+            ErrorCode::EnvMissing => "E_ENV_MISSING",
+            ErrorCode::HashMismatch => "E_HASH_MISMATCH",
+            ErrorCode::ImageDigestMismatch => "E_IMAGE_DIGEST_MISMATCH",
+            ErrorCode::VmCrashloop => "E_VM_CRASHLOOP",
+            ErrorCode::VmFlapping => "E_VM_FLAPPING",
+            ErrorCode::ClockDrift => "E_CLOCK_DRIFT",
+            ErrorCode::VmBootTimeout => "E_VM_BOOT_TIMEOUT",
+            ErrorCode::VmNotOwned => "E_VM_NOT_OWNED",
+            ErrorCode::InstanceLocked => "E_INSTANCE_LOCKED",
+            ErrorCode::HealthCheckFailing => "E_HEALTH_CHECK_FAILING",
+            ErrorCode::Unknown => "E_UNKNOWN",
+        }
+    }
+
+    /// Exit status of a command failing with this code. 2 is taken by
+    /// `update`'s "changed the VM", so codes start at 10.
+    pub fn exit_status(self) -> i32 {
+        match self {
+            ErrorCode::VmmUnreachable => 10,
+            ErrorCode::ApiUnreachable => 11,
+            ErrorCode::ApiError => 12,
+            ErrorCode::ApiSchemaUnsupported => 13,
+            ErrorCode::ConfigInvalid => 14,
+            ErrorCode::EnvMissing => 15,
+            ErrorCode::HashMismatch => 16,
+            ErrorCode::ImageDigestMismatch => 17,
+            ErrorCode::VmCrashloop => 18,
+            ErrorCode::VmBootTimeout => 19,
+            ErrorCode::VmNotOwned => 20,
+            ErrorCode::InstanceLocked => 21,
+            ErrorCode::HealthCheckFailing => 22,
+            ErrorCode::VmFlapping => 23,
+            ErrorCode::ClockDrift => 24,
+            ErrorCode::Unknown => 1,
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            ErrorCode::VmmUnreachable => "The VMM does not answer",
+            ErrorCode::ApiUnreachable => "The platform API cannot be reached",
+            ErrorCode::ApiError => {
+                "The platform API answered with an error or an unusable response"
+            }
+            ErrorCode::ApiSchemaUnsupported => {
+                "The API uses a compose config schema newer than this launcher understands"
+            }
+            ErrorCode::ConfigInvalid => {
+                "The platform config, or the compose config from the API, is invalid"
+            }
+            ErrorCode::EnvMissing => "Required env keys have no value",
+            ErrorCode::HashMismatch => {
+                "Compose hashes disagree, or the live VM diverges from an exported deployment"
+            }
+            ErrorCode::ImageDigestMismatch => {
+                "The image no longer matches its pinned digest, or has none"
+            }
+            ErrorCode::VmCrashloop => "The VM keeps failing; recreation is held off",
+            ErrorCode::VmFlapping => {
+                "The VM was recreated too often within the window; the launcher is degraded"
+            }
+            ErrorCode::ClockDrift => {
+                "The host clock is off by more than the tolerance; no VM is created"
+            }
+            ErrorCode::VmBootTimeout => {
+                "The VM did not reach the running state within the boot timeout"
+            }
+            ErrorCode::VmNotOwned => "The VM was not created or adopted by this launcher",
+            ErrorCode::InstanceLocked => "Another launcher manages this state directory",
+            ErrorCode::HealthCheckFailing => "An operator health check fails",
+            ErrorCode::Unknown => "Any other failure",
+        }
+    }
+
+    /// An error carrying this code, with `message` as its text
+    pub fn error(self, message: impl Into<String>) -> anyhow::Error {
+        anyhow::Error::new(CodedError {
+            code: self,
+            message: message.into(),
+        })
+    }
+
+    /// The code attached anywhere in `e`'s chain with [`ErrorCode::error`]
+    pub fn attached(e: &anyhow::Error) -> Option<Self> {
+        e.chain()
+            .find_map(|cause| cause.downcast_ref::<CodedError>())
+            .map(|coded| coded.code)
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An error whose code is known where it is raised
+#[derive(Debug)]
+struct CodedError {
+    code: ErrorCode,
+    message: String,
+}
+
+impl fmt::Display for CodedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CodedError {}
+
+/// Print every code with its exit status and meaning, or the list as JSON
+pub fn run_error_codes_command(json: bool) -> anyhow::Result<()> {
+    if json {
+        let codes: Vec<_> = ErrorCode::ALL
+            .iter()
+            .map(|code| {
+                serde_json::json!({
+                    "code": code,
+                    "exit_status": code.exit_status(),
+                    "description": code.description(),
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&codes)?);
+    } else {
+        for code in ErrorCode::ALL {
+            println!(
+                "{:<26} {:>3}  {}",
+                code.as_str(),
+                code.exit_status(),
+                code.description()
+            );
+        }
+    }
+    Ok(())
+}
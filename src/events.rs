@@ -0,0 +1,155 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Typed events emitted by the updater loop, for embedders that want to react
+//! to the same things the notifier and audit log see.
+
+use std::fmt;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Events buffered per subscriber before the slowest one starts lagging
+const EVENT_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type")]
+#[non_exhaustive]
+pub enum Event {
+    /// A compose config was fetched and resolved to a desired deployment
+    ConfigFetched {
+        compose_hash: String,
+        updated_at: String,
+    },
+    /// The running VM does not match the desired compose hash
+    HashMismatch {
+        vm_id: String,
+        running_app_id: String,
+        desired_hash: String,
+        /// The compose config's description, if any
+        changelog: Option<String>,
+    },
+    /// A VM was created for the given compose hash
+    VmCreated { vm_id: String, compose_hash: String },
+    /// A VM was removed
+    VmRemoved { vm_id: String },
+    /// A stopped VM was started
+    VmStarted { vm_id: String },
+    /// A VM is running the desired deployment after a create or resize
+    UpdateApplied { vm_id: String, compose_hash: String },
+    /// An update check failed; `code` is stable, e.g. `E_VMM_UNREACHABLE`
+    Error { message: String, code: String },
+    /// A phase of creating a VM started
+    CreatePhaseStarted { phase: CreatePhase },
+    /// A phase of creating a VM ended; `error` is set if it failed
+    CreatePhaseFinished {
+        phase: CreatePhase,
+        duration: Duration,
+        error: Option<String>,
+    },
+}
+
+/// Steps of creating a VM, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum CreatePhase {
+    /// Fetching the env encryption public key from the KMS
+    EncryptionKey,
+    EncryptEnv,
+    /// Asking the VMM for its compose hash of the final VM config, which
+    /// must match the launcher's
+    ComposeHash,
+    CreateVm,
+    /// Waiting for the new VM to boot
+    Boot,
+}
+
+impl fmt::Display for CreatePhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CreatePhase::EncryptionKey => "encryption key",
+            CreatePhase::EncryptEnv => "env encryption",
+            CreatePhase::ComposeHash => "compose hash",
+            CreatePhase::CreateVm => "create",
+            CreatePhase::Boot => "boot",
+        })
+    }
+}
+
+/// Runs the phases of one VM creation, emitting progress events and
+/// recording how long each phase took
+pub(crate) struct CreateProgress {
+    events: EventBus,
+    timings: Vec<(CreatePhase, Duration)>,
+}
+
+impl CreateProgress {
+    pub(crate) fn new(events: &EventBus) -> Self {
+        Self {
+            events: events.clone(),
+            timings: Vec::new(),
+        }
+    }
+
+    pub(crate) async fn run<T>(
+        &mut self,
+        phase: CreatePhase,
+        step: impl Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        self.events.emit(Event::CreatePhaseStarted { phase });
+        let started = Instant::now();
+        let result = step.await;
+        let duration = started.elapsed();
+        self.timings.push((phase, duration));
+        self.events.emit(Event::CreatePhaseFinished {
+            phase,
+            duration,
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+        });
+        result
+    }
+
+    /// Per-phase durations and the total, e.g. `manifest 2ms, boot 41.2s (total 41.5s)`
+    pub(crate) fn summary(&self) -> String {
+        let phases: Vec<String> = self
+            .timings
+            .iter()
+            .map(|(phase, duration)| format!("{} {:.1?}", phase, duration))
+            .collect();
+        let total: Duration = self.timings.iter().map(|(_, duration)| *duration).sum();
+        format!("{} (total {:.1?})", phases.join(", "), total)
+    }
+}
+
+/// Broadcast channel of [`Event`]s. Emitting never blocks; subscribers that
+/// fall behind receive `RecvError::Lagged` and skip ahead.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_CAPACITY);
+        Self { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    pub fn emit(&self, event: Event) {
+        // No subscribers is the normal case for the CLI
+        let _ = self.sender.send(event);
+    }
+}
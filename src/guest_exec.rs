@@ -0,0 +1,180 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Diagnostic commands run inside the managed VM through the dstack guest
+//! agent, for debugging the app layer on a host without a console into the
+//! TEE. Only read-only commands from a fixed list are sent, and only where
+//! the guest agent offers command execution at all; production guest images
+//! may not.
+
+use std::io::{BufRead, IsTerminal, Write};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::capture;
+
+#[derive(clap::Subcommand)]
+pub enum VmCommands {
+    /// Run a diagnostic command inside the validator VM, e.g. `vm exec -- docker ps`
+    Exec {
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
+        /// Seconds the command may run inside the VM
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+        /// Command and arguments: docker ps/logs/inspect/stats/top/images/info/version,
+        /// cat, head, tail, ls, df, free, uptime, ps, or dmesg
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+}
+
+/// Programs accepted by `vm exec`, with the subcommands allowed for those
+/// that have them. Everything here only reads.
+const ALLOWED_COMMANDS: &[(&str, &[&str])] = &[
+    (
+        "docker",
+        &[
+            "ps", "logs", "inspect", "stats", "top", "images", "info", "version",
+        ],
+    ),
+    ("cat", &[]),
+    ("head", &[]),
+    ("tail", &[]),
+    ("ls", &[]),
+    ("df", &[]),
+    ("free", &[]),
+    ("uptime", &[]),
+    ("ps", &[]),
+    ("dmesg", &[]),
+];
+
+/// Commands return once; nothing streams back through the guest agent
+const FOLLOW_FLAGS: &[&str] = &["-f", "-F", "-w", "--follow", "--follow-new"];
+
+/// What a command printed inside the VM, and how it exited
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecOutput {
+    pub exit_code: i32,
+    #[serde(default)]
+    pub stdout: String,
+    #[serde(default)]
+    pub stderr: String,
+}
+
+/// Refuse anything but the read-only commands in `ALLOWED_COMMANDS`
+pub fn check_allowed(command: &[String]) -> Result<()> {
+    let (program, args) = command.split_first().context("No command given")?;
+    let Some((_, subcommands)) = ALLOWED_COMMANDS.iter().find(|(p, _)| p == program) else {
+        let programs: Vec<&str> = ALLOWED_COMMANDS.iter().map(|(p, _)| *p).collect();
+        anyhow::bail!(
+            "'{}' is not an allowed diagnostic command (allowed: {})",
+            program,
+            programs.join(", ")
+        );
+    };
+    if !subcommands.is_empty() {
+        let subcommand = args.first().map(String::as_str).unwrap_or_default();
+        if !subcommands.contains(&subcommand) {
+            anyhow::bail!(
+                "'{} {}' is not allowed (allowed: {})",
+                program,
+                subcommand,
+                subcommands.join(", ")
+            );
+        }
+    }
+    for arg in args {
+        if FOLLOW_FLAGS.contains(&arg.as_str()) {
+            anyhow::bail!(
+                "'{}' would not return; use `platform logs --follow` to stream console output",
+                arg
+            );
+        }
+        // The agent gets an argv, not a shell line, but keep it that way
+        if arg.contains(['`', '$', ';', '|', '&', '<', '>', '\n']) {
+            anyhow::bail!("Argument '{}' contains shell metacharacters", arg);
+        }
+    }
+    if program == "docker"
+        && args.first().map(String::as_str) == Some("stats")
+        && !args.iter().any(|a| a == "--no-stream")
+    {
+        anyhow::bail!("'docker stats' needs --no-stream");
+    }
+    Ok(())
+}
+
+/// Ask on the terminal before running `command`; without a terminal,
+/// only `--yes` runs it
+pub fn confirm(vm_id: &str, command: &[String]) -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!("Not running on a terminal; pass --yes to run the command without a prompt");
+    }
+    eprint!(
+        "Run `{}` inside validator VM {}? [y/N] ",
+        command.join(" "),
+        vm_id
+    );
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Run `command` in `vm_id` via the VMM's guest API
+pub async fn exec(
+    vmm_url: &str,
+    vm_id: &str,
+    command: &[String],
+    timeout: Duration,
+) -> Result<ExecOutput> {
+    // The shared client's request timeout is shorter than a slow command
+    let client = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(10))
+        .timeout(timeout + Duration::from_secs(10))
+        .danger_accept_invalid_certs(true)
+        .build()
+        .context("Failed to create HTTP client")?;
+    let url = format!("{}/prpc/Guest.Exec?json", vmm_url);
+    let response = client
+        .post(&url)
+        .json(&json!({
+            "id": vm_id,
+            "command": command,
+            "timeout_secs": timeout.as_secs(),
+        }))
+        .send()
+        .await
+        .context("Guest agent unreachable")?;
+    let status = response.status();
+    if status == reqwest::StatusCode::NOT_FOUND {
+        anyhow::bail!(
+            "The guest agent of VM {} does not permit command execution (its image may not include it)",
+            vm_id
+        );
+    }
+    if !status.is_success() {
+        let body = capture::error_body(response).await;
+        anyhow::bail!("Guest agent returned {}: {}", status, body);
+    }
+    let body: Value = response
+        .json()
+        .await
+        .context("Invalid guest agent response")?;
+    if let Some(error) = body.get("error").and_then(|e| e.as_str()) {
+        anyhow::bail!("Guest agent refused the command: {}", error);
+    }
+    if body.get("exit_code").is_none() {
+        anyhow::bail!(
+            "The guest agent of VM {} does not permit command execution (its image may not include it)",
+            vm_id
+        );
+    }
+    serde_json::from_value(body).context("Invalid guest agent response")
+}
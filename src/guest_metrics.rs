@@ -0,0 +1,202 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! App-level view of the managed VM, read from the dstack guest agent through
+//! the VMM: which containers run and how often they restart. A VM can be
+//! "running" while the validator inside it is crashlooping.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContainerMetrics {
+    pub name: String,
+    #[serde(default)]
+    pub image: String,
+    /// Docker state, e.g. `running`, `restarting`, `exited`
+    pub state: String,
+    /// Docker status line, e.g. `Up 3 hours`
+    #[serde(default)]
+    pub status: String,
+    /// Docker's `RestartCount`, or where the guest agent does not report
+    /// it, the restarts this launcher has seen since it started
+    #[serde(default)]
+    pub restarts: u32,
+    /// Whether `restarts` is Docker's count
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub restarts_reported: bool,
+}
+
+impl ContainerMetrics {
+    pub fn is_running(&self) -> bool {
+        self.state == "running"
+    }
+}
+
+/// Containers inside the managed VM as of `collected_at`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GuestMetrics {
+    pub vm_id: String,
+    pub collected_at: u64,
+    pub containers: Vec<ContainerMetrics>,
+}
+
+impl GuestMetrics {
+    /// Every container runs; false for a VM with no containers yet
+    pub fn all_running(&self) -> bool {
+        !self.containers.is_empty() && self.containers.iter().all(|c| c.is_running())
+    }
+
+    /// For containers without Docker's `RestartCount`, carry restart counts
+    /// over from the previous sample of the same VM, counting a container
+    /// that was not running and now is as one restart
+    pub fn count_restarts(&mut self, previous: Option<&GuestMetrics>) {
+        let Some(previous) = previous.filter(|p| p.vm_id == self.vm_id) else {
+            return;
+        };
+        let before: BTreeMap<&str, &ContainerMetrics> = previous
+            .containers
+            .iter()
+            .map(|c| (c.name.as_str(), c))
+            .collect();
+        for container in self.containers.iter_mut().filter(|c| !c.restarts_reported) {
+            if let Some(prev) = before.get(container.name.as_str()) {
+                container.restarts = prev.restarts;
+                if !prev.is_running() && container.is_running() {
+                    container.restarts += 1;
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for GuestMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let running = self.containers.iter().filter(|c| c.is_running()).count();
+        write!(
+            f,
+            "{}/{} containers running",
+            running,
+            self.containers.len()
+        )?;
+        let unhealthy: Vec<String> = self
+            .containers
+            .iter()
+            .filter(|c| !c.is_running())
+            .map(|c| format!("{}: {}", c.name, c.state))
+            .collect();
+        if !unhealthy.is_empty() {
+            write!(f, " ({})", unhealthy.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Ask the guest agent (via the VMM's guest API) for the VM's containers
+pub async fn collect(
+    client: &reqwest::Client,
+    vmm_url: &str,
+    vm_id: &str,
+    now: u64,
+) -> Result<GuestMetrics> {
+    let url = format!("{}/prpc/Guest.ListContainers?json", vmm_url);
+    let response = client
+        .post(&url)
+        .json(&json!({ "id": vm_id }))
+        .send()
+        .await
+        .context("Guest agent unreachable")?;
+    let status = response.status();
+    if !status.is_success() {
+        anyhow::bail!("Guest agent returned {}", status);
+    }
+    let body: Value = response
+        .json()
+        .await
+        .context("Invalid guest agent response")?;
+    Ok(GuestMetrics {
+        vm_id: vm_id.to_string(),
+        collected_at: now,
+        containers: parse_containers(&body),
+    })
+}
+
+fn parse_containers(body: &Value) -> Vec<ContainerMetrics> {
+    let str_field = |c: &Value, key: &str| {
+        c.get(key)
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+    body.get("containers")
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+        .map(|c| {
+            // Docker reports names with a leading slash
+            let name = c
+                .get("names")
+                .and_then(|n| n.as_array())
+                .and_then(|n| n.first())
+                .and_then(|n| n.as_str())
+                .map(|n| n.trim_start_matches('/').to_string())
+                .unwrap_or_else(|| str_field(c, "id"));
+            let restart_count = ["restart_count", "RestartCount"]
+                .iter()
+                .find_map(|key| c.get(*key).and_then(|v| v.as_u64()))
+                .map(|count| u32::try_from(count).unwrap_or(u32::MAX));
+            ContainerMetrics {
+                name,
+                image: str_field(c, "image"),
+                state: str_field(c, "state"),
+                status: str_field(c, "status"),
+                restarts: restart_count.unwrap_or(0),
+                restarts_reported: restart_count.is_some(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(containers: Value) -> GuestMetrics {
+        GuestMetrics {
+            vm_id: "vm-1".to_string(),
+            collected_at: 0,
+            containers: parse_containers(&json!({ "containers": containers })),
+        }
+    }
+
+    #[test]
+    fn docker_restart_count_is_used_when_reported() {
+        let previous =
+            sample(json!([{ "names": ["/app"], "state": "exited", "restart_count": 2 }]));
+        let mut metrics =
+            sample(json!([{ "names": ["/app"], "state": "running", "restart_count": 7 }]));
+        metrics.count_restarts(Some(&previous));
+        assert_eq!(metrics.containers[0].name, "app");
+        assert_eq!(metrics.containers[0].restarts, 7);
+    }
+
+    #[test]
+    fn restarts_are_counted_without_docker_count() {
+        let mut previous = sample(json!([{ "names": ["/app"], "state": "exited" }]));
+        previous.containers[0].restarts = 1;
+        let mut metrics = sample(json!([{ "names": ["/app"], "state": "running" }]));
+        metrics.count_restarts(Some(&previous));
+        assert_eq!(metrics.containers[0].restarts, 2);
+
+        // A different VM starts from zero
+        previous.vm_id = "vm-2".to_string();
+        let mut metrics = sample(json!([{ "names": ["/app"], "state": "running" }]));
+        metrics.count_restarts(Some(&previous));
+        assert_eq!(metrics.containers[0].restarts, 0);
+    }
+}
@@ -0,0 +1,234 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::defaults;
+
+fn default_interval_secs() -> u64 {
+    defaults::HEALTH_CHECK_INTERVAL_SECS
+}
+
+fn default_timeout_secs() -> u64 {
+    defaults::HEALTH_CHECK_TIMEOUT_SECS
+}
+
+/// An operator-defined check: the command passes when it exits with status 0
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthCheckConfig {
+    pub name: String,
+    /// Executable to run (not interpreted by a shell)
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// Result of the latest run of a check
+enum Outcome {
+    /// The first run has not finished yet
+    Pending,
+    Passing,
+    Failing(String),
+}
+
+struct RunningCheck {
+    config: HealthCheckConfig,
+    outcome: Arc<Mutex<Outcome>>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl RunningCheck {
+    fn new(config: &HealthCheckConfig) -> Self {
+        Self {
+            config: config.clone(),
+            outcome: Arc::new(Mutex::new(Outcome::Pending)),
+            task: None,
+        }
+    }
+}
+
+impl Drop for RunningCheck {
+    fn drop(&mut self) {
+        if let Some(task) = &self.task {
+            task.abort();
+        }
+    }
+}
+
+/// Runs the configured checks, each in its own task on its own interval, so
+/// a slow check never holds up update checks, and remembers their results
+pub struct HealthChecks {
+    checks: Vec<RunningCheck>,
+}
+
+impl HealthChecks {
+    pub fn new(configs: &[HealthCheckConfig]) -> Self {
+        Self {
+            checks: configs.iter().map(RunningCheck::new).collect(),
+        }
+    }
+
+    /// Replace the configured checks, keeping checks that did not change running
+    pub fn reconfigure(&mut self, configs: &[HealthCheckConfig]) {
+        let mut previous = std::mem::take(&mut self.checks);
+        self.checks = configs
+            .iter()
+            .map(
+                |config| match previous.iter().position(|c| c.config == *config) {
+                    Some(i) => previous.swap_remove(i),
+                    None => RunningCheck::new(config),
+                },
+            )
+            .collect();
+    }
+
+    /// Start every check that is not running yet; returns without waiting for them
+    pub fn start(&mut self) {
+        for check in &mut self.checks {
+            if check.task.is_none() {
+                check.task = Some(tokio::spawn(run_periodically(
+                    check.config.clone(),
+                    check.outcome.clone(),
+                )));
+            }
+        }
+    }
+
+    /// Combined result of the latest run of every check; checks that have not
+    /// finished a run yet count as passing
+    pub fn status(&self) -> Result<()> {
+        let failing: Vec<String> = self
+            .checks
+            .iter()
+            .filter_map(|check| match &*check.outcome.lock().unwrap() {
+                Outcome::Failing(reason) => Some(format!("{} ({})", check.config.name, reason)),
+                _ => None,
+            })
+            .collect();
+        if failing.is_empty() {
+            Ok(())
+        } else {
+            anyhow::bail!("Health checks failing: {}", failing.join("; "))
+        }
+    }
+
+    /// Wait until every started check has finished its first run
+    pub async fn settled(&self) {
+        while self.checks.iter().any(|check| {
+            check.task.is_some() && matches!(*check.outcome.lock().unwrap(), Outcome::Pending)
+        }) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// As `status`, but also failing while any check has not finished a run
+    pub fn ready(&self) -> Result<()> {
+        self.status()?;
+        let pending: Vec<&str> = self
+            .checks
+            .iter()
+            .filter(|check| matches!(*check.outcome.lock().unwrap(), Outcome::Pending))
+            .map(|check| check.config.name.as_str())
+            .collect();
+        if !pending.is_empty() {
+            anyhow::bail!("Health checks not run yet: {}", pending.join(", "));
+        }
+        Ok(())
+    }
+}
+
+async fn run_periodically(config: HealthCheckConfig, outcome: Arc<Mutex<Outcome>>) {
+    let interval = Duration::from_secs(config.interval_secs.max(1));
+    loop {
+        let result = run_check(&config, &[]).await;
+        {
+            let mut outcome = outcome.lock().unwrap();
+            match (&*outcome, &result) {
+                (Outcome::Failing(_), Err(_)) => {}
+                (_, Err(e)) => warn!("Health check '{}' failing: {:#}", config.name, e),
+                (Outcome::Failing(_), Ok(())) => {
+                    info!("Health check '{}' passing again", config.name)
+                }
+                _ => {}
+            }
+            *outcome = match result {
+                Ok(()) => Outcome::Passing,
+                Err(e) => Outcome::Failing(format!("{:#}", e)),
+            };
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Run a check once, with `env` added to its environment
+pub(crate) async fn run_check(config: &HealthCheckConfig, env: &[(&str, String)]) -> Result<()> {
+    let mut command = tokio::process::Command::new(&config.command);
+    command
+        .args(&config.args)
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true);
+
+    let output = tokio::time::timeout(Duration::from_secs(config.timeout_secs), command.output())
+        .await
+        .map_err(|_| anyhow::anyhow!("timed out after {}s", config.timeout_secs))?
+        .map_err(|e| anyhow::anyhow!("failed to run {}: {}", config.command, e))?;
+
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let detail = stderr.trim().lines().last().unwrap_or_default();
+    match output.status.code() {
+        Some(code) => anyhow::bail!("exit status {}: {}", code, detail),
+        None => anyhow::bail!("terminated by signal: {}", detail),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(name: &str, command: &str) -> HealthCheckConfig {
+        HealthCheckConfig {
+            name: name.to_string(),
+            command: command.to_string(),
+            args: Vec::new(),
+            interval_secs: 3600,
+            timeout_secs: 5,
+        }
+    }
+
+    #[tokio::test]
+    async fn checks_run_in_the_background() {
+        let mut checks = HealthChecks::new(&[check("ok", "true"), check("broken", "false")]);
+        assert!(checks.status().is_ok());
+        assert!(checks.ready().is_err());
+
+        checks.start();
+        tokio::time::timeout(Duration::from_secs(10), checks.settled())
+            .await
+            .expect("checks never ran");
+        let failing = checks.status().unwrap_err().to_string();
+        assert!(
+            failing.contains("broken") && !failing.contains("ok ("),
+            "{}",
+            failing
+        );
+
+        // An unchanged check keeps its result; a removed one stops counting
+        checks.reconfigure(&[check("ok", "true")]);
+        assert!(checks.ready().is_ok());
+    }
+}
@@ -0,0 +1,351 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Deployment history for change-management and compliance reporting. It is
+//! reconstructed from the audit log, so it covers everything the log still
+//! holds and verifies the same way: one row per deployment attempt, with the
+//! VMs, hashes, and image involved, when it started and ended, and how it went.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::admin_api::RemoteArgs;
+use crate::audit::AuditEntry;
+use crate::timefmt::DisplayTimezone;
+
+#[derive(clap::Subcommand)]
+pub enum HistoryCommands {
+    /// Write every recorded deployment as CSV or JSON, for change-management records
+    Export {
+        #[command(flatten)]
+        remote: RemoteArgs,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+        /// Only deployments started on or after this date (YYYY-MM-DD, UTC, or RFC 3339)
+        #[arg(long, value_parser = parse_since)]
+        since: Option<u64>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeploymentKind {
+    /// A VM created to replace the old one, or the first one
+    Create,
+    /// The running VM's resources changed in place
+    Resize,
+    /// An existing VM taken over with `adopt`
+    Adopt,
+    /// A candidate tried next to the running VM before replacing it
+    Canary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Succeeded,
+    Failed,
+    /// Interrupted before the old VM was removed, and discarded on restart
+    RolledBack,
+    /// Written by a launcher that did not record how deployments ended
+    Unknown,
+}
+
+impl DeploymentKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            DeploymentKind::Create => "create",
+            DeploymentKind::Resize => "resize",
+            DeploymentKind::Adopt => "adopt",
+            DeploymentKind::Canary => "canary",
+        }
+    }
+}
+
+impl Outcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Succeeded => "succeeded",
+            Outcome::Failed => "failed",
+            Outcome::RolledBack => "rolled_back",
+            Outcome::Unknown => "unknown",
+        }
+    }
+}
+
+/// One deployment attempt. Timestamps are Unix seconds.
+#[derive(Debug, Clone, Serialize)]
+pub struct Deployment {
+    pub kind: DeploymentKind,
+    pub outcome: Outcome,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    pub duration_secs: Option<u64>,
+    pub vm_id: Option<String>,
+    pub previous_vm_id: Option<String>,
+    pub previous_compose_hash: Option<String>,
+    pub compose_hash: Option<String>,
+    pub image: Option<String>,
+    pub changelog: Option<String>,
+    pub error: Option<String>,
+    /// Audit log entry the row was built from
+    pub audit_seq: u64,
+    /// Platform API endpoint the compose config came from, where recorded
+    pub api_url: Option<String>,
+}
+
+const CSV_COLUMNS: [&str; 14] = [
+    "kind",
+    "outcome",
+    "started_at",
+    "finished_at",
+    "duration_secs",
+    "vm_id",
+    "previous_vm_id",
+    "previous_compose_hash",
+    "compose_hash",
+    "image",
+    "changelog",
+    "error",
+    "audit_seq",
+    "api_url",
+];
+
+fn text(details: &Value, key: &str) -> Option<String> {
+    details.get(key).and_then(|v| v.as_str()).map(String::from)
+}
+
+impl Deployment {
+    fn new(kind: DeploymentKind, outcome: Outcome, entry: &AuditEntry) -> Self {
+        let details = &entry.details;
+        Self {
+            kind,
+            outcome,
+            started_at: entry.timestamp,
+            finished_at: Some(entry.timestamp),
+            duration_secs: Some(0),
+            vm_id: text(details, "vm_id"),
+            previous_vm_id: text(details, "old_vm_id"),
+            previous_compose_hash: text(details, "old_compose_hash"),
+            compose_hash: text(details, "compose_hash"),
+            image: text(details, "image"),
+            changelog: text(details, "changelog"),
+            error: text(details, "error"),
+            audit_seq: entry.seq,
+            api_url: text(details, "api_url"),
+        }
+    }
+}
+
+/// Deployments recorded in `entries`, oldest first
+pub fn deployments(entries: &[AuditEntry]) -> Vec<Deployment> {
+    // Image and changelog are recorded when the VM is created
+    let created: HashMap<&str, &AuditEntry> = entries
+        .iter()
+        .filter(|entry| entry.action == "CreateVm")
+        .filter_map(|entry| Some((entry.details.get("vm_id")?.as_str()?, entry)))
+        .collect();
+    let finished: Vec<&str> = entries
+        .iter()
+        .filter(|entry| entry.action == "DeploymentFinished")
+        .filter_map(|entry| entry.details.get("vm_id")?.as_str())
+        .collect();
+
+    let mut deployments = Vec::new();
+    for entry in entries {
+        let details = &entry.details;
+        let deployment = match entry.action.as_str() {
+            "DeploymentFinished" => {
+                let outcome = match details.get("outcome").and_then(|o| o.as_str()) {
+                    Some("succeeded") => Outcome::Succeeded,
+                    Some("failed") => Outcome::Failed,
+                    _ => Outcome::Unknown,
+                };
+                let mut deployment = Deployment::new(DeploymentKind::Create, outcome, entry);
+                if let Some(started_at) = details.get("started_at").and_then(|s| s.as_u64()) {
+                    deployment.started_at = started_at;
+                    deployment.duration_secs = Some(entry.timestamp.saturating_sub(started_at));
+                }
+                if let Some(create) = deployment.vm_id.as_deref().and_then(|id| created.get(id)) {
+                    deployment.image = deployment.image.or(text(&create.details, "image"));
+                    deployment.changelog = text(&create.details, "changelog");
+                }
+                deployment
+            }
+            // Created by a launcher that did not record the end of deployments
+            "CreateVm" => {
+                let vm_id = details.get("vm_id").and_then(|v| v.as_str());
+                let canary = details.get("canary").and_then(|c| c.as_bool()) == Some(true);
+                // Created stopped: a warm standby, deployed only when promoted
+                let stopped = details.get("stopped").and_then(|s| s.as_bool()) == Some(true);
+                if canary || stopped || vm_id.is_some_and(|id| finished.contains(&id)) {
+                    continue;
+                }
+                let mut deployment =
+                    Deployment::new(DeploymentKind::Create, Outcome::Unknown, entry);
+                if let Some(failed) = entries.iter().find(|e| {
+                    e.action == "VmBootFailed"
+                        && e.details.get("vm_id").and_then(|v| v.as_str()) == vm_id
+                }) {
+                    deployment.outcome = Outcome::Failed;
+                    deployment.error = text(&failed.details, "error");
+                    deployment.finished_at = Some(failed.timestamp);
+                    deployment.duration_secs =
+                        Some(failed.timestamp.saturating_sub(entry.timestamp));
+                } else {
+                    deployment.finished_at = None;
+                    deployment.duration_secs = None;
+                }
+                deployment
+            }
+            "ResizeVm" => Deployment::new(DeploymentKind::Resize, Outcome::Succeeded, entry),
+            "AdoptVm" => Deployment::new(DeploymentKind::Adopt, Outcome::Succeeded, entry),
+            "CanaryPassed" | "CanaryFailed" => {
+                let outcome = if entry.action == "CanaryPassed" {
+                    Outcome::Succeeded
+                } else {
+                    Outcome::Failed
+                };
+                let mut deployment = Deployment::new(DeploymentKind::Canary, outcome, entry);
+                if let Some(create) = deployment.vm_id.as_deref().and_then(|id| created.get(id)) {
+                    deployment.started_at = create.timestamp;
+                    deployment.duration_secs =
+                        Some(entry.timestamp.saturating_sub(create.timestamp));
+                    deployment.image = text(&create.details, "image");
+                    deployment.changelog = text(&create.details, "changelog");
+                }
+                deployment
+            }
+            "UpdateRolledBack" => {
+                let mut deployment =
+                    Deployment::new(DeploymentKind::Create, Outcome::RolledBack, entry);
+                deployment.vm_id = None;
+                if let Some(started_at) = details.get("started_at").and_then(|s| s.as_u64()) {
+                    deployment.started_at = started_at;
+                    deployment.duration_secs = Some(entry.timestamp.saturating_sub(started_at));
+                }
+                deployment
+            }
+            _ => continue,
+        };
+        deployments.push(deployment);
+    }
+    deployments
+}
+
+/// One line per deployment, e.g. `2025-03-01 14:05:09 CET create succeeded
+/// VM vm-2 (replaced vm-1), <old hash> -> <new hash>, image dstack-0.5.2, 41s`
+pub(crate) fn print_deployment(deployment: &Deployment, tz: DisplayTimezone) {
+    let mut line = format!(
+        "{} {} {}",
+        tz.format(deployment.started_at),
+        deployment.kind.as_str(),
+        deployment.outcome.as_str()
+    );
+    if let Some(vm_id) = &deployment.vm_id {
+        let _ = write!(line, " VM {}", vm_id);
+    }
+    if let Some(previous) = &deployment.previous_vm_id {
+        let _ = write!(line, " (replaced {})", previous);
+    }
+    match (&deployment.previous_compose_hash, &deployment.compose_hash) {
+        (Some(old), Some(new)) => {
+            let _ = write!(line, ", {} -> {}", old, new);
+        }
+        (None, Some(new)) => {
+            let _ = write!(line, ", {}", new);
+        }
+        _ => {}
+    }
+    if let Some(image) = &deployment.image {
+        let _ = write!(line, ", image {}", image);
+    }
+    if let Some(duration) = deployment.duration_secs.filter(|secs| *secs > 0) {
+        let _ = write!(line, ", {}s", duration);
+    }
+    if let Some(error) = &deployment.error {
+        let _ = write!(line, ": {}", error);
+    }
+    println!("{}", line);
+}
+
+/// Midnight UTC of a `YYYY-MM-DD` date, or an RFC 3339 timestamp
+pub fn parse_since(since: &str) -> Result<u64> {
+    if let Ok(date) = NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        let midnight = date.and_hms_opt(0, 0, 0).context("Invalid date")?;
+        return Ok(midnight.and_utc().timestamp().max(0) as u64);
+    }
+    let at = DateTime::parse_from_rfc3339(since).context(format!(
+        "Invalid date '{}' (use YYYY-MM-DD or RFC 3339, e.g. 2025-03-01T00:00:00Z)",
+        since
+    ))?;
+    Ok(at.timestamp().max(0) as u64)
+}
+
+/// RFC 3339 in UTC, so spreadsheets and other hosts read the same instant
+fn rfc3339(unix_secs: u64) -> String {
+    DateTime::<Utc>::from_timestamp(unix_secs as i64, 0)
+        .map(|at| at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+        .unwrap_or_else(|| unix_secs.to_string())
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub fn to_csv(deployments: &[Deployment]) -> String {
+    let mut csv = CSV_COLUMNS.join(",");
+    csv.push('\n');
+    for d in deployments {
+        let optional = |value: &Option<String>| value.as_deref().map(csv_field).unwrap_or_default();
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            d.kind.as_str(),
+            d.outcome.as_str(),
+            rfc3339(d.started_at),
+            d.finished_at.map(rfc3339).unwrap_or_default(),
+            d.duration_secs.map(|s| s.to_string()).unwrap_or_default(),
+            optional(&d.vm_id),
+            optional(&d.previous_vm_id),
+            optional(&d.previous_compose_hash),
+            optional(&d.compose_hash),
+            optional(&d.image),
+            optional(&d.changelog),
+            optional(&d.error),
+            d.audit_seq,
+            optional(&d.api_url)
+        );
+    }
+    csv
+}
+
+/// `deployments` started at or after `since`, in `format`
+pub fn export(entries: &[AuditEntry], format: ExportFormat, since: Option<u64>) -> Result<String> {
+    let deployments: Vec<Deployment> = deployments(entries)
+        .into_iter()
+        .filter(|d| since.is_none_or(|since| d.started_at >= since))
+        .collect();
+    match format {
+        ExportFormat::Csv => Ok(to_csv(&deployments)),
+        ExportFormat::Json => {
+            serde_json::to_string_pretty(&deployments).context("Failed to serialize history")
+        }
+    }
+}
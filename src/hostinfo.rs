@@ -0,0 +1,196 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Host capability probing: CPU, virtualization support, hugepages, NUMA
+//! layout, and free resources. Every probe is best-effort; whatever cannot be
+//! read (e.g. on a non-Linux host or in a container) is reported as absent
+//! rather than failing the whole probe.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::paths;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HostInfo {
+    pub cpu: CpuInfo,
+    pub virtualization: Virtualization,
+    pub memory: MemoryInfo,
+    pub hugepages: HugepageInfo,
+    pub numa_nodes: Vec<NumaNode>,
+    /// Filesystem holding the launcher state, which normally also holds VM disks
+    pub disk: Option<DiskInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CpuInfo {
+    pub arch: String,
+    pub model: Option<String>,
+    pub logical_cpus: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Virtualization {
+    /// `/dev/kvm` is present
+    pub kvm: bool,
+    /// CPU advertises hardware virtualization (vmx or svm)
+    pub hardware_virt: bool,
+    /// KVM was loaded with TDX enabled
+    pub tdx: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryInfo {
+    pub total_mb: Option<u64>,
+    pub available_mb: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HugepageInfo {
+    pub total: u64,
+    pub free: u64,
+    pub page_size_kb: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NumaNode {
+    pub id: u32,
+    /// CPU list in kernel notation, e.g. `0-15,32-47`
+    pub cpus: String,
+    pub total_mb: Option<u64>,
+    pub free_mb: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiskInfo {
+    pub path: String,
+    pub total_gb: u64,
+    pub available_gb: u64,
+}
+
+impl HostInfo {
+    pub fn probe() -> Self {
+        let cpuinfo = read_trimmed("/proc/cpuinfo").unwrap_or_default();
+        let meminfo = parse_meminfo(&read_trimmed("/proc/meminfo").unwrap_or_default());
+        let kb_to_mb = |key: &str| meminfo.get(key).map(|kb| kb / 1024);
+
+        Self {
+            cpu: CpuInfo {
+                arch: std::env::consts::ARCH.to_string(),
+                model: cpuinfo_field(&cpuinfo, "model name"),
+                logical_cpus: std::thread::available_parallelism()
+                    .map(|n| n.get())
+                    .unwrap_or(1),
+            },
+            virtualization: Virtualization {
+                kvm: Path::new("/dev/kvm").exists(),
+                hardware_virt: cpuinfo_field(&cpuinfo, "flags")
+                    .map(|flags| flags.split_whitespace().any(|f| f == "vmx" || f == "svm"))
+                    .unwrap_or(false),
+                tdx: read_trimmed("/sys/module/kvm_intel/parameters/tdx")
+                    .is_some_and(|v| v == "Y" || v == "1"),
+            },
+            memory: MemoryInfo {
+                total_mb: kb_to_mb("MemTotal"),
+                available_mb: kb_to_mb("MemAvailable"),
+            },
+            hugepages: HugepageInfo {
+                total: meminfo.get("HugePages_Total").copied().unwrap_or(0),
+                free: meminfo.get("HugePages_Free").copied().unwrap_or(0),
+                page_size_kb: meminfo.get("Hugepagesize").copied(),
+            },
+            numa_nodes: probe_numa_nodes(),
+            disk: probe_disk(&paths::state_dir()),
+        }
+    }
+
+    /// One-line description for logs
+    pub fn summary(&self) -> String {
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+        format!(
+            "{}, {} CPUs, {} MB RAM available, KVM {}, TDX {}, {} NUMA node(s), {} free hugepages",
+            self.cpu.arch,
+            self.cpu.logical_cpus,
+            self.memory
+                .available_mb
+                .map(|mb| mb.to_string())
+                .unwrap_or_else(|| "?".to_string()),
+            yes_no(self.virtualization.kvm),
+            yes_no(self.virtualization.tdx),
+            self.numa_nodes.len(),
+            self.hugepages.free
+        )
+    }
+}
+
+fn read_trimmed(path: impl AsRef<Path>) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+/// First value of a `key : value` line in /proc/cpuinfo
+fn cpuinfo_field(cpuinfo: &str, key: &str) -> Option<String> {
+    cpuinfo.lines().find_map(|line| {
+        let (k, v) = line.split_once(':')?;
+        (k.trim() == key).then(|| v.trim().to_string())
+    })
+}
+
+/// Parse /proc/meminfo-style `Key: value [kB]` lines into numbers
+fn parse_meminfo(content: &str) -> BTreeMap<String, u64> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            let value = value.split_whitespace().next()?.parse().ok()?;
+            Some((key.trim().to_string(), value))
+        })
+        .collect()
+}
+
+fn probe_numa_nodes() -> Vec<NumaNode> {
+    let Ok(entries) = std::fs::read_dir("/sys/devices/system/node") else {
+        return Vec::new();
+    };
+    let mut nodes: Vec<NumaNode> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let id = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("node")?
+                .parse()
+                .ok()?;
+            let dir = entry.path();
+            // Per-node meminfo lines are prefixed with "Node <id>"
+            let meminfo: BTreeMap<String, u64> =
+                parse_meminfo(&read_trimmed(dir.join("meminfo")).unwrap_or_default())
+                    .into_iter()
+                    .filter_map(|(key, value)| Some((key.rsplit_once(' ')?.1.to_string(), value)))
+                    .collect();
+            Some(NumaNode {
+                id,
+                cpus: read_trimmed(dir.join("cpulist")).unwrap_or_default(),
+                total_mb: meminfo.get("MemTotal").map(|kb| kb / 1024),
+                free_mb: meminfo.get("MemFree").map(|kb| kb / 1024),
+            })
+        })
+        .collect();
+    nodes.sort_by_key(|node| node.id);
+    nodes
+}
+
+/// Space on the filesystem holding `path`, or its nearest existing ancestor
+fn probe_disk(path: &Path) -> Option<DiskInfo> {
+    let existing = path.ancestors().find(|p| p.exists())?;
+    const GB: u64 = 1024 * 1024 * 1024;
+    Some(DiskInfo {
+        path: existing.display().to_string(),
+        total_gb: fs2::total_space(existing).ok()? / GB,
+        available_gb: fs2::available_space(existing).ok()? / GB,
+    })
+}
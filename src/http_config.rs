@@ -0,0 +1,73 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! User agent and extra headers sent with platform API requests, e.g. tenant
+//! identifiers required by private mirrors, and the rate API responses are
+//! read at on constrained links.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HttpConfig {
+    /// Replaces the default `validator-auto-updater/<version> (host <id>)`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    /// Added to every platform API request
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Read API responses at no more than this many bytes per second
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes_per_sec: Option<u64>,
+}
+
+impl HttpConfig {
+    pub fn user_agent(&self) -> String {
+        self.user_agent.clone().unwrap_or_else(default_user_agent)
+    }
+
+    pub fn header_map(&self) -> Result<HeaderMap> {
+        let mut map = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let (name, value) = parse_header(name, value)?;
+            map.insert(name, value);
+        }
+        Ok(map)
+    }
+}
+
+pub fn parse_header(name: &str, value: &str) -> Result<(HeaderName, HeaderValue)> {
+    let header_name = HeaderName::from_bytes(name.as_bytes())
+        .context(format!("Invalid header name '{}'", name))?;
+    let header_value =
+        HeaderValue::from_str(value).context(format!("Invalid value for header '{}'", name))?;
+    Ok((header_name, header_value))
+}
+
+pub fn default_user_agent() -> String {
+    format!(
+        "{}/{} (host {})",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        host_id()
+    )
+}
+
+/// Stable, non-reversible host identifier derived from the machine id, so
+/// API operators can tell launchers apart without learning the machine id
+pub fn host_id() -> String {
+    let machine_id = std::fs::read_to_string("/etc/machine-id")
+        .or_else(|_| std::fs::read_to_string("/proc/sys/kernel/hostname"))
+        .unwrap_or_default();
+    let machine_id = machine_id.trim();
+    if machine_id.is_empty() {
+        return "unknown".to_string();
+    }
+    let digest = Sha256::digest(format!("validator-launcher:{}", machine_id).as_bytes());
+    hex::encode(&digest[..6])
+}
@@ -0,0 +1,68 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Library surface of the validator launcher: the updater itself, for
+//! embedding, plus tooling that needs to agree with the launcher bit-for-bit
+//! (e.g. predicting a validator's app_id).
+
+pub mod admin_api;
+#[cfg(feature = "metrics")]
+mod admin_server;
+mod alerting;
+pub mod allowed_envs;
+mod api_backoff;
+mod api_schema;
+pub mod audit;
+mod bandwidth;
+mod canary;
+mod capture;
+pub mod clock;
+pub mod compose_hash;
+mod compose_patch;
+mod conditional;
+#[cfg(feature = "tui")]
+mod config_schema;
+#[cfg(feature = "tui")]
+pub mod config_tui;
+pub mod defaults;
+mod deployment;
+mod disclosure;
+mod drift;
+pub mod endpoints;
+mod env_metadata;
+pub mod envelope;
+pub mod error_code;
+pub mod events;
+pub mod guest_exec;
+pub mod guest_metrics;
+mod health;
+pub mod history;
+pub mod hostinfo;
+pub mod http_config;
+mod limits;
+mod log_dedup;
+pub mod missing_env;
+mod mqtt;
+pub mod network;
+mod notify;
+pub mod paths;
+mod power;
+mod provisioning;
+mod push;
+pub mod reconcile;
+mod recovery;
+pub mod retention;
+pub mod rollout;
+mod state;
+#[cfg(feature = "metrics")]
+mod status_page;
+mod store;
+pub mod subscribe;
+mod timefmt;
+mod updater;
+pub mod utilization;
+mod validation;
+mod vmm_supervisor;
+
+pub use updater::*;
@@ -0,0 +1,92 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::sync::Mutex;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::log_dedup::DedupLog;
+use crate::VmParameters;
+
+/// The same violations are found on every poll until the API or the caps change
+static VIOLATIONS: Mutex<DedupLog> = Mutex::new(DedupLog::new("ALERT"));
+
+/// What to do when the API requests more resources than the local caps allow
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitPolicy {
+    /// Refuse to deploy the config
+    #[default]
+    Reject,
+    /// Deploy with the offending values lowered to the cap
+    Clamp,
+}
+
+/// Hard local caps on what the launcher may ever request from the VMM
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_vcpu: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_memory_mb: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_disk_gb: Option<u32>,
+    #[serde(default)]
+    pub policy: LimitPolicy,
+}
+
+impl ResourceLimits {
+    /// Check the requested parameters against the caps, clamping or rejecting per policy
+    pub fn apply(&self, mut params: VmParameters) -> Result<VmParameters> {
+        let mut violations = Vec::new();
+
+        let checks = [
+            ("vcpu", &mut params.vcpu, self.max_vcpu),
+            ("memory_mb", &mut params.memory, self.max_memory_mb),
+            ("disk_gb", &mut params.disk_size, self.max_disk_gb),
+        ];
+        for (name, value, cap) in checks {
+            let Some(cap) = cap else {
+                continue;
+            };
+            if *value > cap {
+                violations.push(format!("{}={} exceeds local cap {}", name, value, cap));
+                if self.policy == LimitPolicy::Clamp {
+                    *value = cap;
+                }
+            }
+        }
+
+        let mut log = VIOLATIONS.lock().unwrap();
+        if violations.is_empty() {
+            log.clear();
+            return Ok(params);
+        }
+
+        match self.policy {
+            LimitPolicy::Reject => {
+                log.error(format!(
+                    "platform API requested resources above local caps, refusing to deploy: {}",
+                    violations.join(", ")
+                ));
+                anyhow::bail!(
+                    "Requested VM resources exceed local caps: {}",
+                    violations.join(", ")
+                );
+            }
+            LimitPolicy::Clamp => {
+                log.warn(format!(
+                    "platform API requested resources above local caps, clamping: {}",
+                    violations.join(", ")
+                ));
+                Ok(params)
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.max_vcpu.is_none() && self.max_memory_mb.is_none() && self.max_disk_gb.is_none()
+    }
+}
@@ -0,0 +1,104 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::time::{Duration, Instant};
+
+use tracing::{error, info, warn, Level};
+
+/// How often a still-repeating error is summarized
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Collapses repeated identical errors: the first occurrence is logged in
+/// full, repeats are counted and summarized at most once per interval, and
+/// recovery is logged once the error stops.
+pub struct DedupLog {
+    label: &'static str,
+    current: Option<Repeat>,
+}
+
+struct Repeat {
+    message: String,
+    occurrences: u64,
+    suppressed: u64,
+    since: Instant,
+    last_logged: Instant,
+}
+
+impl DedupLog {
+    pub const fn new(label: &'static str) -> Self {
+        Self {
+            label,
+            current: None,
+        }
+    }
+
+    pub fn error(&mut self, message: String) {
+        self.record(Level::ERROR, message);
+    }
+
+    /// As `error`, for conditions that are logged as warnings
+    pub fn warn(&mut self, message: String) {
+        self.record(Level::WARN, message);
+    }
+
+    fn record(&mut self, level: Level, message: String) {
+        let now = Instant::now();
+        if let Some(repeat) = self.current.as_mut().filter(|r| r.message == message) {
+            repeat.occurrences += 1;
+            repeat.suppressed += 1;
+            if now.duration_since(repeat.last_logged) >= SUMMARY_INTERVAL {
+                log(
+                    level,
+                    format_args!(
+                        "{} still failing for {:?}: {} ({} occurrences suppressed)",
+                        self.label,
+                        now.duration_since(repeat.since),
+                        repeat.message,
+                        repeat.suppressed
+                    ),
+                );
+                repeat.suppressed = 0;
+                repeat.last_logged = now;
+            }
+            return;
+        }
+
+        if let Some(previous) = self.current.take() {
+            if previous.suppressed > 0 {
+                info!(
+                    "{}: previous error repeated {} more times before changing",
+                    self.label, previous.suppressed
+                );
+            }
+        }
+        log(level, format_args!("{}: {}", self.label, message));
+        self.current = Some(Repeat {
+            message,
+            occurrences: 1,
+            suppressed: 0,
+            since: now,
+            last_logged: now,
+        });
+    }
+
+    /// Note success, logging a recovery notice if an error was being repeated
+    pub fn clear(&mut self) {
+        if let Some(previous) = self.current.take() {
+            info!(
+                "{} recovered after {} consecutive failure(s) over {:?}",
+                self.label,
+                previous.occurrences,
+                previous.since.elapsed()
+            );
+        }
+    }
+}
+
+fn log(level: Level, message: std::fmt::Arguments) {
+    if level == Level::ERROR {
+        error!("{}", message);
+    } else {
+        warn!("{}", message);
+    }
+}
@@ -0,0 +1,124 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! What an update check does when the API requires an env key that has no
+//! local value. Keys fall into classes, and each class has its own action, so
+//! an operator can keep secrets strict while letting a missing optional key
+//! through.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::env_metadata::EnvMetadata;
+use crate::ComposeConfig;
+
+/// What to do about a missing value; when several keys are missing, the
+/// strictest of their actions applies to the update
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingEnvAction {
+    /// Log a warning and deploy without the value
+    Warn,
+    /// Leave the VM as it is and raise an alert instead of deploying
+    Hold,
+    /// Fail every update check until the value is set
+    #[default]
+    Fail,
+}
+
+/// Class of a required env key, from the most specific
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum KeyClass {
+    /// Keys the API marks as secret in `env_metadata`
+    Secret,
+    /// Keys listed in `required_env`
+    Required,
+    /// Keys listed in `provisioning.env_keys` only
+    Provisioning,
+}
+
+impl KeyClass {
+    pub const ALL: [KeyClass; 3] = [KeyClass::Secret, KeyClass::Required, KeyClass::Provisioning];
+
+    fn of(key: &str, config: &ComposeConfig, metadata: &EnvMetadata) -> Self {
+        if metadata.get(key).is_some_and(|meta| meta.secret) {
+            KeyClass::Secret
+        } else if config.required_env.iter().any(|k| k == key) {
+            KeyClass::Required
+        } else {
+            KeyClass::Provisioning
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MissingEnvPolicy {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<MissingEnvAction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required: Option<MissingEnvAction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provisioning: Option<MissingEnvAction>,
+}
+
+impl MissingEnvPolicy {
+    pub fn action(&self, class: KeyClass) -> MissingEnvAction {
+        match class {
+            KeyClass::Secret => self.secret,
+            KeyClass::Required => self.required,
+            KeyClass::Provisioning => self.provisioning,
+        }
+        .unwrap_or_default()
+    }
+
+    pub fn set(&mut self, class: KeyClass, action: MissingEnvAction) {
+        let slot = match class {
+            KeyClass::Secret => &mut self.secret,
+            KeyClass::Required => &mut self.required,
+            KeyClass::Provisioning => &mut self.provisioning,
+        };
+        *slot = Some(action);
+    }
+
+    /// Missing keys grouped by the action that applies to each
+    pub fn classify(
+        &self,
+        missing: &[String],
+        config: &ComposeConfig,
+        metadata: &EnvMetadata,
+    ) -> BTreeMap<MissingEnvAction, Vec<String>> {
+        let mut actions: BTreeMap<MissingEnvAction, Vec<String>> = BTreeMap::new();
+        for key in missing {
+            let action = self.action(KeyClass::of(key, config, metadata));
+            actions.entry(action).or_default().push(key.clone());
+        }
+        actions
+    }
+}
+
+impl fmt::Display for MissingEnvPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "secret {:?}, required {:?}, provisioning {:?}",
+            self.action(KeyClass::Secret),
+            self.action(KeyClass::Required),
+            self.action(KeyClass::Provisioning)
+        )
+    }
+}
@@ -0,0 +1,520 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Publishes status and transition events to an MQTT broker, for monitoring
+//! setups built around MQTT. Speaks just enough MQTT 3.1.1 for QoS 0
+//! publishes. One task publishes every message in order over a single
+//! connection, so the broker never sees two sessions with our client id.
+
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "notifications")]
+use {
+    crate::notify::{NotificationSink, TransitionEvent},
+    anyhow::{Context, Result},
+    serde_json::Value,
+    std::sync::atomic::{AtomicBool, Ordering},
+    std::sync::Mutex,
+    std::time::{Duration, Instant},
+    tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    tokio::net::TcpStream,
+    tokio::sync::mpsc,
+    tracing::{debug, info, warn},
+};
+
+use crate::{defaults, http_config};
+
+#[cfg(feature = "notifications")]
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Status is republished at least this often even when unchanged, so
+/// subscribers can tell a quiet launcher from a dead one
+#[cfg(feature = "notifications")]
+const STATUS_REFRESH: Duration = Duration::from_secs(60);
+#[cfg(feature = "notifications")]
+const KEEP_ALIVE_SECS: u16 = 60;
+/// Messages waiting for the broker; newer ones are dropped beyond this
+#[cfg(feature = "notifications")]
+const QUEUE_CAPACITY: usize = 256;
+#[cfg(feature = "notifications")]
+const RECONNECT_MIN: Duration = Duration::from_secs(1);
+#[cfg(feature = "notifications")]
+const RECONNECT_MAX: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MqttConfig {
+    /// `mqtt://host[:1883]`, or `mqtts://host[:8883]` for TLS
+    pub broker_url: String,
+    /// Topics are `<prefix>/status` and `<prefix>/events`; defaults to
+    /// `platform-validator/<host id>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topic_prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// PEM CA bundle for a broker with a private CA (mqtts only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_cert: Option<String>,
+}
+
+impl MqttConfig {
+    pub fn topic_prefix(&self) -> String {
+        self.topic_prefix
+            .clone()
+            .unwrap_or_else(|| format!("{}/{}", defaults::MQTT_TOPIC_ROOT, http_config::host_id()))
+    }
+
+    /// Broker and topics, without credentials
+    pub fn describe(&self) -> String {
+        format!("{} ({}/...)", self.broker_url, self.topic_prefix())
+    }
+}
+
+#[cfg(feature = "notifications")]
+struct Broker {
+    host: String,
+    port: u16,
+    tls: bool,
+}
+
+#[cfg(feature = "notifications")]
+impl Broker {
+    fn parse(url: &str) -> Result<Self> {
+        let parsed = reqwest::Url::parse(url).context(format!("Invalid broker URL '{}'", url))?;
+        let (tls, default_port) = match parsed.scheme() {
+            "mqtt" => (false, 1883),
+            "mqtts" => (true, 8883),
+            scheme => anyhow::bail!("Unsupported broker scheme '{}' (use mqtt or mqtts)", scheme),
+        };
+        let host = parsed
+            .host_str()
+            .context(format!("Broker URL '{}' has no host", url))?
+            .to_string();
+        Ok(Self {
+            host,
+            port: parsed.port().unwrap_or(default_port),
+            tls,
+        })
+    }
+}
+
+/// Check a config before it is saved
+#[cfg_attr(not(any(feature = "tui", feature = "notifications")), allow(dead_code))]
+pub fn validate(config: &MqttConfig) -> anyhow::Result<()> {
+    #[cfg(feature = "notifications")]
+    Broker::parse(&config.broker_url)?;
+    #[cfg(not(feature = "notifications"))]
+    let _ = config;
+    Ok(())
+}
+
+/// One QoS 0 message
+#[cfg(feature = "notifications")]
+struct Message {
+    topic: String,
+    payload: Vec<u8>,
+    retain: bool,
+}
+
+/// Publishes transition events, and the launcher status as a retained message
+#[cfg(feature = "notifications")]
+pub struct MqttSink {
+    config: MqttConfig,
+    /// Messages for the publishing task, which exits once this is dropped
+    queue: mpsc::Sender<Message>,
+    /// Whether a full queue was reported since it last had room
+    overflowed: AtomicBool,
+    /// Last status published, with the time it was sent
+    last_status: Mutex<Option<(String, Instant)>>,
+}
+
+#[cfg(feature = "notifications")]
+impl MqttSink {
+    pub fn new(config: MqttConfig) -> Result<Self> {
+        validate(&config)?;
+        if let Some(path) = &config.ca_cert {
+            std::fs::metadata(path).context(format!("Failed to read CA bundle {}", path))?;
+        }
+        let (queue, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        tokio::spawn(publisher(
+            config.clone(),
+            format!("validator-launcher-{}", http_config::host_id()),
+            receiver,
+        ));
+        Ok(Self {
+            config,
+            queue,
+            overflowed: AtomicBool::new(false),
+            last_status: Mutex::new(None),
+        })
+    }
+
+    fn send(&self, message: Message) {
+        match self.queue.try_send(message) {
+            Ok(()) => self.overflowed.store(false, Ordering::Relaxed),
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                if !self.overflowed.swap(true, Ordering::Relaxed) {
+                    warn!(
+                        "MQTT broker {} is not keeping up; dropping messages until it does",
+                        self.config.broker_url
+                    );
+                }
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {}
+        }
+    }
+}
+
+#[cfg(feature = "notifications")]
+impl NotificationSink for MqttSink {
+    fn notify(&self, event: &TransitionEvent) {
+        self.send(Message {
+            topic: format!("{}/events", self.config.topic_prefix()),
+            payload: event.to_json().to_string().into_bytes(),
+            retain: false,
+        });
+    }
+
+    fn status(&self, status: &Value) {
+        // The check timestamp changes every poll; only the rest counts as a change
+        let mut compared = status.clone();
+        if let Some(last_check) = compared
+            .get_mut("last_check")
+            .and_then(|c| c.as_object_mut())
+        {
+            last_check.remove("at");
+        }
+        let compared = compared.to_string();
+        {
+            let mut last = self.last_status.lock().unwrap();
+            if let Some((previous, at)) = last.as_ref() {
+                if *previous == compared && at.elapsed() < STATUS_REFRESH {
+                    return;
+                }
+            }
+            *last = Some((compared, Instant::now()));
+        }
+        self.send(Message {
+            topic: format!("{}/status", self.config.topic_prefix()),
+            payload: status.to_string().into_bytes(),
+            retain: true,
+        });
+    }
+}
+
+/// A connection to the broker, with or without TLS
+#[cfg(feature = "notifications")]
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+#[cfg(feature = "notifications")]
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// Publish queued messages in order, keeping one connection open and
+/// reconnecting with backoff when it fails. A message is retried until it is
+/// written, so an outage delays later ones instead of reordering them.
+#[cfg(feature = "notifications")]
+async fn publisher(config: MqttConfig, client_id: String, mut queue: mpsc::Receiver<Message>) {
+    let mut pending = None;
+    let mut delay = RECONNECT_MIN;
+    let mut failing = false;
+    loop {
+        if pending.is_none() {
+            pending = match queue.recv().await {
+                Some(message) => Some(message),
+                None => return,
+            };
+        }
+        let result = match connect(&config, &client_id).await {
+            Ok(stream) => {
+                if failing {
+                    info!("Reconnected to MQTT broker {}", config.broker_url);
+                    failing = false;
+                }
+                let connected_at = Instant::now();
+                let result = serve(stream, &mut pending, &mut queue).await;
+                if connected_at.elapsed() >= RECONNECT_MAX {
+                    delay = RECONNECT_MIN;
+                }
+                result
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            // The sink was dropped and every message sent
+            Ok(()) => return,
+            Err(e) if !failing => {
+                warn!(
+                    "MQTT broker {} unavailable, holding messages until it is back: {:#}",
+                    config.broker_url, e
+                );
+                failing = true;
+            }
+            Err(e) => debug!(
+                "MQTT broker {} still unavailable: {:#}",
+                config.broker_url, e
+            ),
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(RECONNECT_MAX);
+    }
+}
+
+/// Connect and start a clean session
+#[cfg(feature = "notifications")]
+async fn connect(config: &MqttConfig, client_id: &str) -> Result<Box<dyn Stream>> {
+    let broker = Broker::parse(&config.broker_url)?;
+    let tcp = tokio::time::timeout(
+        CONNECT_TIMEOUT,
+        TcpStream::connect((broker.host.as_str(), broker.port)),
+    )
+    .await
+    .context("Timed out connecting")??;
+    let mut stream: Box<dyn Stream> = if broker.tls {
+        let mut builder = tokio_native_tls::native_tls::TlsConnector::builder();
+        if let Some(path) = &config.ca_cert {
+            let pem = std::fs::read(path).context(format!("Failed to read CA bundle {}", path))?;
+            let cert = tokio_native_tls::native_tls::Certificate::from_pem(&pem)
+                .context(format!("Invalid CA bundle {}", path))?;
+            builder.add_root_certificate(cert);
+        }
+        let connector = tokio_native_tls::TlsConnector::from(builder.build()?);
+        let tls = tokio::time::timeout(CONNECT_TIMEOUT, connector.connect(&broker.host, tcp))
+            .await
+            .context("Timed out during TLS handshake")??;
+        Box::new(tls)
+    } else {
+        Box::new(tcp)
+    };
+
+    write(&mut stream, &connect_packet(config, client_id)).await?;
+    let mut connack = [0u8; 4];
+    tokio::time::timeout(CONNECT_TIMEOUT, stream.read_exact(&mut connack))
+        .await
+        .context("Timed out waiting for CONNACK")??;
+    if connack[0] != 0x20 {
+        anyhow::bail!("Broker did not acknowledge the connection");
+    }
+    match connack[3] {
+        0 => Ok(stream),
+        4 | 5 => anyhow::bail!("Broker refused the credentials"),
+        code => anyhow::bail!("Broker refused the connection (code {})", code),
+    }
+}
+
+/// Publish `pending` and then each queued message as it arrives, pinging
+/// while idle. Returns once the queue is closed and drained.
+#[cfg(feature = "notifications")]
+async fn serve(
+    stream: Box<dyn Stream>,
+    pending: &mut Option<Message>,
+    queue: &mut mpsc::Receiver<Message>,
+) -> Result<()> {
+    let idle = Duration::from_secs(KEEP_ALIVE_SECS as u64 / 2);
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let mut last_read = Instant::now();
+    let mut buf = [0u8; 64];
+    loop {
+        // Cleared only once written, so a failed write is retried on the next connection
+        if let Some(message) = pending.as_ref() {
+            write(&mut writer, &publish_packet(message)).await?;
+            *pending = None;
+        }
+        tokio::select! {
+            message = queue.recv() => match message {
+                Some(message) => *pending = Some(message),
+                None => {
+                    // DISCONNECT
+                    write(&mut writer, &[0xE0, 0x00]).await?;
+                    writer.shutdown().await.ok();
+                    return Ok(());
+                }
+            },
+            // Only PINGRESP arrives for QoS 0 publishes
+            read = reader.read(&mut buf) => {
+                if read? == 0 {
+                    anyhow::bail!("Broker closed the connection");
+                }
+                last_read = Instant::now();
+            }
+            _ = tokio::time::sleep(idle) => {
+                if last_read.elapsed() > Duration::from_secs(KEEP_ALIVE_SECS.into()) {
+                    anyhow::bail!("Broker stopped answering pings");
+                }
+                // PINGREQ
+                write(&mut writer, &[0xC0, 0x00]).await?;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "notifications")]
+async fn write<W: AsyncWrite + Unpin>(writer: &mut W, bytes: &[u8]) -> Result<()> {
+    tokio::time::timeout(CONNECT_TIMEOUT, writer.write_all(bytes))
+        .await
+        .context("Timed out writing to the broker")??;
+    Ok(())
+}
+
+#[cfg(feature = "notifications")]
+fn push_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+/// Fixed header: packet type and flags, then the variable-length remaining length
+#[cfg(feature = "notifications")]
+fn packet(first_byte: u8, body: Vec<u8>) -> Vec<u8> {
+    let mut packet = vec![first_byte];
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    packet.extend(body);
+    packet
+}
+
+#[cfg(feature = "notifications")]
+fn connect_packet(config: &MqttConfig, client_id: &str) -> Vec<u8> {
+    let mut flags = 0x02; // clean session
+    if config.username.is_some() {
+        flags |= 0x80;
+    }
+    if config.password.is_some() {
+        flags |= 0x40;
+    }
+    let mut body = Vec::new();
+    push_str(&mut body, "MQTT");
+    body.push(4); // protocol level 3.1.1
+    body.push(flags);
+    body.extend_from_slice(&KEEP_ALIVE_SECS.to_be_bytes());
+    push_str(&mut body, client_id);
+    if let Some(username) = &config.username {
+        push_str(&mut body, username);
+    }
+    if let Some(password) = &config.password {
+        push_str(&mut body, password);
+    }
+    packet(0x10, body)
+}
+
+#[cfg(feature = "notifications")]
+fn publish_packet(message: &Message) -> Vec<u8> {
+    let mut body = Vec::new();
+    push_str(&mut body, &message.topic);
+    body.extend_from_slice(&message.payload);
+    packet(0x30 | u8::from(message.retain), body)
+}
+
+#[cfg(all(test, feature = "notifications"))]
+mod tests {
+    use super::*;
+
+    fn config(broker_url: &str) -> MqttConfig {
+        MqttConfig {
+            broker_url: broker_url.to_string(),
+            topic_prefix: Some("lab".to_string()),
+            username: None,
+            password: None,
+            ca_cert: None,
+        }
+    }
+
+    #[test]
+    fn remaining_length_is_variable_length() {
+        let header = |len: usize| {
+            let packet = packet(0x30, vec![0; len]);
+            packet[..packet.len() - len].to_vec()
+        };
+        assert_eq!(header(0), [0x30, 0x00]);
+        assert_eq!(header(127), [0x30, 0x7F]);
+        assert_eq!(header(128), [0x30, 0x80, 0x01]);
+        assert_eq!(header(16_383), [0x30, 0xFF, 0x7F]);
+        assert_eq!(header(16_384), [0x30, 0x80, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn connect_packet_encodes_credentials() {
+        let mut config = config("mqtt://broker");
+        assert_eq!(
+            connect_packet(&config, "c"),
+            [0x10, 13, 0, 4, b'M', b'Q', b'T', b'T', 4, 0x02, 0, 60, 0, 1, b'c']
+        );
+        config.username = Some("u".to_string());
+        config.password = Some("p".to_string());
+        assert_eq!(
+            connect_packet(&config, "c"),
+            [
+                0x10, 19, 0, 4, b'M', b'Q', b'T', b'T', 4, 0xC2, 0, 60, 0, 1, b'c', 0, 1, b'u', 0,
+                1, b'p'
+            ]
+        );
+    }
+
+    #[test]
+    fn publish_packet_sets_retain() {
+        let message = Message {
+            topic: "t".to_string(),
+            payload: b"x".to_vec(),
+            retain: true,
+        };
+        assert_eq!(publish_packet(&message), [0x31, 4, 0, 1, b't', b'x']);
+    }
+
+    /// One packet from the client: its first byte and body
+    async fn read_packet(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+        let first = stream.read_u8().await.unwrap();
+        let (mut len, mut shift) = (0usize, 0);
+        loop {
+            let byte = stream.read_u8().await.unwrap();
+            len |= ((byte & 0x7F) as usize) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut body = vec![0; len];
+        stream.read_exact(&mut body).await.unwrap();
+        (first, body)
+    }
+
+    #[tokio::test]
+    async fn publishes_in_order_over_one_connection() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("mqtt://{}", listener.local_addr().unwrap());
+        let (queue, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        for n in 0..3 {
+            queue
+                .send(Message {
+                    topic: "t".to_string(),
+                    payload: vec![b'0' + n],
+                    retain: false,
+                })
+                .await
+                .unwrap();
+        }
+        let task = tokio::spawn(publisher(config(&url), "c".to_string(), receiver));
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        assert_eq!(read_packet(&mut stream).await.0, 0x10);
+        stream.write_all(&[0x20, 0x02, 0x00, 0x00]).await.unwrap();
+        let mut payloads = Vec::new();
+        for _ in 0..3 {
+            let (first, body) = read_packet(&mut stream).await;
+            assert_eq!(first, 0x30);
+            payloads.push(*body.last().unwrap());
+        }
+        assert_eq!(payloads, b"012");
+
+        drop(queue);
+        assert_eq!(read_packet(&mut stream).await, (0xE0, vec![]));
+        task.await.unwrap();
+    }
+}
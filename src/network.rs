@@ -0,0 +1,41 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Named deployments of the platform. Each preset bundles the endpoints of
+//! one network, so operators pick `mainnet`, `testnet`, or `devnet` instead
+//! of assembling URLs by hand.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    #[default]
+    Mainnet,
+    Testnet,
+    Devnet,
+}
+
+impl Network {
+    /// Where the compose config of the validator VM is published
+    pub fn api_url(self) -> &'static str {
+        match self {
+            Network::Mainnet => "https://api.platform.network/config/compose/validator_vm",
+            Network::Testnet => "https://api.testnet.platform.network/config/compose/validator_vm",
+            Network::Devnet => "https://api.devnet.platform.network/config/compose/validator_vm",
+        }
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Devnet => "devnet",
+        })
+    }
+}
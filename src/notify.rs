@@ -0,0 +1,424 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "notifications")]
+use anyhow::Context;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "notifications")]
+use serde_json::json;
+use tracing::{info, warn};
+
+#[cfg(feature = "notifications")]
+use crate::unix_now;
+use crate::{alerting, mqtt};
+
+/// Where state-transition notifications are delivered
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationConfig {
+    /// URLs that receive a JSON POST for every transition
+    #[serde(default)]
+    pub webhooks: Vec<String>,
+    /// Broker that receives transitions and the launcher status
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mqtt: Option<mqtt::MqttConfig>,
+    /// PagerDuty incidents, one per failure condition
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pagerduty: Option<alerting::PagerDutyConfig>,
+    /// Opsgenie alerts, one per failure condition
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opsgenie: Option<alerting::OpsgenieConfig>,
+}
+
+/// Why checks are failing; a failure keeps its condition while the cause
+/// persists, and alerting services deduplicate on it
+pub mod conditions {
+    /// The VMM does not answer
+    pub const VMM_UNREACHABLE: &str = "vmm-unreachable";
+    /// The VM keeps failing and recreation is held off
+    pub const VM_CRASHLOOP: &str = "vm-crashloop";
+    /// The VM was recreated too often and the launcher is degraded
+    pub const VM_FLAPPING: &str = "vm-flapping";
+    /// A VM is not created because the host clock is off
+    pub const CLOCK_DRIFT: &str = "clock-drift";
+    /// A deployment is held until required env values are set
+    pub const ENV_MISSING: &str = "env-missing";
+    /// An operator health check fails
+    pub const HEALTH_CHECK_FAILING: &str = "health-check-failing";
+    /// Any other failure to check for or apply an update
+    pub const UPDATE_CHECK_FAILING: &str = "update-check-failing";
+}
+
+/// A failed check, with its condition from [`conditions`]
+#[derive(Debug, Clone)]
+pub struct CheckFailure {
+    pub condition: &'static str,
+    /// Stable error code, e.g. `E_VMM_UNREACHABLE`
+    pub code: &'static str,
+    pub error: String,
+}
+
+/// A change worth telling an operator about. Steady state (still healthy,
+/// still failing) never produces an event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransitionEvent {
+    /// Checks started failing, or kept failing for a different reason
+    Failing {
+        error: String,
+        condition: &'static str,
+        /// Stable error code of `error`
+        code: &'static str,
+        /// Condition of the failure this one superseded, which has cleared
+        replaces: Option<&'static str>,
+        /// Operator notes on the current deployment
+        annotations: Vec<String>,
+    },
+    /// Checks succeed again after failing
+    Recovered {
+        failures: u64,
+        duration: Duration,
+        condition: &'static str,
+    },
+    /// A new or resized VM is now running the desired deployment
+    UpdateApplied {
+        vm_id: String,
+        compose_hash: String,
+        changelog: Option<String>,
+    },
+    /// An update is waiting on the operator (observe mode)
+    UpdatePending {
+        plan: String,
+        changelog: Option<String>,
+        annotations: Vec<String>,
+    },
+}
+
+impl TransitionEvent {
+    #[cfg(feature = "notifications")]
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TransitionEvent::Failing { .. } => "failing",
+            TransitionEvent::Recovered { .. } => "recovered",
+            TransitionEvent::UpdateApplied { .. } => "update_applied",
+            TransitionEvent::UpdatePending { .. } => "update_pending",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        let message = match self {
+            TransitionEvent::Failing { error, code, .. } => {
+                format!("Validator launcher is failing [{}]: {}", code, error)
+            }
+            TransitionEvent::Recovered {
+                failures, duration, ..
+            } => format!(
+                "Validator launcher recovered after {} failed check(s) over {:?}",
+                failures, duration
+            ),
+            TransitionEvent::UpdateApplied {
+                vm_id,
+                compose_hash,
+                ..
+            } => format!(
+                "Validator VM {} updated to compose hash {}",
+                vm_id, compose_hash
+            ),
+            TransitionEvent::UpdatePending { plan, .. } => {
+                format!("Validator update pending: would {}", plan)
+            }
+        };
+        match self.annotations() {
+            [] => message,
+            notes => format!("{} (operator notes: {})", message, notes.join("; ")),
+        }
+    }
+
+    /// Operator notes on the deployment, for the events an operator acts on
+    pub fn annotations(&self) -> &[String] {
+        match self {
+            TransitionEvent::Failing { annotations, .. }
+            | TransitionEvent::UpdatePending { annotations, .. } => annotations,
+            _ => &[],
+        }
+    }
+
+    /// Failure condition the event opens or clears
+    #[cfg(feature = "notifications")]
+    pub fn condition(&self) -> Option<&'static str> {
+        match self {
+            TransitionEvent::Failing { condition, .. }
+            | TransitionEvent::Recovered { condition, .. } => Some(condition),
+            _ => None,
+        }
+    }
+
+    /// Description of the update, for the events that concern one
+    pub fn changelog(&self) -> Option<&str> {
+        match self {
+            TransitionEvent::UpdateApplied { changelog, .. }
+            | TransitionEvent::UpdatePending { changelog, .. } => changelog.as_deref(),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "notifications")]
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut body = json!({
+            "event": self.kind(),
+            "message": self.message(),
+            "timestamp": unix_now(),
+        });
+        if let Some(condition) = self.condition() {
+            body["condition"] = json!(condition);
+        }
+        if let TransitionEvent::Failing { code, .. } = self {
+            body["code"] = json!(code);
+        }
+        if let Some(changelog) = self.changelog() {
+            body["changelog"] = json!(changelog);
+        }
+        if !self.annotations().is_empty() {
+            body["annotations"] = json!(self.annotations());
+        }
+        body
+    }
+}
+
+/// A destination for transition events. Delivery must not block the caller.
+pub trait NotificationSink: Send + Sync {
+    fn notify(&self, event: &TransitionEvent);
+
+    /// The launcher status after each check, for sinks that track it
+    fn status(&self, _status: &serde_json::Value) {}
+
+    /// The first check since start succeeded, so nothing a previous run
+    /// reported as failing still is
+    fn all_clear(&self) {}
+}
+
+/// POSTs the event as JSON to a URL
+#[cfg(feature = "notifications")]
+pub struct WebhookSink {
+    url: String,
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "notifications")]
+impl WebhookSink {
+    pub fn new(url: String) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .context("Failed to create webhook client")?;
+        Ok(Self { url, client })
+    }
+}
+
+#[cfg(feature = "notifications")]
+impl NotificationSink for WebhookSink {
+    fn notify(&self, event: &TransitionEvent) {
+        let request = self.client.post(&self.url).json(&event.to_json());
+        let url = self.url.clone();
+        tokio::spawn(async move {
+            match request.send().await {
+                Ok(response) if response.status().is_success() => {}
+                Ok(response) => warn!("Webhook {} returned status {}", url, response.status()),
+                Err(e) => warn!("Failed to deliver webhook to {}: {}", url, e),
+            }
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Health {
+    Healthy,
+    Failing {
+        since: Instant,
+        failures: u64,
+        condition: &'static str,
+    },
+}
+
+/// Tracks health across checks and fans transitions out to the sinks
+pub struct Notifier {
+    sinks: Vec<Box<dyn NotificationSink>>,
+    health: Option<Health>,
+    /// Operator notes on the current deployment, attached to alerts
+    annotations: Vec<String>,
+}
+
+impl Notifier {
+    pub fn from_config(config: Option<&NotificationConfig>) -> Self {
+        #[cfg_attr(not(feature = "notifications"), allow(unused_mut))]
+        let mut sinks: Vec<Box<dyn NotificationSink>> = Vec::new();
+        for url in config.map(|c| c.webhooks.as_slice()).unwrap_or_default() {
+            #[cfg(feature = "notifications")]
+            match WebhookSink::new(url.clone()) {
+                Ok(sink) => sinks.push(Box::new(sink)),
+                Err(e) => warn!("Skipping webhook {}: {:#}", url, e),
+            }
+            #[cfg(not(feature = "notifications"))]
+            warn!(
+                "Skipping webhook {}: this build lacks the `notifications` feature",
+                url
+            );
+        }
+        if let Some(mqtt) = config.and_then(|c| c.mqtt.clone()) {
+            #[cfg(feature = "notifications")]
+            match mqtt::MqttSink::new(mqtt) {
+                Ok(sink) => sinks.push(Box::new(sink)),
+                Err(e) => warn!("Skipping MQTT publishing: {:#}", e),
+            }
+            #[cfg(not(feature = "notifications"))]
+            warn!(
+                "Not publishing to MQTT broker {}: this build lacks the `notifications` feature",
+                mqtt.broker_url
+            );
+        }
+        if let Some(pagerduty) = config.and_then(|c| c.pagerduty.clone()) {
+            #[cfg(feature = "notifications")]
+            match alerting::PagerDutySink::new(pagerduty) {
+                Ok(sink) => sinks.push(Box::new(sink)),
+                Err(e) => warn!("Skipping PagerDuty: {:#}", e),
+            }
+            #[cfg(not(feature = "notifications"))]
+            {
+                let _ = pagerduty;
+                warn!("Not alerting PagerDuty: this build lacks the `notifications` feature");
+            }
+        }
+        if let Some(opsgenie) = config.and_then(|c| c.opsgenie.clone()) {
+            #[cfg(feature = "notifications")]
+            match alerting::OpsgenieSink::new(opsgenie) {
+                Ok(sink) => sinks.push(Box::new(sink)),
+                Err(e) => warn!("Skipping Opsgenie: {:#}", e),
+            }
+            #[cfg(not(feature = "notifications"))]
+            {
+                let _ = opsgenie;
+                warn!("Not alerting Opsgenie: this build lacks the `notifications` feature");
+            }
+        }
+        Self {
+            sinks,
+            health: None,
+            annotations: Vec::new(),
+        }
+    }
+
+    /// Replace the sinks, keeping the current health so a reload does not
+    /// re-announce an ongoing failure
+    pub fn reconfigure(&mut self, config: Option<&NotificationConfig>) {
+        self.sinks = Self::from_config(config).sinks;
+    }
+
+    pub fn set_annotations(&mut self, annotations: Vec<String>) {
+        self.annotations = annotations;
+    }
+
+    /// Feed the outcome of one check; only transitions are emitted
+    pub fn record_check(&mut self, result: Result<(), CheckFailure>) {
+        let next = match (self.health, result) {
+            (
+                Some(Health::Failing {
+                    since,
+                    failures,
+                    condition,
+                }),
+                Err(failure),
+            ) => {
+                if failure.condition != condition {
+                    self.emit(TransitionEvent::Failing {
+                        error: failure.error,
+                        condition: failure.condition,
+                        code: failure.code,
+                        replaces: Some(condition),
+                        annotations: self.annotations.clone(),
+                    });
+                }
+                Health::Failing {
+                    since,
+                    failures: failures + 1,
+                    condition: failure.condition,
+                }
+            }
+            (_, Err(failure)) => {
+                self.emit(TransitionEvent::Failing {
+                    error: failure.error,
+                    condition: failure.condition,
+                    code: failure.code,
+                    replaces: None,
+                    annotations: self.annotations.clone(),
+                });
+                Health::Failing {
+                    since: Instant::now(),
+                    failures: 1,
+                    condition: failure.condition,
+                }
+            }
+            (
+                Some(Health::Failing {
+                    since,
+                    failures,
+                    condition,
+                }),
+                Ok(()),
+            ) => {
+                self.emit(TransitionEvent::Recovered {
+                    failures,
+                    duration: since.elapsed(),
+                    condition,
+                });
+                Health::Healthy
+            }
+            (None, Ok(())) => {
+                for sink in &self.sinks {
+                    sink.all_clear();
+                }
+                Health::Healthy
+            }
+            (_, Ok(())) => Health::Healthy,
+        };
+        self.health = Some(next);
+    }
+
+    pub fn update_applied(&self, vm_id: &str, compose_hash: &str, changelog: Option<&str>) {
+        self.emit(TransitionEvent::UpdateApplied {
+            vm_id: vm_id.to_string(),
+            compose_hash: compose_hash.to_string(),
+            changelog: changelog.map(String::from),
+        });
+    }
+
+    pub fn update_pending(&self, plan: &str, changelog: Option<&str>) {
+        self.emit(TransitionEvent::UpdatePending {
+            plan: plan.to_string(),
+            changelog: changelog.map(String::from),
+            annotations: self.annotations.clone(),
+        });
+    }
+
+    /// Hand the current status to the sinks; `status` is only built if one is configured
+    pub fn publish_status(&self, status: impl FnOnce() -> serde_json::Value) {
+        if self.sinks.is_empty() {
+            return;
+        }
+        let status = status();
+        for sink in &self.sinks {
+            sink.status(&status);
+        }
+    }
+
+    fn emit(&self, event: TransitionEvent) {
+        match event.changelog() {
+            Some(changelog) => info!("Transition: {} ({})", event.message(), changelog),
+            None => info!("Transition: {}", event.message()),
+        }
+        for sink in &self.sinks {
+            sink.notify(&event);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Location of the platform config file and the state directory.
+//!
+//! Production hosts are Linux and use the fixed system paths. Elsewhere the
+//! defaults are per-user directories, so the CLI can be developed on macOS
+//! and Windows without root. Either can be overridden with `--config` /
+//! `--state-dir` or the matching environment variables.
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+
+use crate::{PLATFORM_CONFIG_PATH, STATE_DIR};
+
+/// Environment variable overriding the platform config file
+pub const CONFIG_ENV: &str = "PLATFORM_VALIDATOR_CONFIG";
+/// Environment variable overriding the state directory
+pub const STATE_DIR_ENV: &str = "PLATFORM_VALIDATOR_STATE_DIR";
+
+#[derive(Default)]
+struct Overrides {
+    config: Option<PathBuf>,
+    state_dir: Option<PathBuf>,
+}
+
+static OVERRIDES: OnceLock<Overrides> = OnceLock::new();
+
+/// Apply command-line overrides; only the first call has an effect, so call
+/// it before anything reads a path
+pub fn set_overrides(config: Option<PathBuf>, state_dir: Option<PathBuf>) {
+    let _ = OVERRIDES.set(Overrides { config, state_dir });
+}
+
+fn from_env(key: &str) -> Option<PathBuf> {
+    std::env::var_os(key)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Create the state directory if needed
+pub fn ensure_state_dir() -> Result<()> {
+    let dir = state_dir();
+    std::fs::create_dir_all(&dir).context(format!("Failed to create {}", dir.display()))
+}
+
+/// Platform config file: `--config`, then `PLATFORM_VALIDATOR_CONFIG`, then the platform default
+pub fn config_path() -> PathBuf {
+    OVERRIDES
+        .get()
+        .and_then(|o| o.config.clone())
+        .or_else(|| from_env(CONFIG_ENV))
+        .unwrap_or_else(default_config_path)
+}
+
+/// State directory: `--state-dir`, then `PLATFORM_VALIDATOR_STATE_DIR`, then the platform default
+pub fn state_dir() -> PathBuf {
+    OVERRIDES
+        .get()
+        .and_then(|o| o.state_dir.clone())
+        .or_else(|| from_env(STATE_DIR_ENV))
+        .unwrap_or_else(default_state_dir)
+}
+
+#[cfg(target_os = "linux")]
+fn default_config_path() -> PathBuf {
+    PathBuf::from(PLATFORM_CONFIG_PATH)
+}
+
+#[cfg(target_os = "linux")]
+fn default_state_dir() -> PathBuf {
+    PathBuf::from(STATE_DIR)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn default_config_path() -> PathBuf {
+    user_dir()
+        .map(|dir| dir.join("config.json"))
+        .unwrap_or_else(|| PathBuf::from(PLATFORM_CONFIG_PATH))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn default_state_dir() -> PathBuf {
+    user_dir()
+        .map(|dir| dir.join("state"))
+        .unwrap_or_else(|| PathBuf::from(STATE_DIR))
+}
+
+/// Per-user application directory on development platforms
+#[cfg(target_os = "macos")]
+fn user_dir() -> Option<PathBuf> {
+    Some(from_env("HOME")?.join("Library/Application Support/platform-validator"))
+}
+
+#[cfg(windows)]
+fn user_dir() -> Option<PathBuf> {
+    Some(from_env("APPDATA")?.join("platform-validator"))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn user_dir() -> Option<PathBuf> {
+    from_env("XDG_CONFIG_HOME")
+        .or_else(|| Some(from_env("HOME")?.join(".config")))
+        .map(|dir| dir.join("platform-validator"))
+}
@@ -0,0 +1,164 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Host power events from systemd-logind. Inhibitor locks are taken with
+//! `systemd-inhibit` and shutdown/sleep announcements read from `gdbus
+//! monitor`, so no D-Bus library is linked. Where logind or the tools are
+//! missing, both are quietly unavailable.
+
+use std::fmt;
+use std::process::Stdio;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc;
+use tracing::debug;
+
+fn default_inhibit_updates() -> bool {
+    true
+}
+
+/// What the launcher does around host shutdown and suspend
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PowerPolicy {
+    /// Block shutdown and suspend while the VM is being recreated or resized
+    #[serde(default = "default_inhibit_updates")]
+    pub inhibit_updates: bool,
+    /// Stop the VM through the VMM before the host shuts down
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stop_vm_on_shutdown: bool,
+}
+
+impl Default for PowerPolicy {
+    fn default() -> Self {
+        Self {
+            inhibit_updates: default_inhibit_updates(),
+            stop_vm_on_shutdown: false,
+        }
+    }
+}
+
+impl fmt::Display for PowerPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} shutdown during updates, {} the VM on shutdown",
+            if self.inhibit_updates {
+                "block"
+            } else {
+                "allow"
+            },
+            if self.stop_vm_on_shutdown {
+                "stop"
+            } else {
+                "leave"
+            }
+        )
+    }
+}
+
+/// A logind inhibitor lock, released when dropped
+pub struct Inhibitor {
+    _child: tokio::process::Child,
+}
+
+/// Take an inhibitor lock for `what` (e.g. `shutdown:sleep`) in `mode`
+/// `block` or `delay`. None where logind or `systemd-inhibit` is missing.
+pub fn inhibit(what: &str, mode: &str, why: &str) -> Option<Inhibitor> {
+    let child = tokio::process::Command::new("systemd-inhibit")
+        .arg(format!("--what={}", what))
+        .arg(format!("--mode={}", mode))
+        .arg("--who=validator-launcher")
+        .arg(format!("--why={}", why))
+        .args(["sleep", "infinity"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn();
+    match child {
+        Ok(child) => Some(Inhibitor { _child: child }),
+        Err(e) => {
+            debug!("No {} inhibitor lock: {}", mode, e);
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerEvent {
+    Shutdown,
+    Sleep,
+    Resume,
+}
+
+impl PowerEvent {
+    /// The event in a `gdbus monitor` line, e.g.
+    /// `/org/freedesktop/login1: org.freedesktop.login1.Manager.PrepareForShutdown (true,)`
+    fn parse(line: &str) -> Option<Self> {
+        let (_, signal) = line.split_once("org.freedesktop.login1.Manager.")?;
+        let starting = signal.contains("(true");
+        match signal.split_whitespace().next()? {
+            "PrepareForShutdown" if starting => Some(Self::Shutdown),
+            "PrepareForSleep" if starting => Some(Self::Sleep),
+            "PrepareForSleep" => Some(Self::Resume),
+            _ => None,
+        }
+    }
+}
+
+/// Host shutdown and sleep announcements, for as long as the returned
+/// receiver lives. It yields nothing where logind cannot be watched.
+pub fn watch() -> mpsc::Receiver<PowerEvent> {
+    let (sender, receiver) = mpsc::channel(8);
+    let child = tokio::process::Command::new("gdbus")
+        .args([
+            "monitor",
+            "--system",
+            "--dest",
+            "org.freedesktop.login1",
+            "--object-path",
+            "/org/freedesktop/login1",
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            debug!("Not watching host power events: {}", e);
+            return receiver;
+        }
+    };
+    let Some(stdout) = child.stdout.take() else {
+        return receiver;
+    };
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(stdout).lines();
+        loop {
+            tokio::select! {
+                line = lines.next_line() => match line {
+                    Ok(Some(line)) => {
+                        if let Some(event) = PowerEvent::parse(&line) {
+                            if sender.send(event).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    Ok(None) | Err(_) => break,
+                },
+                _ = sender.closed() => return,
+            }
+        }
+        if let Ok(status) = child.wait().await {
+            debug!(
+                "Stopped watching host power events: gdbus monitor exited with {}",
+                status
+            );
+        }
+    });
+    receiver
+}
@@ -0,0 +1,229 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::defaults;
+use crate::drift::format_port;
+use crate::log_dedup::DedupLog;
+use crate::VmParameters;
+
+/// The resource parameters of a deployment that the compose hash does not
+/// cover. Kept in launcher state so provisioning changes are not ignored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProvisioningFingerprint {
+    pub vcpu: u32,
+    pub memory: u32,
+    pub disk_size: u32,
+    /// Normalized and sorted, see `drift::format_port`
+    pub ports: Vec<String>,
+    pub hugepages: bool,
+    pub pin_numa: bool,
+    /// Digest of the effective user_config; absent in state written before it was tracked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_config_digest: Option<String>,
+}
+
+/// How a provisioning change is applied to the running VM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdatePath {
+    /// Stop the VM, resize it, and start it again
+    Resize,
+    /// Remove the VM and create it with the new parameters
+    Recreate,
+}
+
+impl ProvisioningFingerprint {
+    pub fn from_params(params: &VmParameters) -> Self {
+        let mut ports: Vec<String> = params
+            .ports
+            .iter()
+            .map(|p| {
+                format_port(
+                    &p.protocol,
+                    p.host_address.as_deref(),
+                    p.host_port as u64,
+                    p.vm_port as u64,
+                )
+            })
+            .collect();
+        ports.sort();
+        Self {
+            vcpu: params.vcpu,
+            memory: params.memory,
+            disk_size: params.disk_size,
+            ports,
+            hugepages: params.hugepages,
+            pin_numa: params.pin_numa,
+            user_config_digest: Some(hex::encode(Sha256::digest(params.user_config.as_bytes()))),
+        }
+    }
+
+    /// Fill fields an older launcher did not record from `current`, so they
+    /// are taken as the baseline rather than reported as changed
+    pub fn backfill(&mut self, current: &Self) {
+        if self.user_config_digest.is_none() {
+            self.user_config_digest = current.user_config_digest.clone();
+        }
+    }
+
+    /// Short digest for logs
+    pub fn digest(&self) -> String {
+        let canonical = serde_json::to_vec(self).unwrap_or_default();
+        hex::encode(&Sha256::digest(canonical)[..8])
+    }
+
+    /// Names of the fields that differ from `previous`
+    pub fn changed_fields(&self, previous: &Self) -> Vec<&'static str> {
+        let mut fields = Vec::new();
+        if self.vcpu != previous.vcpu {
+            fields.push("vcpu");
+        }
+        if self.memory != previous.memory {
+            fields.push("memory");
+        }
+        if self.disk_size != previous.disk_size {
+            fields.push("disk_size");
+        }
+        if self.ports != previous.ports {
+            fields.push("ports");
+        }
+        if self.hugepages != previous.hugepages {
+            fields.push("hugepages");
+        }
+        if self.pin_numa != previous.pin_numa {
+            fields.push("pin_numa");
+        }
+        if self.user_config_digest != previous.user_config_digest {
+            fields.push("user_config");
+        }
+        fields
+    }
+
+    /// vCPU, memory, and disk growth can be applied in place; anything else
+    /// (ports, hugepages, NUMA pinning, user_config, shrinking the disk) needs a new VM
+    pub fn update_path(&self, previous: &Self) -> UpdatePath {
+        let resizable = self.ports == previous.ports
+            && self.hugepages == previous.hugepages
+            && self.pin_numa == previous.pin_numa
+            && self.user_config_digest == previous.user_config_digest
+            && self.disk_size >= previous.disk_size;
+        if resizable {
+            UpdatePath::Resize
+        } else {
+            UpdatePath::Recreate
+        }
+    }
+}
+
+/// Combine the API-provided user_config with the operator's local one.
+///
+/// When both are JSON objects they are merged recursively with local values
+/// winning; otherwise a non-empty local value replaces the API value.
+pub fn merge_user_config(api: &str, local: Option<&str>) -> String {
+    // Merged on every poll, so a mismatch is only logged when it first appears
+    static MISMATCH: Mutex<DedupLog> = Mutex::new(DedupLog::new("user_config"));
+
+    let Some(local) = local.filter(|l| !l.trim().is_empty()) else {
+        return api.to_string();
+    };
+    if api.trim().is_empty() {
+        return local.to_string();
+    }
+    match (
+        serde_json::from_str::<Value>(api),
+        serde_json::from_str::<Value>(local),
+    ) {
+        (Ok(mut base @ Value::Object(_)), Ok(overlay @ Value::Object(_))) => {
+            MISMATCH.lock().unwrap().clear();
+            merge_json(&mut base, overlay);
+            base.to_string()
+        }
+        _ => {
+            MISMATCH.lock().unwrap().warn(
+                "the API and local values are not both JSON objects; using the local value"
+                    .to_string(),
+            );
+            local.to_string()
+        }
+    }
+}
+
+fn merge_json(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge_json(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+fn default_boot_base_secs() -> u64 {
+    defaults::BOOT_TIMEOUT_SECS
+}
+
+fn default_boot_secs_per_disk_gb() -> u64 {
+    defaults::BOOT_TIMEOUT_SECS_PER_DISK_GB
+}
+
+/// How long a VM may take to reach the running state. A new VM provisions
+/// its disk on first boot, which takes longer the larger the disk; starting
+/// an existing VM reuses its disk and only gets the base timeout.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BootTimeout {
+    #[serde(default = "default_boot_base_secs")]
+    pub base_secs: u64,
+    /// Added per GB of disk when a new VM boots for the first time
+    #[serde(default = "default_boot_secs_per_disk_gb")]
+    pub secs_per_disk_gb: u64,
+    /// One timeout for every boot, replacing the computed ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fixed_secs: Option<u64>,
+}
+
+impl Default for BootTimeout {
+    fn default() -> Self {
+        Self {
+            base_secs: default_boot_base_secs(),
+            secs_per_disk_gb: default_boot_secs_per_disk_gb(),
+            fixed_secs: None,
+        }
+    }
+}
+
+impl BootTimeout {
+    /// The timeout for a new VM with a `new_disk_gb` GB disk, or for starting
+    /// an existing VM when `new_disk_gb` is None
+    pub fn for_boot(&self, new_disk_gb: Option<u32>) -> Duration {
+        let secs = match (self.fixed_secs, new_disk_gb) {
+            (Some(fixed), _) => fixed,
+            (None, Some(disk_gb)) => self
+                .base_secs
+                .saturating_add(self.secs_per_disk_gb.saturating_mul(disk_gb.into())),
+            (None, None) => self.base_secs,
+        };
+        Duration::from_secs(secs)
+    }
+}
+
+impl fmt::Display for BootTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.fixed_secs {
+            Some(fixed) => write!(f, "{}s for every boot", fixed),
+            None => write!(
+                f,
+                "{}s, plus {}s per GB of disk for a new VM",
+                self.base_secs, self.secs_per_disk_gb
+            ),
+        }
+    }
+}
@@ -0,0 +1,202 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Server-push update notifications. With `push_updates` on, the launcher
+//! keeps a Server-Sent Events stream open to `<api url>/events` and checks
+//! for an update as soon as the API announces a change. While the stream is
+//! up, polling slows to a safety net; when it drops, the launcher checks at
+//! once, polls at its usual interval, and reconnects with backoff.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tokio::sync::Notify;
+use tracing::{debug, info, warn};
+
+/// A stream silent for this long (no event, no keep-alive comment) is
+/// taken as dead
+const IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+const RECONNECT_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_MAX: Duration = Duration::from_secs(60);
+/// Longest a single connection is kept before it is renewed
+const CONNECTION_LIFETIME: Duration = Duration::from_secs(24 * 3600);
+
+/// Events that announce a new compose config; anything else is a keep-alive
+const CHANGE_EVENTS: &[&str] = &["message", "config-changed"];
+
+/// The background connection to the API's event stream. Dropping it closes
+/// the stream.
+pub struct PushStream {
+    url: String,
+    task: tokio::task::JoinHandle<()>,
+    changed: Arc<Notify>,
+    connected: Arc<AtomicBool>,
+}
+
+impl PushStream {
+    /// Open the stream of `api_url` with `client` and keep it open
+    pub fn spawn(client: reqwest::Client, api_url: &str) -> Self {
+        let url = stream_url(api_url);
+        let changed = Arc::new(Notify::new());
+        let connected = Arc::new(AtomicBool::new(false));
+        let task = tokio::spawn(maintain(
+            client,
+            url.clone(),
+            changed.clone(),
+            connected.clone(),
+        ));
+        Self {
+            url,
+            task,
+            changed,
+            connected,
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for PushStream {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+pub fn stream_url(api_url: &str) -> String {
+    format!("{}/events", api_url.trim_end_matches('/'))
+}
+
+/// Resolves when the API announced a change or the stream dropped; never
+/// without a stream
+pub async fn changed(stream: Option<&PushStream>) {
+    match stream {
+        Some(stream) => stream.changed.notified().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn maintain(
+    client: reqwest::Client,
+    url: String,
+    changed: Arc<Notify>,
+    connected: Arc<AtomicBool>,
+) {
+    let mut delay = RECONNECT_MIN;
+    let mut warned = false;
+    loop {
+        let result = listen(&client, &url, &changed, &connected).await;
+        if connected.swap(false, Ordering::Relaxed) {
+            // Whatever was announced while reconnecting would be missed
+            changed.notify_one();
+            delay = RECONNECT_MIN;
+            warned = false;
+        }
+        match result {
+            Ok(()) => debug!("Push stream {} closed, reconnecting", url),
+            Err(e) if !warned => {
+                warn!(
+                    "Push stream {} unavailable, polling until it is back: {:#}",
+                    url, e
+                );
+                warned = true;
+            }
+            Err(e) => debug!("Push stream {} still unavailable: {:#}", url, e),
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(RECONNECT_MAX);
+    }
+}
+
+/// One connection: returns when the server closes the stream
+async fn listen(
+    client: &reqwest::Client,
+    url: &str,
+    changed: &Notify,
+    connected: &AtomicBool,
+) -> Result<()> {
+    let mut response = client
+        .get(url)
+        .header(reqwest::header::ACCEPT, "text/event-stream")
+        .header(reqwest::header::CACHE_CONTROL, "no-cache")
+        // The client's timeout covers the whole body, which never ends here
+        .timeout(CONNECTION_LIFETIME)
+        .send()
+        .await
+        .context("Failed to connect")?;
+    if !response.status().is_success() {
+        anyhow::bail!("API returned status {}", response.status());
+    }
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if !content_type.starts_with("text/event-stream") {
+        anyhow::bail!("API answered with '{}', not an event stream", content_type);
+    }
+    connected.store(true, Ordering::Relaxed);
+    info!("Push stream {} connected", url);
+
+    let mut parser = EventParser::default();
+    loop {
+        let chunk = match tokio::time::timeout(IDLE_TIMEOUT, response.chunk()).await {
+            Ok(chunk) => chunk.context("Stream interrupted")?,
+            Err(_) => anyhow::bail!("No data for {}s", IDLE_TIMEOUT.as_secs()),
+        };
+        let Some(chunk) = chunk else {
+            return Ok(());
+        };
+        for event in parser.feed(&chunk) {
+            if CHANGE_EVENTS.contains(&event.as_str()) {
+                debug!("Push stream announced a change ({})", event);
+                changed.notify_one();
+            }
+        }
+    }
+}
+
+/// Splits an event stream into the types of its complete events
+#[derive(Default)]
+struct EventParser {
+    line: Vec<u8>,
+    event: Option<String>,
+    has_data: bool,
+}
+
+impl EventParser {
+    fn feed(&mut self, bytes: &[u8]) -> Vec<String> {
+        let mut events = Vec::new();
+        for &byte in bytes {
+            if byte != b'\n' {
+                self.line.push(byte);
+                continue;
+            }
+            let line = String::from_utf8_lossy(&self.line)
+                .trim_end_matches('\r')
+                .to_string();
+            self.line.clear();
+            if line.is_empty() {
+                // A blank line dispatches the event, if it had data
+                let event = self.event.take();
+                if std::mem::take(&mut self.has_data) {
+                    events.push(event.unwrap_or_else(|| "message".to_string()));
+                }
+            } else if let Some(name) = line.strip_prefix("event:") {
+                self.event = Some(name.trim().to_string());
+            } else if line.starts_with("data:") || line == "data" {
+                self.has_data = true;
+            }
+            // Comments (`:`), `id:` and `retry:` need no handling
+        }
+        events
+    }
+}
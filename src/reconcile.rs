@@ -0,0 +1,560 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! The launcher's core loop, without the validator: fetch the desired app,
+//! compare its compose hash with the app_id of the VM running it, and create,
+//! replace, or start that VM on a dstack VMM until the two agree.
+//!
+//! Three pieces plug together:
+//!
+//! - a [`DesiredStateProvider`] says what should run: a VM name, a compose
+//!   hash, and whatever the backend needs to create the VM;
+//! - a [`Backend`] lists, creates, removes, and starts VMs; [`DstackVmm`]
+//!   talks to a dstack VMM directly;
+//! - a [`Reconciler`] decides with [`plan`] and acts, once or on an interval.
+//!
+//! The validator's own update loop does not run through a [`Reconciler`]:
+//! it makes the same decisions with [`replacement`] and [`restart`], and
+//! adds canaries, rollouts, crashloop handling, and drift and provisioning
+//! checks around them.
+
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::recovery::VmCondition;
+use crate::{capture, compose_hash};
+
+/// What should run, as told by a [`DesiredStateProvider`]
+#[derive(Debug, Clone)]
+pub struct DesiredApp<S> {
+    /// Name of the VM running the app
+    pub name: String,
+    /// Compose hash of the app; its VM runs under the app_id derived from it
+    pub compose_hash: String,
+    /// What the backend needs to create the VM
+    pub spec: S,
+}
+
+/// A VM as reported by the backend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObservedVm {
+    pub id: String,
+    pub name: Option<String>,
+    pub status: String,
+    pub app_id: Option<String>,
+}
+
+impl ObservedVm {
+    /// An entry of the dstack VMM's `Status` RPC
+    pub fn from_status_entry(vm: &Value) -> Option<Self> {
+        let id = vm.get("id").and_then(|i| i.as_str())?;
+        let app_id = vm
+            .get("appId")
+            .or_else(|| vm.get("app_id"))
+            .and_then(|a| a.as_str());
+        Some(Self {
+            id: id.to_string(),
+            name: vm.get("name").and_then(|n| n.as_str()).map(String::from),
+            status: vm
+                .get("status")
+                .and_then(|s| s.as_str())
+                .unwrap_or("unknown")
+                .to_string(),
+            app_id: app_id.map(String::from),
+        })
+    }
+}
+
+/// Why a VM is replaced
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplaceReason {
+    /// The VM reports no app_id, so what it runs is unknown
+    NoAppId,
+    /// The VM was killed or errored
+    Failed { status: String },
+    /// The VM runs another app than the desired one
+    AppIdChanged { running: String, desired: String },
+    /// The VM is stopped and is not to be started as is
+    Stopped,
+}
+
+/// What it takes to bring the VM to the desired state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Create,
+    Replace {
+        vm_id: String,
+        reason: ReplaceReason,
+    },
+    Start {
+        vm_id: String,
+    },
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Create => write!(f, "create a new VM"),
+            Action::Replace { vm_id, reason } => match reason {
+                ReplaceReason::NoAppId => write!(f, "recreate VM {} (it reports no app_id)", vm_id),
+                ReplaceReason::Failed { status } => {
+                    write!(f, "recreate VM {} (in '{}' state)", vm_id, status)
+                }
+                ReplaceReason::AppIdChanged { running, desired } => write!(
+                    f,
+                    "recreate VM {}: app_id changes {} -> {}",
+                    vm_id, running, desired
+                ),
+                ReplaceReason::Stopped => write!(f, "recreate stopped VM {}", vm_id),
+            },
+            Action::Start { vm_id } => write!(f, "start stopped VM {}", vm_id),
+        }
+    }
+}
+
+/// What brings `vm` (None when there is none) to run the app with
+/// `compose_hash`; None when it already does. A stopped VM of the right app
+/// is started with `start_stopped`, and replaced otherwise.
+pub fn plan(compose_hash: &str, vm: Option<&ObservedVm>, start_stopped: bool) -> Option<Action> {
+    let Some(vm) = vm else {
+        return Some(Action::Create);
+    };
+    replacement(compose_hash, vm).or_else(|| restart(vm, start_stopped))
+}
+
+/// The first half of [`plan`]: whether `vm` runs another app than the one
+/// with `compose_hash`, or is beyond restarting. Implementations with checks
+/// of their own (e.g. parameter drift) make them between the two halves.
+pub fn replacement(compose_hash: &str, vm: &ObservedVm) -> Option<Action> {
+    let replace = |reason| {
+        Some(Action::Replace {
+            vm_id: vm.id.clone(),
+            reason,
+        })
+    };
+    let Some(running_app_id) = vm.app_id.as_deref() else {
+        return replace(ReplaceReason::NoAppId);
+    };
+    if VmCondition::classify(&vm.status) == VmCondition::Failed {
+        return replace(ReplaceReason::Failed {
+            status: vm.status.clone(),
+        });
+    }
+    let running = compose_hash::app_id_from_hash(running_app_id);
+    let desired = compose_hash::app_id_from_hash(compose_hash);
+    if running != desired {
+        return replace(ReplaceReason::AppIdChanged {
+            running: running.to_string(),
+            desired: desired.to_string(),
+        });
+    }
+    None
+}
+
+/// The second half of [`plan`]: what a stopped VM running the right app needs
+pub fn restart(vm: &ObservedVm, start_stopped: bool) -> Option<Action> {
+    if VmCondition::classify(&vm.status) != VmCondition::Stopped {
+        return None;
+    }
+    let vm_id = vm.id.clone();
+    Some(if start_stopped {
+        Action::Start { vm_id }
+    } else {
+        Action::Replace {
+            vm_id,
+            reason: ReplaceReason::Stopped,
+        }
+    })
+}
+
+/// Source of the desired app
+pub trait DesiredStateProvider {
+    /// What the backend needs to create the app's VM
+    type Spec;
+
+    fn desired(&self) -> impl Future<Output = Result<DesiredApp<Self::Spec>>> + Send;
+}
+
+/// Where the app's VM runs
+pub trait Backend<S> {
+    fn list_vms(&self) -> impl Future<Output = Result<Vec<ObservedVm>>> + Send;
+
+    /// The VM running the app called `name`, if any
+    fn find_vm(&self, name: &str) -> impl Future<Output = Result<Option<ObservedVm>>> + Send
+    where
+        Self: Sync,
+    {
+        async move {
+            Ok(self
+                .list_vms()
+                .await?
+                .into_iter()
+                .find(|vm| vm.name.as_deref() == Some(name)))
+        }
+    }
+
+    /// Create and start the VM of `app`, returning its id
+    fn create_vm(&self, app: &DesiredApp<S>) -> impl Future<Output = Result<String>> + Send;
+
+    /// Stop and remove a VM
+    fn remove_vm(&self, vm_id: &str) -> impl Future<Output = Result<()>> + Send;
+
+    fn start_vm(&self, vm_id: &str) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// A borrowed provider, e.g. one object serving as provider and backend both
+impl<T: DesiredStateProvider + Sync> DesiredStateProvider for &T {
+    type Spec = T::Spec;
+
+    fn desired(&self) -> impl Future<Output = Result<DesiredApp<Self::Spec>>> + Send {
+        (**self).desired()
+    }
+}
+
+impl<S, T: Backend<S> + Sync> Backend<S> for &T {
+    fn list_vms(&self) -> impl Future<Output = Result<Vec<ObservedVm>>> + Send {
+        (**self).list_vms()
+    }
+
+    fn find_vm(&self, name: &str) -> impl Future<Output = Result<Option<ObservedVm>>> + Send {
+        (**self).find_vm(name)
+    }
+
+    fn create_vm(&self, app: &DesiredApp<S>) -> impl Future<Output = Result<String>> + Send {
+        (**self).create_vm(app)
+    }
+
+    fn remove_vm(&self, vm_id: &str) -> impl Future<Output = Result<()>> + Send {
+        (**self).remove_vm(vm_id)
+    }
+
+    fn start_vm(&self, vm_id: &str) -> impl Future<Output = Result<()>> + Send {
+        (**self).start_vm(vm_id)
+    }
+}
+
+/// Drives a backend toward what a provider wants
+pub struct Reconciler<P, B> {
+    provider: P,
+    backend: B,
+    start_stopped: bool,
+}
+
+impl<P, B> Reconciler<P, B>
+where
+    P: DesiredStateProvider + Sync,
+    P::Spec: Sync,
+    B: Backend<P::Spec> + Sync,
+{
+    /// A reconciler that replaces stopped VMs
+    pub fn new(provider: P, backend: B) -> Self {
+        Self {
+            provider,
+            backend,
+            start_stopped: false,
+        }
+    }
+
+    /// Start a stopped VM running the right app instead of replacing it
+    pub fn start_stopped(mut self, start_stopped: bool) -> Self {
+        self.start_stopped = start_stopped;
+        self
+    }
+
+    /// Fetch the desired app and act once. Returns what was done, None when
+    /// the VM already matched.
+    pub async fn reconcile_once(&self) -> Result<Option<Action>> {
+        let app = self
+            .provider
+            .desired()
+            .await
+            .context("Failed to fetch the desired app")?;
+        let vm = self.backend.find_vm(&app.name).await?;
+        let Some(action) = plan(&app.compose_hash, vm.as_ref(), self.start_stopped) else {
+            return Ok(None);
+        };
+        info!("Reconciling {}: {}", app.name, action);
+        match &action {
+            Action::Create => {
+                self.backend.create_vm(&app).await?;
+            }
+            Action::Replace { vm_id, .. } => {
+                self.backend.remove_vm(vm_id).await?;
+                self.backend.create_vm(&app).await?;
+            }
+            Action::Start { vm_id } => self.backend.start_vm(vm_id).await?,
+        }
+        Ok(Some(action))
+    }
+
+    /// Reconcile every `interval`, logging failures, until the task is dropped
+    pub async fn run(&self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.reconcile_once().await {
+                warn!("Reconcile failed: {:#}", e);
+            }
+        }
+    }
+}
+
+/// A [`Backend`] talking to a dstack VMM's prpc API. The spec of an app is
+/// the body of the `CreateVm` request, without `name`, which is filled in.
+#[derive(Debug, Clone)]
+pub struct DstackVmm {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl DstackVmm {
+    /// The VMM at `url`, e.g. `http://127.0.0.1:10000`
+    pub fn new(url: impl Into<String>) -> Self {
+        Self::with_client(reqwest::Client::new(), url)
+    }
+
+    pub fn with_client(client: reqwest::Client, url: impl Into<String>) -> Self {
+        Self {
+            client,
+            url: url.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    async fn rpc_call(&self, method: &str, params: Value) -> Result<Value> {
+        let response = self
+            .client
+            .post(format!("{}/prpc/{}?json", self.url, method))
+            .json(&params)
+            .send()
+            .await
+            .context(format!("Failed to call {}", method))?;
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = capture::error_body(response).await;
+            anyhow::bail!("{} failed with status {}: {}", method, status, error_text);
+        }
+        response
+            .json()
+            .await
+            .context(format!("Failed to parse the {} response", method))
+    }
+}
+
+impl Backend<Value> for DstackVmm {
+    async fn list_vms(&self) -> Result<Vec<ObservedVm>> {
+        let response = self.rpc_call("Status", json!({})).await?;
+        let vms = response
+            .get("vms")
+            .and_then(|v| v.as_array())
+            .context("Invalid status response")?;
+        Ok(vms
+            .iter()
+            .filter_map(ObservedVm::from_status_entry)
+            .collect())
+    }
+
+    async fn create_vm(&self, app: &DesiredApp<Value>) -> Result<String> {
+        let mut request = app.spec.clone();
+        request
+            .as_object_mut()
+            .context("The CreateVm request is not an object")?
+            .insert("name".to_string(), json!(app.name));
+        let response = self.rpc_call("CreateVm", request).await?;
+        response
+            .get("id")
+            .and_then(|i| i.as_str())
+            .map(String::from)
+            .context("Invalid create VM response")
+    }
+
+    async fn remove_vm(&self, vm_id: &str) -> Result<()> {
+        // Stopping an already stopped VM fails; removal is what matters
+        if let Err(e) = self.rpc_call("StopVm", json!({ "id": vm_id })).await {
+            warn!("Failed to stop VM {}, removing anyway: {:#}", vm_id, e);
+        }
+        self.rpc_call("RemoveVm", json!({ "id": vm_id })).await?;
+        Ok(())
+    }
+
+    async fn start_vm(&self, vm_id: &str) -> Result<()> {
+        self.rpc_call("StartVm", json!({ "id": vm_id })).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    const HASH: &str = "1654d1aa99f9a4e3c5b1c0e2d7f8a6b4c3d2e1f0a9b8c7d6e5f4a3b2c1d0e9f8";
+    const OTHER_HASH: &str = "9b0e77aa99f9a4e3c5b1c0e2d7f8a6b4c3d2e1f0a9b8c7d6e5f4a3b2c1d0e9f8";
+
+    fn vm(status: &str, app_id: Option<&str>) -> ObservedVm {
+        ObservedVm {
+            id: "vm-1".to_string(),
+            name: Some("app".to_string()),
+            status: status.to_string(),
+            app_id: app_id.map(|hash| compose_hash::app_id_from_hash(hash).to_string()),
+        }
+    }
+
+    #[test]
+    fn plan_converged_and_create() {
+        assert_eq!(plan(HASH, Some(&vm("running", Some(HASH))), false), None);
+        assert_eq!(plan(HASH, None, false), Some(Action::Create));
+    }
+
+    #[test]
+    fn plan_replaces_other_apps_and_failed_vms() {
+        let replace = |reason| {
+            Some(Action::Replace {
+                vm_id: "vm-1".to_string(),
+                reason,
+            })
+        };
+        assert_eq!(
+            plan(HASH, Some(&vm("running", Some(OTHER_HASH))), true),
+            replace(ReplaceReason::AppIdChanged {
+                running: compose_hash::app_id_from_hash(OTHER_HASH).to_string(),
+                desired: compose_hash::app_id_from_hash(HASH).to_string(),
+            })
+        );
+        assert_eq!(
+            plan(HASH, Some(&vm("running", None)), true),
+            replace(ReplaceReason::NoAppId)
+        );
+        assert_eq!(
+            plan(HASH, Some(&vm("error", Some(HASH))), true),
+            replace(ReplaceReason::Failed {
+                status: "error".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn plan_starts_or_replaces_stopped_vms() {
+        let stopped = vm("stopped", Some(HASH));
+        assert_eq!(
+            plan(HASH, Some(&stopped), true),
+            Some(Action::Start {
+                vm_id: "vm-1".to_string()
+            })
+        );
+        assert_eq!(
+            plan(HASH, Some(&stopped), false),
+            Some(Action::Replace {
+                vm_id: "vm-1".to_string(),
+                reason: ReplaceReason::Stopped,
+            })
+        );
+    }
+
+    struct Fixed(&'static str);
+
+    impl DesiredStateProvider for Fixed {
+        type Spec = ();
+
+        async fn desired(&self) -> Result<DesiredApp<()>> {
+            Ok(DesiredApp {
+                name: "app".to_string(),
+                compose_hash: self.0.to_string(),
+                spec: (),
+            })
+        }
+    }
+
+    /// VMs in memory, with every call recorded
+    #[derive(Default)]
+    struct Fake {
+        vms: Mutex<Vec<ObservedVm>>,
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl Fake {
+        fn with(vm: ObservedVm) -> Self {
+            Self {
+                vms: Mutex::new(vec![vm]),
+                ..Default::default()
+            }
+        }
+
+        fn calls(&self) -> Vec<String> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    impl Backend<()> for Fake {
+        async fn list_vms(&self) -> Result<Vec<ObservedVm>> {
+            Ok(self.vms.lock().unwrap().clone())
+        }
+
+        async fn create_vm(&self, app: &DesiredApp<()>) -> Result<String> {
+            self.calls.lock().unwrap().push("create".to_string());
+            let mut vms = self.vms.lock().unwrap();
+            let id = format!("vm-{}", vms.len() + 2);
+            vms.push(ObservedVm {
+                id: id.clone(),
+                name: Some(app.name.clone()),
+                status: "running".to_string(),
+                app_id: Some(compose_hash::app_id_from_hash(&app.compose_hash).to_string()),
+            });
+            Ok(id)
+        }
+
+        async fn remove_vm(&self, vm_id: &str) -> Result<()> {
+            self.calls.lock().unwrap().push(format!("remove {}", vm_id));
+            self.vms.lock().unwrap().retain(|vm| vm.id != vm_id);
+            Ok(())
+        }
+
+        async fn start_vm(&self, vm_id: &str) -> Result<()> {
+            self.calls.lock().unwrap().push(format!("start {}", vm_id));
+            for vm in self.vms.lock().unwrap().iter_mut() {
+                if vm.id == vm_id {
+                    vm.status = "running".to_string();
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn reconcile_once_creates_then_converges() {
+        let backend = Fake::default();
+        let reconciler = Reconciler::new(Fixed(HASH), &backend);
+        assert_eq!(
+            reconciler.reconcile_once().await.unwrap(),
+            Some(Action::Create)
+        );
+        assert_eq!(reconciler.reconcile_once().await.unwrap(), None);
+        assert_eq!(backend.calls(), ["create"]);
+    }
+
+    #[tokio::test]
+    async fn reconcile_once_replaces_the_old_app() {
+        let backend = Fake::with(vm("running", Some(OTHER_HASH)));
+        let reconciler = Reconciler::new(Fixed(HASH), &backend);
+        assert!(matches!(
+            reconciler.reconcile_once().await.unwrap(),
+            Some(Action::Replace { .. })
+        ));
+        assert_eq!(backend.calls(), ["remove vm-1", "create"]);
+        assert_eq!(reconciler.reconcile_once().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn reconcile_once_starts_a_stopped_vm_when_asked() {
+        let backend = Fake::with(vm("stopped", Some(HASH)));
+        let reconciler = Reconciler::new(Fixed(HASH), &backend).start_stopped(true);
+        reconciler.reconcile_once().await.unwrap();
+        assert_eq!(backend.calls(), ["start vm-1"]);
+        assert_eq!(reconciler.reconcile_once().await.unwrap(), None);
+    }
+}
@@ -0,0 +1,142 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use serde::{Deserialize, Serialize};
+
+use crate::defaults;
+
+/// How the launcher interprets the VMM-reported status of an existing VM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmCondition {
+    /// Running or transitioning; compared against the desired config as usual
+    Up,
+    /// Shut down cleanly; its disk is intact and it can simply be started again
+    Stopped,
+    /// Killed or errored; logs are captured and the VM is recreated
+    Failed,
+}
+
+impl VmCondition {
+    pub fn classify(status: &str) -> Self {
+        match status {
+            "stopped" | "exited" => Self::Stopped,
+            "killed" | "error" => Self::Failed,
+            _ => Self::Up,
+        }
+    }
+}
+
+/// How stopped and failed VMs are brought back
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecoveryPolicy {
+    /// Start a cleanly stopped VM whose config is current instead of recreating it
+    #[serde(default = "default_start_stopped")]
+    pub start_stopped: bool,
+    /// Failures within the window after which recreation is held off
+    #[serde(default = "default_crashloop_threshold")]
+    pub crashloop_threshold: u32,
+    #[serde(default = "default_crashloop_window_secs")]
+    pub crashloop_window_secs: u64,
+    /// Before keeping a VM found at startup, wait for it to boot and for its
+    /// guest agent to report the app running; recreate it if it never does
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub verify_on_start: bool,
+    /// Automatic recreations within the window after which the launcher
+    /// stops recreating and reports itself degraded; 0 disables the limit
+    #[serde(default = "default_max_recreations")]
+    pub max_recreations: u32,
+    #[serde(default = "default_recreation_window_secs")]
+    pub recreation_window_secs: u64,
+    /// Wait before the second recreation within the window, doubled for
+    /// each one after that
+    #[serde(default = "default_recreate_backoff_secs")]
+    pub recreate_backoff_secs: u64,
+}
+
+fn default_start_stopped() -> bool {
+    defaults::RECOVERY_START_STOPPED
+}
+
+fn default_crashloop_threshold() -> u32 {
+    defaults::CRASHLOOP_THRESHOLD
+}
+
+fn default_crashloop_window_secs() -> u64 {
+    defaults::CRASHLOOP_WINDOW_SECS
+}
+
+fn default_max_recreations() -> u32 {
+    defaults::MAX_RECREATIONS
+}
+
+fn default_recreation_window_secs() -> u64 {
+    defaults::RECREATION_WINDOW_SECS
+}
+
+fn default_recreate_backoff_secs() -> u64 {
+    defaults::RECREATE_BACKOFF_SECS
+}
+
+impl Default for RecoveryPolicy {
+    fn default() -> Self {
+        Self {
+            start_stopped: default_start_stopped(),
+            crashloop_threshold: default_crashloop_threshold(),
+            crashloop_window_secs: default_crashloop_window_secs(),
+            verify_on_start: false,
+            max_recreations: default_max_recreations(),
+            recreation_window_secs: default_recreation_window_secs(),
+            recreate_backoff_secs: default_recreate_backoff_secs(),
+        }
+    }
+}
+
+impl RecoveryPolicy {
+    /// A threshold of 0 disables the hold-off
+    pub fn is_crashlooping(&self, recent_failures: usize) -> bool {
+        self.crashloop_threshold > 0 && recent_failures >= self.crashloop_threshold as usize
+    }
+
+    /// A limit of 0 disables the circuit breaker
+    pub fn is_flapping(&self, recent_recreations: usize) -> bool {
+        self.max_recreations > 0 && recent_recreations >= self.max_recreations as usize
+    }
+
+    /// Seconds the next recreation waits after the last of `recent_recreations`
+    pub fn recreate_backoff(&self, recent_recreations: usize) -> u64 {
+        let Some(doublings) = recent_recreations.checked_sub(1) else {
+            return 0;
+        };
+        self.recreate_backoff_secs
+            .saturating_mul(1u64 << doublings.min(16))
+            .min(defaults::RECREATE_BACKOFF_MAX_SECS)
+    }
+}
+
+impl std::fmt::Display for RecoveryPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} stopped VMs, hold off after {} failures in {}s",
+            if self.start_stopped {
+                "start"
+            } else {
+                "recreate"
+            },
+            self.crashloop_threshold,
+            self.crashloop_window_secs
+        )?;
+        if self.verify_on_start {
+            write!(f, ", verify VMs found at startup")?;
+        }
+        if self.max_recreations > 0 {
+            write!(
+                f,
+                ", at most {} recreations in {}s with {}s backoff",
+                self.max_recreations, self.recreation_window_secs, self.recreate_backoff_secs
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,406 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Bounds on the launcher's local data so the state directory cannot grow
+//! until it fills the host's root filesystem.
+
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::deployment::DeploymentRecord;
+use crate::{audit, capture, defaults, paths, unix_now, utilization};
+
+/// Temp files younger than this may still be in the middle of a write
+const STALE_TEMP_AGE: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// Keep at most this many audit log entries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_max_entries: Option<usize>,
+    /// Drop audit log entries older than this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_max_age_days: Option<u64>,
+    /// Upper bound on the total size of the state directory
+    #[serde(default = "default_max_disk_mb")]
+    pub max_disk_mb: u64,
+}
+
+fn default_max_disk_mb() -> u64 {
+    defaults::RETENTION_MAX_DISK_MB
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            audit_max_entries: None,
+            audit_max_age_days: None,
+            max_disk_mb: default_max_disk_mb(),
+        }
+    }
+}
+
+impl fmt::Display for RetentionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let or_unlimited = |value: Option<String>| value.unwrap_or_else(|| "unlimited".to_string());
+        write!(
+            f,
+            "audit entries {}, audit age {}, state dir {} MB",
+            or_unlimited(self.audit_max_entries.map(|n| n.to_string())),
+            or_unlimited(self.audit_max_age_days.map(|d| format!("{}d", d))),
+            self.max_disk_mb
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PruneReport {
+    pub audit_entries: usize,
+    pub captures: usize,
+    pub utilization_samples: usize,
+    pub deployments: usize,
+    pub temp_files: Vec<String>,
+    /// State directory size before pruning
+    pub state_dir_bytes: u64,
+}
+
+impl PruneReport {
+    pub fn is_empty(&self) -> bool {
+        self.audit_entries == 0
+            && self.captures == 0
+            && self.utilization_samples == 0
+            && self.deployments == 0
+            && self.temp_files.is_empty()
+    }
+}
+
+impl fmt::Display for PruneReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} audit entries, {} captures, {} utilization samples, {} previous deployments, {} stale temp files (state dir was {} KB)",
+            self.audit_entries,
+            self.captures,
+            self.utilization_samples,
+            self.deployments,
+            self.temp_files.len(),
+            self.state_dir_bytes / 1024
+        )
+    }
+}
+
+/// Audit entries the disk budget never prunes below; only
+/// `audit_max_entries` and `audit_max_age_days` go further
+pub const AUDIT_MIN_ENTRIES: usize = 1000;
+
+/// What the state directory holds, in bytes. Each list is oldest first.
+#[derive(Debug, Default, Clone)]
+struct Usage {
+    /// Everything pruning leaves alone: state, the current deployment,
+    /// keys, the compose base
+    fixed: u64,
+    captures: Vec<u64>,
+    utilization_samples: Vec<u64>,
+    /// Kept deployments other than the current one
+    deployments: Vec<u64>,
+    /// (timestamp, bytes) per audit entry
+    audit: Vec<(u64, u64)>,
+}
+
+/// How many of the oldest items of each kind to drop
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Plan {
+    captures: usize,
+    utilization_samples: usize,
+    deployments: usize,
+    audit_entries: usize,
+}
+
+/// Apply the policy to the state directory; with `dry_run`, only report
+pub fn prune(policy: &RetentionPolicy, dry_run: bool) -> Result<PruneReport> {
+    let mut report = PruneReport {
+        state_dir_bytes: dir_size(&paths::state_dir()),
+        ..Default::default()
+    };
+
+    report.temp_files = stale_temp_files(&paths::state_dir());
+    if !dry_run {
+        for path in &report.temp_files {
+            std::fs::remove_file(path).context(format!("Failed to remove {}", path))?;
+        }
+    }
+
+    let captures = capture::list();
+    let mut usage = Usage {
+        captures: captures.iter().map(|(_, size)| *size).collect(),
+        utilization_samples: utilization::sample_sizes()?,
+        deployments: DeploymentRecord::previous_sizes()?,
+        audit: audit::entry_sizes()?,
+        ..Default::default()
+    };
+    let prunable: u64 = usage.captures.iter().sum::<u64>()
+        + usage.utilization_samples.iter().sum::<u64>()
+        + usage.deployments.iter().sum::<u64>()
+        + usage.audit.iter().map(|(_, size)| size).sum::<u64>();
+    usage.fixed = report.state_dir_bytes.saturating_sub(prunable);
+
+    let plan = plan(policy, &usage, unix_now());
+    report.captures = plan.captures;
+    report.utilization_samples = plan.utilization_samples;
+    report.deployments = plan.deployments;
+    report.audit_entries = plan.audit_entries;
+    if dry_run {
+        return Ok(report);
+    }
+    for (path, _) in &captures[..plan.captures] {
+        std::fs::remove_file(path).context(format!("Failed to remove {}", path.display()))?;
+    }
+    if plan.utilization_samples > 0 {
+        utilization::drop_oldest(plan.utilization_samples)?;
+    }
+    if plan.deployments > 0 {
+        DeploymentRecord::drop_oldest_previous(plan.deployments)?;
+    }
+    if plan.audit_entries > 0 {
+        report.audit_entries = audit::prune_oldest(plan.audit_entries)?;
+    }
+
+    Ok(report)
+}
+
+/// What to drop so the state directory fits the policy. Audit entries past
+/// the entry and age limits go first; then, while over the disk budget,
+/// captures, utilization samples, and previous deployments, oldest first,
+/// and only then audit entries, down to [`AUDIT_MIN_ENTRIES`].
+fn plan(policy: &RetentionPolicy, usage: &Usage, now: u64) -> Plan {
+    let mut plan = Plan {
+        audit_entries: audit_entries_to_prune(policy, &usage.audit, now),
+        ..Default::default()
+    };
+    let audit_kept: u64 = usage.audit[plan.audit_entries..]
+        .iter()
+        .map(|(_, size)| size)
+        .sum();
+    let mut excess = (usage.fixed
+        + usage.captures.iter().sum::<u64>()
+        + usage.utilization_samples.iter().sum::<u64>()
+        + usage.deployments.iter().sum::<u64>()
+        + audit_kept)
+        .saturating_sub(policy.max_disk_mb * 1024 * 1024);
+
+    let mut take = |sizes: &mut dyn Iterator<Item = u64>, count: &mut usize| {
+        for size in sizes {
+            if excess == 0 {
+                break;
+            }
+            excess = excess.saturating_sub(size);
+            *count += 1;
+        }
+    };
+    take(&mut usage.captures.iter().copied(), &mut plan.captures);
+    take(
+        &mut usage.utilization_samples.iter().copied(),
+        &mut plan.utilization_samples,
+    );
+    take(
+        &mut usage.deployments.iter().copied(),
+        &mut plan.deployments,
+    );
+    let floor = usage.audit.len().saturating_sub(AUDIT_MIN_ENTRIES);
+    if plan.audit_entries < floor {
+        let mut audit = usage.audit[plan.audit_entries..floor]
+            .iter()
+            .map(|(_, size)| *size);
+        take(&mut audit, &mut plan.audit_entries);
+    }
+    plan
+}
+
+/// How many of the oldest audit entries are past the entry and age limits.
+/// `sizes` holds (timestamp, bytes) per entry, oldest first.
+fn audit_entries_to_prune(policy: &RetentionPolicy, sizes: &[(u64, u64)], now: u64) -> usize {
+    let mut count = 0;
+    if let Some(max_entries) = policy.audit_max_entries {
+        count = count.max(sizes.len().saturating_sub(max_entries));
+    }
+    if let Some(days) = policy.audit_max_age_days {
+        let cutoff = now.saturating_sub(days * 86_400);
+        count = count.max(sizes.iter().take_while(|(at, _)| *at < cutoff).count());
+    }
+    count
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(meta) if meta.is_dir() => dir_size(&entry.path()),
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Leftovers of atomic writes interrupted by a crash
+fn stale_temp_files(dir: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().ends_with(".tmp"))
+        .filter(|entry| {
+            entry
+                .metadata()
+                .and_then(|meta| meta.modified())
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                .is_some_and(|age| age >= STALE_TEMP_AGE)
+        })
+        .map(|entry| entry.path().display().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: u64 = 1024 * 1024;
+
+    fn policy(max_disk_mb: u64) -> RetentionPolicy {
+        RetentionPolicy {
+            max_disk_mb,
+            ..Default::default()
+        }
+    }
+
+    fn audit(entries: usize, size: u64) -> Vec<(u64, u64)> {
+        (0..entries).map(|i| (i as u64, size)).collect()
+    }
+
+    #[test]
+    fn nothing_to_prune_within_budget() {
+        let usage = Usage {
+            fixed: MB,
+            captures: vec![1000; 5],
+            utilization_samples: vec![100; 10],
+            deployments: vec![5000; 4],
+            audit: audit(5000, 200),
+        };
+        assert_eq!(plan(&policy(256), &usage, 10_000), Plan::default());
+    }
+
+    #[test]
+    fn entry_and_age_limits() {
+        let sizes = audit(100, 10);
+        let mut policy = policy(256);
+        policy.audit_max_entries = Some(60);
+        assert_eq!(audit_entries_to_prune(&policy, &sizes, 0), 40);
+
+        // Entry i was written at i seconds; a day later, all are older than 0 days
+        policy.audit_max_entries = None;
+        policy.audit_max_age_days = Some(1);
+        assert_eq!(audit_entries_to_prune(&policy, &sizes, 86_400 + 50), 50);
+
+        // The stricter limit wins
+        policy.audit_max_entries = Some(30);
+        assert_eq!(audit_entries_to_prune(&policy, &sizes, 86_400 + 50), 70);
+    }
+
+    #[test]
+    fn other_data_goes_before_audit_entries() {
+        // 1 MB budget: under 0.5 MB fixed, 0.75 MB of captures, small audit log
+        let usage = Usage {
+            fixed: MB / 2 - 16 * 1024,
+            captures: vec![MB / 4; 3],
+            utilization_samples: vec![1024; 4],
+            deployments: vec![4096; 2],
+            audit: audit(10, 100),
+        };
+        let plan = plan(&policy(1), &usage, 0);
+        assert_eq!(plan.captures, 1);
+        assert_eq!(plan.utilization_samples, 0);
+        assert_eq!(plan.deployments, 0);
+        assert_eq!(plan.audit_entries, 0);
+    }
+
+    #[test]
+    fn prunes_in_order_until_within_budget() {
+        let usage = Usage {
+            fixed: 0,
+            captures: vec![MB / 2; 2],
+            utilization_samples: vec![MB / 4; 4],
+            deployments: vec![MB / 4; 2],
+            audit: audit(AUDIT_MIN_ENTRIES + 10, 0),
+        };
+        // 2.5 MB in total, 1 MB budget: all captures and two samples go
+        let plan = plan(&policy(1), &usage, 0);
+        assert_eq!(
+            plan,
+            Plan {
+                captures: 2,
+                utilization_samples: 2,
+                deployments: 0,
+                audit_entries: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn oversized_other_data_never_empties_the_audit_log() {
+        let usage = Usage {
+            fixed: 300 * MB,
+            captures: vec![MB; 3],
+            utilization_samples: vec![1024; 100],
+            deployments: vec![MB; 4],
+            audit: audit(AUDIT_MIN_ENTRIES * 3, 512),
+        };
+        let plan = plan(&policy(256), &usage, 0);
+        assert_eq!(plan.captures, 3);
+        assert_eq!(plan.utilization_samples, 100);
+        assert_eq!(plan.deployments, 4);
+        assert_eq!(plan.audit_entries, AUDIT_MIN_ENTRIES * 2);
+    }
+
+    #[test]
+    fn small_audit_log_is_kept_whole() {
+        let usage = Usage {
+            fixed: 2 * MB,
+            audit: audit(AUDIT_MIN_ENTRIES / 2, 4096),
+            ..Default::default()
+        };
+        assert_eq!(plan(&policy(1), &usage, 0).audit_entries, 0);
+    }
+
+    #[test]
+    fn audit_over_budget_is_pruned_to_fit() {
+        // 2000 entries of 1 KB against a 1 MB budget: 976 go, leaving 1024
+        let usage = Usage {
+            audit: audit(2000, 1024),
+            ..Default::default()
+        };
+        assert_eq!(plan(&policy(1), &usage, 0).audit_entries, 976);
+    }
+
+    #[test]
+    fn explicit_limits_go_below_the_floor() {
+        let mut policy = policy(256);
+        policy.audit_max_entries = Some(10);
+        let usage = Usage {
+            audit: audit(AUDIT_MIN_ENTRIES, 10),
+            ..Default::default()
+        };
+        assert_eq!(
+            plan(&policy, &usage, 0).audit_entries,
+            AUDIT_MIN_ENTRIES - 10
+        );
+    }
+}
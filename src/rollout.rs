@@ -0,0 +1,401 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! `rollout`: release an update across a fleet of launchers in waves through
+//! their admin APIs. Each launcher runs with `require_update_approval`, so it
+//! holds the update until approved here. A wave is approved only after every
+//! launcher updated so far has stayed healthy for the soak time, and the
+//! rollout halts at the first failure, leaving later waves on the old config.
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+
+use crate::admin_api::{self, StatusReport};
+
+/// How often launchers are polled while a wave updates and soaks
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(clap::Args)]
+pub struct RolloutArgs {
+    /// Admin API of each launcher, e.g. `10.0.0.5:9850` (comma-separated or repeated)
+    #[arg(long, value_delimiter = ',', required_unless_present = "hosts_file")]
+    pub hosts: Vec<String>,
+    /// File listing one admin API address per line (`#` starts a comment)
+    #[arg(long)]
+    pub hosts_file: Option<PathBuf>,
+    /// File holding the operator token accepted by every launcher (defaults to $LAUNCHER_ADMIN_TOKEN)
+    #[arg(long)]
+    pub token_file: Option<PathBuf>,
+    /// Compose hash to roll out (defaults to the update the launchers are holding)
+    #[arg(long)]
+    pub compose_hash: Option<String>,
+    /// Cumulative wave sizes, as host counts or percentages of the fleet
+    #[arg(long, default_value = "1,25%,100%")]
+    pub waves: String,
+    /// Seconds every updated launcher must stay healthy before the next wave
+    #[arg(long, default_value_t = 600)]
+    pub soak: u64,
+    /// Seconds a wave may take to update and become healthy
+    #[arg(long, default_value_t = 1800)]
+    pub wave_timeout: u64,
+}
+
+/// Launchers in the order they are updated
+fn load_hosts(args: &RolloutArgs) -> Result<Vec<String>> {
+    let mut hosts: Vec<String> = args.hosts.iter().map(|h| h.trim().to_string()).collect();
+    if let Some(path) = &args.hosts_file {
+        let content =
+            std::fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+        hosts.extend(
+            content
+                .lines()
+                .map(|line| line.split('#').next().unwrap_or("").trim())
+                .filter(|line| !line.is_empty())
+                .map(String::from),
+        );
+    }
+    hosts.retain(|host| !host.is_empty());
+    if hosts.is_empty() {
+        anyhow::bail!("No launchers given");
+    }
+    let mut seen = BTreeSet::new();
+    if let Some(duplicate) = hosts.iter().find(|host| !seen.insert(host.as_str())) {
+        anyhow::bail!("Launcher {} is listed more than once", duplicate);
+    }
+    Ok(hosts)
+}
+
+/// Number of launchers updated after each wave; the last wave always covers the fleet
+fn wave_sizes(spec: &str, fleet: usize) -> Result<Vec<usize>> {
+    let mut sizes: Vec<usize> = Vec::new();
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let size = match part.strip_suffix('%') {
+            Some(percent) => {
+                let percent: usize = percent
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|p| (1..=100).contains(p))
+                    .context(format!("Invalid wave '{}': expected 1% to 100%", part))?;
+                (fleet * percent).div_ceil(100)
+            }
+            None => part
+                .parse::<usize>()
+                .ok()
+                .filter(|n| *n > 0)
+                .context(format!(
+                    "Invalid wave '{}': expected a host count or a percentage",
+                    part
+                ))?
+                .min(fleet),
+        };
+        match sizes.last() {
+            Some(&last) if size < last => anyhow::bail!(
+                "Waves are cumulative and must not shrink: '{}' is {} launchers after {}",
+                part,
+                size,
+                last
+            ),
+            Some(&last) if size == last => {}
+            _ => sizes.push(size),
+        }
+    }
+    if sizes.last() != Some(&fleet) {
+        sizes.push(fleet);
+    }
+    Ok(sizes)
+}
+
+/// Why an updated launcher is not (yet) healthy, if it isn't
+fn unhealthy(report: &StatusReport, target: &str) -> Option<String> {
+    if report.compose_hash.as_deref() != Some(target) {
+        return Some(format!(
+            "runs compose hash {}",
+            report.compose_hash.as_deref().unwrap_or("(none)")
+        ));
+    }
+    if let Some(pending) = &report.pending_update {
+        return Some(format!("update in progress: {}", pending));
+    }
+    match &report.last_check {
+        Some(check) => {
+            if let Some(error) = &check.error {
+                return Some(format!("last check failed: {}", error));
+            }
+        }
+        None => return Some("no update check yet".to_string()),
+    }
+    match &report.guest {
+        Some(guest) if !guest.all_running() => Some(format!("guest: {}", guest)),
+        _ => None,
+    }
+}
+
+struct Rollout {
+    hosts: Vec<String>,
+    token: String,
+    target: String,
+    /// VM failures each launcher had already counted before the rollout
+    baseline_failures: Vec<usize>,
+}
+
+impl Rollout {
+    async fn status(&self, host: &str) -> Result<StatusReport> {
+        admin_api::request(reqwest::Method::GET, host, &self.token, "/status").await
+    }
+
+    fn halt(&self, host: &str, reason: impl std::fmt::Display, updated: usize) -> anyhow::Error {
+        anyhow::anyhow!(
+            "Rollout halted: {}: {}. {} of {} launchers were approved; the rest stay on their current config",
+            host,
+            reason,
+            updated,
+            self.hosts.len()
+        )
+    }
+
+    /// Approve the wave and wait until each of its launchers runs the target healthily
+    async fn update_wave(&self, wave: std::ops::Range<usize>, timeout: Duration) -> Result<()> {
+        for index in wave.clone() {
+            let host = &self.hosts[index];
+            admin_api::approve(host, &self.token, &self.target)
+                .await
+                .map_err(|e| self.halt(host, format!("{:#}", e), index))?;
+        }
+        let deadline = Instant::now() + timeout;
+        let mut waiting: Vec<usize> = wave.clone().collect();
+        while !waiting.is_empty() {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            let mut still_waiting = Vec::new();
+            for index in waiting {
+                let host = &self.hosts[index];
+                let reason = match self.status(host).await {
+                    Ok(report) => {
+                        if report.recent_failures > self.baseline_failures[index] {
+                            return Err(self.halt(
+                                host,
+                                "the VM failed after the update",
+                                wave.end,
+                            ));
+                        }
+                        // A restarted launcher forgets its approval
+                        if report.awaiting_approval.as_deref() == Some(self.target.as_str()) {
+                            admin_api::approve(host, &self.token, &self.target)
+                                .await
+                                .ok();
+                        }
+                        unhealthy(&report, &self.target)
+                    }
+                    Err(e) => Some(format!("{:#}", e)),
+                };
+                match reason {
+                    None => println!("  ✓ {} updated and healthy", host),
+                    Some(reason) if Instant::now() >= deadline => {
+                        return Err(self.halt(
+                            host,
+                            format!("not healthy after {}s ({})", timeout.as_secs(), reason),
+                            wave.end,
+                        ));
+                    }
+                    Some(_) => still_waiting.push(index),
+                }
+            }
+            waiting = still_waiting;
+        }
+        Ok(())
+    }
+
+    /// Require every launcher updated so far to stay healthy for the soak time
+    async fn soak(&self, updated: usize, soak: Duration) -> Result<()> {
+        println!("  Soaking {} launcher(s) for {}s", updated, soak.as_secs());
+        let until = Instant::now() + soak;
+        while Instant::now() < until {
+            tokio::time::sleep(POLL_INTERVAL.min(until.saturating_duration_since(Instant::now())))
+                .await;
+            for (index, host) in self.hosts[..updated].iter().enumerate() {
+                let report = self
+                    .status(host)
+                    .await
+                    .map_err(|e| self.halt(host, format!("{:#}", e), updated))?;
+                if report.recent_failures > self.baseline_failures[index] {
+                    return Err(self.halt(host, "the VM failed during the soak", updated));
+                }
+                if let Some(reason) = unhealthy(&report, &self.target) {
+                    return Err(self.halt(host, reason, updated));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub async fn run_rollout_command(args: RolloutArgs) -> Result<()> {
+    let hosts = load_hosts(&args)?;
+    let sizes = wave_sizes(&args.waves, hosts.len())?;
+    let token = admin_api::read_token(args.token_file.as_deref())?;
+
+    let mut reports = Vec::new();
+    for host in &hosts {
+        let report: StatusReport =
+            admin_api::request(reqwest::Method::GET, host, &token, "/status")
+                .await
+                .context(format!(
+                    "Launcher {} is unreachable; nothing was approved",
+                    host
+                ))?;
+        reports.push(report);
+    }
+    let target = match args.compose_hash.clone() {
+        Some(hash) => hash,
+        None => {
+            let held: BTreeSet<&str> = reports
+                .iter()
+                .filter_map(|r| r.awaiting_approval.as_deref())
+                .collect();
+            match held.len() {
+                0 => anyhow::bail!(
+                    "No launcher is holding an update; pass --compose-hash or wait for their next check"
+                ),
+                1 => held.into_iter().next().unwrap_or_default().to_string(),
+                _ => anyhow::bail!(
+                    "Launchers are holding different updates ({}); pass --compose-hash",
+                    held.into_iter().collect::<Vec<_>>().join(", ")
+                ),
+            }
+        }
+    };
+
+    // Launchers already on the target go first and count toward the first waves
+    let (mut order, mut rest): (Vec<usize>, Vec<usize>) = (0..hosts.len())
+        .partition(|&i| reports[i].compose_hash.as_deref() == Some(target.as_str()));
+    let already = order.len();
+    order.append(&mut rest);
+    let rollout = Rollout {
+        hosts: order.iter().map(|&i| hosts[i].clone()).collect(),
+        token,
+        target,
+        baseline_failures: order.iter().map(|&i| reports[i].recent_failures).collect(),
+    };
+
+    println!(
+        "Rolling out compose hash {} to {} launchers in waves of {}",
+        rollout.target,
+        rollout.hosts.len(),
+        sizes
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    if let Some(changelog) = reports
+        .iter()
+        .filter(|r| r.awaiting_approval.as_deref() == Some(rollout.target.as_str()))
+        .find_map(|r| r.approval_changelog.as_deref())
+    {
+        println!("Changes in this update:");
+        for line in changelog.lines() {
+            println!("  {}", line);
+        }
+    }
+    if already > 0 {
+        println!("  {} launcher(s) already run it", already);
+    }
+    let waves: Vec<usize> = sizes.into_iter().filter(|&end| end > already).collect();
+    let mut start = already;
+    for (number, &end) in waves.iter().enumerate() {
+        println!(
+            "Wave {}/{}: {}",
+            number + 1,
+            waves.len(),
+            rollout.hosts[start..end].join(", ")
+        );
+        rollout
+            .update_wave(start..end, Duration::from_secs(args.wave_timeout))
+            .await?;
+        if end < rollout.hosts.len() {
+            rollout.soak(end, Duration::from_secs(args.soak)).await?;
+        }
+        start = end;
+    }
+    println!(
+        "✓ Rollout of {} complete: {} launchers updated",
+        rollout.target,
+        rollout.hosts.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waves_are_cumulative_and_end_with_the_fleet() {
+        assert_eq!(wave_sizes("1,25%,100%", 10).unwrap(), vec![1, 3, 10]);
+        assert_eq!(wave_sizes("1,25%", 10).unwrap(), vec![1, 3, 10]);
+        // Percentages round up, so a small fleet still gets a canary wave
+        assert_eq!(wave_sizes("10%", 3).unwrap(), vec![1, 3]);
+        // Counts past the fleet are capped and repeated sizes merged
+        assert_eq!(wave_sizes("2,2,50", 4).unwrap(), vec![2, 4]);
+        assert_eq!(wave_sizes("1, ,100%", 1).unwrap(), vec![1]);
+        assert_eq!(wave_sizes("", 5).unwrap(), vec![5]);
+    }
+
+    #[test]
+    fn invalid_waves_are_rejected() {
+        for spec in ["0", "0%", "101%", "-1", "x", "5,2"] {
+            assert!(wave_sizes(spec, 10).is_err(), "{}", spec);
+        }
+        assert!(wave_sizes("50%,2", 10).is_err());
+    }
+
+    fn report(value: serde_json::Value) -> StatusReport {
+        let mut base = serde_json::json!({
+            "version": "0.0.0",
+            "vm_id": "vm-1",
+            "compose_hash": "target",
+            "last_check": { "at": 1 },
+        });
+        for (key, value) in value.as_object().unwrap() {
+            base[key] = value.clone();
+        }
+        serde_json::from_value(base).unwrap()
+    }
+
+    #[test]
+    fn healthy_only_on_target_with_a_clean_check() {
+        assert_eq!(unhealthy(&report(serde_json::json!({})), "target"), None);
+        let reasons = [
+            (
+                serde_json::json!({ "compose_hash": "old" }),
+                "runs compose hash old",
+            ),
+            (
+                serde_json::json!({ "compose_hash": null }),
+                "runs compose hash (none)",
+            ),
+            (
+                serde_json::json!({ "pending_update": "creating VM" }),
+                "update in progress: creating VM",
+            ),
+            (
+                serde_json::json!({ "last_check": null }),
+                "no update check yet",
+            ),
+            (
+                serde_json::json!({ "last_check": { "at": 1, "error": "boom" } }),
+                "last check failed: boom",
+            ),
+        ];
+        for (fields, reason) in reasons {
+            assert_eq!(
+                unhealthy(&report(fields), "target").as_deref(),
+                Some(reason)
+            );
+        }
+    }
+}
@@ -0,0 +1,267 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::provisioning::ProvisioningFingerprint;
+use crate::{store, ComposeConfig};
+
+pub(crate) const STATE_KEY: &str = "state.json";
+
+/// Launcher state persisted under the state directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdaterState {
+    /// VM currently managed by the launcher
+    #[serde(default)]
+    pub vm_id: Option<String>,
+    /// Compose hash of the managed VM
+    #[serde(default)]
+    pub current_hash: Option<String>,
+    /// VMs created or adopted by this launcher; only these are removed
+    /// without `--force`
+    #[serde(default)]
+    pub owned_vm_ids: Vec<String>,
+    /// Resource parameters the managed VM was provisioned with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provisioning: Option<ProvisioningFingerprint>,
+    /// VMM-reported digest of the image the managed VM was validated with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned_image: Option<PinnedImage>,
+    /// Stopped VM kept ready to replace the managed VM, with `warm_standby`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub standby: Option<StandbyVm>,
+    /// Canary VM being verified; promoted or removed once the probes finish
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canary: Option<String>,
+    /// VM failures within the crashloop window, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recent_failures: Vec<VmFailure>,
+    /// Unix timestamps of automatic recreations within the flap window, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recent_recreations: Vec<u64>,
+    /// Recreation in progress; only left behind when the launcher died mid-update
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_update: Option<UpdateIntent>,
+    /// Unix timestamp an operator stopped the VM with `stop` or `remove`; the launcher
+    /// leaves the VM alone until `start` (or `recreate` after `remove`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stopped_by_operator: Option<u64>,
+    /// Unix timestamp an operator paused auto-updates with `pause`; until
+    /// `resume`, checks only report what they would change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paused: Option<u64>,
+    /// Operator notes on the current deployment, oldest first; a new
+    /// deployment starts without any
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<Annotation>,
+}
+
+/// Free-text note an operator attached with `annotate`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Annotation {
+    pub text: String,
+    /// Unix timestamp the note was added
+    pub at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PinnedImage {
+    pub name: String,
+    pub digest: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StandbyVm {
+    pub vm_id: String,
+    /// Compose hash the standby was created with; only a VM due for this
+    /// hash is replaced by it
+    pub compose_hash: String,
+    /// Unix timestamp the standby was created
+    pub created_at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VmFailure {
+    pub vm_id: String,
+    /// Unix timestamp the failure was first seen
+    pub at: u64,
+}
+
+/// Step of a recreation; each is recorded before it starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdatePhase {
+    RemoveOld,
+    Create,
+    /// Deliver the full env and real ports to the verified canary, which is `new_vm_id`
+    Promote,
+    Boot,
+}
+
+impl fmt::Display for UpdatePhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UpdatePhase::RemoveOld => "remove old VM",
+            UpdatePhase::Create => "create VM",
+            UpdatePhase::Promote => "promote canary",
+            UpdatePhase::Boot => "boot VM",
+        })
+    }
+}
+
+/// A planned recreation, persisted so an interrupted one is finished with
+/// the same compose config rather than whatever the API serves after a restart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateIntent {
+    #[serde(default)]
+    pub old_vm_id: Option<String>,
+    pub compose_hash: String,
+    pub phase: UpdatePhase,
+    #[serde(default)]
+    pub new_vm_id: Option<String>,
+    pub started_at: u64,
+    pub compose_config: ComposeConfig,
+    /// Platform API endpoint the compose config came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+}
+
+impl UpdateIntent {
+    /// Step once the old VM is gone: a verified canary is promoted, otherwise
+    /// a VM is created
+    pub fn after_remove_old(&self) -> UpdatePhase {
+        if self.new_vm_id.is_some() {
+            UpdatePhase::Promote
+        } else {
+            UpdatePhase::Create
+        }
+    }
+}
+
+impl fmt::Display for UpdateIntent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} -> {} at step '{}'",
+            self.old_vm_id.as_deref().unwrap_or("(no VM)"),
+            self.compose_hash,
+            self.phase
+        )?;
+        if let Some(new_vm_id) = &self.new_vm_id {
+            write!(f, " (new VM {})", new_vm_id)?;
+        }
+        Ok(())
+    }
+}
+
+impl UpdaterState {
+    /// Load the state file, treating a missing file as empty state
+    pub fn load() -> Result<Self> {
+        Ok(store::get_json(&store::open()?, STATE_KEY)?.unwrap_or_default())
+    }
+
+    pub fn save(&self) -> Result<()> {
+        store::put_json(&store::open()?, STATE_KEY, self)
+    }
+
+    /// Record a VM as the managed one and mark it as owned by this launcher
+    pub fn set_managed(&mut self, vm_id: &str, hash: &str, provisioning: ProvisioningFingerprint) {
+        self.set_current(vm_id, hash);
+        self.provisioning = Some(provisioning);
+        self.claim(vm_id);
+    }
+
+    /// Mark a VM as created or adopted by this launcher
+    pub fn claim(&mut self, vm_id: &str) {
+        if !self.owns(vm_id) {
+            self.owned_vm_ids.push(vm_id.to_string());
+        }
+    }
+
+    pub fn owns(&self, vm_id: &str) -> bool {
+        self.owned_vm_ids.iter().any(|id| id == vm_id)
+    }
+
+    /// Record the VM the launcher manages and the compose hash it runs.
+    /// Annotations only carry over if neither changed.
+    pub fn set_current(&mut self, vm_id: &str, hash: &str) {
+        if self.vm_id.as_deref() != Some(vm_id) || self.current_hash.as_deref() != Some(hash) {
+            self.annotations.clear();
+        }
+        self.vm_id = Some(vm_id.to_string());
+        self.current_hash = Some(hash.to_string());
+    }
+
+    /// Forget a VM that has been removed
+    pub fn forget(&mut self, vm_id: &str) {
+        if self.vm_id.as_deref() == Some(vm_id) {
+            self.vm_id = None;
+            self.current_hash = None;
+            self.provisioning = None;
+            self.pinned_image = None;
+            self.annotations.clear();
+        }
+        if self.standby_id() == Some(vm_id) {
+            self.standby = None;
+        }
+        if self.canary.as_deref() == Some(vm_id) {
+            self.canary = None;
+        }
+        self.owned_vm_ids.retain(|id| id != vm_id);
+    }
+
+    pub fn standby_id(&self) -> Option<&str> {
+        self.standby.as_ref().map(|standby| standby.vm_id.as_str())
+    }
+
+    /// Count a failure of `vm_id` unless it is already counted, dropping
+    /// failures older than `window_secs`. Returns the failures in the window.
+    pub fn record_failure(&mut self, vm_id: &str, now: u64, window_secs: u64) -> usize {
+        let cutoff = now.saturating_sub(window_secs);
+        self.recent_failures.retain(|failure| failure.at > cutoff);
+        if !self
+            .recent_failures
+            .iter()
+            .any(|failure| failure.vm_id == vm_id)
+        {
+            self.recent_failures.push(VmFailure {
+                vm_id: vm_id.to_string(),
+                at: now,
+            });
+        }
+        self.recent_failures.len()
+    }
+
+    /// Recreations within `window_secs` of `now`, oldest first
+    pub fn recreations_within(&self, now: u64, window_secs: u64) -> Vec<u64> {
+        let cutoff = now.saturating_sub(window_secs);
+        self.recent_recreations
+            .iter()
+            .copied()
+            .filter(|at| *at > cutoff)
+            .collect()
+    }
+
+    /// Record an automatic recreation and forget those outside the window
+    pub fn record_recreation(&mut self, now: u64, window_secs: u64) {
+        self.recent_recreations = self.recreations_within(now, window_secs);
+        self.recent_recreations.push(now);
+    }
+
+    /// Load, modify, and save the state in one transaction, logging rather
+    /// than failing on errors. A state file that cannot be read is left as
+    /// it is, so the VMs it records are not forgotten; the change is lost.
+    pub fn update(f: impl FnOnce(&mut Self)) {
+        let result = store::open().and_then(|store| store::update_json(&store, STATE_KEY, f));
+        if let Err(e) = result {
+            tracing::error!(
+                "ALERT: failed to update launcher state, leaving it unchanged: {:#}",
+                e
+            );
+        }
+    }
+}
@@ -0,0 +1,219 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Read-only HTML status page served at `/` of the admin API when enabled,
+//! for a glance at a host from a browser. It shows what `status` and
+//! `events` show, without secrets, and needs no token.
+
+use std::fmt::Write;
+
+use crate::admin_api::{CheckOutcome, EventRecord, StatusReport};
+use crate::timefmt::DisplayTimezone;
+
+/// Seconds between automatic reloads of the page
+const REFRESH_SECS: u32 = 30;
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:1.5em}\
+td,th{border-bottom:1px solid #ddd;padding:4px 12px;text-align:left;vertical-align:top}\
+th{background:#f4f4f4}code{font-size:90%}.ok{color:#080}.bad{color:#b00}";
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn row(html: &mut String, name: &str, value: &str) {
+    let _ = write!(html, "<tr><th>{}</th><td>{}</td></tr>", name, value);
+}
+
+/// The page for a status report, its most recent events, and when the
+/// current deployment was made
+pub fn render(
+    report: &StatusReport,
+    events: &[EventRecord],
+    deployed_at: Option<u64>,
+    now: u64,
+    tz: DisplayTimezone,
+) -> String {
+    let or_none = |value: Option<&str>| {
+        value
+            .map(|v| format!("<code>{}</code>", escape(v)))
+            .unwrap_or_else(|| "(none)".to_string())
+    };
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+         <meta http-equiv=\"refresh\" content=\"{}\">\
+         <title>Validator launcher</title><style>{}</style></head><body>\
+         <h1>Validator launcher</h1><table>",
+        REFRESH_SECS, STYLE
+    );
+    row(&mut html, "Version", &escape(&report.version));
+    if let Some(observe) = report.observe {
+        row(
+            &mut html,
+            "Mode",
+            if observe { "observe" } else { "active" },
+        );
+    }
+    row(&mut html, "Managed VM", &or_none(report.vm_id.as_deref()));
+    row(
+        &mut html,
+        "Compose hash",
+        &or_none(report.compose_hash.as_deref()),
+    );
+    if let Some(hashes) = &report.hash_check {
+        let class = if hashes.disagreeing().is_empty() {
+            "ok"
+        } else {
+            "bad"
+        };
+        row(
+            &mut html,
+            "Hash check",
+            &format!(
+                "<span class=\"{}\">{}</span>",
+                class,
+                escape(&hashes.to_string())
+            ),
+        );
+    }
+    row(
+        &mut html,
+        "Last deployed",
+        &deployed_at
+            .map(|at| tz.format(at))
+            .unwrap_or_else(|| "(unknown)".to_string()),
+    );
+    let last_check = match &report.last_check {
+        Some(CheckOutcome {
+            at, error: None, ..
+        }) => {
+            format!("<span class=\"ok\">ok</span> at {}", tz.format(*at))
+        }
+        Some(CheckOutcome {
+            at,
+            error: Some(error),
+            error_code,
+        }) => format!(
+            "<span class=\"bad\">failed</span> at {}{}: {}",
+            tz.format(*at),
+            error_code
+                .as_ref()
+                .map(|code| format!(" [{}]", escape(code)))
+                .unwrap_or_default(),
+            escape(error)
+        ),
+        None => "(not yet)".to_string(),
+    };
+    row(&mut html, "Last check", &last_check);
+
+    let mut pending = Vec::new();
+    if let Some(intent) = &report.pending_update {
+        pending.push(format!("in progress: {}", escape(intent)));
+    }
+    if let Some(hash) = &report.awaiting_approval {
+        pending.push(format!(
+            "awaiting approval: update to <code>{}</code>",
+            escape(hash)
+        ));
+        if let Some(changelog) = &report.approval_changelog {
+            pending.push(format!(
+                "changes: {}",
+                escape(changelog).replace('\n', "<br>")
+            ));
+        }
+    }
+    if let Some(at) = report.paused {
+        pending.push(format!("auto-updates paused since {}", tz.format(at)));
+    }
+    if let Some(at) = report.stopped_by_operator {
+        pending.push(format!("VM stopped by operator since {}", tz.format(at)));
+    }
+    row(
+        &mut html,
+        "Pending",
+        &if pending.is_empty() {
+            "(none)".to_string()
+        } else {
+            pending.join("<br>")
+        },
+    );
+    if !report.annotations.is_empty() {
+        let notes: Vec<String> = report
+            .annotations
+            .iter()
+            .map(|annotation| format!("{}: {}", tz.format(annotation.at), escape(&annotation.text)))
+            .collect();
+        row(&mut html, "Annotations", &notes.join("<br>"));
+    }
+    row(
+        &mut html,
+        "Recent VM failures",
+        &report.recent_failures.to_string(),
+    );
+    match &report.guest {
+        Some(guest) => {
+            let class = if guest.all_running() { "ok" } else { "bad" };
+            let mut containers = format!("<span class=\"{}\">{}</span>", class, guest);
+            for container in &guest.containers {
+                let _ = write!(
+                    containers,
+                    "<br>{} [{}] {} restarts={}",
+                    escape(&container.name),
+                    escape(&container.state),
+                    escape(&container.status),
+                    container.restarts
+                );
+            }
+            row(&mut html, "Guest", &containers);
+        }
+        None if report.vm_id.is_some() => row(&mut html, "Guest", "(guest agent unreachable)"),
+        None => {}
+    }
+    html.push_str("</table><h2>Recent events</h2>");
+
+    if events.is_empty() {
+        html.push_str("<p>No events since the launcher started</p>");
+    } else {
+        html.push_str("<table><tr><th>Time</th><th>Event</th><th>Details</th></tr>");
+        for record in events.iter().rev() {
+            let kind = record
+                .event
+                .get("type")
+                .and_then(|t| t.as_str())
+                .unwrap_or("Unknown");
+            let mut details = record.event.clone();
+            if let Some(fields) = details.as_object_mut() {
+                fields.remove("type");
+            }
+            let _ = write!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td><code>{}</code></td></tr>",
+                tz.format(record.at),
+                escape(kind),
+                escape(&details.to_string())
+            );
+        }
+        html.push_str("</table>");
+    }
+    let _ = write!(
+        html,
+        "<p><small>Generated {}; reloads every {}s</small></p></body></html>",
+        tz.format(now),
+        REFRESH_SECS
+    );
+    html
+}
@@ -0,0 +1,577 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Embedded key-value store for the launcher's records in the state
+//! directory: its state, the deployment record, and the cached compose base
+//! and env metadata. Each key is a file under its historical name, so
+//! existing state directories open as they are. The audit log is not a
+//! key-value record and keeps its own hash-chained file.
+//!
+//! Writes are transactional. The new values are written and synced next to
+//! their keys, the previous values are copied to `<key>.bak`, and a journal
+//! (`store-txn.json`) listing the writes commits them before anything is moved
+//! into place. A crash before the commit leaves the old values, and the next
+//! writer deletes its orphaned `<key>.txn.tmp` files; after it, reads see the
+//! committed values and the next writer finishes the transaction. Reads never
+//! take the lock or write. A value that no longer parses is moved aside as
+//! `<key>.corrupt-<time>` and its backup restored. `store.json` records the
+//! schema version, and older layouts are migrated once, when first opened.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{paths, unix_now};
+
+const META_KEY: &str = "store.json";
+const JOURNAL: &str = "store-txn.json";
+const LOCK: &str = "store.lock";
+/// Suffix of the files holding a transaction's new values
+const TXN_TMP: &str = ".txn.tmp";
+
+/// Migrations from each schema version to the next, indexed by the version
+/// migrated from
+const MIGRATIONS: &[fn(&FileStore) -> Result<()>] = &[
+    // 0 -> 1: records written before the store existed are read as they are
+    |_| Ok(()),
+];
+
+/// Layout this build reads and writes
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// A set (`Some`) or delete (`None`) of one key
+pub type Write = (String, Option<Vec<u8>>);
+
+pub trait Store {
+    /// The value of `key`, or None if it is not set
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    /// Apply all of `writes`, or none of them if interrupted
+    fn commit(&self, writes: Vec<Write>) -> Result<()>;
+    /// Read, modify, and write `key` with no other writer in between
+    fn update(
+        &self,
+        key: &str,
+        f: &mut dyn FnMut(Option<Vec<u8>>) -> Result<Option<Vec<u8>>>,
+    ) -> Result<()>;
+    /// Set aside the value of `key` as corrupt and restore its backup, if any
+    fn recover(&self, key: &str) -> Result<Option<Vec<u8>>>;
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Meta {
+    schema_version: u32,
+}
+
+/// Keys written by a committed transaction
+#[derive(Debug, Serialize, Deserialize)]
+struct Journal {
+    /// Key and the temporary file holding its new value (None to delete it)
+    writes: Vec<(String, Option<String>)>,
+}
+
+/// The store in a directory, one file per key
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+/// The store in the state directory
+pub fn open() -> Result<FileStore> {
+    FileStore::open(paths::state_dir())
+}
+
+impl FileStore {
+    /// Open the store in `dir`, migrating an older layout. A store at the
+    /// current schema is opened without locking or writing anything. A
+    /// missing directory is created on the first write.
+    pub fn open(dir: PathBuf) -> Result<Self> {
+        let store = Self { dir };
+        if store.schema_version()? < SCHEMA_VERSION && store.dir.exists() {
+            let _lock = store.lock()?;
+            store.prepare_locked()?;
+        }
+        Ok(store)
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(key)
+    }
+
+    fn lock(&self) -> Result<File> {
+        use fs2::FileExt;
+
+        std::fs::create_dir_all(&self.dir)
+            .context(format!("Failed to create {}", self.dir.display()))?;
+        let path = self.path(LOCK);
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .context(format!("Failed to open {}", path.display()))?;
+        file.lock_exclusive()
+            .context(format!("Failed to lock {}", path.display()))?;
+        Ok(file)
+    }
+
+    /// Schema of the store, failing if a newer launcher wrote it
+    fn schema_version(&self) -> Result<u32> {
+        let meta: Meta = match std::fs::read(self.path(META_KEY)) {
+            Ok(content) => serde_json::from_slice(&content)
+                .context(format!("Failed to parse {}", self.path(META_KEY).display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Meta::default(),
+            Err(e) => {
+                return Err(e).context(format!("Failed to read {}", self.path(META_KEY).display()))
+            }
+        };
+        if meta.schema_version > SCHEMA_VERSION {
+            anyhow::bail!(
+                "{} was written by a newer launcher (store schema {}, this build reads up to {})",
+                self.dir.display(),
+                meta.schema_version,
+                SCHEMA_VERSION
+            );
+        }
+        Ok(meta.schema_version)
+    }
+
+    /// Finish a committed transaction, delete the files of an uncommitted
+    /// one, and migrate an older layout; the caller holds the lock
+    fn prepare_locked(&self) -> Result<()> {
+        self.replay()?;
+        self.remove_orphans()?;
+        self.migrate()
+    }
+
+    /// Delete new values no journal commits: left by a writer that died
+    /// before its commit. Only called with the lock held and no journal.
+    fn remove_orphans(&self) -> Result<()> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e).context(format!("Failed to list {}", self.dir.display())),
+        };
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().ends_with(TXN_TMP) {
+                warn!(
+                    "Removing {} left by an interrupted store write",
+                    entry.path().display()
+                );
+                std::fs::remove_file(entry.path())
+                    .context(format!("Failed to remove {}", entry.path().display()))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn migrate(&self) -> Result<()> {
+        let schema_version = self.schema_version()?;
+        if schema_version == SCHEMA_VERSION {
+            return Ok(());
+        }
+        for (from, migration) in MIGRATIONS.iter().enumerate().skip(schema_version as usize) {
+            migration(self).context(format!("Failed to migrate the store from schema {}", from))?;
+        }
+        let migrated = Meta {
+            schema_version: SCHEMA_VERSION,
+        };
+        self.write_synced(&self.path(META_KEY), &serde_json::to_vec_pretty(&migrated)?)?;
+        info!(
+            "Migrated {} from store schema {} to {}",
+            self.dir.display(),
+            schema_version,
+            SCHEMA_VERSION
+        );
+        Ok(())
+    }
+
+    /// Write `content` to `path` atomically and durably
+    fn write_synced(&self, path: &Path, content: &[u8]) -> Result<()> {
+        use std::io::Write;
+
+        let tmp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+        let mut file =
+            File::create(&tmp_path).context(format!("Failed to write {}", tmp_path.display()))?;
+        file.write_all(content)
+            .and_then(|_| file.sync_all())
+            .context(format!("Failed to write {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, path)
+            .context(format!("Failed to replace {}", path.display()))?;
+        self.sync_dir();
+        Ok(())
+    }
+
+    /// Make renames in the directory durable
+    fn sync_dir(&self) {
+        #[cfg(unix)]
+        if let Ok(dir) = File::open(&self.dir) {
+            let _ = dir.sync_all();
+        }
+    }
+
+    /// The committed transaction not yet moved into place, if any
+    fn journal(&self) -> Result<Option<Journal>> {
+        let path = self.path(JOURNAL);
+        let content = match std::fs::read(&path) {
+            Ok(content) => content,
+            // Another process finished it first
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context(format!("Failed to read {}", path.display())),
+        };
+        match serde_json::from_slice(&content) {
+            Ok(journal) => Ok(Some(journal)),
+            Err(e) => {
+                // The journal is renamed into place whole, so this is damage, not a crash
+                warn!("Ignoring unreadable store journal: {}", e);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Move the values of a committed transaction into place. Safe to
+    /// repeat; the caller holds the lock.
+    fn replay(&self) -> Result<()> {
+        let path = self.path(JOURNAL);
+        let Some(journal) = self.journal()? else {
+            return match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(e).context(format!("Failed to remove {}", path.display()))
+                }
+                _ => Ok(()),
+            };
+        };
+        for (key, tmp) in &journal.writes {
+            let target = self.path(key);
+            match tmp {
+                Some(tmp) => match std::fs::rename(self.path(tmp), &target) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => {
+                        return Err(e).context(format!("Failed to replace {}", target.display()))
+                    }
+                },
+                None => {
+                    for path in [target.clone(), backup_path(&target)] {
+                        match std::fs::remove_file(&path) {
+                            Ok(()) => {}
+                            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                            Err(e) => {
+                                return Err(e)
+                                    .context(format!("Failed to remove {}", path.display()))
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        self.sync_dir();
+        std::fs::remove_file(&path).context(format!("Failed to remove {}", path.display()))
+    }
+
+    /// Commit `writes`; the caller holds the lock
+    fn commit_locked(&self, writes: Vec<Write>) -> Result<()> {
+        use std::io::Write;
+
+        let mut journal = Journal { writes: Vec::new() };
+        for (key, value) in writes {
+            let target = self.path(&key);
+            let tmp = match value {
+                Some(value) => {
+                    let tmp = format!("{}{}", key, TXN_TMP);
+                    let tmp_path = self.path(&tmp);
+                    let mut file = File::create(&tmp_path)
+                        .context(format!("Failed to write {}", tmp_path.display()))?;
+                    file.write_all(&value)
+                        .and_then(|_| file.sync_all())
+                        .context(format!("Failed to write {}", tmp_path.display()))?;
+                    Some(tmp)
+                }
+                None => None,
+            };
+            if tmp.is_some() && target.exists() {
+                let previous = std::fs::read(&target)
+                    .context(format!("Failed to back up {}", target.display()))?;
+                self.write_synced(&backup_path(&target), &previous)?;
+            }
+            journal.writes.push((key, tmp));
+        }
+        self.write_synced(&self.path(JOURNAL), &serde_json::to_vec(&journal)?)?;
+        self.replay()
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".bak")
+}
+
+fn read_optional(path: &Path) -> Result<Option<Vec<u8>>> {
+    match std::fs::read(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).context(format!("Failed to read {}", path.display())),
+    }
+}
+
+impl Store for FileStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        // A committed transaction not yet moved into place holds the value
+        let pending = self
+            .journal()?
+            .and_then(|journal| journal.writes.into_iter().find(|(k, _)| k == key));
+        match pending {
+            Some((_, None)) => Ok(None),
+            Some((_, Some(tmp))) => match read_optional(&self.path(&tmp))? {
+                Some(content) => Ok(Some(content)),
+                // Moved into place since the journal was read
+                None => read_optional(&self.path(key)),
+            },
+            None => read_optional(&self.path(key)),
+        }
+    }
+
+    fn commit(&self, writes: Vec<Write>) -> Result<()> {
+        let _lock = self.lock()?;
+        self.prepare_locked()?;
+        self.commit_locked(writes)
+    }
+
+    fn update(
+        &self,
+        key: &str,
+        f: &mut dyn FnMut(Option<Vec<u8>>) -> Result<Option<Vec<u8>>>,
+    ) -> Result<()> {
+        let _lock = self.lock()?;
+        self.prepare_locked()?;
+        let value = f(self.get(key)?)?;
+        self.commit_locked(vec![(key.to_string(), value)])
+    }
+
+    fn recover(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let _lock = self.lock()?;
+        self.prepare_locked()?;
+        let path = self.path(key);
+        let backup = backup_path(&path);
+        if !backup.exists() {
+            return Ok(None);
+        }
+        let corrupt = with_suffix(&path, &format!(".corrupt-{}", unix_now()));
+        if path.exists() {
+            std::fs::rename(&path, &corrupt)
+                .context(format!("Failed to move aside {}", path.display()))?;
+        }
+        let previous =
+            std::fs::read(&backup).context(format!("Failed to restore {}", path.display()))?;
+        self.write_synced(&path, &previous)?;
+        warn!(
+            "{} was corrupt; restored the previous version (corrupt copy kept as {})",
+            path.display(),
+            corrupt.display()
+        );
+        self.get(key)
+    }
+}
+
+/// A JSON value, restoring the backup if the stored one does not parse
+pub fn get_json<T: DeserializeOwned>(store: &dyn Store, key: &str) -> Result<Option<T>> {
+    let Some(content) = store.get(key)? else {
+        return Ok(None);
+    };
+    match serde_json::from_slice(&content) {
+        Ok(value) => Ok(Some(value)),
+        Err(e) => match store.recover(key)? {
+            Some(backup) => serde_json::from_slice(&backup)
+                .map(Some)
+                .context(format!("Failed to parse {} or its backup", key)),
+            None => Err(e).context(format!("Failed to parse {}", key)),
+        },
+    }
+}
+
+pub fn put_json<T: Serialize>(store: &dyn Store, key: &str, value: &T) -> Result<()> {
+    let json = serde_json::to_vec_pretty(value).context(format!("Failed to serialize {}", key))?;
+    store.commit(vec![(key.to_string(), Some(json))])
+}
+
+/// Modify a JSON value (the default if unset) with no other writer in between
+pub fn update_json<T: DeserializeOwned + Serialize + Default>(
+    store: &dyn Store,
+    key: &str,
+    f: impl FnOnce(&mut T),
+) -> Result<()> {
+    // Restore a corrupt value before taking the lock, which recovery needs
+    get_json::<T>(store, key)?;
+    let mut f = Some(f);
+    store.update(key, &mut |content| {
+        let mut value = match content {
+            Some(content) => {
+                serde_json::from_slice(&content).context(format!("Failed to parse {}", key))?
+            }
+            None => T::default(),
+        };
+        if let Some(f) = f.take() {
+            f(&mut value);
+        }
+        let json =
+            serde_json::to_vec_pretty(&value).context(format!("Failed to serialize {}", key))?;
+        Ok(Some(json))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// An empty directory of its own for each test
+    fn test_dir() -> PathBuf {
+        static NEXT: AtomicU32 = AtomicU32::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "launcher-store-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn current(dir: &Path) {
+        let meta = serde_json::to_vec(&Meta {
+            schema_version: SCHEMA_VERSION,
+        })
+        .unwrap();
+        std::fs::write(dir.join(META_KEY), meta).unwrap();
+    }
+
+    #[test]
+    fn commits_and_deletes() {
+        let dir = test_dir();
+        let store = FileStore::open(dir.clone()).unwrap();
+        store
+            .commit(vec![
+                ("a".into(), Some(b"1".to_vec())),
+                ("b".into(), Some(b"2".to_vec())),
+            ])
+            .unwrap();
+        store
+            .commit(vec![("a".into(), Some(b"3".to_vec())), ("b".into(), None)])
+            .unwrap();
+        assert_eq!(store.get("a").unwrap(), Some(b"3".to_vec()));
+        assert_eq!(store.get("b").unwrap(), None);
+        assert_eq!(std::fs::read(dir.join("a.bak")).unwrap(), b"1");
+        assert!(!dir.join(JOURNAL).exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn committed_journal_is_read_then_replayed() {
+        let dir = test_dir();
+        current(&dir);
+        std::fs::write(dir.join("a"), b"old").unwrap();
+        std::fs::write(dir.join("b"), b"gone").unwrap();
+        std::fs::write(dir.join("a.txn.tmp"), b"new").unwrap();
+        let journal = Journal {
+            writes: vec![("a".into(), Some("a.txn.tmp".into())), ("b".into(), None)],
+        };
+        std::fs::write(dir.join(JOURNAL), serde_json::to_vec(&journal).unwrap()).unwrap();
+
+        // Reads see the committed values without finishing the transaction
+        let store = FileStore::open(dir.clone()).unwrap();
+        assert_eq!(store.get("a").unwrap(), Some(b"new".to_vec()));
+        assert_eq!(store.get("b").unwrap(), None);
+        assert!(dir.join(JOURNAL).exists());
+        assert!(!dir.join(LOCK).exists());
+
+        // The next writer does
+        store
+            .commit(vec![("c".into(), Some(b"3".to_vec()))])
+            .unwrap();
+        assert!(!dir.join(JOURNAL).exists());
+        assert!(!dir.join("a.txn.tmp").exists());
+        assert!(!dir.join("b").exists());
+        assert_eq!(std::fs::read(dir.join("a")).unwrap(), b"new");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn uncommitted_write_is_dropped() {
+        let dir = test_dir();
+        current(&dir);
+        std::fs::write(dir.join("a"), b"old").unwrap();
+        // The writer died after writing its new value but before the journal
+        std::fs::write(dir.join("a.txn.tmp"), b"new").unwrap();
+        std::fs::write(dir.join("b.txn.tmp"), b"new").unwrap();
+
+        let store = FileStore::open(dir.clone()).unwrap();
+        assert_eq!(store.get("a").unwrap(), Some(b"old".to_vec()));
+        store
+            .commit(vec![("c".into(), Some(b"3".to_vec()))])
+            .unwrap();
+        assert!(!dir.join("a.txn.tmp").exists());
+        assert!(!dir.join("b.txn.tmp").exists());
+        assert_eq!(store.get("a").unwrap(), Some(b"old".to_vec()));
+        assert_eq!(store.get("b").unwrap(), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_value_is_restored_from_backup() {
+        let dir = test_dir();
+        let store = FileStore::open(dir.clone()).unwrap();
+        put_json(&store, "n", &1u32).unwrap();
+        put_json(&store, "n", &2u32).unwrap();
+        std::fs::write(dir.join("n"), b"{not json").unwrap();
+
+        assert_eq!(get_json::<u32>(&store, "n").unwrap(), Some(1));
+        assert_eq!(store.get("n").unwrap(), Some(b"1".to_vec()));
+        let kept = std::fs::read_dir(&dir)
+            .unwrap()
+            .flatten()
+            .any(|e| e.file_name().to_string_lossy().starts_with("n.corrupt-"));
+        assert!(kept);
+
+        // Without a backup the parse error surfaces
+        std::fs::write(dir.join("m"), b"{not json").unwrap();
+        assert!(get_json::<u32>(&store, "m").is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn migrates_older_layout_once() {
+        let dir = test_dir();
+        std::fs::write(dir.join("a"), b"1").unwrap();
+        let store = FileStore::open(dir.clone()).unwrap();
+        let meta: Meta =
+            serde_json::from_slice(&std::fs::read(dir.join(META_KEY)).unwrap()).unwrap();
+        assert_eq!(meta.schema_version, SCHEMA_VERSION);
+        assert_eq!(store.get("a").unwrap(), Some(b"1".to_vec()));
+
+        // A missing directory is left alone until the first write
+        let missing = dir.join("missing");
+        FileStore::open(missing.clone()).unwrap();
+        assert!(!missing.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_newer_schema() {
+        let dir = test_dir();
+        let meta = serde_json::to_vec(&Meta {
+            schema_version: SCHEMA_VERSION + 1,
+        })
+        .unwrap();
+        std::fs::write(dir.join(META_KEY), meta).unwrap();
+        assert!(FileStore::open(dir.clone()).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,630 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! WebSocket subscription to the compose config. With `api_transport` set to
+//! `websocket`, the launcher keeps one connection open to
+//! `wss://<api url>/subscribe`, and the API pushes the compose config over it,
+//! exactly as served to a GET, whenever it changes. While subscribed, update
+//! checks use the last pushed config instead of polling; while the connection
+//! is down they fall back to HTTP, and the launcher reconnects with backoff
+//! and subscribes again, naming the config it already has.
+//!
+//! Speaks just enough RFC 6455 for this: text messages (fragmented or not),
+//! pings, and close. The transport needs the `websocket` feature; without it
+//! the setting is accepted and the launcher keeps polling.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+#[cfg(feature = "websocket")]
+use {
+    crate::{compose_patch, read_key_material, ApiTlsConfig},
+    anyhow::Context,
+    base64::Engine,
+    reqwest::header::HeaderMap,
+    serde_json::{json, Value},
+    std::time::{Duration, Instant},
+    tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    tokio::net::TcpStream,
+    tokio::sync::mpsc,
+    tokio_native_tls::native_tls,
+    tracing::{debug, info, warn},
+};
+
+#[cfg(feature = "websocket")]
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// A connection without any frame for this long (our pings go unanswered)
+/// is taken as dead
+#[cfg(feature = "websocket")]
+const IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+#[cfg(feature = "websocket")]
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+#[cfg(feature = "websocket")]
+const RECONNECT_MIN: Duration = Duration::from_secs(1);
+#[cfg(feature = "websocket")]
+const RECONNECT_MAX: Duration = Duration::from_secs(60);
+#[cfg(feature = "websocket")]
+const MAX_HANDSHAKE_BYTES: usize = 16 * 1024;
+
+/// Appended to the handshake key before hashing, per RFC 6455
+#[cfg(feature = "websocket")]
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+#[cfg(feature = "websocket")]
+const OPCODE_CONTINUATION: u8 = 0x0;
+#[cfg(feature = "websocket")]
+const OPCODE_TEXT: u8 = 0x1;
+#[cfg(feature = "websocket")]
+const OPCODE_BINARY: u8 = 0x2;
+#[cfg(feature = "websocket")]
+const OPCODE_CLOSE: u8 = 0x8;
+#[cfg(feature = "websocket")]
+const OPCODE_PING: u8 = 0x9;
+#[cfg(feature = "websocket")]
+const OPCODE_PONG: u8 = 0xA;
+
+/// How the compose config is fetched from the platform API
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiTransport {
+    /// Poll with HTTP GET requests
+    #[default]
+    Http,
+    /// Subscribe over one persistent WebSocket connection
+    Websocket,
+}
+
+impl fmt::Display for ApiTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ApiTransport::Http => "http",
+            ApiTransport::Websocket => "websocket",
+        })
+    }
+}
+
+/// A compose config pushed by the API; `seq` tells successive ones apart
+#[derive(Debug, Clone)]
+pub struct Pushed {
+    pub seq: u64,
+    pub payload: String,
+}
+
+#[derive(Default)]
+struct Shared {
+    latest: Mutex<Option<Pushed>>,
+    changed: Notify,
+    connected: AtomicBool,
+}
+
+/// The background connection to the API's subscription endpoint. Dropping it
+/// closes the connection.
+pub struct Subscription {
+    url: String,
+    #[cfg(feature = "websocket")]
+    task: tokio::task::JoinHandle<()>,
+    shared: Arc<Shared>,
+}
+
+impl Subscription {
+    /// Subscribe at `api_url`, sending `headers` with the handshake and
+    /// accepting messages up to `max_bytes`
+    #[cfg(feature = "websocket")]
+    pub fn spawn(
+        api_url: &str,
+        tls: Option<&ApiTlsConfig>,
+        headers: HeaderMap,
+        max_bytes: usize,
+    ) -> Result<Self> {
+        let url = subscribe_url(api_url)?;
+        let connector = tokio_native_tls::TlsConnector::from(tls_connector(tls)?);
+        let shared = Arc::new(Shared::default());
+        let task = tokio::spawn(maintain(
+            Endpoint::parse(&url)?,
+            connector,
+            headers,
+            max_bytes,
+            shared.clone(),
+        ));
+        Ok(Self { url, task, shared })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.shared.connected.load(Ordering::Relaxed)
+    }
+
+    /// The last config pushed over the current connection; None while
+    /// disconnected, or before the API sent one
+    pub fn latest(&self) -> Option<Pushed> {
+        if !self.is_connected() {
+            return None;
+        }
+        self.shared
+            .latest
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+#[cfg(feature = "websocket")]
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// `api_url` with a WebSocket scheme and `/subscribe` appended
+pub fn subscribe_url(api_url: &str) -> Result<String> {
+    let url = api_url.trim_end_matches('/');
+    let rest = if let Some(rest) = url.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        anyhow::bail!("API URL '{}' is neither http nor https", api_url);
+    };
+    Ok(format!("{}/subscribe", rest))
+}
+
+/// Resolves when the API pushed a config or the connection dropped; never
+/// without a subscription
+pub async fn changed(subscription: Option<&Subscription>) {
+    match subscription {
+        Some(subscription) => subscription.shared.changed.notified().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// The platform API's TLS settings for a connection made outside reqwest
+#[cfg(feature = "websocket")]
+fn tls_connector(tls: Option<&ApiTlsConfig>) -> Result<native_tls::TlsConnector> {
+    let mut builder = native_tls::TlsConnector::builder();
+    match tls {
+        Some(tls) => {
+            let cert = std::fs::read(&tls.client_cert).context(format!(
+                "Failed to read client certificate {}",
+                tls.client_cert
+            ))?;
+            let key = read_key_material(&tls.client_key)?;
+            builder.identity(
+                native_tls::Identity::from_pkcs8(&cert, &key)
+                    .context("Failed to load mTLS client identity (key must be PKCS#8 PEM)")?,
+            );
+            // Verified against the system roots without a CA bundle, as for the HTTP client
+            if let Some(path) = &tls.ca_cert {
+                let pem =
+                    std::fs::read(path).context(format!("Failed to read CA bundle {}", path))?;
+                builder.add_root_certificate(
+                    native_tls::Certificate::from_pem(&pem)
+                        .context(format!("Failed to parse CA bundle {}", path))?,
+                );
+            }
+        }
+        // As for the HTTP client
+        None => {
+            builder.danger_accept_invalid_certs(true);
+        }
+    }
+    builder.build().context("Failed to create TLS connector")
+}
+
+#[cfg(feature = "websocket")]
+struct Endpoint {
+    url: String,
+    host: String,
+    port: u16,
+    tls: bool,
+    /// Path and query of the request
+    target: String,
+}
+
+#[cfg(feature = "websocket")]
+impl Endpoint {
+    fn parse(url: &str) -> Result<Self> {
+        let parsed = reqwest::Url::parse(url).context(format!("Invalid URL '{}'", url))?;
+        let tls = parsed.scheme() == "wss";
+        let host = parsed
+            .host_str()
+            .context(format!("URL '{}' has no host", url))?
+            .to_string();
+        let target = match parsed.query() {
+            Some(query) => format!("{}?{}", parsed.path(), query),
+            None => parsed.path().to_string(),
+        };
+        Ok(Self {
+            url: url.to_string(),
+            port: parsed
+                .port_or_known_default()
+                .unwrap_or(if tls { 443 } else { 80 }),
+            host,
+            tls,
+            target,
+        })
+    }
+
+    fn host_header(&self) -> String {
+        match (self.tls, self.port) {
+            (true, 443) | (false, 80) => self.host.clone(),
+            _ => format!("{}:{}", self.host, self.port),
+        }
+    }
+}
+
+#[cfg(feature = "websocket")]
+async fn maintain(
+    endpoint: Endpoint,
+    connector: tokio_native_tls::TlsConnector,
+    headers: HeaderMap,
+    max_bytes: usize,
+    shared: Arc<Shared>,
+) {
+    let mut delay = RECONNECT_MIN;
+    let mut warned = false;
+    loop {
+        let result = connect(&endpoint, &connector, &headers, max_bytes, &shared).await;
+        if shared.connected.swap(false, Ordering::Relaxed) {
+            // Whatever was pushed while reconnecting would be missed
+            shared.changed.notify_one();
+            delay = RECONNECT_MIN;
+            warned = false;
+        }
+        match result {
+            Ok(()) => debug!("Subscription {} closed, reconnecting", endpoint.url),
+            Err(e) if !warned => {
+                warn!(
+                    "Subscription {} unavailable, polling until it is back: {:#}",
+                    endpoint.url, e
+                );
+                warned = true;
+            }
+            Err(e) => debug!("Subscription {} still unavailable: {:#}", endpoint.url, e),
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(RECONNECT_MAX);
+    }
+}
+
+#[cfg(feature = "websocket")]
+async fn connect(
+    endpoint: &Endpoint,
+    connector: &tokio_native_tls::TlsConnector,
+    headers: &HeaderMap,
+    max_bytes: usize,
+    shared: &Shared,
+) -> Result<()> {
+    let tcp = tokio::time::timeout(
+        CONNECT_TIMEOUT,
+        TcpStream::connect((endpoint.host.as_str(), endpoint.port)),
+    )
+    .await
+    .context("Timed out connecting")?
+    .context("Failed to connect")?;
+    if !endpoint.tls {
+        return session(tcp, endpoint, headers, max_bytes, shared).await;
+    }
+    let tls = tokio::time::timeout(CONNECT_TIMEOUT, connector.connect(&endpoint.host, tcp))
+        .await
+        .context("Timed out during TLS handshake")??;
+    session(tls, endpoint, headers, max_bytes, shared).await
+}
+
+/// One connection: returns when the server closes it
+#[cfg(feature = "websocket")]
+async fn session<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    stream: S,
+    endpoint: &Endpoint,
+    headers: &HeaderMap,
+    max_bytes: usize,
+    shared: &Shared,
+) -> Result<()> {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+    tokio::time::timeout(
+        CONNECT_TIMEOUT,
+        handshake(&mut reader, &mut writer, endpoint, headers),
+    )
+    .await
+    .context("Timed out during the WebSocket handshake")??;
+
+    // Frames are read on their own task, so pings can go out in between
+    let (frames_tx, mut frames) = mpsc::channel(4);
+    let read_task = tokio::spawn(async move {
+        loop {
+            let frame = read_frame(&mut reader, max_bytes).await;
+            let failed = frame.is_err();
+            if frames_tx.send(frame).await.is_err() || failed {
+                return;
+            }
+        }
+    });
+
+    let result = async {
+        let have = shared
+            .latest
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .map(|pushed| compose_patch::content_sha256(&pushed.payload));
+        let subscribe = json!({ "type": "subscribe", "sha256": have }).to_string();
+        write_frame(&mut writer, OPCODE_TEXT, subscribe.as_bytes()).await?;
+        shared.connected.store(true, Ordering::Relaxed);
+        info!("Subscribed to compose config changes at {}", endpoint.url);
+
+        let mut ping =
+            tokio::time::interval_at(tokio::time::Instant::now() + PING_INTERVAL, PING_INTERVAL);
+        let mut last_frame = Instant::now();
+        let mut message: Option<Vec<u8>> = None;
+        loop {
+            let frame = tokio::select! {
+                frame = frames.recv() => frame.context("Connection lost")??,
+                _ = ping.tick() => {
+                    if last_frame.elapsed() > IDLE_TIMEOUT {
+                        anyhow::bail!("No data for {}s", IDLE_TIMEOUT.as_secs());
+                    }
+                    write_frame(&mut writer, OPCODE_PING, b"").await?;
+                    continue;
+                }
+            };
+            last_frame = Instant::now();
+            match frame.opcode {
+                OPCODE_PING => write_frame(&mut writer, OPCODE_PONG, &frame.payload).await?,
+                OPCODE_PONG => {}
+                OPCODE_CLOSE => {
+                    write_frame(&mut writer, OPCODE_CLOSE, &frame.payload)
+                        .await
+                        .ok();
+                    return Ok(());
+                }
+                OPCODE_TEXT | OPCODE_BINARY | OPCODE_CONTINUATION => {
+                    let text = match (frame.opcode, message.as_mut()) {
+                        (OPCODE_CONTINUATION, Some(text)) => text,
+                        (OPCODE_CONTINUATION, None) => {
+                            anyhow::bail!("Continuation frame without a message")
+                        }
+                        _ => message.insert(Vec::new()),
+                    };
+                    if text.len() + frame.payload.len() > max_bytes {
+                        anyhow::bail!("Message exceeded {} bytes", max_bytes);
+                    }
+                    text.extend_from_slice(&frame.payload);
+                    if frame.fin {
+                        let text = message.take().unwrap_or_default();
+                        receive(
+                            shared,
+                            String::from_utf8(text).context("Message is not UTF-8")?,
+                        );
+                    }
+                }
+                opcode => anyhow::bail!("Unexpected frame opcode {:#x}", opcode),
+            }
+        }
+    }
+    .await;
+    read_task.abort();
+    result
+}
+
+/// Handle a complete message: a compose config, or a control message
+/// carrying a `type` (e.g. an acknowledgement or an error)
+#[cfg(feature = "websocket")]
+fn receive(shared: &Shared, text: String) {
+    if let Ok(Value::Object(object)) = serde_json::from_str::<Value>(&text) {
+        if let Some(kind) = object.get("type").and_then(|t| t.as_str()) {
+            match kind {
+                "error" => warn!(
+                    "Subscription error from the API: {}",
+                    object
+                        .get("message")
+                        .and_then(|m| m.as_str())
+                        .unwrap_or("(no message)")
+                ),
+                kind => debug!("Subscription message '{}'", kind),
+            }
+            return;
+        }
+    }
+    debug!("Compose config pushed ({} bytes)", text.len());
+    {
+        let mut latest = shared.latest.lock().unwrap_or_else(|e| e.into_inner());
+        let seq = latest.as_ref().map_or(1, |pushed| pushed.seq + 1);
+        *latest = Some(Pushed { seq, payload: text });
+    }
+    shared.changed.notify_one();
+}
+
+#[cfg(feature = "websocket")]
+async fn handshake<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
+    reader: &mut BufReader<R>,
+    writer: &mut W,
+    endpoint: &Endpoint,
+    headers: &HeaderMap,
+) -> Result<()> {
+    let key = base64::engine::general_purpose::STANDARD.encode(rand::random::<[u8; 16]>());
+    let mut request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n",
+        endpoint.target,
+        endpoint.host_header(),
+        key
+    );
+    for (name, value) in headers {
+        if let Ok(value) = value.to_str() {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+    }
+    request.push_str("\r\n");
+    writer.write_all(request.as_bytes()).await?;
+
+    let mut status_line = String::new();
+    let mut accept = None;
+    let mut read = 0;
+    loop {
+        let mut line = String::new();
+        read += reader.read_line(&mut line).await?;
+        if read > MAX_HANDSHAKE_BYTES {
+            anyhow::bail!("Handshake response too large");
+        }
+        let line = line.trim_end();
+        if status_line.is_empty() {
+            if line.is_empty() {
+                anyhow::bail!("Connection closed during the handshake");
+            }
+            status_line = line.to_string();
+            continue;
+        }
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-accept") {
+                accept = Some(value.trim().to_string());
+            }
+        }
+    }
+    if status_line.split_whitespace().nth(1) != Some("101") {
+        anyhow::bail!(
+            "API answered '{}' instead of switching to WebSocket",
+            status_line
+        );
+    }
+    let expected = base64::engine::general_purpose::STANDARD
+        .encode(sha1(format!("{}{}", key, ACCEPT_GUID).as_bytes()));
+    if accept.as_deref() != Some(expected.as_str()) {
+        anyhow::bail!("API sent an invalid Sec-WebSocket-Accept");
+    }
+    Ok(())
+}
+
+#[cfg(feature = "websocket")]
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+#[cfg(feature = "websocket")]
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R, max_bytes: usize) -> Result<Frame> {
+    let mut head = [0u8; 2];
+    reader.read_exact(&mut head).await?;
+    let len = match head[1] & 0x7F {
+        126 => reader.read_u16().await? as u64,
+        127 => reader.read_u64().await?,
+        len => len as u64,
+    };
+    if len > max_bytes as u64 {
+        anyhow::bail!("Frame of {} bytes exceeds {} bytes", len, max_bytes);
+    }
+    let mask = if head[1] & 0x80 != 0 {
+        let mut mask = [0u8; 4];
+        reader.read_exact(&mut mask).await?;
+        Some(mask)
+    } else {
+        None
+    };
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload).await?;
+    if let Some(mask) = mask {
+        apply_mask(&mut payload, mask);
+    }
+    Ok(Frame {
+        fin: head[0] & 0x80 != 0,
+        opcode: head[0] & 0x0F,
+        payload,
+    })
+}
+
+/// Write one final frame; frames from a client are always masked
+#[cfg(feature = "websocket")]
+async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    opcode: u8,
+    payload: &[u8],
+) -> Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(0x80 | len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(0x80 | 127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    let mask = rand::random::<[u8; 4]>();
+    frame.extend_from_slice(&mask);
+    let start = frame.len();
+    frame.extend_from_slice(payload);
+    apply_mask(&mut frame[start..], mask);
+    writer.write_all(&frame).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+#[cfg(feature = "websocket")]
+fn apply_mask(payload: &mut [u8], mask: [u8; 4]) {
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+/// SHA-1, needed only for the handshake's `Sec-WebSocket-Accept`
+#[cfg(feature = "websocket")]
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut words = [0u32; 80];
+        for (word, bytes) in words.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in words.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (bytes, value) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
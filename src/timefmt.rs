@@ -0,0 +1,70 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Result;
+use chrono::{DateTime, Local, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::PlatformConfig;
+
+/// Timezone used when showing timestamps to operators
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisplayTimezone {
+    /// The host's local timezone
+    Local,
+    Named(Tz),
+}
+
+impl DisplayTimezone {
+    /// Accepts `local` or an IANA name such as `UTC` or `Europe/Berlin`
+    pub fn parse(name: &str) -> Result<Self> {
+        if name.eq_ignore_ascii_case("local") {
+            return Ok(Self::Local);
+        }
+        name.parse::<Tz>().map(Self::Named).map_err(|_| {
+            anyhow::anyhow!(
+                "Unknown timezone '{}' (use 'local' or an IANA name like 'Europe/Berlin')",
+                name
+            )
+        })
+    }
+
+    /// The configured timezone, falling back to host local time
+    pub fn from_config(platform_config: &PlatformConfig) -> Self {
+        match platform_config.timezone.as_deref().map(Self::parse) {
+            Some(Ok(tz)) => tz,
+            Some(Err(e)) => {
+                tracing::warn!("{:#}, showing host local time", e);
+                Self::Local
+            }
+            None => Self::Local,
+        }
+    }
+
+    /// e.g. `2025-03-01 14:05:09 CET`, or with a numeric offset for local time
+    pub fn format(&self, unix_secs: u64) -> String {
+        let Some(utc) = DateTime::<Utc>::from_timestamp(unix_secs as i64, 0) else {
+            return unix_secs.to_string();
+        };
+        match self {
+            Self::Local => Local
+                .from_utc_datetime(&utc.naive_utc())
+                .format("%Y-%m-%d %H:%M:%S %:z")
+                .to_string(),
+            Self::Named(tz) => tz
+                .from_utc_datetime(&utc.naive_utc())
+                .format("%Y-%m-%d %H:%M:%S %Z")
+                .to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for DisplayTimezone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Local => f.write_str("local"),
+            Self::Named(tz) => f.write_str(tz.name()),
+        }
+    }
+}
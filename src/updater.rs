@@ -0,0 +1,6315 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+use tracing::{debug, error, info, warn};
+
+use crate::allowed_envs::{self, AllowedEnvs};
+use crate::{
+    admin_api, alerting, api_backoff, api_schema, audit, bandwidth, canary, capture, clock,
+    compose_hash, compose_patch, conditional, defaults, deployment, disclosure, drift, endpoints,
+    env_metadata, envelope, error_code, events, guest_exec, health, hostinfo, http_config, limits,
+    log_dedup, missing_env, network, notify, paths, power, provisioning, push, reconcile, recovery,
+    retention, state, store, subscribe, timefmt, utilization, validation, vmm_supervisor,
+};
+#[cfg(feature = "metrics")]
+use crate::{admin_server, guest_metrics};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(defaults::POLL_INTERVAL_SECS);
+/// Poll interval while the push event stream is connected
+const PUSH_POLL_INTERVAL: Duration = Duration::from_secs(defaults::PUSH_POLL_SECS);
+const VM_KILL_TIMEOUT: Duration = Duration::from_secs(60);
+/// How often local data is checked against the retention policy
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+/// How often the service looks for orphaned VMs
+const ORPHAN_SCAN_INTERVAL: Duration = Duration::from_secs(3600);
+/// How long a destructive step waits for an unreachable VMM to come back
+const VMM_WAIT_TIMEOUT: Duration = Duration::from_secs(300);
+const VMM_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+const MUTATION_LOCK_RETRY: Duration = Duration::from_secs(1);
+/// How often `apply --wait` re-checks the guest and health checks
+const READY_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Creation attempts after the old VM is gone, when the VMM drops out mid-create
+const CREATE_ATTEMPTS: u32 = 3;
+/// Number of console log lines captured when a new VM fails to come up
+const FAILURE_LOG_LINES: usize = 100;
+pub(crate) const VM_NAME: &str = "validator_vm";
+/// Upper bound on the compose config response we are willing to buffer
+const MAX_API_RESPONSE_BYTES: usize = 4 * 1024 * 1024;
+/// Platform config file on Linux hosts; see [`paths::config_path`]
+pub const PLATFORM_CONFIG_PATH: &str = "/etc/platform-validator/config.json";
+/// State directory on Linux hosts; see [`paths::state_dir`]
+pub const STATE_DIR: &str = "/var/lib/platform-validator";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComposeConfig {
+    pub vm_type: String,
+    /// Full compose content; empty when the API sent `compose_patch` instead
+    #[serde(default)]
+    pub compose_content: String,
+    /// Human-readable changelog: why the platform is asking for this update
+    #[serde(default)]
+    pub description: Option<String>,
+    pub updated_at: String,
+    #[serde(default)]
+    pub required_env: Vec<String>,
+    #[serde(default)]
+    pub provisioning: VmProvisioningConfig,
+    /// Per-key descriptions, examples, and secret flags for env keys
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub env_metadata: env_metadata::EnvMetadata,
+    /// Compose hash the platform computed for this config, if it publishes one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compose_hash: Option<String>,
+    /// Compose hash algorithm version the platform expects (absent means 1)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash_version: Option<u32>,
+    /// Compose config schema version (absent means 1)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>,
+    /// Optional features the API is using in this response
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+    /// Patch against the compose content the launcher offered as its base
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compose_patch: Option<compose_patch::ComposePatch>,
+}
+
+impl ComposeConfig {
+    /// The description, if the API sent a non-blank one
+    pub fn changelog(&self) -> Option<&str> {
+        self.description
+            .as_deref()
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+    }
+
+    /// Keys that need a local value: `required_env`, then `provisioning.env_keys`
+    pub fn required_env_keys(&self) -> Vec<String> {
+        let mut keys = self.required_env.clone();
+        for key in &self.provisioning.env_keys {
+            if !keys.contains(key) {
+                keys.push(key.clone());
+            }
+        }
+        keys
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VmProvisioningConfig {
+    #[serde(default)]
+    pub env_keys: Vec<String>,
+    #[serde(default)]
+    pub manifest_defaults: ManifestDefaults,
+    #[serde(default)]
+    pub vm_parameters: VmParameters,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestDefaults {
+    pub manifest_version: u32,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub runner: String,
+    #[serde(default)]
+    pub kms_enabled: bool,
+    #[serde(default)]
+    pub gateway_enabled: bool,
+    #[serde(default)]
+    pub local_key_provider_enabled: bool,
+    #[serde(default)]
+    pub key_provider_id: String,
+    #[serde(default)]
+    pub public_logs: bool,
+    #[serde(default)]
+    pub public_sysinfo: bool,
+    #[serde(default)]
+    pub public_tcbinfo: bool,
+    #[serde(default)]
+    pub no_instance_id: bool,
+    #[serde(default)]
+    pub secure_time: bool,
+}
+
+impl Default for ManifestDefaults {
+    fn default() -> Self {
+        Self {
+            manifest_version: 2,
+            name: Some(VM_NAME.to_string()),
+            runner: "docker-compose".to_string(),
+            kms_enabled: true,
+            gateway_enabled: true,
+            local_key_provider_enabled: false,
+            key_provider_id: String::new(),
+            public_logs: true,
+            public_sysinfo: true,
+            public_tcbinfo: true,
+            no_instance_id: false,
+            secure_time: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VmParameters {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub image: String,
+    pub vcpu: u32,
+    pub memory: u32,
+    pub disk_size: u32,
+    #[serde(default)]
+    pub user_config: String,
+    #[serde(default)]
+    pub ports: Vec<PortMapping>,
+    #[serde(default)]
+    pub hugepages: bool,
+    #[serde(default)]
+    pub pin_numa: bool,
+    #[serde(default)]
+    pub stopped: bool,
+}
+
+impl Default for VmParameters {
+    fn default() -> Self {
+        Self {
+            name: Some(VM_NAME.to_string()),
+            image: "dstack-0.5.2".to_string(),
+            vcpu: 16,
+            memory: 16 * 1024,
+            disk_size: 200,
+            user_config: String::new(),
+            ports: Vec::new(),
+            hugepages: false,
+            pin_numa: false,
+            stopped: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortMapping {
+    pub protocol: String,
+    pub host_port: u16,
+    pub vm_port: u16,
+    #[serde(default)]
+    pub host_address: Option<String>,
+}
+
+impl Default for PortMapping {
+    fn default() -> Self {
+        Self {
+            protocol: "tcp".to_string(),
+            host_port: 0,
+            vm_port: 0,
+            host_address: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlatformConfig {
+    #[serde(default)]
+    pub dstack_vmm_url: Option<String>,
+    #[serde(default)]
+    pub env: Option<std::collections::HashMap<String, String>>,
+    /// VMM RPC endpoint used by the launcher itself (host side)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vmm_url: Option<String>,
+    /// Platform network whose endpoints are used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<network::Network>,
+    /// Compose config endpoint of the platform API, overriding the network's
+    /// (e.g. a staging or mock API)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+    /// Compose config endpoints tried in order while the API URL is
+    /// unreachable or answers 5xx
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub api_mirrors: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poll_interval_secs: Option<u64>,
+    /// Random spread of each poll interval, in percent either way
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poll_jitter_percent: Option<u8>,
+    /// While the API answers 304 Not Modified, seconds between full checks
+    /// of an up-to-date VM (0 checks it on every poll)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vm_recheck_secs: Option<u64>,
+    /// Keep an event stream open to the platform API and check for an
+    /// update as soon as it announces one
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub push_updates: bool,
+    /// How the compose config is fetched: polled over HTTP, or pushed over
+    /// a WebSocket subscription
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_transport: Option<subscribe::ApiTransport>,
+    /// Timezone for displayed timestamps (`local` or an IANA name)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_tls: Option<ApiTlsConfig>,
+    /// User agent and extra headers for platform API requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<http_config::HttpConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource_limits: Option<limits::ResourceLimits>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drift_policy: Option<drift::DriftPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notifications: Option<notify::NotificationConfig>,
+    /// How stopped and failed VMs are brought back
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recovery: Option<recovery::RecoveryPolicy>,
+    /// Read-only admin API for remote observers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_api: Option<admin_api::AdminApiConfig>,
+    /// Bounds on local data under the state directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention: Option<retention::RetentionPolicy>,
+    /// External records (load balancers, DNS) updated after the VM is recreated
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub endpoint_integrations: Vec<endpoints::EndpointIntegration>,
+    /// Public IP announced to endpoint integrations, if it can't be derived from port bindings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_ip: Option<String>,
+    /// Refuse to trust a VM whose image digest differs from the one pinned when it was deployed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub require_image_digest: bool,
+    /// Local user_config, merged over the API-provided value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_config: Option<String>,
+    /// Runners and manifest versions each guest image supports, by image name
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub image_support: std::collections::BTreeMap<String, validation::ImageSupport>,
+    /// Operator-defined checks that feed into the launcher's health
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub health_checks: Vec<health::HealthCheckConfig>,
+    /// Verify the new deployment in a canary VM before replacing the running one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canary: Option<canary::CanaryConfig>,
+    /// Hold updates of a running VM until approved through the admin API (`rollout`, `approve`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub require_update_approval: bool,
+    /// Remove VMs the launcher created but no longer manages, found by the hourly orphan scan
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub remove_orphans: bool,
+    /// Keep a stopped VM with the current config ready to replace a failed one
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub warm_standby: bool,
+    /// Local additions to and removals from the resolved allowed_envs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_envs: Option<allowed_envs::AllowedEnvsOverride>,
+    /// What an update check does when a required env key has no value, per key class
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub missing_env: Option<missing_env::MissingEnvPolicy>,
+    /// How long a VM may take to boot, scaled by disk size for a new VM
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub boot_timeout: Option<provisioning::BootTimeout>,
+    /// Manifest disclosure flags kept off regardless of the API's manifest defaults
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disclosure: Option<disclosure::DisclosurePolicy>,
+    /// Shutdown and suspend handling through systemd-logind
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub power: Option<power::PowerPolicy>,
+    /// VMM process the launcher starts and restarts itself (all-in-one mode)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedded_vmm: Option<vmm_supervisor::EmbeddedVmmConfig>,
+    /// Host clock drift tolerated before creating a VM, which attests as it boots
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock_check: Option<clock::ClockPolicy>,
+}
+
+/// Client identity presented to the platform API for mutual TLS
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiTlsConfig {
+    /// Path to the PEM-encoded client certificate (chain)
+    pub client_cert: String,
+    /// Path to the PEM-encoded PKCS#8 private key
+    pub client_key: String,
+    /// Optional PEM CA bundle used to verify the platform API server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_cert: Option<String>,
+}
+
+impl ApiTlsConfig {
+    pub(crate) fn load_identity(&self) -> Result<reqwest::Identity> {
+        let cert = std::fs::read(&self.client_cert).context(format!(
+            "Failed to read client certificate {}",
+            self.client_cert
+        ))?;
+        let key = read_key_material(&self.client_key)?;
+
+        reqwest::Identity::from_pkcs8_pem(&cert, &key)
+            .context("Failed to load mTLS client identity (key must be PKCS#8 PEM)")
+    }
+
+    pub(crate) fn load_ca_cert(&self) -> Result<Option<reqwest::Certificate>> {
+        let Some(path) = &self.ca_cert else {
+            return Ok(None);
+        };
+        let pem = std::fs::read(path).context(format!("Failed to read CA bundle {}", path))?;
+        let cert = reqwest::Certificate::from_pem(&pem)
+            .context(format!("Failed to parse CA bundle {}", path))?;
+        Ok(Some(cert))
+    }
+}
+
+/// Read private key material, refusing files that other users can read
+pub(crate) fn read_key_material(path: &str) -> Result<Vec<u8>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = std::fs::metadata(path)
+            .context(format!("Failed to stat key file {}", path))?
+            .permissions()
+            .mode();
+        if mode & 0o077 != 0 {
+            anyhow::bail!(
+                "Key file {} is accessible by other users (mode {:o}); run 'chmod 600 {}'",
+                path,
+                mode & 0o777,
+                path
+            );
+        }
+    }
+
+    std::fs::read(path).context(format!("Failed to read key file {}", path))
+}
+
+impl PlatformConfig {
+    pub fn load() -> Result<Self> {
+        let path = paths::config_path();
+        let config_content =
+            std::fs::read_to_string(&path).context(format!("Failed to read {}", path.display()))?;
+
+        let config = serde_json::from_str(&config_content)
+            .map_err(|e| error_code::ErrorCode::ConfigInvalid.error(e.to_string()))
+            .context("Failed to parse config JSON")?;
+
+        Ok(config)
+    }
+
+    /// Atomically replace the config file: write a temp file, fsync, then rename,
+    /// so readers never observe a partially written config
+    pub fn save(&self) -> Result<()> {
+        use std::io::Write;
+
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize config")?;
+
+        let path = paths::config_path();
+        let old_sha256 = std::fs::read(&path)
+            .ok()
+            .map(|old| hex::encode(Sha256::digest(old)));
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .context(format!("Failed to create {}", parent.display()))?;
+        }
+        let tmp_path = path.with_extension(format!("json.tmp.{}", std::process::id()));
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            // The config holds secrets such as HOTKEY_PASSPHRASE
+            options.mode(0o600);
+        }
+        let mut file = options
+            .open(&tmp_path)
+            .context(format!("Failed to write to {}", tmp_path.display()))?;
+        file.write_all(json.as_bytes())
+            .and_then(|_| file.sync_all())
+            .context(format!("Failed to write to {}", tmp_path.display()))?;
+
+        std::fs::rename(&tmp_path, &path).map_err(|e| {
+            let _ = std::fs::remove_file(&tmp_path);
+            anyhow::Error::new(e).context(format!("Failed to write to {}", path.display()))
+        })?;
+
+        // Hashes only, as the config holds secrets
+        let new_sha256 = hex::encode(Sha256::digest(json.as_bytes()));
+        if old_sha256.as_deref() != Some(new_sha256.as_str()) {
+            ValidatorUpdater::audit(
+                "ConfigChanged",
+                json!({
+                    "path": path.display().to_string(),
+                    "old_sha256": old_sha256,
+                    "new_sha256": new_sha256,
+                    // Only the subcommand: its arguments may be secrets
+                    "command": std::env::args()
+                        .skip_while(|arg| arg != "config")
+                        .nth(1)
+                        .map(|command| format!("config {}", command)),
+                }),
+            );
+        }
+        Ok(())
+    }
+
+    /// Take an exclusive lock serializing read-modify-write cycles on the config.
+    /// The lock is released when the returned file is dropped.
+    pub fn lock() -> Result<std::fs::File> {
+        use fs2::FileExt;
+
+        let path = paths::config_path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .context(format!("Failed to create {}", parent.display()))?;
+        }
+        let lock_path = path.with_extension("json.lock");
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .context(format!("Failed to open {}", lock_path.display()))?;
+        file.lock_exclusive()
+            .context(format!("Failed to lock {}", lock_path.display()))?;
+        Ok(file)
+    }
+
+    pub fn ensure_env_map(&mut self) {
+        if self.env.is_none() {
+            self.env = Some(std::collections::HashMap::new());
+        }
+    }
+}
+
+/// Take the lock that makes a launcher the only one reconciling the VM of
+/// its state directory, failing at once if another holds it. The lock is
+/// released when the returned file is dropped or the process exits.
+fn lock_instance() -> Result<std::fs::File> {
+    use fs2::FileExt;
+    use std::io::{Read, Seek, Write};
+
+    paths::ensure_state_dir()?;
+    let lock_path = paths::state_dir().join("launcher.lock");
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(&lock_path)
+        .context(format!("Failed to open {}", lock_path.display()))?;
+    if file.try_lock_exclusive().is_err() {
+        let mut holder = String::new();
+        let _ = file.read_to_string(&mut holder);
+        let holder = match holder.trim() {
+            "" => String::new(),
+            pid => format!(" (pid {})", pid),
+        };
+        return Err(error_code::ErrorCode::InstanceLocked.error(format!(
+            "Another launcher{} is already managing the VM of {}; stop it first",
+            holder,
+            paths::state_dir().display()
+        )));
+    }
+    // The pid is informational, for the message above
+    let _ = file
+        .set_len(0)
+        .and_then(|_| file.rewind())
+        .and_then(|_| writeln!(file, "{}", std::process::id()));
+    Ok(file)
+}
+
+/// Take the lock held while the VM is changed: by the service for each
+/// check, and by every command that creates, starts, stops or removes a VM,
+/// so they never interleave. Waits for a change in progress to finish. The
+/// lock is released when the returned file is dropped or the process exits.
+async fn lock_mutations() -> Result<std::fs::File> {
+    use fs2::FileExt;
+
+    paths::ensure_state_dir()?;
+    let lock_path = paths::state_dir().join("mutations.lock");
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .context(format!("Failed to open {}", lock_path.display()))?;
+    let mut waiting = false;
+    loop {
+        match file.try_lock_exclusive() {
+            Ok(()) => return Ok(file),
+            Err(e) if e.kind() == fs2::lock_contended_error().kind() => {
+                if !std::mem::replace(&mut waiting, true) {
+                    info!("Waiting for the running launcher to finish changing the VM");
+                }
+                sleep(MUTATION_LOCK_RETRY).await;
+            }
+            Err(e) => {
+                return Err(e).context(format!("Failed to lock {}", lock_path.display()));
+            }
+        }
+    }
+}
+
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Where a resolved setting came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingSource {
+    Flag,
+    Env,
+    Config,
+    Default,
+}
+
+impl std::fmt::Display for SettingSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SettingSource::Flag => "command line",
+            SettingSource::Env => "environment",
+            SettingSource::Config => "config",
+            SettingSource::Default => "default",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedUrl {
+    pub url: String,
+    pub source: SettingSource,
+}
+
+impl std::fmt::Display for ResolvedUrl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (from {})", self.url, self.source)
+    }
+}
+
+/// Environment variable overriding the platform API URL
+pub const API_URL_ENV: &str = "PLATFORM_API_URL";
+
+static API_URL_FLAG: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+
+/// Apply `--api-url`; only the first call has an effect, so call it before
+/// anything resolves the API URL
+pub fn set_api_url_flag(url: Option<String>) {
+    if let Some(url) = url {
+        let _ = API_URL_FLAG.set(url);
+    }
+}
+
+/// Check that `url` can serve as the platform API URL
+pub fn check_api_url(url: &str) -> Result<()> {
+    let parsed = reqwest::Url::parse(url).context(format!("Invalid API URL '{}'", url))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        anyhow::bail!("API URL '{}' must use http or https", url);
+    }
+    Ok(())
+}
+
+/// Fetch a compose config payload from `url` for `apply --from`, refusing
+/// error responses and bodies over MAX_API_RESPONSE_BYTES
+pub async fn fetch_payload(url: &str) -> Result<String> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()?;
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .context(format!("Failed to fetch {}", url))?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = capture::error_body(response).await;
+        anyhow::bail!("Fetching {} returned {}: {}", url, status, body);
+    }
+    let body = bandwidth::read_body(response, None, MAX_API_RESPONSE_BYTES, |extra| {
+        client.get(url).headers(extra)
+    })
+    .await
+    .context(format!("Failed to read response from {}", url))?;
+    String::from_utf8(body).context(format!("Response from {} is not valid UTF-8", url))
+}
+
+/// Resolve the compose config endpoint of the platform API.
+///
+/// Precedence: `--api-url`, then the `PLATFORM_API_URL` environment variable,
+/// then `api_url` in the platform config, then the endpoint of the `network`
+/// in the platform config, mainnet by default.
+pub fn resolve_api_url(platform_config: &PlatformConfig) -> ResolvedUrl {
+    if let Some(url) = API_URL_FLAG.get() {
+        return ResolvedUrl {
+            url: url.clone(),
+            source: SettingSource::Flag,
+        };
+    }
+    if let Some(url) = std::env::var(API_URL_ENV).ok().filter(|u| !u.is_empty()) {
+        return ResolvedUrl {
+            url,
+            source: SettingSource::Env,
+        };
+    }
+    if let Some(url) = &platform_config.api_url {
+        return ResolvedUrl {
+            url: url.clone(),
+            source: SettingSource::Config,
+        };
+    }
+    ResolvedUrl {
+        url: platform_config
+            .network
+            .unwrap_or_default()
+            .api_url()
+            .to_string(),
+        source: if platform_config.network.is_some() {
+            SettingSource::Config
+        } else {
+            SettingSource::Default
+        },
+    }
+}
+
+/// Resolve the VMM RPC URL the launcher talks to (host side).
+///
+/// Precedence: `VMM_URL` environment variable, then `vmm_url` in the platform
+/// config, then [`defaults::VMM_RPC_URL`].
+pub fn resolve_vmm_url(platform_config: &PlatformConfig) -> ResolvedUrl {
+    if let Some(url) = std::env::var("VMM_URL").ok().filter(|u| !u.is_empty()) {
+        return ResolvedUrl {
+            url,
+            source: SettingSource::Env,
+        };
+    }
+    match &platform_config.vmm_url {
+        Some(url) => ResolvedUrl {
+            url: url.clone(),
+            source: SettingSource::Config,
+        },
+        None => ResolvedUrl {
+            url: defaults::VMM_RPC_URL.to_string(),
+            source: SettingSource::Default,
+        },
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResolvedInterval {
+    pub interval: Duration,
+    pub source: SettingSource,
+}
+
+impl std::fmt::Display for ResolvedInterval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}s (from {})", self.interval.as_secs(), self.source)
+    }
+}
+
+/// Resolve the interval between update checks.
+///
+/// Precedence: `run --poll-interval`, then the `POLL_INTERVAL_SECS`
+/// environment variable, then `poll_interval_secs` in the platform config,
+/// then [`defaults::POLL_INTERVAL_SECS`]. Zero or unparsable values are skipped.
+pub fn resolve_poll_interval(
+    platform_config: &PlatformConfig,
+    flag: Option<u64>,
+) -> ResolvedInterval {
+    let from_env = std::env::var("POLL_INTERVAL_SECS")
+        .ok()
+        .and_then(|secs| secs.trim().parse::<u64>().ok());
+    let (secs, source) = [
+        (flag, SettingSource::Flag),
+        (from_env, SettingSource::Env),
+        (platform_config.poll_interval_secs, SettingSource::Config),
+    ]
+    .into_iter()
+    .find_map(|(secs, source)| secs.filter(|s| *s > 0).map(|s| (s, source)))
+    .unwrap_or((defaults::POLL_INTERVAL_SECS, SettingSource::Default));
+    ResolvedInterval {
+        interval: Duration::from_secs(secs),
+        source,
+    }
+}
+
+/// `interval` spread randomly by up to `percent` either way, so launchers
+/// started together do not poll the API in lockstep
+fn jittered(interval: Duration, percent: u8) -> Duration {
+    if percent == 0 {
+        return interval;
+    }
+    let spread = f64::from(percent.min(defaults::POLL_JITTER_MAX_PERCENT)) / 100.0;
+    interval.mul_f64(1.0 + rand::thread_rng().gen_range(-spread..=spread))
+}
+
+/// Resolve the VMM URL passed to the VM as `DSTACK_VMM_URL` (guest side).
+///
+/// Precedence: `DSTACK_VMM_URL` in the config `env` map, then
+/// `dstack_vmm_url` in the platform config, then [`defaults::GUEST_VMM_URL`].
+pub fn resolve_guest_vmm_url(platform_config: &PlatformConfig) -> ResolvedUrl {
+    let from_env_map = platform_config
+        .env
+        .as_ref()
+        .and_then(|env| env.get("DSTACK_VMM_URL"));
+    match from_env_map.or(platform_config.dstack_vmm_url.as_ref()) {
+        Some(url) => ResolvedUrl {
+            url: url.clone(),
+            source: SettingSource::Config,
+        },
+        None => ResolvedUrl {
+            url: defaults::GUEST_VMM_URL.to_string(),
+            source: SettingSource::Default,
+        },
+    }
+}
+
+/// Settings that can change while the daemon runs, compared on every reload
+#[derive(Debug, Clone, PartialEq)]
+struct ReloadableSettings {
+    vmm_url: String,
+    api_url: String,
+    api_mirrors: Vec<String>,
+    poll_interval: ResolvedInterval,
+    poll_jitter_percent: u8,
+    push_updates: bool,
+    api_transport: subscribe::ApiTransport,
+    api_tls: Option<ApiTlsConfig>,
+    http: http_config::HttpConfig,
+    drift_policy: drift::DriftPolicy,
+    resource_limits: Option<limits::ResourceLimits>,
+    notifications: Option<notify::NotificationConfig>,
+    health_checks: Vec<health::HealthCheckConfig>,
+    retention: retention::RetentionPolicy,
+}
+
+impl ReloadableSettings {
+    fn from_config(platform_config: &PlatformConfig, poll_interval_flag: Option<u64>) -> Self {
+        Self {
+            vmm_url: resolve_vmm_url(platform_config).url,
+            api_url: resolve_api_url(platform_config).url,
+            api_mirrors: platform_config.api_mirrors.clone(),
+            poll_interval: resolve_poll_interval(platform_config, poll_interval_flag),
+            poll_jitter_percent: platform_config
+                .poll_jitter_percent
+                .unwrap_or(defaults::POLL_JITTER_PERCENT),
+            push_updates: platform_config.push_updates,
+            api_transport: platform_config.api_transport.unwrap_or_default(),
+            api_tls: platform_config.api_tls.clone(),
+            http: platform_config.http.clone().unwrap_or_default(),
+            drift_policy: platform_config.drift_policy.unwrap_or_default(),
+            resource_limits: platform_config.resource_limits.clone(),
+            notifications: platform_config.notifications.clone(),
+            health_checks: platform_config.health_checks.clone(),
+            retention: platform_config.retention.clone().unwrap_or_default(),
+        }
+    }
+
+    /// Human-readable list of what differs from `previous`
+    fn changes_from(&self, previous: &Self) -> Vec<String> {
+        let mut changes = Vec::new();
+        if self.vmm_url != previous.vmm_url {
+            changes.push(format!("vmm_url: {} -> {}", previous.vmm_url, self.vmm_url));
+        }
+        if self.api_url != previous.api_url {
+            changes.push(format!("api_url: {} -> {}", previous.api_url, self.api_url));
+        }
+        if self.api_mirrors != previous.api_mirrors {
+            changes.push(format!(
+                "api_mirrors: [{}] -> [{}]",
+                previous.api_mirrors.join(", "),
+                self.api_mirrors.join(", ")
+            ));
+        }
+        if self.poll_interval != previous.poll_interval {
+            changes.push(format!(
+                "poll_interval: {} -> {}",
+                previous.poll_interval, self.poll_interval
+            ));
+        }
+        if self.poll_jitter_percent != previous.poll_jitter_percent {
+            changes.push(format!(
+                "poll_jitter_percent: {} -> {}",
+                previous.poll_jitter_percent, self.poll_jitter_percent
+            ));
+        }
+        if self.push_updates != previous.push_updates {
+            changes.push(format!(
+                "push_updates: {} -> {}",
+                previous.push_updates, self.push_updates
+            ));
+        }
+        if self.api_transport != previous.api_transport {
+            changes.push(format!(
+                "api_transport: {} -> {}",
+                previous.api_transport, self.api_transport
+            ));
+        }
+        if self.api_tls != previous.api_tls {
+            changes.push("api_tls changed".to_string());
+        }
+        if self.http != previous.http {
+            changes.push("http changed".to_string());
+        }
+        if self.drift_policy != previous.drift_policy {
+            changes.push(format!(
+                "drift_policy: {:?} -> {:?}",
+                previous.drift_policy, self.drift_policy
+            ));
+        }
+        if self.resource_limits != previous.resource_limits {
+            changes.push(format!(
+                "resource_limits: {:?} -> {:?}",
+                previous.resource_limits, self.resource_limits
+            ));
+        }
+        if self.notifications != previous.notifications {
+            changes.push("notifications changed".to_string());
+        }
+        if self.health_checks != previous.health_checks {
+            changes.push("health_checks changed".to_string());
+        }
+        if self.retention != previous.retention {
+            changes.push(format!(
+                "retention: {} -> {}",
+                previous.retention, self.retention
+            ));
+        }
+        changes
+    }
+}
+
+/// The VMM could not be reached or answered as if restarting; distinct from
+/// the VMM rejecting a request
+#[derive(Debug)]
+struct VmmUnavailable(String);
+
+impl std::fmt::Display for VmmUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "VMM unavailable: {}", self.0)
+    }
+}
+
+impl std::error::Error for VmmUnavailable {}
+
+fn is_vmm_unavailable(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| cause.is::<VmmUnavailable>())
+}
+
+/// Recreation is held off because the VM keeps failing
+#[derive(Debug)]
+struct Crashloop(String);
+
+impl std::fmt::Display for Crashloop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Crashloop {}
+
+/// Recreation is held off because the VM was recreated too often
+#[derive(Debug)]
+struct Flapping(String);
+
+impl std::fmt::Display for Flapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Flapping {}
+
+/// A VM is not created because the host clock is off
+#[derive(Debug)]
+struct ClockDrift(String);
+
+impl std::fmt::Display for ClockDrift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ClockDrift {}
+
+/// The platform API answered with an HTTP error status
+#[derive(Debug)]
+struct ApiStatus {
+    status: reqwest::StatusCode,
+    /// `Retry-After` in seconds, as sent with 429 and 503
+    retry_after: Option<Duration>,
+    message: String,
+}
+
+impl std::fmt::Display for ApiStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ApiStatus {}
+
+/// Whether a failed compose config request is worth retrying at a mirror:
+/// the endpoint was unreachable or answered with a server error
+fn fails_over(e: &anyhow::Error) -> bool {
+    match e
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<ApiStatus>())
+    {
+        Some(status) => status.status.is_server_error(),
+        None => error_code::ErrorCode::attached(e) == Some(error_code::ErrorCode::ApiUnreachable),
+    }
+}
+
+/// How a failed check failed to use the platform API, if that is why it
+/// failed, with the `Retry-After` the API asked for
+fn api_failure(e: &anyhow::Error) -> Option<(api_backoff::ApiFailure, Option<Duration>)> {
+    use api_backoff::ApiFailure;
+    if let Some(status) = e
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<ApiStatus>())
+    {
+        return Some((
+            ApiFailure::from_status(status.status.as_u16()),
+            status.retry_after,
+        ));
+    }
+    match error_code::ErrorCode::attached(e)? {
+        error_code::ErrorCode::ApiUnreachable => Some((ApiFailure::Network, None)),
+        error_code::ErrorCode::ApiError => Some((ApiFailure::Server, None)),
+        _ => None,
+    }
+}
+
+/// A deployment is held because required env keys under the `hold` policy have no value
+#[derive(Debug)]
+struct EnvHeld(String);
+
+impl std::fmt::Display for EnvHeld {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for EnvHeld {}
+
+/// Stable code of a failure, for logs, JSON output, alerts, and exit statuses
+pub fn classify_error(e: &anyhow::Error) -> error_code::ErrorCode {
+    use error_code::ErrorCode;
+    if let Some(code) = ErrorCode::attached(e) {
+        code
+    } else if e.chain().any(|cause| cause.is::<ApiStatus>()) {
+        ErrorCode::ApiError
+    } else if is_vmm_unavailable(e) {
+        ErrorCode::VmmUnreachable
+    } else if e.chain().any(|cause| cause.is::<Crashloop>()) {
+        ErrorCode::VmCrashloop
+    } else if e.chain().any(|cause| cause.is::<Flapping>()) {
+        ErrorCode::VmFlapping
+    } else if e.chain().any(|cause| cause.is::<ClockDrift>()) {
+        ErrorCode::ClockDrift
+    } else if e.chain().any(|cause| cause.is::<EnvHeld>()) {
+        ErrorCode::EnvMissing
+    } else {
+        ErrorCode::Unknown
+    }
+}
+
+/// Alert condition of a failed update check
+fn failure_condition(e: &anyhow::Error) -> &'static str {
+    if is_vmm_unavailable(e) {
+        notify::conditions::VMM_UNREACHABLE
+    } else if e.chain().any(|cause| cause.is::<Crashloop>()) {
+        notify::conditions::VM_CRASHLOOP
+    } else if e.chain().any(|cause| cause.is::<Flapping>()) {
+        notify::conditions::VM_FLAPPING
+    } else if e.chain().any(|cause| cause.is::<ClockDrift>()) {
+        notify::conditions::CLOCK_DRIFT
+    } else if e.chain().any(|cause| cause.is::<EnvHeld>()) {
+        notify::conditions::ENV_MISSING
+    } else {
+        notify::conditions::UPDATE_CHECK_FAILING
+    }
+}
+
+/// A compose config response, or word from the API that the cached one is current
+enum ComposeResponse {
+    NotModified,
+    /// With the validators to make the next request conditional on
+    Config(Box<ComposeConfig>, Option<conditional::Validators>),
+}
+
+/// Outcome of [`ValidatorUpdater::apply`], printed as JSON for automation
+#[derive(Debug, Clone, Serialize)]
+pub struct ApplyReport {
+    /// Whether the VM was created, removed, started or resized
+    pub changed: bool,
+    pub outcome: ApplyOutcome,
+    /// What the plan called for, or None when the host had already converged
+    pub action: Option<String>,
+    /// Why a planned action was not taken
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub vm_id: Option<String>,
+    pub compose_hash: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplyOutcome {
+    /// The VM already matched; nothing was planned
+    Converged,
+    Applied,
+    /// Deferred until approval, the end of a backoff, or observe mode is off
+    Held,
+    /// Not done until an operator acts, e.g. after `stop`
+    Blocked,
+}
+
+/// One check made by [`ValidatorUpdater::verify_deployment`]
+#[derive(Debug, Clone)]
+pub struct VerifyCheck {
+    pub ok: bool,
+    pub what: String,
+}
+
+/// Outcome of [`ValidatorUpdater::verify_deployment`], one line per check
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    pub checks: Vec<VerifyCheck>,
+}
+
+impl VerifyReport {
+    fn check(&mut self, ok: bool, what: String) {
+        self.checks.push(VerifyCheck { ok, what });
+    }
+
+    /// The checks that failed
+    pub fn divergences(&self) -> impl Iterator<Item = &VerifyCheck> {
+        self.checks.iter().filter(|check| !check.ok)
+    }
+}
+
+impl std::fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, check) in self.checks.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "  {} {}", if check.ok { "✓" } else { "✗" }, check.what)?;
+        }
+        Ok(())
+    }
+}
+
+/// The validator VM a plan was made against
+#[derive(Debug, Clone)]
+pub struct PlannedVm {
+    pub id: String,
+    pub status: String,
+    /// Whether the VM already runs under the planned app_id
+    pub app_id_unchanged: bool,
+    pub drift: Vec<String>,
+}
+
+/// Outcome of [`ValidatorUpdater::dry_run`] and [`ValidatorUpdater::simulate`]
+#[derive(Debug, Clone)]
+pub struct PlanReport {
+    /// The payload was a bare compose file, planned as the content of the
+    /// currently published config
+    pub compose_only: bool,
+    /// Features the config asks for that this launcher does not implement
+    pub unsupported_features: Vec<String>,
+    pub hash_version: compose_hash::HashVersion,
+    pub compose_hash: String,
+    pub vm_params: VmParameters,
+    pub allowed_envs: Vec<String>,
+    pub changelog: Option<String>,
+    /// Env keys with no local value that are deployed without them
+    pub env_skipped: Vec<String>,
+    /// Env keys with no local value that hold the deployment
+    pub env_held: Vec<String>,
+    /// None when no validator VM exists
+    pub vm: Option<PlannedVm>,
+    /// The planned action, or None when the VM is up to date
+    pub action: Option<String>,
+}
+
+impl std::fmt::Display for PlanReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.compose_only {
+            writeln!(
+                f,
+                "Payload is not a compose config; using it as compose content"
+            )?;
+        }
+        writeln!(f, "Validation:")?;
+        writeln!(f, "  ✓ Payload is valid")?;
+        for feature in &self.unsupported_features {
+            writeln!(
+                f,
+                "  ! Feature '{}' is not implemented by this launcher",
+                feature
+            )?;
+        }
+
+        let params = &self.vm_params;
+        writeln!(f, "Deployment:")?;
+        writeln!(
+            f,
+            "  Compose hash ({}): {}",
+            self.hash_version, self.compose_hash
+        )?;
+        writeln!(
+            f,
+            "  app_id: {}",
+            compose_hash::app_id_from_hash(&self.compose_hash)
+        )?;
+        writeln!(
+            f,
+            "  VM: image={} vcpu={} memory={}MB disk={}GB",
+            params.image, params.vcpu, params.memory, params.disk_size
+        )?;
+        writeln!(f, "  Allowed envs: {}", self.allowed_envs.join(", "))?;
+        match &self.changelog {
+            Some(changelog) => {
+                writeln!(f, "  Changes:")?;
+                for line in changelog.lines() {
+                    writeln!(f, "    {}", line)?;
+                }
+            }
+            None => writeln!(f, "  Changes: (no description provided)")?,
+        }
+        if !self.env_skipped.is_empty() {
+            writeln!(
+                f,
+                "  ! No local value for: {} (deployed without them)",
+                self.env_skipped.join(", ")
+            )?;
+        }
+        if !self.env_held.is_empty() {
+            writeln!(
+                f,
+                "  ! No local value for: {} (deployment held)",
+                self.env_held.join(", ")
+            )?;
+        }
+
+        write!(f, "Running VM:")?;
+        let Some(vm) = &self.vm else {
+            return write!(f, "\n  (none)");
+        };
+        write!(f, "\n  {} ({})", vm.id, vm.status)?;
+        if vm.app_id_unchanged {
+            write!(
+                f,
+                "\n  ✓ app_id unchanged ({})",
+                compose_hash::app_id_from_hash(&self.compose_hash)
+            )?;
+        }
+        for drift in &vm.drift {
+            write!(f, "\n  ! Drift: {}", drift)?;
+        }
+        Ok(())
+    }
+}
+
+/// VM output the VMM keeps logs of
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogChannel {
+    /// Serial console: boot messages and guest system logs
+    #[default]
+    Serial,
+    /// Standard output of the VM process
+    Stdout,
+    /// Standard error of the VM process
+    Stderr,
+}
+
+impl LogChannel {
+    fn as_str(self) -> &'static str {
+        match self {
+            LogChannel::Serial => "serial",
+            LogChannel::Stdout => "stdout",
+            LogChannel::Stderr => "stderr",
+        }
+    }
+}
+
+/// The validator VM as the VMM currently reports it
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveVm {
+    pub id: String,
+    pub name: Option<String>,
+    /// Run state, e.g. `running` or `stopped`
+    pub state: String,
+    /// Truncated compose hash of the running deployment
+    pub app_id: Option<String>,
+}
+
+/// Result of [`ValidatorUpdater::live_status`]
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveStatus {
+    /// None when no validator VM exists
+    pub vm: Option<LiveVm>,
+    /// Compose hash of the config currently published by the platform API
+    pub latest_hash: Option<String>,
+    /// Why the latest config could not be fetched
+    pub api_error: Option<String>,
+    /// What the next check would do, or None when up to date (or unknown)
+    pub pending_update: Option<String>,
+    /// The latest config's compose hash as computed by each component
+    pub hashes: Option<compose_hash::HashComparison>,
+}
+
+/// A VM that looks like one of the launcher's but is not the one it manages
+#[derive(Debug, Clone, Serialize)]
+pub struct OrphanVm {
+    pub id: String,
+    pub name: Option<String>,
+    pub status: String,
+    /// Created or adopted by this launcher (e.g. a VM whose removal failed),
+    /// as opposed to one only named like the validator VM
+    pub owned: bool,
+    /// Why removing it failed or was skipped; None once removed (or on a dry run)
+    pub error: Option<String>,
+}
+
+/// A VM entry as reported by the VMM Status RPC
+#[derive(Debug, Clone)]
+struct VmInfo {
+    id: String,
+    name: Option<String>,
+    status: String,
+    app_id: Option<String>,
+    /// Raw `configuration` object, when the VMM reports it
+    configuration: Option<Value>,
+}
+
+impl VmInfo {
+    fn from_status_entry(vm: &Value) -> Option<Self> {
+        let observed = reconcile::ObservedVm::from_status_entry(vm)?;
+        Some(Self {
+            id: observed.id,
+            name: observed.name,
+            status: observed.status,
+            app_id: observed.app_id,
+            configuration: vm.get("configuration").cloned(),
+        })
+    }
+
+    fn observed(&self) -> reconcile::ObservedVm {
+        reconcile::ObservedVm {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            status: self.status.clone(),
+            app_id: self.app_id.clone(),
+        }
+    }
+}
+
+/// What a check does to an existing validator VM
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum UpdatePlan {
+    /// Replace it, or start it when stopped (see [`reconcile::replacement`]
+    /// and [`reconcile::restart`])
+    Reconcile(reconcile::Action),
+    /// Recreate it because its parameters drifted and the policy reconciles drift
+    ReconcileDrift { vm_id: String },
+    /// Apply a change to its resources
+    Provision {
+        vm_id: String,
+        path: provisioning::UpdatePath,
+    },
+}
+
+impl std::fmt::Display for UpdatePlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdatePlan::Reconcile(action) => write!(f, "{}", action),
+            UpdatePlan::ReconcileDrift { vm_id } => {
+                write!(f, "recreate VM {} to reconcile drift", vm_id)
+            }
+            UpdatePlan::Provision {
+                vm_id,
+                path: provisioning::UpdatePath::Recreate,
+            } => write!(f, "recreate VM {} for a provisioning change", vm_id),
+            UpdatePlan::Provision {
+                vm_id,
+                path: provisioning::UpdatePath::Resize,
+            } => write!(f, "resize VM {} in place", vm_id),
+        }
+    }
+}
+
+/// What a check does to `vm` to run `desired`, or None when it is up to
+/// date. `provisioning` is the pending resource change from
+/// [`provisioning_change`]. The daemon, `apply`, `dry-run`, `simulate` and
+/// `status` all plan through this, so they cannot disagree.
+fn plan_update(
+    vm: &VmInfo,
+    desired: &DesiredDeployment,
+    platform_config: &PlatformConfig,
+    provisioning: Option<provisioning::UpdatePath>,
+) -> Option<UpdatePlan> {
+    let observed = vm.observed();
+    if let Some(action) = reconcile::replacement(&desired.hash, &observed) {
+        return Some(UpdatePlan::Reconcile(action));
+    }
+    let drifted = vm
+        .configuration
+        .as_ref()
+        .is_some_and(|actual| !drift::detect_drift(&desired.vm_params, actual).is_empty());
+    if platform_config.drift_policy.unwrap_or_default() == drift::DriftPolicy::Reconcile && drifted
+    {
+        return Some(UpdatePlan::ReconcileDrift {
+            vm_id: vm.id.clone(),
+        });
+    }
+    if let Some(path) = provisioning {
+        return Some(UpdatePlan::Provision {
+            vm_id: vm.id.clone(),
+            path,
+        });
+    }
+    let start_stopped = platform_config
+        .recovery
+        .clone()
+        .unwrap_or_default()
+        .start_stopped;
+    reconcile::restart(&observed, start_stopped).map(UpdatePlan::Reconcile)
+}
+
+/// How to apply a change between the resources `vm_id` was provisioned with
+/// and the desired ones, if they differ. None while nothing is recorded for
+/// the VM; see [`ValidatorUpdater::record_provisioning_baseline`].
+fn provisioning_change(
+    state: &state::UpdaterState,
+    vm_id: &str,
+    desired: &DesiredDeployment,
+) -> Option<provisioning::UpdatePath> {
+    let new = desired.fingerprint();
+    let mut previous = state
+        .provisioning
+        .clone()
+        .filter(|_| state.vm_id.as_deref() == Some(vm_id))?;
+    previous.backfill(&new);
+    (previous != new).then(|| new.update_path(&previous))
+}
+
+/// Everything derived from a compose config that determines the deployed VM
+#[derive(Debug, Clone)]
+struct DesiredDeployment {
+    allowed_envs: Vec<String>,
+    /// The API's manifest defaults with the disclosure policy applied
+    manifest_defaults: ManifestDefaults,
+    vm_params: VmParameters,
+    /// Name in the app manifest, also the VM's name unless `vm_params` sets one
+    vm_name: String,
+    /// The serialized app manifest passed to the VMM as `compose_file`
+    compose_file: String,
+    hash_version: compose_hash::HashVersion,
+    hash: String,
+}
+
+impl DesiredDeployment {
+    fn fingerprint(&self) -> provisioning::ProvisioningFingerprint {
+        provisioning::ProvisioningFingerprint::from_params(&self.vm_params)
+    }
+
+    /// The deployment record, holding the manifest defaults as deployed, so
+    /// the manifest can be rebuilt from the record alone
+    fn record(
+        &self,
+        vm_id: &str,
+        config: &ComposeConfig,
+        api_url: Option<String>,
+    ) -> deployment::DeploymentRecord {
+        let mut compose_config = config.clone();
+        compose_config.provisioning.manifest_defaults = self.manifest_defaults.clone();
+        deployment::DeploymentRecord {
+            vm_id: vm_id.to_string(),
+            compose_config,
+            vm_params: self.vm_params.clone(),
+            allowed_envs: self.allowed_envs.clone(),
+            compose_file: self.compose_file.clone(),
+            image: self.vm_params.image.clone(),
+            compose_hash: self.hash.clone(),
+            hash_version: self.hash_version,
+            deployed_at: unix_now(),
+            api_url,
+        }
+    }
+}
+
+/// Keeps the validator VM in line with the compose config published by the
+/// platform API. Construct with [`ValidatorUpdater::new`] and drive with
+/// [`ValidatorUpdater::run`]; [`ValidatorUpdater::subscribe`] yields the
+/// updater's events.
+pub struct ValidatorUpdater {
+    vmm_url: String,
+    /// Compose config endpoint of the platform API
+    api_url: String,
+    /// Endpoints tried after `api_url`, in order
+    api_mirrors: Vec<String>,
+    /// Which of `api_url` (0) and the mirrors last served the compose config
+    api_endpoint: std::sync::Mutex<usize>,
+    http_client: reqwest::Client,
+    api_client: reqwest::Client,
+    /// Managed VM and its compose hash, restored from the state file at startup
+    current_hash: Option<String>,
+    vm_id: Option<String>,
+    /// No check has looked at the VM yet in this process
+    first_check: bool,
+    /// When resource use was last sampled for the right-sizing report
+    last_utilization_sample: Option<u64>,
+    /// Last drift reported, so alerts fire only when it changes
+    last_drift: Vec<drift::ParameterDrift>,
+    /// Never issue destructive RPCs, only report what would be done
+    observe: bool,
+    /// Also remove VMs this launcher did not create or adopt (`--force`)
+    remove_unowned: bool,
+    last_observed_plan: Option<String>,
+    /// Auto-updates are paused (`pause`), as of the last scheduled check
+    paused: bool,
+    /// Compose config used instead of the API's (`apply`)
+    compose_override: Option<ComposeConfig>,
+    /// Unimplemented API features last reported, so warnings fire only on change
+    unsupported_features: Vec<String>,
+    /// Compose hash whose canary failed; not retried until the API publishes another
+    failed_canary: Option<String>,
+    /// Compose hash a warm standby could not be created for; retried once
+    /// the API publishes another config or the launcher restarts
+    failed_standby: Option<String>,
+    /// Wait before the next check while the platform API fails
+    api_backoff: api_backoff::ApiBackoff,
+    /// Event stream from the platform API, with `push_updates`
+    push: Option<push::PushStream>,
+    /// WebSocket subscription to the compose config, with the `websocket`
+    /// API transport
+    subscription: Option<subscribe::Subscription>,
+    /// Endpoint integration updates still running in the background
+    endpoint_updates: Vec<tokio::task::JoinHandle<()>>,
+    /// Whether the missing `websocket` feature was reported for the current setting
+    #[cfg(not(feature = "websocket"))]
+    websocket_unavailable: bool,
+    /// The config derived from the last pushed one, with its `seq`
+    pushed_config: std::sync::Mutex<Option<(u64, ComposeConfig)>>,
+    /// Rate API responses are read at, if capped
+    api_bandwidth: Option<u64>,
+    last_prune: Option<std::time::Instant>,
+    last_orphan_scan: Option<std::time::Instant>,
+    /// Live state served by the admin API
+    admin: std::sync::Arc<admin_api::AdminShared>,
+    /// Whether the current crashloop has been alerted on
+    crashloop_alerted: bool,
+    /// Whether the current run of too many recreations has been alerted on
+    flapping_alerted: bool,
+    /// Unix timestamp the recreation backoff ends, while one is deferred
+    recreation_deferred_until: Option<u64>,
+    last_good_config: std::sync::Mutex<Option<PlatformConfig>>,
+    /// Last compose config fetched, for conditional requests
+    compose_cache: std::sync::Mutex<Option<conditional::Cached<ComposeConfig>>>,
+    /// When the last check found the VM up to date, and the platform
+    /// config it used, so a check with the same config and an unchanged
+    /// compose config can be skipped
+    converged: Option<(std::time::Instant, Value)>,
+    /// Clock offset seen in the last platform API response, in seconds
+    api_clock_offset: std::sync::Mutex<Option<f64>>,
+    poll_interval: Duration,
+    /// `run --poll-interval`, which overrides the environment and the config
+    poll_interval_flag: Option<u64>,
+    poll_jitter_percent: u8,
+    /// Settings currently in effect, for hot-reload change detection
+    settings: Option<ReloadableSettings>,
+    check_errors: log_dedup::DedupLog,
+    notifier: notify::Notifier,
+    health_checks: health::HealthChecks,
+    config_errors: std::sync::Mutex<log_dedup::DedupLog>,
+    /// Failures to load the platform config where the defaults stand in
+    config_fallbacks: std::sync::Mutex<log_dedup::DedupLog>,
+    reload_errors: log_dedup::DedupLog,
+    events: events::EventBus,
+}
+
+impl ValidatorUpdater {
+    pub async fn new(vmm_url: String, platform_config: &PlatformConfig) -> Result<Self> {
+        let http_client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .danger_accept_invalid_certs(true)
+            .build()
+            .context("Failed to create HTTP client")?;
+
+        let api_client = Self::build_api_client(platform_config)?;
+        let state = state::UpdaterState::load().unwrap_or_else(|e| {
+            warn!("Failed to load launcher state, starting fresh: {:#}", e);
+            Default::default()
+        });
+
+        Ok(Self {
+            vmm_url,
+            api_url: resolve_api_url(platform_config).url,
+            api_mirrors: platform_config.api_mirrors.clone(),
+            api_endpoint: std::sync::Mutex::new(0),
+            http_client,
+            api_client,
+            current_hash: state.current_hash,
+            vm_id: state.vm_id,
+            first_check: true,
+            last_utilization_sample: None,
+            last_drift: Vec::new(),
+            observe: false,
+            remove_unowned: false,
+            last_observed_plan: None,
+            paused: false,
+            compose_override: None,
+            unsupported_features: Vec::new(),
+            failed_canary: None,
+            failed_standby: None,
+            api_backoff: Default::default(),
+            push: None,
+            subscription: None,
+            endpoint_updates: Vec::new(),
+            #[cfg(not(feature = "websocket"))]
+            websocket_unavailable: false,
+            pushed_config: std::sync::Mutex::new(None),
+            api_bandwidth: platform_config
+                .http
+                .as_ref()
+                .and_then(|h| h.max_bytes_per_sec),
+            last_prune: None,
+            last_orphan_scan: None,
+            admin: Default::default(),
+            crashloop_alerted: false,
+            flapping_alerted: false,
+            recreation_deferred_until: None,
+            last_good_config: std::sync::Mutex::new(None),
+            compose_cache: std::sync::Mutex::new(None),
+            converged: None,
+            api_clock_offset: std::sync::Mutex::new(None),
+            poll_interval: POLL_INTERVAL,
+            poll_interval_flag: None,
+            poll_jitter_percent: defaults::POLL_JITTER_PERCENT,
+            settings: None,
+            check_errors: log_dedup::DedupLog::new("Update check failed"),
+            notifier: notify::Notifier::from_config(platform_config.notifications.as_ref()),
+            health_checks: health::HealthChecks::new(&platform_config.health_checks),
+            config_errors: std::sync::Mutex::new(log_dedup::DedupLog::new(
+                "Platform config unreadable",
+            )),
+            config_fallbacks: std::sync::Mutex::new(log_dedup::DedupLog::new(
+                "Failed to load platform config, using defaults",
+            )),
+            reload_errors: log_dedup::DedupLog::new(
+                "Keeping current settings, failed to reload config",
+            ),
+            events: events::EventBus::new(),
+        })
+    }
+
+    /// Receive the events emitted from now on
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<events::Event> {
+        self.events.subscribe()
+    }
+
+    /// Shadow mode: fetch, compare, and report, but never mutate VMs
+    pub fn set_observe(&mut self, observe: bool) {
+        self.observe = observe;
+        self.admin.set_observe(observe);
+    }
+
+    /// Check for updates every `secs` seconds, whatever the environment and the config say
+    pub fn set_poll_interval(&mut self, secs: u64) {
+        self.poll_interval_flag = Some(secs);
+        self.poll_interval = Duration::from_secs(secs);
+    }
+
+    /// Let operator commands remove a VM this launcher did not create or
+    /// adopt, such as one named like the validator VM but made by hand
+    pub fn set_remove_unowned(&mut self, remove_unowned: bool) {
+        self.remove_unowned = remove_unowned;
+    }
+
+    /// Build the client used for the platform API, with the mTLS identity if configured
+    fn build_api_client(platform_config: &PlatformConfig) -> Result<reqwest::Client> {
+        let http = platform_config.http.clone().unwrap_or_default();
+        let mut builder = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .user_agent(http.user_agent())
+            .default_headers(http.header_map()?);
+
+        match &platform_config.api_tls {
+            Some(tls) => {
+                info!(
+                    "Using mTLS client certificate {} for platform API",
+                    tls.client_cert
+                );
+                builder = builder.identity(tls.load_identity()?);
+                // The server is verified against the CA bundle if given, or
+                // else the system roots; a client certificate is pointless
+                // sent to an unverified server
+                if let Some(ca) = tls.load_ca_cert()? {
+                    builder = builder.add_root_certificate(ca);
+                }
+            }
+            None => builder = builder.danger_accept_invalid_certs(true),
+        }
+
+        builder
+            .build()
+            .context("Failed to create platform API client")
+    }
+
+    async fn rpc_call(&self, method: &str, params: Value) -> Result<Value> {
+        let url = format!("{}/prpc/{}?json", self.vmm_url, method);
+        info!("Making RPC call to: {}", url);
+
+        let response = match self.http_client.post(&url).json(&params).send().await {
+            Ok(response) => response,
+            Err(e) if e.is_connect() || e.is_timeout() => {
+                return Err(anyhow::Error::new(VmmUnavailable(e.to_string()))
+                    .context("Failed to make RPC call"));
+            }
+            Err(e) => return Err(anyhow::Error::new(e).context("Failed to make RPC call")),
+        };
+
+        let status = response.status();
+        if matches!(
+            status,
+            reqwest::StatusCode::BAD_GATEWAY
+                | reqwest::StatusCode::SERVICE_UNAVAILABLE
+                | reqwest::StatusCode::GATEWAY_TIMEOUT
+        ) {
+            return Err(anyhow::Error::new(VmmUnavailable(format!(
+                "{} returned {}",
+                method, status
+            ))));
+        }
+        if !status.is_success() {
+            let error_text = capture::error_body(response).await;
+            anyhow::bail!("RPC call failed with status {}: {}", status, error_text);
+        }
+
+        response
+            .json::<Value>()
+            .await
+            .context("Failed to parse RPC response")
+    }
+
+    async fn fetch_compose_config(&self) -> Result<ComposeConfig> {
+        Ok(self.fetch_compose_config_if_changed().await?.0)
+    }
+
+    /// Fetch the full compose config without recording anything: no cached
+    /// base, env metadata, or conditional request. For commands that only look.
+    async fn peek_compose_config(&self) -> Result<ComposeConfig> {
+        if let Some(config) = &self.compose_override {
+            return Ok(config.clone());
+        }
+        match self.request_compose_config(None, None).await? {
+            ComposeResponse::Config(config, _) if config.compose_patch.is_some() => {
+                anyhow::bail!("API sent a compose patch although no base was offered")
+            }
+            ComposeResponse::Config(config, _) => Ok(*config),
+            ComposeResponse::NotModified => {
+                anyhow::bail!("API answered 304 Not Modified to an unconditional request")
+            }
+        }
+    }
+
+    /// Fetch the compose config, and whether it may have changed since the
+    /// last fetch; only a 304 Not Modified from the API says it did not
+    async fn fetch_compose_config_if_changed(&self) -> Result<(ComposeConfig, bool)> {
+        if let Some(config) = &self.compose_override {
+            return Ok((config.clone(), true));
+        }
+        let cached = self
+            .compose_cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        let base = compose_patch::load_base();
+        let base_sha256 = base.as_deref().map(compose_patch::content_sha256);
+        let pushed = self.subscription.as_ref().and_then(|s| s.latest());
+        let response = match &pushed {
+            Some(pushed) => {
+                if let Some((seq, config)) =
+                    &*self.pushed_config.lock().unwrap_or_else(|e| e.into_inner())
+                {
+                    if *seq == pushed.seq {
+                        debug!("No compose config pushed since the last check");
+                        return Ok((config.clone(), false));
+                    }
+                }
+                let config = Self::parse_compose_config(&pushed.payload)?;
+                // Subscriptions are only opened to the API URL
+                *self.api_endpoint.lock().unwrap_or_else(|e| e.into_inner()) = 0;
+                ComposeResponse::Config(Box::new(config), None)
+            }
+            None => {
+                self.request_compose_config(
+                    base_sha256.as_deref(),
+                    cached.as_ref().map(|cached| &cached.validators),
+                )
+                .await?
+            }
+        };
+        let (mut config, mut validators) = match (response, cached) {
+            (ComposeResponse::Config(config, validators), _) => (*config, validators),
+            (ComposeResponse::NotModified, Some(cached)) => {
+                debug!("Compose config not modified since the last fetch");
+                return Ok((cached.value, false));
+            }
+            (ComposeResponse::NotModified, None) => {
+                anyhow::bail!("API answered 304 Not Modified to an unconditional request")
+            }
+        };
+
+        if let Some(patch) = config.compose_patch.take() {
+            let patched = base
+                .as_deref()
+                .context("API sent a compose patch but no base is cached")
+                .and_then(|base| patch.apply(base));
+            match patched {
+                Ok(content) => {
+                    info!(
+                        "Applied compose patch ({} hunk(s)) to cached base {}",
+                        patch.hunks.len(),
+                        patch.base_sha256
+                    );
+                    config.compose_content = content;
+                }
+                Err(e) => {
+                    warn!("Compose patch rejected, fetching full content: {:#}", e);
+                    match self.request_compose_config(None, None).await? {
+                        ComposeResponse::Config(full, full_validators) => {
+                            config = *full;
+                            validators = full_validators;
+                        }
+                        ComposeResponse::NotModified => {
+                            anyhow::bail!(
+                                "API answered 304 Not Modified to an unconditional request"
+                            )
+                        }
+                    }
+                    if config.compose_patch.is_some() {
+                        anyhow::bail!("API sent a compose patch although no base was offered");
+                    }
+                }
+            }
+        }
+
+        compose_patch::update_base(&config.compose_content);
+        env_metadata::update_cache(&config.env_metadata);
+        if let Some(pushed) = pushed {
+            *self.pushed_config.lock().unwrap_or_else(|e| e.into_inner()) =
+                Some((pushed.seq, config.clone()));
+        }
+        *self.compose_cache.lock().unwrap_or_else(|e| e.into_inner()) =
+            validators.map(|validators| conditional::Cached {
+                validators,
+                value: config.clone(),
+            });
+        Ok((config, true))
+    }
+
+    /// Fetch the compose config from the API URL, falling back to each
+    /// mirror in turn while the endpoint tried is unreachable or answers 5xx
+    async fn request_compose_config(
+        &self,
+        base_sha256: Option<&str>,
+        validators: Option<&conditional::Validators>,
+    ) -> Result<ComposeResponse> {
+        let endpoints: Vec<&str> = std::iter::once(self.api_url.as_str())
+            .chain(self.api_mirrors.iter().map(String::as_str))
+            .collect();
+        let mut failures = Vec::new();
+        for (index, url) in endpoints.iter().enumerate() {
+            match self
+                .request_compose_config_from(url, base_sha256, validators)
+                .await
+            {
+                Err(e) if index + 1 < endpoints.len() && fails_over(&e) => {
+                    debug!(
+                        "Platform API {} failed, trying the next mirror: {:#}",
+                        url, e
+                    );
+                    failures.push(format!("{}: {:#}", url, e));
+                }
+                Err(e) if index > 0 => {
+                    return Err(e.context(format!(
+                        "API URL and {} mirror(s) failed ({})",
+                        index,
+                        failures.join("; ")
+                    )));
+                }
+                Err(e) => return Err(e),
+                Ok(response) => {
+                    let previous = std::mem::replace(
+                        &mut *self.api_endpoint.lock().unwrap_or_else(|e| e.into_inner()),
+                        index,
+                    );
+                    if index != previous {
+                        if index == 0 {
+                            info!("Platform API {} is answering again", url);
+                        } else {
+                            warn!(
+                                "Using platform API mirror {} ({})",
+                                url,
+                                failures.join("; ")
+                            );
+                        }
+                    }
+                    return Ok(response);
+                }
+            }
+        }
+        unreachable!("the API URL is always tried")
+    }
+
+    /// Fetch the compose config from `url`, offering `base_sha256` as a
+    /// patch base if given, and asking for it only if it changed if
+    /// `validators` are given
+    async fn request_compose_config_from(
+        &self,
+        url: &str,
+        base_sha256: Option<&str>,
+        validators: Option<&conditional::Validators>,
+    ) -> Result<ComposeResponse> {
+        // The client's timeout is too short for a full-size response at a low cap
+        let timeout = bandwidth::request_timeout(
+            Duration::from_secs(10),
+            self.api_bandwidth,
+            MAX_API_RESPONSE_BYTES,
+        );
+        let request = |extra: reqwest::header::HeaderMap| {
+            let mut request = self.api_client.get(url).timeout(timeout);
+            if let Some(base_sha256) = base_sha256 {
+                request = request.header(compose_patch::BASE_SHA256_HEADER, base_sha256);
+            }
+            request
+                .header(
+                    compose_hash::SUPPORTED_VERSIONS_HEADER,
+                    compose_hash::HashVersion::supported_header_value(),
+                )
+                .header(
+                    api_schema::SCHEMA_VERSION_HEADER,
+                    api_schema::SUPPORTED_SCHEMA_VERSION.to_string(),
+                )
+                .header(
+                    api_schema::FEATURES_HEADER,
+                    api_schema::features_header_value(),
+                )
+                .headers(extra)
+        };
+        let sent = std::time::SystemTime::now();
+        let response = request(
+            validators
+                .map(conditional::Validators::request_headers)
+                .unwrap_or_default(),
+        )
+        .send()
+        .await
+        .map_err(|e| error_code::ErrorCode::ApiUnreachable.error(e.to_string()))
+        .context("Failed to fetch compose config")?;
+        self.record_api_clock(response.headers(), sent);
+
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_MODIFIED && validators.is_some() {
+            return Ok(ComposeResponse::NotModified);
+        }
+        if !status.is_success() {
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse().ok())
+                .map(Duration::from_secs);
+            let error_text = capture::error_body(response).await;
+            return Err(anyhow::Error::new(ApiStatus {
+                status,
+                retry_after,
+                message: format!("API returned status {}: {}", status, error_text),
+            }));
+        }
+
+        if let Some(length) = response.content_length() {
+            if length > MAX_API_RESPONSE_BYTES as u64 {
+                return Err(error_code::ErrorCode::ApiError.error(format!(
+                    "API response too large: {} bytes (limit {} bytes)",
+                    length, MAX_API_RESPONSE_BYTES
+                )));
+            }
+        }
+
+        // Captive portals and misbehaving proxies tend to answer with HTML
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_ascii_lowercase());
+        if let Some(content_type) = &content_type {
+            if !content_type.contains("json") {
+                let body = self
+                    .read_api_body(response, &request)
+                    .await
+                    .unwrap_or_default();
+                error!(
+                    "API returned unexpected content-type '{}': {}",
+                    content_type,
+                    capture::for_log("api-response", &body, capture::MAX_LOGGED_BODY_BYTES)
+                );
+                return Err(error_code::ErrorCode::ApiError.error(format!(
+                    "API returned unexpected content-type '{}' (expected JSON)",
+                    content_type
+                )));
+            }
+        }
+
+        let validators = conditional::Validators::from_headers(response.headers());
+        let response_text = self.read_api_body(response, &request).await?;
+        let config = Self::parse_compose_config(&response_text)?;
+        Ok(ComposeResponse::Config(Box::new(config), validators))
+    }
+
+    /// Parse a compose config as served by the API, over HTTP or a subscription
+    fn parse_compose_config(response_text: &str) -> Result<ComposeConfig> {
+        match serde_json::from_str::<ComposeConfig>(response_text) {
+            Ok(config) => Ok(config),
+            Err(e) => {
+                error!(
+                    "Failed to parse compose config JSON ({} bytes). Response: {}",
+                    response_text.len(),
+                    capture::for_log(
+                        "compose-config",
+                        response_text,
+                        capture::MAX_LOGGED_BODY_BYTES,
+                    )
+                );
+                Err(error_code::ErrorCode::ApiError.error(e.to_string()))
+                    .context("Failed to parse compose config")
+            }
+        }
+    }
+
+    /// Keep the clock offset seen in a platform API response's `Date`
+    /// header, warning when it first goes beyond the tolerance
+    fn record_api_clock(&self, headers: &reqwest::header::HeaderMap, sent: std::time::SystemTime) {
+        let Some(offset) = headers
+            .get(reqwest::header::DATE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|date| clock::offset_from_date(date, sent, std::time::SystemTime::now()))
+        else {
+            return;
+        };
+        let policy = self
+            .load_platform_config()
+            .ok()
+            .and_then(|config| config.clock_check)
+            .unwrap_or_default();
+        let previous = self
+            .api_clock_offset
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .replace(offset);
+        if policy.exceeded(offset) && !previous.is_some_and(|p| policy.exceeded(p)) {
+            warn!(
+                "Host clock is {} the platform API's (tolerance {}s); VM attestation and TLS may fail",
+                clock::describe(offset),
+                policy.max_drift_secs()
+            );
+        }
+    }
+
+    /// Refuse to create a VM on a host whose clock is off: the VM's quote
+    /// verification and TLS to the KMS would fail with unrelated-looking
+    /// errors. A drift that cannot be measured does not block anything.
+    async fn ensure_clock_trusted(&self, platform_config: &PlatformConfig) -> Result<()> {
+        let policy = platform_config.clock_check.clone().unwrap_or_default();
+        let api_offset = *self
+            .api_clock_offset
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let (offset, source) = match policy.measure(api_offset).await {
+            Ok(measured) => measured,
+            Err(e) => {
+                warn!("Could not measure host clock drift: {:#}", e);
+                return Ok(());
+            }
+        };
+        if !policy.exceeded(offset) {
+            return Ok(());
+        }
+        let message = format!(
+            "Host clock is {} {} (tolerance {}s); the VM's attestation and TLS would fail. Fix the host's time synchronisation",
+            clock::describe(offset),
+            source,
+            policy.max_drift_secs()
+        );
+        Self::audit(
+            "ClockDriftDetected",
+            json!({
+                "offset_secs": offset,
+                "source": source,
+                "max_drift_secs": policy.max_drift_secs(),
+                "refused": !policy.warn_only,
+            }),
+        );
+        if policy.warn_only {
+            warn!("ALERT: {}", message);
+            return Ok(());
+        }
+        error!("ALERT: {}", message);
+        Err(anyhow::Error::new(ClockDrift(message)))
+    }
+
+    /// Read a response body at the configured bandwidth cap, resuming it
+    /// with `request` if interrupted, and aborting once it exceeds
+    /// MAX_API_RESPONSE_BYTES
+    async fn read_api_body(
+        &self,
+        response: reqwest::Response,
+        request: impl Fn(reqwest::header::HeaderMap) -> reqwest::RequestBuilder,
+    ) -> Result<String> {
+        let body = bandwidth::read_body(
+            response,
+            self.api_bandwidth,
+            MAX_API_RESPONSE_BYTES,
+            request,
+        )
+        .await?;
+        String::from_utf8(body).context("API response is not valid UTF-8")
+    }
+
+    /// [`Self::load_platform_config`], or the defaults if it fails, warning
+    /// once per distinct error rather than on every poll
+    fn platform_config_or_default(&self) -> PlatformConfig {
+        let result = self.load_platform_config();
+        let mut fallbacks = self
+            .config_fallbacks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        match result {
+            Ok(config) => {
+                fallbacks.clear();
+                config
+            }
+            Err(e) => {
+                fallbacks.warn(format!("{:#}", e));
+                PlatformConfig::default()
+            }
+        }
+    }
+
+    /// Load the platform config; if the file exists but cannot be parsed,
+    /// report it and keep using the last config that loaded successfully
+    fn load_platform_config(&self) -> Result<PlatformConfig> {
+        let mut last_good = self
+            .last_good_config
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+
+        let mut config_errors = self.config_errors.lock().unwrap_or_else(|e| e.into_inner());
+
+        match PlatformConfig::load() {
+            Ok(config) => {
+                config_errors.clear();
+                *last_good = Some(config.clone());
+                Ok(config)
+            }
+            Err(e) if paths::config_path().exists() => match last_good.as_ref() {
+                Some(config) => {
+                    config_errors.error(format!(
+                        "{} is unreadable or corrupted ({:#}); using last known good config",
+                        paths::config_path().display(),
+                        e
+                    ));
+                    Ok(config.clone())
+                }
+                None => Err(e.context(format!(
+                    "Platform config {} is corrupted and no previous config is available",
+                    paths::config_path().display()
+                ))),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub(crate) fn build_env_vars(platform_config: &PlatformConfig) -> Vec<Value> {
+        let mut env_vars = Vec::new();
+        let mut seen_keys = std::collections::HashSet::<String>::new();
+
+        // First, add all environment variables from platform config.env
+        // These are the values set via "config set-env" command for API-required keys
+        if let Some(custom_env) = &platform_config.env {
+            for (key, value) in custom_env {
+                env_vars.push(json!({
+                    "key": key,
+                    "value": value
+                }));
+                seen_keys.insert(key.clone());
+            }
+        }
+
+        // Add DSTACK_VMM_URL (always added from platform config, unless already in env)
+        if !seen_keys.contains("DSTACK_VMM_URL") {
+            env_vars.push(json!({
+                "key": "DSTACK_VMM_URL",
+                "value": resolve_guest_vmm_url(platform_config).url
+            }));
+            seen_keys.insert("DSTACK_VMM_URL".to_string());
+        }
+
+        info!(
+            "Built {} environment variables for VM from platform config",
+            env_vars.len()
+        );
+        env_vars
+    }
+
+    fn log_vm_parameters(vm_type: &str, params: &VmParameters) {
+        info!(
+            target: "validator-updater",
+            "Validator VM hardware spec resolved: vm_type={}, image={}, vcpu={}, memory_mb={}, disk_gb={}",
+            vm_type,
+            params.image,
+            params.vcpu,
+            params.memory,
+            params.disk_size,
+        );
+    }
+
+    fn check_required_env(
+        &self,
+        required_env: &[String],
+        env_vars: &[Value],
+    ) -> Result<Vec<String>> {
+        let mut missing = Vec::new();
+
+        for required in required_env {
+            let found = env_vars.iter().any(|env| {
+                env.get("key")
+                    .and_then(|k| k.as_str())
+                    .map(|k| k == required)
+                    .unwrap_or(false)
+            });
+
+            if !found {
+                missing.push(required.clone());
+            }
+        }
+
+        Ok(missing)
+    }
+
+    /// Apply the missing env policy to the required keys without a value.
+    /// Returns the keys whose absence holds any deployment.
+    async fn ensure_required_env(
+        &self,
+        required_env_keys: &[String],
+        config: &ComposeConfig,
+    ) -> Result<Vec<String>> {
+        if required_env_keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let platform_config = self.load_platform_config().unwrap_or_default();
+
+        // Build env vars from platform config (merges API keys with local values)
+        let env_vars = Self::build_env_vars(&platform_config);
+
+        // Check which required keys are missing values
+        let missing = self.check_required_env(required_env_keys, &env_vars)?;
+        let metadata = &config.env_metadata;
+        let describe = |keys: &[String]| {
+            keys.iter()
+                .map(|key| env_metadata::describe_key(key, metadata))
+                .collect::<Vec<_>>()
+        };
+        let mut actions = platform_config
+            .missing_env
+            .unwrap_or_default()
+            .classify(&missing, config, metadata);
+
+        if let Some(keys) = actions.remove(&missing_env::MissingEnvAction::Warn) {
+            warn!(
+                "Deploying without values for required environment variable keys: {}",
+                describe(&keys).join("; ")
+            );
+        }
+        if let Some(missing) = actions.remove(&missing_env::MissingEnvAction::Fail) {
+            let described = describe(&missing);
+            error!(
+                "Missing values for required environment variable keys: {}",
+                described.join("; ")
+            );
+            anyhow::bail!(
+                "Missing values for required environment variable keys: {}. Please set them with 'validator-auto-updater config set-env <key> <value>'",
+                described.join("; ")
+            );
+        }
+
+        Ok(actions
+            .remove(&missing_env::MissingEnvAction::Hold)
+            .unwrap_or_default())
+    }
+
+    async fn list_vms(&self) -> Result<Vec<VmInfo>> {
+        let response = self
+            .rpc_call("Status", json!({}))
+            .await
+            .context("Failed to get VM status")?;
+
+        let vms = response
+            .get("vms")
+            .and_then(|v| v.as_array())
+            .context("Invalid status response")?;
+
+        Ok(vms.iter().filter_map(VmInfo::from_status_entry).collect())
+    }
+
+    /// Block until the VMM answers again, up to VMM_WAIT_TIMEOUT
+    async fn wait_for_vmm(&self, before: &str) -> Result<()> {
+        self.wait_for_vmm_within(before, VMM_WAIT_TIMEOUT).await
+    }
+
+    async fn wait_for_vmm_within(&self, before: &str, wait: Duration) -> Result<()> {
+        let started = std::time::Instant::now();
+        let mut warned = false;
+        loop {
+            match self.list_vms().await {
+                Ok(_) => {
+                    if warned {
+                        info!(
+                            "VMM is back after {}s, continuing to {}",
+                            started.elapsed().as_secs(),
+                            before
+                        );
+                    }
+                    return Ok(());
+                }
+                Err(e) if is_vmm_unavailable(&e) && started.elapsed() < wait => {
+                    if !warned {
+                        warn!("VMM unavailable, holding off before {}: {:#}", before, e);
+                        warned = true;
+                    }
+                    sleep(VMM_RETRY_INTERVAL).await;
+                }
+                Err(e) => {
+                    return Err(e.context(format!("VMM not available to {}", before)));
+                }
+            }
+        }
+    }
+
+    async fn find_validator_vm(&self) -> Result<Option<VmInfo>> {
+        let vms = self.list_vms().await?;
+
+        // A VM recorded in state (e.g. adopted under another name) takes precedence
+        let state = state::UpdaterState::load().unwrap_or_default();
+        if let Some(managed_id) = &state.vm_id {
+            if let Some(vm) = vms.iter().find(|vm| &vm.id == managed_id) {
+                return Ok(Some(vm.clone()));
+            }
+        }
+
+        // The warm standby and a canary share the name, but are only ever promoted explicitly
+        let unpromoted = state
+            .pending_update
+            .as_ref()
+            .filter(|intent| intent.phase != state::UpdatePhase::Boot)
+            .and_then(|intent| intent.new_vm_id.as_ref());
+        for vm in vms.into_iter().filter(|vm| {
+            state.standby_id() != Some(&vm.id)
+                && state.canary.as_ref() != Some(&vm.id)
+                && unpromoted != Some(&vm.id)
+        }) {
+            if vm.name.as_deref() == Some(VM_NAME) || vm.app_id.as_deref() == Some(VM_NAME) {
+                if vm.app_id.is_none() {
+                    warn!("Found VM {} but appId is missing", vm.id);
+                }
+                return Ok(Some(vm));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Take over an existing VM after checking its app_id against the current API config
+    pub async fn adopt_vm(&mut self, vm_id: &str) -> Result<()> {
+        self.ensure_mutations_allowed("adopt a VM")?;
+        let _changes = lock_mutations().await?;
+        let vm = self
+            .list_vms()
+            .await?
+            .into_iter()
+            .find(|vm| vm.id == vm_id)
+            .context(format!("VM {} not found on the VMM", vm_id))?;
+
+        let config = self.fetch_compose_config().await?;
+        let platform_config = self.load_platform_config().unwrap_or_default();
+        let desired = Self::resolve_desired(&config, &platform_config)?;
+        let expected_app_id = compose_hash::app_id_from_hash(&desired.hash);
+
+        let app_id = vm
+            .app_id
+            .as_deref()
+            .context(format!("VM {} does not report an app_id", vm_id))?;
+        if compose_hash::app_id_from_hash(app_id) != expected_app_id {
+            anyhow::bail!(
+                "VM {} app_id {} does not match the current API config ({}); refusing to adopt",
+                vm_id,
+                app_id,
+                expected_app_id
+            );
+        }
+
+        // The app_id pins the compose; the guest agent, where the VM is up
+        // and the agent answers, tells whether the app itself runs. A VM
+        // that is not ready yet is still adopted: the update check watches
+        // it from here on like any other managed VM.
+        let app_ready = if vm.status == "running" {
+            match self.guest_ready(vm_id).await {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!(
+                        "VM {} app_id matches, but its app is not ready: {:#}",
+                        vm_id, e
+                    );
+                    false
+                }
+            }
+        } else {
+            warn!(
+                "VM {} is in '{}' state; adopting it without checking its app",
+                vm_id, vm.status
+            );
+            false
+        };
+
+        let mut state = state::UpdaterState::load()?;
+        if let Some(previous) = state.vm_id.as_deref().filter(|id| *id != vm_id) {
+            warn!("Replacing previously managed VM {} in state", previous);
+        }
+        state.set_managed(vm_id, &desired.hash, desired.fingerprint());
+        state.pinned_image = self
+            .image_digest(&desired.vm_params.image)
+            .await
+            .ok()
+            .flatten()
+            .map(|digest| state::PinnedImage {
+                name: desired.vm_params.image.clone(),
+                digest,
+            });
+        state.save()?;
+        desired
+            .record(vm_id, &config, self.config_source())
+            .save()?;
+
+        Self::audit(
+            "AdoptVm",
+            json!({
+                "vm_id": vm_id,
+                "app_id": app_id,
+                "compose_hash": desired.hash,
+                "status": vm.status,
+                "app_ready": app_ready,
+                "api_url": self.config_source(),
+            }),
+        );
+        self.update_endpoints(
+            &platform_config,
+            vm_id,
+            &desired.hash,
+            &desired.vm_params.ports,
+        );
+        self.vm_id = Some(vm_id.to_string());
+        self.current_hash = Some(desired.hash);
+        self.finish_endpoint_updates().await;
+        Ok(())
+    }
+
+    /// Record `vm_id` running `hash` as the managed VM, in memory and in the
+    /// state file, so a restarted launcher picks up where this one left off.
+    /// Observe mode leaves the state file alone.
+    fn set_current(&mut self, vm_id: &str, hash: String) {
+        let changed = self.vm_id.as_deref() != Some(vm_id)
+            || self.current_hash.as_deref() != Some(hash.as_str());
+        if changed && !self.observe {
+            state::UpdaterState::update(|state| state.set_current(vm_id, &hash));
+        }
+        self.vm_id = Some(vm_id.to_string());
+        self.current_hash = Some(hash);
+    }
+
+    fn ensure_mutations_allowed(&self, action: &str) -> Result<()> {
+        if self.observe {
+            anyhow::bail!("Refusing to {} in observe mode", action);
+        }
+        Ok(())
+    }
+
+    /// Record an action in the audit log; failures are logged but never fatal
+    fn audit(action: &str, details: Value) {
+        if let Err(e) = audit::record(action, details) {
+            warn!("Failed to write audit log entry for {}: {}", action, e);
+        }
+    }
+
+    /// Compose hash of `vm_id` if it is the managed VM, for audit entries
+    fn managed_hash(&self, vm_id: &str) -> Option<&str> {
+        self.current_hash
+            .as_deref()
+            .filter(|_| self.vm_id.as_deref() == Some(vm_id))
+    }
+
+    async fn stop_vm(&self, vm_id: &str) -> Result<()> {
+        self.ensure_mutations_allowed("stop VM")?;
+        info!("Stopping VM: {}", vm_id);
+        Self::audit(
+            "StopVm",
+            json!({ "vm_id": vm_id, "compose_hash": self.managed_hash(vm_id) }),
+        );
+
+        match timeout(
+            VM_KILL_TIMEOUT,
+            self.rpc_call("StopVm", json!({ "id": vm_id })),
+        )
+        .await
+        {
+            Ok(Ok(_)) => {
+                info!("VM {} stop command sent, waiting for VM to stop...", vm_id);
+                // Wait for VM to actually stop
+                sleep(Duration::from_secs(5)).await;
+                Ok(())
+            }
+            Ok(Err(e)) => {
+                warn!(
+                    "Failed to stop VM {}: {}, will try to remove anyway",
+                    vm_id, e
+                );
+                Ok(()) // Don't fail, just warn
+            }
+            Err(_) => {
+                warn!("Timeout stopping VM {}", vm_id);
+                Ok(()) // Don't fail, just warn
+            }
+        }
+    }
+
+    async fn remove_vm(&self, vm_id: &str) -> Result<()> {
+        self.ensure_mutations_allowed("remove VM")?;
+        info!("Removing VM: {}", vm_id);
+
+        // Retry removal up to 3 times with delays
+        for attempt in 1..=3 {
+            match self.rpc_call("RemoveVm", json!({ "id": vm_id })).await {
+                Ok(_) => {
+                    info!("VM {} removed successfully", vm_id);
+                    Self::audit(
+                        "RemoveVm",
+                        json!({ "vm_id": vm_id, "compose_hash": self.managed_hash(vm_id) }),
+                    );
+                    self.events.emit(events::Event::VmRemoved {
+                        vm_id: vm_id.to_string(),
+                    });
+                    return Ok(());
+                }
+                Err(e) => {
+                    if attempt < 3 {
+                        warn!(
+                            "Failed to remove VM {} (attempt {}/3): {}, retrying...",
+                            vm_id, attempt, e
+                        );
+                        sleep(Duration::from_secs(3)).await;
+                    } else {
+                        error!("Failed to remove VM {} after 3 attempts", vm_id);
+                        return Err(e);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fail unless this launcher created or adopted `vm_id`, or was told to
+    /// remove it anyway; a VM that merely has the validator's name is not ours
+    fn ensure_removable(&self, vm_id: &str) -> Result<()> {
+        if state::UpdaterState::load()?.owns(vm_id) {
+            return Ok(());
+        }
+        if !self.remove_unowned {
+            return Err(error_code::ErrorCode::VmNotOwned.error(format!(
+                "VM {} was not created or adopted by this launcher; refusing to remove it (`adopt {}` to manage it, or pass --force)",
+                vm_id, vm_id
+            )));
+        }
+        warn!(
+            "Removing VM {}, which this launcher does not own (--force)",
+            vm_id
+        );
+        Ok(())
+    }
+
+    async fn kill_and_remove_vm(&self, vm_id: &str) -> Result<()> {
+        self.ensure_mutations_allowed("remove VM")?;
+        self.ensure_removable(vm_id)?;
+        info!("Killing and removing VM: {}", vm_id);
+
+        // Always stop first (won't fail even if error)
+        let _ = self.stop_vm(vm_id).await;
+
+        // Wait a bit more to ensure VM is fully stopped
+        sleep(Duration::from_secs(2)).await;
+
+        // Try to remove with retries
+        self.remove_vm(vm_id).await?;
+        state::UpdaterState::update(|state| state.forget(vm_id));
+
+        info!("VM {} successfully killed and removed", vm_id);
+        Ok(())
+    }
+
+    /// Create the VM, riding out VMM restarts. The old VM is already gone at
+    /// this point, so giving up would leave nothing running until the next poll.
+    async fn create_vm_resilient(
+        &mut self,
+        compose_config: &ComposeConfig,
+        desired: &DesiredDeployment,
+        vm_params: &VmParameters,
+        progress: &mut events::CreateProgress,
+    ) -> Result<String> {
+        let mut attempt = 1;
+        loop {
+            self.wait_for_vmm("create the VM").await?;
+            // A CreateVm sent before the VMM dropped out may have gone through
+            if attempt > 1 {
+                if let Some(vm) = self.find_validator_vm().await? {
+                    info!("VM {} was created before the VMM dropped out", vm.id);
+                    state::UpdaterState::update(|state| state.claim(&vm.id));
+                    return Ok(vm.id);
+                }
+            }
+            match self
+                .create_vm(compose_config, desired, vm_params, None, progress)
+                .await
+            {
+                Err(e) if is_vmm_unavailable(&e) && attempt < CREATE_ATTEMPTS => {
+                    warn!(
+                        "VMM dropped out while creating the VM (attempt {}/{}): {:#}",
+                        attempt, CREATE_ATTEMPTS, e
+                    );
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Create and start a VM running `desired`'s manifest with `vm_params`,
+    /// which may differ from `desired.vm_params` in ports and state only. A
+    /// canary has the app_id of the VM it is promoted to, but only the env
+    /// [`canary::env`] leaves it.
+    async fn create_vm(
+        &self,
+        compose_config: &ComposeConfig,
+        desired: &DesiredDeployment,
+        vm_params: &VmParameters,
+        canary: Option<&canary::CanaryConfig>,
+        progress: &mut events::CreateProgress,
+    ) -> Result<String> {
+        self.ensure_mutations_allowed("create VM")?;
+        info!(
+            "Creating new VM with compose hash: {} (image: {})",
+            desired.hash, vm_params.image
+        );
+
+        // Load platform configuration (always use values from platform config)
+        let platform_config = self.platform_config_or_default();
+
+        info!(
+            "Loaded platform config for VM creation: guest VMM URL={}, env vars count={}",
+            resolve_guest_vmm_url(&platform_config),
+            platform_config.env.as_ref().map(|e| e.len()).unwrap_or(0)
+        );
+        // A stopped VM attests only once started
+        if !vm_params.stopped {
+            self.ensure_clock_trusted(&platform_config).await?;
+        }
+
+        let app_id_truncated = compose_hash::app_id_from_hash(&desired.hash);
+
+        // Build environment variables from platform config
+        let mut env_vars = Self::build_env_vars(&platform_config);
+        if let Some(canary) = canary {
+            env_vars = canary::env(
+                env_vars,
+                canary,
+                &compose_config.env_metadata,
+                &desired.allowed_envs,
+            );
+        }
+
+        // Get encryption public key from KMS
+        let pubkey_hex = progress
+            .run(
+                events::CreatePhase::EncryptionKey,
+                self.env_encrypt_pubkey(app_id_truncated),
+            )
+            .await?;
+
+        // Encrypt environment variables
+        let encrypted_env = progress
+            .run(events::CreatePhase::EncryptEnv, async {
+                envelope::seal(&env_vars, &pubkey_hex).map(hex::encode)
+            })
+            .await?;
+
+        let mut report = validation::ValidationReport::default();
+        validation::validate_vm_parameters(vm_params, &mut report);
+        report.into_result()?;
+
+        let vm_config = json!({
+            "name": vm_params.name.clone().unwrap_or_else(|| desired.vm_name.clone()),
+            "image": vm_params.image,
+            "compose_file": desired.compose_file,
+            "vcpu": vm_params.vcpu,
+            "memory": vm_params.memory,
+            "disk_size": vm_params.disk_size,
+            "user_config": vm_params.user_config,
+            "ports": vm_params.ports,
+            "encrypted_env": encrypted_env,
+            "hugepages": vm_params.hugepages,
+            "pin_numa": vm_params.pin_numa,
+            "stopped": vm_params.stopped,
+        });
+
+        // Get the compose hash from VMM to validate
+        let vmm_hash = progress
+            .run(events::CreatePhase::ComposeHash, async {
+                let hash_response = self
+                    .rpc_call("GetComposeHash", vm_config.clone())
+                    .await
+                    .context("Failed to get compose hash from VMM")?;
+
+                hash_response
+                    .get("hash")
+                    .and_then(|h| h.as_str())
+                    .map(String::from)
+                    .context("Invalid hash response")
+            })
+            .await?;
+
+        info!("VMM computed compose hash: {}", vmm_hash);
+        let comparison = compose_hash::HashComparison {
+            launcher: desired.hash.clone(),
+            api: None,
+            vmm: Some(vmm_hash.clone()),
+        };
+        if !comparison.disagreeing().is_empty() {
+            return Err(error_code::ErrorCode::HashMismatch.error(format!(
+                "Refusing to create the VM: the VMM computes a different compose hash ({})",
+                comparison
+            )));
+        }
+
+        // Create the VM
+        let vm_id = progress
+            .run(events::CreatePhase::CreateVm, async {
+                let response = self
+                    .rpc_call("CreateVm", vm_config)
+                    .await
+                    .context("Failed to create VM")?;
+
+                response
+                    .get("id")
+                    .and_then(|i| i.as_str())
+                    .map(String::from)
+                    .context("Invalid create VM response")
+            })
+            .await?;
+
+        info!("VM created with ID: {}", vm_id);
+        // Claimed right away, so a canary left behind by a crash can be removed later
+        state::UpdaterState::update(|state| state.claim(&vm_id));
+        let mut details = json!({
+            "vm_id": vm_id,
+            "compose_hash": desired.hash,
+            "vmm_compose_hash": vmm_hash,
+            "image": vm_params.image,
+        });
+        if let Some(changelog) = compose_config.changelog() {
+            details["changelog"] = json!(changelog);
+        }
+        if canary.is_some() {
+            details["canary"] = json!(true);
+        }
+        if vm_params.stopped {
+            details["stopped"] = json!(true);
+        }
+        Self::audit("CreateVm", details);
+        self.events.emit(events::Event::VmCreated {
+            vm_id: vm_id.clone(),
+            compose_hash: desired.hash.clone(),
+        });
+        Ok(vm_id)
+    }
+
+    /// Public key the KMS wants env vars of `app_id` encrypted with
+    async fn env_encrypt_pubkey(&self, app_id: &str) -> Result<String> {
+        info!("Getting encryption key for app_id: {}", app_id);
+        let pubkey_response = self
+            .rpc_call("GetAppEnvEncryptPubKey", json!({ "app_id": app_id }))
+            .await
+            .context("Failed to get encryption public key")?;
+
+        pubkey_response
+            .get("public_key")
+            .and_then(|k| k.as_str())
+            .map(String::from)
+            .context("Invalid public key response")
+    }
+
+    /// Turn the stopped canary into the validator. It already has the
+    /// desired app_id, so only the full env and the real host ports are
+    /// delivered before it is started.
+    async fn promote_canary(
+        &self,
+        vm_id: &str,
+        desired: &DesiredDeployment,
+        platform_config: &PlatformConfig,
+    ) -> Result<()> {
+        self.ensure_mutations_allowed("promote the canary")?;
+        let vm_params = &desired.vm_params;
+        let pubkey_hex = self
+            .env_encrypt_pubkey(compose_hash::app_id_from_hash(&desired.hash))
+            .await?;
+        let encrypted_env =
+            envelope::seal(&Self::build_env_vars(platform_config), &pubkey_hex).map(hex::encode)?;
+
+        // Resumed promotions may find the VM already started
+        let vm = self
+            .list_vms()
+            .await?
+            .into_iter()
+            .find(|vm| vm.id == vm_id)
+            .context(format!("Canary VM {} disappeared before promotion", vm_id))?;
+        let running = recovery::VmCondition::classify(&vm.status) == recovery::VmCondition::Up;
+        if running {
+            self.stop_vm_and_wait(vm_id).await?;
+        }
+        info!("Promoting canary VM {}", vm_id);
+        self.rpc_call(
+            "UpgradeApp",
+            json!({
+                "id": vm_id,
+                "compose_file": desired.compose_file,
+                "encrypted_env": encrypted_env,
+                "user_config": vm_params.user_config,
+                "update_ports": true,
+                "ports": vm_params.ports,
+            }),
+        )
+        .await
+        .context(format!("Failed to promote canary VM {}", vm_id))?;
+        self.rpc_call("StartVm", json!({ "id": vm_id }))
+            .await
+            .context(format!("Failed to start VM {}", vm_id))?;
+        Self::audit(
+            "CanaryPromoted",
+            json!({ "vm_id": vm_id, "app_id": desired.hash }),
+        );
+        self.events.emit(events::Event::VmStarted {
+            vm_id: vm_id.to_string(),
+        });
+        Ok(())
+    }
+
+    /// The boot timeout of a new VM with a `new_disk_gb` GB disk, or of an
+    /// existing VM when None
+    fn boot_timeout(&self, new_disk_gb: Option<u32>) -> Duration {
+        self.load_platform_config()
+            .unwrap_or_default()
+            .boot_timeout
+            .unwrap_or_default()
+            .for_boot(new_disk_gb)
+    }
+
+    /// Poll the VMM until the VM is running, failing fast on error states
+    async fn wait_for_vm_running(&self, vm_id: &str, boot_timeout: Duration) -> Result<()> {
+        let deadline = tokio::time::Instant::now() + boot_timeout;
+        let mut last_status = "unknown".to_string();
+
+        loop {
+            let response = self.rpc_call("Status", json!({})).await;
+            if let Ok(response) = response {
+                let vm = response
+                    .get("vms")
+                    .and_then(|v| v.as_array())
+                    .and_then(|vms| {
+                        vms.iter()
+                            .find(|vm| vm.get("id").and_then(|i| i.as_str()) == Some(vm_id))
+                    });
+                let Some(vm) = vm else {
+                    anyhow::bail!("VM {} disappeared from the VMM while booting", vm_id);
+                };
+
+                let field = |key: &str| {
+                    vm.get(key)
+                        .and_then(|v| v.as_str())
+                        .unwrap_or_default()
+                        .to_string()
+                };
+                let status = field("status");
+                let boot_progress = field("boot_progress");
+                let boot_error = field("boot_error");
+
+                if !boot_error.is_empty() {
+                    anyhow::bail!("VM {} reported boot error: {}", vm_id, boot_error);
+                }
+                if matches!(status.as_str(), "exited" | "stopped" | "killed" | "error") {
+                    anyhow::bail!("VM {} entered '{}' state while booting", vm_id, status);
+                }
+                if status == "running" && (boot_progress.is_empty() || boot_progress == "done") {
+                    info!("VM {} is running", vm_id);
+                    return Ok(());
+                }
+                last_status = if boot_progress.is_empty() {
+                    status
+                } else {
+                    format!("{} ({})", status, boot_progress)
+                };
+            }
+
+            if tokio::time::Instant::now() >= deadline {
+                return Err(error_code::ErrorCode::VmBootTimeout.error(format!(
+                    "VM {} did not reach running state within {:?} (last status: {})",
+                    vm_id, boot_timeout, last_status
+                )));
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Fetch the last `lines` lines of one of the VM's log channels from the VMM
+    async fn fetch_vm_logs(&self, vm_id: &str, channel: &str, lines: usize) -> Result<String> {
+        let response = self
+            .request_vm_logs(&self.http_client, vm_id, channel, lines, false)
+            .await?;
+        response.text().await.context("Failed to read VM logs")
+    }
+
+    async fn request_vm_logs(
+        &self,
+        client: &reqwest::Client,
+        vm_id: &str,
+        channel: &str,
+        lines: usize,
+        follow: bool,
+    ) -> Result<reqwest::Response> {
+        let url = format!("{}/logs", self.vmm_url.trim_end_matches('/'));
+        let response = client
+            .get(&url)
+            .query(&[
+                ("id", vm_id),
+                ("follow", &follow.to_string()),
+                ("ansi", "false"),
+                ("lines", &lines.to_string()),
+                ("ch", channel),
+            ])
+            .send()
+            .await
+            .context("Failed to fetch VM logs")?;
+
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("VMM returned status {} for VM logs", status);
+        }
+        Ok(response)
+    }
+
+    /// Copy the validator VM's logs to stdout: the last `tail` lines, then
+    /// with `follow` new output until the VMM closes the stream
+    pub async fn stream_logs(
+        &mut self,
+        channel: LogChannel,
+        tail: usize,
+        follow: bool,
+    ) -> Result<()> {
+        use std::io::Write;
+
+        let vm = self
+            .find_validator_vm()
+            .await?
+            .context("No validator VM found")?;
+        // The shared client's request timeout would cut a followed stream off
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .danger_accept_invalid_certs(true)
+            .build()
+            .context("Failed to create HTTP client")?;
+        let mut response = self
+            .request_vm_logs(&client, &vm.id, channel.as_str(), tail, follow)
+            .await?;
+        let mut stdout = std::io::stdout().lock();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .context("VM log stream interrupted")?
+        {
+            stdout.write_all(&chunk)?;
+            stdout.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Run an allowed diagnostic command inside the running validator VM
+    /// through the guest agent; every attempt is recorded in the audit log
+    pub async fn exec_in_vm(
+        &mut self,
+        vm_id: &str,
+        command: &[String],
+        timeout: Duration,
+    ) -> Result<guest_exec::ExecOutput> {
+        guest_exec::check_allowed(command)?;
+        let result = guest_exec::exec(&self.vmm_url, vm_id, command, timeout).await;
+        Self::audit(
+            "VmExec",
+            json!({
+                "vm_id": vm_id,
+                "command": command,
+                "exit_code": result.as_ref().ok().map(|output| output.exit_code),
+                "error": result.as_ref().err().map(|e| format!("{:#}", e)),
+            }),
+        );
+        result
+    }
+
+    /// Id of the validator VM, if it is running, for commands that need a live guest
+    pub async fn running_validator_vm(&mut self) -> Result<String> {
+        let vm = self
+            .find_validator_vm()
+            .await?
+            .context("No validator VM found")?;
+        if vm.status != "running" {
+            anyhow::bail!("VM {} is in '{}' state, not running", vm.id, vm.status);
+        }
+        Ok(vm.id)
+    }
+
+    /// Collect console and app log tails for attaching to a failure report
+    async fn capture_failure_logs(&self, vm_id: &str) -> String {
+        let mut snapshot = String::new();
+        for (channel, label) in [("serial", "serial console"), ("stdout", "app output")] {
+            snapshot.push_str(&format!(
+                "\n--- {} (last {} lines) ---\n",
+                label, FAILURE_LOG_LINES
+            ));
+            match self.fetch_vm_logs(vm_id, channel, FAILURE_LOG_LINES).await {
+                Ok(logs) if logs.trim().is_empty() => snapshot.push_str("(empty)"),
+                Ok(logs) => snapshot.push_str(logs.trim_end()),
+                Err(e) => snapshot.push_str(&format!("(unavailable: {})", e)),
+            }
+        }
+        snapshot
+    }
+
+    /// The API's manifest defaults with the local disclosure policy applied,
+    /// and the flags it turned off
+    fn manifest_defaults(
+        defaults: &ManifestDefaults,
+        platform_config: &PlatformConfig,
+    ) -> (ManifestDefaults, Vec<&'static str>) {
+        platform_config
+            .disclosure
+            .clone()
+            .unwrap_or_default()
+            .apply(defaults)
+    }
+
+    fn build_app_manifest(
+        compose_content: &str,
+        defaults: &ManifestDefaults,
+        vm_name: &str,
+        allowed_envs: &[String],
+    ) -> Value {
+        json!({
+            "manifest_version": defaults.manifest_version,
+            "name": defaults
+                .name
+                .clone()
+                .unwrap_or_else(|| vm_name.to_string()),
+            "runner": defaults.runner.clone(),
+            "docker_compose_file": compose_content,
+            "kms_enabled": defaults.kms_enabled,
+            "gateway_enabled": defaults.gateway_enabled,
+            "local_key_provider_enabled": defaults.local_key_provider_enabled,
+            "key_provider_id": defaults.key_provider_id.clone(),
+            "public_logs": defaults.public_logs,
+            "public_sysinfo": defaults.public_sysinfo,
+            "public_tcbinfo": defaults.public_tcbinfo,
+            "allowed_envs": allowed_envs,
+            "no_instance_id": defaults.no_instance_id,
+            "secure_time": defaults.secure_time,
+        })
+    }
+
+    /// Derive the desired VM (allowed envs, parameters, manifest, hash) from a compose config
+    fn resolve_desired(
+        config: &ComposeConfig,
+        platform_config: &PlatformConfig,
+    ) -> Result<DesiredDeployment> {
+        let allowed_envs =
+            AllowedEnvs::resolve(config, platform_config.allowed_envs.as_ref()).list();
+
+        let image_support = validation::ImageSupport::for_image(
+            platform_config,
+            &config.provisioning.vm_parameters.image,
+        );
+        let report = validation::validate_compose_config(config, &image_support);
+        if !report.is_ok() {
+            error!("{}", report);
+            report.into_result()?;
+        }
+
+        let mut vm_params = config.provisioning.vm_parameters.clone();
+        if let Some(limits) = &platform_config.resource_limits {
+            vm_params = limits.apply(vm_params)?;
+        }
+        vm_params.user_config = provisioning::merge_user_config(
+            &vm_params.user_config,
+            platform_config.user_config.as_deref(),
+        );
+
+        Self::log_vm_parameters(&config.vm_type, &vm_params);
+
+        // Use VM name from API config (or fallback to vm_type)
+        let vm_name = vm_params
+            .name
+            .clone()
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| config.vm_type.clone());
+
+        let (manifest_defaults, suppressed) =
+            Self::manifest_defaults(&config.provisioning.manifest_defaults, platform_config);
+        if !suppressed.is_empty() {
+            warn!(
+                "Disclosure policy turns off {} requested by the platform API; the compose hash differs from the API's manifest",
+                suppressed.join(", ")
+            );
+        }
+
+        // Build app manifest using values from API config (manifest_defaults come from API)
+        // but environment variables come from platform config
+        let app_compose = Self::build_app_manifest(
+            &config.compose_content,
+            &manifest_defaults,
+            &vm_name,
+            &allowed_envs,
+        );
+
+        let compose_file_str =
+            serde_json::to_string(&app_compose).context("Failed to serialize app_compose")?;
+
+        // Calculate hash the same way as in create_vm (on the JSON stringified app_compose)
+        // Include image version in hash to ensure VM is recreated when image changes
+        let hash_version = compose_hash::HashVersion::negotiate(config.hash_version)?;
+        let new_hash = hash_version.compose_hash(&compose_file_str, &vm_params.image);
+
+        info!(
+            "Computed compose hash {} (image: {}): {}",
+            hash_version, vm_params.image, new_hash
+        );
+
+        Ok(DesiredDeployment {
+            allowed_envs,
+            manifest_defaults,
+            vm_params,
+            vm_name,
+            compose_file: compose_file_str,
+            hash_version,
+            hash: new_hash,
+        })
+    }
+
+    /// Export the current deployment's artifacts, reconstructing them from the
+    /// API config if this deployment predates deployment records
+    pub async fn export_deployment(&mut self, dir: &std::path::Path) -> Result<()> {
+        let record = match deployment::DeploymentRecord::load()? {
+            Some(record) => record,
+            None => {
+                warn!("No deployment record found, reconstructing from the current API config");
+                let vm = self
+                    .find_validator_vm()
+                    .await?
+                    .context("No validator VM found to export")?;
+                let config = self.fetch_compose_config().await?;
+                let platform_config = self.load_platform_config().unwrap_or_default();
+                let desired = Self::resolve_desired(&config, &platform_config)?;
+                let app_id = vm.app_id.as_deref().unwrap_or_default();
+                if app_id.is_empty() || !desired.hash.starts_with(app_id) {
+                    anyhow::bail!(
+                        "Running VM {} (app_id {}) does not match the current API config; cannot reconstruct its deployment",
+                        vm.id,
+                        app_id
+                    );
+                }
+                let record = desired.record(&vm.id, &config, self.config_source());
+                record.save()?;
+                record
+            }
+        };
+
+        record.export(dir)?;
+        Self::audit(
+            "ExportDeployment",
+            json!({ "vm_id": record.vm_id, "compose_hash": record.compose_hash, "dir": dir }),
+        );
+        Ok(())
+    }
+
+    /// Check exported artifacts for internal consistency and against the live VM.
+    /// Returns every check made; the VM matches when none of them failed.
+    pub async fn verify_deployment(&mut self, dir: &std::path::Path) -> Result<VerifyReport> {
+        let exported = deployment::ExportedDeployment::read(dir)?;
+        let mut report = VerifyReport::default();
+        let mut check = |ok: bool, what: String| report.check(ok, what);
+
+        // 1. The recorded hash must follow from the exported manifest and image
+        let recomputed = exported
+            .manifest
+            .hash_version
+            .compose_hash(&exported.compose_file, &exported.manifest.image);
+        check(
+            recomputed == exported.manifest.compose_hash,
+            format!(
+                "compose hash recomputed from {} ({})",
+                deployment::APP_COMPOSE_FILE,
+                recomputed
+            ),
+        );
+        check(
+            exported.vm_params.image == exported.manifest.image,
+            format!("image in vm parameters ({})", exported.vm_params.image),
+        );
+
+        // 2. The manifest must follow from the compose config and allowed envs
+        let vm_name = exported
+            .vm_params
+            .name
+            .clone()
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| exported.compose_config.vm_type.clone());
+        let rebuilt = Self::build_app_manifest(
+            &exported.compose_config.compose_content,
+            &exported.compose_config.provisioning.manifest_defaults,
+            &vm_name,
+            &exported.allowed_envs,
+        );
+        let rebuilt = serde_json::to_string(&rebuilt).context("Failed to serialize manifest")?;
+        check(
+            compose_hash::normalize_manifest(&rebuilt).ok()
+                == compose_hash::normalize_manifest(&exported.compose_file).ok(),
+            format!(
+                "{} matches {}",
+                deployment::APP_COMPOSE_FILE,
+                deployment::COMPOSE_CONFIG_FILE
+            ),
+        );
+
+        // 3. The live VM must be running exactly this deployment
+        let vms = self.list_vms().await?;
+        let vm = match vms.iter().find(|vm| vm.id == exported.manifest.vm_id) {
+            Some(vm) => Some(vm.clone()),
+            None => self.find_validator_vm().await?,
+        };
+        let Some(vm) = vm else {
+            check(false, "live validator VM exists".to_string());
+            return Ok(report);
+        };
+        check(
+            vm.id == exported.manifest.vm_id,
+            format!("live VM id ({})", vm.id),
+        );
+        let app_id = vm.app_id.clone().unwrap_or_default();
+        check(
+            !app_id.is_empty() && exported.manifest.compose_hash.starts_with(&app_id),
+            format!("live VM app_id ({})", app_id),
+        );
+
+        match &vm.configuration {
+            Some(configuration) => {
+                let live_compose = configuration
+                    .get("compose_file")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default();
+                check(
+                    live_compose == exported.compose_file,
+                    "VMM-reported compose file is byte-identical".to_string(),
+                );
+                for drift in drift::detect_drift(&exported.vm_params, configuration) {
+                    check(false, format!("VMM-reported {}", drift));
+                }
+            }
+            None => check(false, "VMM reports the VM configuration".to_string()),
+        }
+
+        Ok(report)
+    }
+
+    /// Run the planning pipeline (validation, manifest, hashing, allowed envs,
+    /// diff against the running VM) on an operator-supplied payload without
+    /// touching any VM or state. `payload` is either a full compose config as
+    /// served by the API or a bare compose file, which then replaces the
+    /// content of the currently published config.
+    pub async fn simulate(&mut self, payload: &str, image: Option<&str>) -> Result<PlanReport> {
+        self.observe = true;
+
+        let (mut config, compose_only) = match serde_json::from_str::<ComposeConfig>(payload) {
+            Ok(config) => (config, false),
+            Err(_) => {
+                let mut config = self.peek_compose_config().await?;
+                config.compose_content = payload.to_string();
+                (config, true)
+            }
+        };
+        if let Some(image) = image {
+            config.provisioning.vm_parameters.image = image.to_string();
+        }
+        let mut report = self.plan(&config).await?;
+        report.compose_only = compose_only;
+        Ok(report)
+    }
+
+    /// Plan the update the next check would make from the config the API
+    /// publishes now, without stopping, removing, or creating a VM.
+    pub async fn dry_run(&mut self) -> Result<PlanReport> {
+        self.observe = true;
+        self.reload_config();
+        let config = self.peek_compose_config().await?;
+        self.plan(&config).await
+    }
+
+    /// Run every step of planning `config` against this host
+    async fn plan(&mut self, config: &ComposeConfig) -> Result<PlanReport> {
+        api_schema::check_schema_version(config.schema_version)?;
+        let platform_config = self.load_platform_config().unwrap_or_default();
+
+        let image_support = validation::ImageSupport::for_image(
+            &platform_config,
+            &config.provisioning.vm_parameters.image,
+        );
+        validation::validate_compose_config(config, &image_support).into_result()?;
+
+        let desired = Self::resolve_desired(config, &platform_config)?;
+        let env_vars = Self::build_env_vars(&platform_config);
+        let missing = self.check_required_env(&config.required_env_keys(), &env_vars)?;
+        let mut actions = platform_config
+            .missing_env
+            .clone()
+            .unwrap_or_default()
+            .classify(&missing, config, &config.env_metadata);
+        if let Some(missing) = actions.remove(&missing_env::MissingEnvAction::Fail) {
+            anyhow::bail!(
+                "Update would be refused: missing environment values for {}",
+                missing.join(", ")
+            );
+        }
+        let mut report = PlanReport {
+            compose_only: false,
+            unsupported_features: api_schema::unsupported_features(&config.features)
+                .into_iter()
+                .map(|feature| feature.to_string())
+                .collect(),
+            hash_version: desired.hash_version,
+            compose_hash: desired.hash.clone(),
+            vm_params: desired.vm_params.clone(),
+            allowed_envs: desired.allowed_envs.clone(),
+            changelog: config.changelog().map(|changelog| changelog.to_string()),
+            env_skipped: actions
+                .remove(&missing_env::MissingEnvAction::Warn)
+                .unwrap_or_default(),
+            env_held: actions
+                .remove(&missing_env::MissingEnvAction::Hold)
+                .unwrap_or_default(),
+            vm: None,
+            action: None,
+        };
+        let hold = |action: String| {
+            if report.env_held.is_empty() {
+                action
+            } else {
+                format!(
+                    "hold ({}) until values are set for {}",
+                    action,
+                    report.env_held.join(", ")
+                )
+            }
+        };
+
+        let Some(vm) = self.find_validator_vm().await? else {
+            report.action = Some(hold("create a new VM".to_string()));
+            return Ok(report);
+        };
+        let action = self
+            .planned_action(&vm, &desired, &platform_config)
+            .map(hold);
+        report.action = action;
+        report.vm = Some(PlannedVm {
+            app_id_unchanged: vm.app_id.as_deref().map(compose_hash::app_id_from_hash)
+                == Some(compose_hash::app_id_from_hash(&desired.hash)),
+            drift: vm
+                .configuration
+                .as_ref()
+                .map(|actual| {
+                    drift::detect_drift(&desired.vm_params, actual)
+                        .iter()
+                        .map(|d| d.to_string())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            id: vm.id,
+            status: vm.status,
+        });
+        Ok(report)
+    }
+
+    /// What the next check would do to `vm`, or None when it is up to date
+    fn planned_action(
+        &self,
+        vm: &VmInfo,
+        desired: &DesiredDeployment,
+        platform_config: &PlatformConfig,
+    ) -> Option<String> {
+        let provisioning = match state::UpdaterState::load() {
+            Ok(state) => provisioning_change(&state, &vm.id, desired),
+            Err(e) => {
+                warn!("Failed to load launcher state: {:#}", e);
+                None
+            }
+        };
+        plan_update(vm, desired, platform_config, provisioning).map(|plan| plan.to_string())
+    }
+
+    /// Reconcile the host toward `payload`, a compose config as served by the
+    /// API, exactly once. Nothing is touched when the VM already matches.
+    /// With `wait`, also wait up to that long for the guest's containers and
+    /// the health checks to pass.
+    pub async fn apply(&mut self, payload: &str, wait: Option<Duration>) -> Result<ApplyReport> {
+        let _changes = lock_mutations().await?;
+        let config: ComposeConfig =
+            serde_json::from_str(payload).context("Payload is not a compose config")?;
+        api_schema::check_schema_version(config.schema_version)?;
+        let platform_config = self.load_platform_config().unwrap_or_default();
+        let desired = Self::resolve_desired(&config, &platform_config)?;
+
+        let vm = self.find_validator_vm().await?;
+        let action = match &vm {
+            Some(vm) => self.planned_action(vm, &desired, &platform_config),
+            None => Some(reconcile::Action::Create.to_string()),
+        };
+        self.compose_override = Some(config);
+        let (changed, outcome, reason) = match &action {
+            Some(action) => {
+                info!("Applying: {}", action);
+                let mut events = self.events.subscribe();
+                let result = self.check_and_update().await;
+                self.finish_endpoint_updates().await;
+                result?;
+                let after = self.find_validator_vm().await?;
+                let changed = vm.as_ref().map(|vm| (&vm.id, &vm.app_id))
+                    != after.as_ref().map(|vm| (&vm.id, &vm.app_id))
+                    || Self::vm_touched(&mut events);
+                if changed {
+                    (true, ApplyOutcome::Applied, None)
+                } else {
+                    let (outcome, reason) = self.apply_hold_reason(&desired.hash);
+                    warn!("Not applied ({:?}): {}", outcome, reason);
+                    (false, outcome, Some(reason))
+                }
+            }
+            None => {
+                info!("Already converged, nothing to apply");
+                if let Some(vm) = vm {
+                    self.set_current(&vm.id, desired.hash.clone());
+                }
+                (false, ApplyOutcome::Converged, None)
+            }
+        };
+
+        if let (Some(timeout), Some(vm_id)) = (wait, self.vm_id.clone()) {
+            if reason.is_none() {
+                self.wait_until_ready(&vm_id, timeout).await?;
+            }
+        }
+        Ok(ApplyReport {
+            changed,
+            outcome,
+            action,
+            reason,
+            vm_id: self.vm_id.clone(),
+            compose_hash: desired.hash,
+        })
+    }
+
+    /// Whether `events` saw a VM created, removed, started or updated
+    fn vm_touched(events: &mut tokio::sync::broadcast::Receiver<events::Event>) -> bool {
+        use tokio::sync::broadcast::error::TryRecvError;
+        loop {
+            match events.try_recv() {
+                Ok(
+                    events::Event::VmCreated { .. }
+                    | events::Event::VmRemoved { .. }
+                    | events::Event::VmStarted { .. }
+                    | events::Event::UpdateApplied { .. },
+                )
+                // Missed events may have been any of these
+                | Err(TryRecvError::Lagged(_)) => return true,
+                Ok(_) => {}
+                Err(TryRecvError::Empty | TryRecvError::Closed) => return false,
+            }
+        }
+    }
+
+    /// Why a check that planned an action for `hash` left the VM alone
+    fn apply_hold_reason(&self, hash: &str) -> (ApplyOutcome, String) {
+        let state = state::UpdaterState::load().unwrap_or_default();
+        if state.stopped_by_operator.is_some() {
+            return (
+                ApplyOutcome::Blocked,
+                "the VM was stopped or removed by an operator; run `start` or `recreate`"
+                    .to_string(),
+            );
+        }
+        if self.observe {
+            return (ApplyOutcome::Held, "observe mode".to_string());
+        }
+        if self.admin.awaiting_approval().as_deref() == Some(hash) {
+            return (
+                ApplyOutcome::Held,
+                format!("compose hash {} is awaiting approval", hash),
+            );
+        }
+        if let Some(until) = self.recreation_deferred_until.filter(|&at| at > unix_now()) {
+            return (
+                ApplyOutcome::Held,
+                format!(
+                    "backing off recreations for {}s",
+                    until.saturating_sub(unix_now())
+                ),
+            );
+        }
+        (
+            ApplyOutcome::Blocked,
+            "the check left the VM unchanged".to_string(),
+        )
+    }
+
+    /// Resolve allowed_envs for a compose config payload, or for the one the API serves now
+    pub async fn resolve_allowed_envs(&self, payload: Option<&str>) -> Result<AllowedEnvs> {
+        let config = match payload {
+            Some(payload) => {
+                serde_json::from_str(payload).context("Payload is not a compose config")?
+            }
+            None => self.fetch_compose_config().await?,
+        };
+        let platform_config = self.load_platform_config().unwrap_or_default();
+        Ok(AllowedEnvs::resolve(
+            &config,
+            platform_config.allowed_envs.as_ref(),
+        ))
+    }
+
+    /// Run a single update check, as the daemon does on each poll, for
+    /// driving updates from cron or external orchestration. Returns whether
+    /// a VM was created, removed, resized, or started.
+    pub async fn update_once(&mut self) -> Result<bool> {
+        let _instance = lock_instance()?;
+        let _changes = lock_mutations().await?;
+        let mut receiver = self.events.subscribe();
+        self.reload_config();
+        self.resume_interrupted_update().await?;
+        let result = self.scheduled_check().await;
+        self.finish_endpoint_updates().await;
+        result?;
+
+        let mut changed = false;
+        loop {
+            match receiver.try_recv() {
+                Ok(event) => {
+                    changed |= matches!(
+                        event,
+                        events::Event::VmCreated { .. }
+                            | events::Event::VmRemoved { .. }
+                            | events::Event::VmStarted { .. }
+                            | events::Event::UpdateApplied { .. }
+                    )
+                }
+                Err(tokio::sync::broadcast::error::TryRecvError::Lagged(_)) => continue,
+                Err(_) => break,
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Wait for the guest's containers to run and the health checks to pass
+    async fn wait_until_ready(&mut self, vm_id: &str, timeout: Duration) -> Result<()> {
+        let started = std::time::Instant::now();
+        loop {
+            self.health_checks.start();
+            let result = match self.guest_ready(vm_id).await {
+                Ok(()) => self.health_checks.ready(),
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => return Ok(()),
+                Err(e) if started.elapsed() >= timeout => {
+                    return Err(e.context(format!(
+                        "VM {} not ready after {}s",
+                        vm_id,
+                        timeout.as_secs()
+                    )));
+                }
+                Err(e) => debug!("VM {} not ready yet: {:#}", vm_id, e),
+            }
+            sleep(READY_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Whether a VM found at startup actually runs its app: it finishes
+    /// booting and its guest agent reports every container running
+    async fn verify_running_app(&mut self, vm_id: &str) -> Result<()> {
+        info!("Verifying existing VM {} before keeping it", vm_id);
+        let boot_timeout = self.boot_timeout(None);
+        self.wait_for_vm_running(vm_id, boot_timeout).await?;
+        self.wait_until_ready(vm_id, boot_timeout).await
+    }
+
+    #[cfg(feature = "metrics")]
+    async fn guest_ready(&self, vm_id: &str) -> Result<()> {
+        let metrics =
+            guest_metrics::collect(&self.http_client, &self.vmm_url, vm_id, unix_now()).await?;
+        if !metrics.all_running() {
+            anyhow::bail!("Guest: {}", metrics);
+        }
+        Ok(())
+    }
+
+    /// Without the guest agent client, a running VM is as ready as can be told
+    #[cfg(not(feature = "metrics"))]
+    async fn guest_ready(&self, _vm_id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Query the VMM and the platform API for the managed VM's live state
+    /// and whether an update is waiting. Nothing is changed.
+    pub async fn live_status(&mut self) -> Result<LiveStatus> {
+        let vm = self.find_validator_vm().await?;
+        let platform_config = self.load_platform_config().unwrap_or_default();
+        let config = self.peek_compose_config().await;
+        let desired = config
+            .as_ref()
+            .map_err(|e| anyhow::anyhow!("{:#}", e))
+            .and_then(|config| Self::resolve_desired(config, &platform_config));
+        let (latest_hash, api_error) = match &desired {
+            Ok(desired) => (Some(desired.hash.clone()), None),
+            Err(e) => (None, Some(format!("{:#}", e))),
+        };
+        let pending_update = match (&vm, &desired) {
+            (Some(vm), Ok(desired)) => self.planned_action(vm, desired, &platform_config),
+            (None, Ok(_)) => Some("create a new VM".to_string()),
+            (_, Err(_)) => None,
+        };
+        let hashes = match (&config, &desired) {
+            (Ok(config), Ok(desired)) => Some(self.compare_hashes(config, desired).await),
+            _ => None,
+        };
+        Ok(LiveStatus {
+            vm: vm.map(|vm| LiveVm {
+                id: vm.id,
+                name: vm.name,
+                state: vm.status,
+                app_id: vm.app_id,
+            }),
+            latest_hash,
+            api_error,
+            pending_update,
+            hashes,
+        })
+    }
+
+    /// The compose hash of `desired` as this launcher, the platform API, and the VMM compute it
+    async fn compare_hashes(
+        &self,
+        config: &ComposeConfig,
+        desired: &DesiredDeployment,
+    ) -> compose_hash::HashComparison {
+        let params = &desired.vm_params;
+        let request = json!({
+            "name": params.name.as_deref().unwrap_or(VM_NAME),
+            "image": params.image,
+            "compose_file": desired.compose_file,
+            "vcpu": params.vcpu,
+            "memory": params.memory,
+            "disk_size": params.disk_size,
+        });
+        let vmm = match self.rpc_call("GetComposeHash", request).await {
+            Ok(response) => response
+                .get("hash")
+                .and_then(|h| h.as_str())
+                .map(String::from),
+            Err(e) => {
+                debug!("VMM did not compute the compose hash: {:#}", e);
+                None
+            }
+        };
+        compose_hash::HashComparison {
+            launcher: desired.hash.clone(),
+            api: config.compose_hash.clone(),
+            vmm,
+        }
+    }
+
+    /// Log the hash comparison whenever the config changes, with an alert
+    /// if a component disagrees, and keep it for `status`
+    async fn cross_check_hashes(&mut self, config: &ComposeConfig, desired: &DesiredDeployment) {
+        let checked = self.admin.hash_check().is_some_and(|previous| {
+            previous.launcher == desired.hash
+                && previous.api == config.compose_hash
+                && previous.vmm.is_some()
+        });
+        if checked {
+            return;
+        }
+        let comparison = self.compare_hashes(config, desired).await;
+        if comparison.disagreeing().is_empty() {
+            info!("Compose hashes: {}", comparison);
+        } else {
+            error!(
+                "ALERT [{}]: components disagree on the compose hash, so the VM would be recreated on every check: {}",
+                error_code::ErrorCode::HashMismatch,
+                comparison
+            );
+        }
+        self.admin.record_hash_check(comparison);
+    }
+
+    /// Pause auto-updates until [`Self::resume_updates`]. Returns false if
+    /// they were already paused.
+    pub fn pause_updates() -> Result<bool> {
+        let mut state = state::UpdaterState::load()?;
+        if state.paused.is_some() {
+            return Ok(false);
+        }
+        state.paused = Some(unix_now());
+        state.save()?;
+        Self::audit("PauseUpdates", json!({ "requested_by": "operator" }));
+        Ok(true)
+    }
+
+    /// Resume auto-updates. Returns when they were paused, or None if they were not.
+    pub fn resume_updates() -> Result<Option<u64>> {
+        let mut state = state::UpdaterState::load()?;
+        let Some(paused) = state.paused.take() else {
+            return Ok(None);
+        };
+        state.save()?;
+        Self::audit(
+            "ResumeUpdates",
+            json!({ "paused_at": paused, "requested_by": "operator" }),
+        );
+        Ok(Some(paused))
+    }
+
+    /// Attach a note to the current deployment. Returns the annotated VM.
+    pub fn annotate(text: &str) -> Result<String> {
+        let text = text.trim();
+        if text.is_empty() {
+            anyhow::bail!("Annotation is empty");
+        }
+        let mut state = state::UpdaterState::load()?;
+        let vm_id = state
+            .vm_id
+            .clone()
+            .context("No deployment to annotate: the launcher manages no VM")?;
+        state.annotations.push(state::Annotation {
+            text: text.to_string(),
+            at: unix_now(),
+        });
+        state.save()?;
+        Self::audit(
+            "Annotate",
+            json!({
+                "vm_id": vm_id,
+                "compose_hash": state.current_hash,
+                "text": text,
+                "requested_by": "operator",
+            }),
+        );
+        Ok(vm_id)
+    }
+
+    /// Remove the notes on the current deployment. Returns how many there were.
+    pub fn clear_annotations() -> Result<usize> {
+        let mut state = state::UpdaterState::load()?;
+        let cleared = std::mem::take(&mut state.annotations);
+        if cleared.is_empty() {
+            return Ok(0);
+        }
+        state.save()?;
+        Self::audit(
+            "ClearAnnotations",
+            json!({ "vm_id": state.vm_id, "cleared": cleared.len(), "requested_by": "operator" }),
+        );
+        Ok(cleared.len())
+    }
+
+    /// An update check on the launcher's own schedule. While auto-updates
+    /// are paused, it runs as in observe mode: pending changes are logged
+    /// and announced, but the VM is never touched.
+    async fn scheduled_check(&mut self) -> Result<()> {
+        let paused = state::UpdaterState::load()
+            .ok()
+            .and_then(|state| state.paused);
+        if paused.is_some() != self.paused {
+            match paused {
+                Some(_) => info!("Auto-updates paused; reporting pending changes without applying them until `resume`"),
+                None => info!("Auto-updates resumed"),
+            }
+            self.paused = paused.is_some();
+        }
+        if !self.paused || self.observe {
+            return self.check_and_update().await;
+        }
+        self.observe = true;
+        let result = self.check_and_update().await;
+        self.observe = false;
+        result
+    }
+
+    async fn check_and_update(&mut self) -> Result<()> {
+        let state = state::UpdaterState::load().unwrap_or_default();
+        self.notifier.set_annotations(
+            state
+                .annotations
+                .into_iter()
+                .map(|annotation| annotation.text)
+                .collect(),
+        );
+        if state.stopped_by_operator.is_some() {
+            let plan = "leave the VM stopped by an operator".to_string();
+            if self.last_observed_plan.as_deref() != Some(plan.as_str()) {
+                info!("VM was stopped or removed by an operator; not starting, creating, or updating it until `start` or `recreate`");
+                self.last_observed_plan = Some(plan);
+            }
+            return Ok(());
+        }
+
+        // Fetch latest compose config
+        let (config, changed) = self.fetch_compose_config_if_changed().await?;
+        if !changed && self.still_converged() {
+            debug!("Compose config and platform config unchanged since the VM was last checked, skipping");
+            return Ok(());
+        }
+        self.converged = None;
+        api_schema::check_schema_version(config.schema_version)?;
+        self.report_unsupported_features(&config.features);
+
+        // Collect required environment variable keys from API
+        // These are just keys - values come from platform config
+        let required_env_keys = config.required_env_keys();
+        let mut held_for_env = Vec::new();
+        if !required_env_keys.is_empty() {
+            info!(
+                "Required environment variable keys from API: {:?}",
+                required_env_keys
+            );
+            held_for_env = self
+                .ensure_required_env(&required_env_keys, &config)
+                .await?;
+        }
+
+        // Load platform configuration (must be loaded to use values from platform config)
+        let platform_config = self.platform_config_or_default();
+
+        info!(
+            "Loaded platform config: guest VMM URL={}, env vars count={}",
+            resolve_guest_vmm_url(&platform_config),
+            platform_config.env.as_ref().map(|e| e.len()).unwrap_or(0)
+        );
+
+        let desired = Self::resolve_desired(&config, &platform_config)?;
+        self.cross_check_hashes(&config, &desired).await;
+        self.events.emit(events::Event::ConfigFetched {
+            compose_hash: desired.hash.clone(),
+            updated_at: config.updated_at.clone(),
+        });
+        let new_hash = desired.hash.clone();
+        let vm_params = desired.vm_params.clone();
+        // A held update the API has since replaced is no longer awaiting approval
+        if self.admin.awaiting_approval().as_deref() != Some(new_hash.as_str()) {
+            self.admin.set_awaiting_approval(None, None);
+        }
+
+        // Find existing VM and its status
+        let vm_info = self.find_validator_vm().await?;
+        self.verify_image_pin(&vm_params.image, platform_config.require_image_digest)
+            .await?;
+
+        let is_first_run = std::mem::replace(&mut self.first_check, false);
+        let recovery_policy = platform_config.recovery.clone().unwrap_or_default();
+
+        // Check if VM exists and whether it runs the desired deployment
+        let should_recreate = if let Some(vm) = &vm_info {
+            let (vm_id, status) = (&vm.id, &vm.status);
+            if recovery::VmCondition::classify(status) == recovery::VmCondition::Failed
+                && !self.observe
+            {
+                self.record_vm_failure(vm_id, status, &recovery_policy)
+                    .await?;
+            }
+            let runs_desired_app = reconcile::replacement(&new_hash, &vm.observed()).is_none();
+            if runs_desired_app {
+                self.report_parameter_drift(
+                    platform_config.drift_policy.unwrap_or_default(),
+                    &vm_params,
+                    vm.configuration.as_ref(),
+                );
+                self.record_provisioning_baseline(vm_id, &desired);
+            }
+            let provisioning = if runs_desired_app {
+                self.pending_provisioning_change(vm_id, &desired)
+            } else {
+                None
+            };
+            let new_hash_truncated = compose_hash::app_id_from_hash(&new_hash);
+
+            match plan_update(vm, &desired, &platform_config, provisioning) {
+                Some(UpdatePlan::Reconcile(reconcile::Action::Replace { reason, .. })) => {
+                    match reason {
+                        reconcile::ReplaceReason::NoAppId => {
+                            warn!("VM exists but has no appId (compose hash), will recreate to ensure consistency")
+                        }
+                        reconcile::ReplaceReason::Failed { .. } => {
+                            warn!("VM is in '{}' state, will recreate", status)
+                        }
+                        reconcile::ReplaceReason::AppIdChanged { running, .. } => {
+                            info!(
+                                "VM compose hash mismatch: existing={}, new={}, will recreate",
+                                running, new_hash_truncated
+                            );
+                            if let Some(changelog) = config.changelog() {
+                                info!("Changes in this update: {}", changelog);
+                            }
+                            self.events.emit(events::Event::HashMismatch {
+                                vm_id: vm_id.clone(),
+                                running_app_id: vm.app_id.clone().unwrap_or_default(),
+                                desired_hash: new_hash.clone(),
+                                changelog: config.changelog().map(String::from),
+                            });
+                        }
+                        reconcile::ReplaceReason::Stopped => {
+                            warn!("VM is in '{}' state, will recreate", status)
+                        }
+                    }
+                    true
+                }
+                Some(UpdatePlan::ReconcileDrift { .. }) => {
+                    warn!("VM parameters drifted from desired values, will recreate to reconcile");
+                    true
+                }
+                Some(UpdatePlan::Provision {
+                    path: provisioning::UpdatePath::Recreate,
+                    ..
+                }) => {
+                    info!("Provisioning change requires recreating the VM");
+                    true
+                }
+                Some(UpdatePlan::Provision {
+                    path: provisioning::UpdatePath::Resize,
+                    ..
+                }) if self.observe => {
+                    warn!(
+                        "Observe mode: would resize VM {} in place (no action taken)",
+                        vm_id
+                    );
+                    return Ok(());
+                }
+                Some(UpdatePlan::Provision {
+                    path: provisioning::UpdatePath::Resize,
+                    ..
+                }) => match self.resize_vm(vm_id, &vm_params).await {
+                    Ok(()) => {
+                        state::UpdaterState::update(|state| {
+                            state.set_managed(vm_id, &new_hash, desired.fingerprint())
+                        });
+                        if let Err(e) = desired.record(vm_id, &config, self.config_source()).save()
+                        {
+                            warn!("Failed to record deployment: {:#}", e);
+                        }
+                        self.vm_id = Some(vm_id.clone());
+                        self.current_hash = Some(new_hash);
+                        info!("VM {} resized in place", vm_id);
+                        self.update_applied(vm_id, &desired.hash, config.changelog());
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        warn!("In-place resize failed, will recreate: {:#}", e);
+                        true
+                    }
+                },
+                Some(UpdatePlan::Reconcile(reconcile::Action::Start { .. })) if self.observe => {
+                    warn!(
+                        "Observe mode: would start stopped VM {} (no action taken)",
+                        vm_id
+                    );
+                    return Ok(());
+                }
+                Some(UpdatePlan::Reconcile(reconcile::Action::Start { .. })) => {
+                    info!(
+                        "VM is in '{}' state with matching compose hash, starting it",
+                        status
+                    );
+                    match self.start_vm(vm_id).await {
+                        Ok(()) => {
+                            self.set_current(vm_id, new_hash);
+                            info!("VM {} started", vm_id);
+                            return Ok(());
+                        }
+                        Err(e) => {
+                            warn!("Failed to start VM, will recreate: {:#}", e);
+                            self.record_vm_failure(vm_id, "failed to start", &recovery_policy)
+                                .await?;
+                            true
+                        }
+                    }
+                }
+                // Never planned for an existing VM
+                Some(UpdatePlan::Reconcile(reconcile::Action::Create)) => true,
+                None if is_first_run && recovery_policy.verify_on_start && !self.observe => {
+                    match self.verify_running_app(vm_id).await {
+                        Ok(()) => {
+                            info!("Existing VM found at startup with matching compose hash ({}) is running its app, keeping it", new_hash_truncated);
+                            self.set_current(vm_id, new_hash);
+                            return Ok(());
+                        }
+                        Err(e) => {
+                            warn!("Existing VM found at startup is not running its app, will recreate: {:#}", e);
+                            self.record_vm_failure(
+                                vm_id,
+                                "app not running at startup",
+                                &recovery_policy,
+                            )
+                            .await?;
+                            true
+                        }
+                    }
+                }
+                None => {
+                    if is_first_run {
+                        info!("Existing VM found at startup with status '{}' and matching compose hash ({}), keeping it", status, new_hash_truncated);
+                    } else {
+                        info!(
+                            "VM compose hash matches ({}), no update needed",
+                            new_hash_truncated
+                        );
+                    }
+                    self.set_current(vm_id, new_hash);
+                    self.converged = serde_json::to_value(&platform_config)
+                        .ok()
+                        .map(|platform_config| (std::time::Instant::now(), platform_config));
+                    return Ok(());
+                }
+            }
+        } else {
+            // No VM exists, need to create
+            info!("No existing VM found, will create new one");
+            true
+        };
+
+        if should_recreate && self.observe {
+            let plan = match &vm_info {
+                Some(vm) => format!("recreate VM {} with compose hash {}", vm.id, new_hash),
+                None => format!("create VM with compose hash {}", new_hash),
+            };
+            if self.last_observed_plan.as_deref() != Some(plan.as_str()) {
+                warn!("Observe mode: would {} (no action taken)", plan);
+                self.notifier.update_pending(&plan, config.changelog());
+                self.last_observed_plan = Some(plan);
+            }
+            return Ok(());
+        }
+
+        if !should_recreate {
+            // VM is fine, no action needed
+            if !self.observe && vm_info.as_ref().is_some_and(|vm| vm.status == "running") {
+                self.maintain_standby(&config, &desired, &platform_config)
+                    .await;
+            }
+            return Ok(());
+        }
+
+        if !held_for_env.is_empty() {
+            let message = format!(
+                "Deployment of compose hash {} held: no value for {}. Set them with 'validator-auto-updater config set-env <key> <value>'",
+                new_hash,
+                held_for_env
+                    .iter()
+                    .map(|key| env_metadata::describe_key(key, &config.env_metadata))
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            );
+            error!("ALERT: {}", message);
+            return Err(anyhow::Error::new(EnvHeld(message)));
+        }
+
+        // Only replacing a healthy VM with a new config waits; creating a
+        // missing VM or replacing a failed one never does
+        let is_upgrade = vm_info.as_ref().is_some_and(|vm| {
+            recovery::VmCondition::classify(&vm.status) != recovery::VmCondition::Failed
+                && vm.app_id.as_deref().is_some_and(|app_id| {
+                    compose_hash::app_id_from_hash(app_id)
+                        != compose_hash::app_id_from_hash(&new_hash)
+                })
+        });
+        if is_upgrade && platform_config.require_update_approval {
+            if !self.admin.is_approved(&new_hash) {
+                let plan = format!("update to compose hash {} (awaiting approval)", new_hash);
+                if self.last_observed_plan.as_deref() != Some(plan.as_str()) {
+                    info!("Holding update to compose hash {} until approved", new_hash);
+                    self.notifier.update_pending(&plan, config.changelog());
+                    self.last_observed_plan = Some(plan);
+                }
+                self.admin
+                    .set_awaiting_approval(Some(new_hash), config.changelog());
+                return Ok(());
+            }
+            Self::audit("UpdateApproved", json!({ "compose_hash": new_hash }));
+        }
+        self.admin.set_awaiting_approval(None, None);
+
+        if !self.recreation_allowed(&recovery_policy)? {
+            return Ok(());
+        }
+
+        // A failed or missing VM is replaced by the warm standby, if one is ready
+        if !is_upgrade
+            && self
+                .promote_standby(vm_info.as_ref(), &config, &desired, &platform_config)
+                .await?
+        {
+            return Ok(());
+        }
+
+        // A canary only protects a running validator; with no old VM there is nothing to keep up
+        let canary_id = match (&platform_config.canary, &vm_info) {
+            (Some(canary_config), Some(_)) => {
+                Some(self.run_canary(canary_config, &config, &desired).await?)
+            }
+            _ => None,
+        };
+
+        let intent = state::UpdateIntent {
+            old_vm_id: vm_info.map(|vm| vm.id),
+            compose_hash: new_hash,
+            phase: state::UpdatePhase::RemoveOld,
+            new_vm_id: canary_id.clone(),
+            started_at: unix_now(),
+            compose_config: config,
+            api_url: self.config_source(),
+        };
+        // From here the intent tracks the canary
+        state::UpdaterState::update(|state| {
+            state.canary = None;
+            state.pending_update = Some(intent.clone());
+        });
+        let result = self.execute_update(intent, &platform_config).await;
+        if let (Err(_), Some(canary_id)) = (&result, canary_id) {
+            // Unless promoted, or kept for the next start to promote
+            let tracked = state::UpdaterState::load().is_ok_and(|state| {
+                state.vm_id.as_deref() == Some(canary_id.as_str())
+                    || state
+                        .pending_update
+                        .is_some_and(|intent| intent.new_vm_id == Some(canary_id.clone()))
+            });
+            if !tracked {
+                if let Err(e) = self.kill_and_remove_vm(&canary_id).await {
+                    warn!("Failed to remove canary VM {}: {:#}", canary_id, e);
+                }
+            }
+        }
+        result
+    }
+
+    /// Whether the last check found the VM up to date recently enough, and
+    /// with the same platform config, for an unchanged compose config to
+    /// need no check of the VM
+    fn still_converged(&self) -> bool {
+        let Some((checked_at, checked_config)) = &self.converged else {
+            return false;
+        };
+        let Ok(platform_config) = self.load_platform_config() else {
+            return false;
+        };
+        let recheck = Duration::from_secs(
+            platform_config
+                .vm_recheck_secs
+                .unwrap_or(defaults::VM_RECHECK_SECS),
+        );
+        checked_at.elapsed() < recheck
+            && serde_json::to_value(&platform_config).ok().as_ref() == Some(checked_config)
+    }
+
+    /// Start the new deployment as a canary next to the running VM and run
+    /// the probes against it. Returns the verified canary, to be promoted in
+    /// place of the old VM. On failure the canary is removed, the running VM
+    /// is kept and this compose hash is not retried.
+    async fn run_canary(
+        &mut self,
+        canary_config: &canary::CanaryConfig,
+        config: &ComposeConfig,
+        desired: &DesiredDeployment,
+    ) -> Result<String> {
+        if self.failed_canary.as_deref() == Some(desired.hash.as_str()) {
+            anyhow::bail!(
+                "Canary for compose hash {} failed; keeping the running VM until the API publishes another config or the launcher restarts",
+                desired.hash
+            );
+        }
+        let params = canary::vm_params(&desired.vm_params, canary_config)?;
+
+        // A canary left behind by a launcher that died mid-check
+        if let Some(stale) = state::UpdaterState::load()?.canary {
+            warn!("Removing leftover canary VM {}", stale);
+            if self.list_vms().await?.iter().any(|vm| vm.id == stale) {
+                self.kill_and_remove_vm(&stale)
+                    .await
+                    .context(format!("Failed to remove leftover canary VM {}", stale))?;
+            }
+            state::UpdaterState::update(|state| state.forget(&stale));
+        }
+
+        info!("Starting canary for compose hash {}", desired.hash);
+        let mut progress = events::CreateProgress::new(&self.events);
+        let vm_id = self
+            .create_vm(config, desired, &params, Some(canary_config), &mut progress)
+            .await
+            .context("Failed to create canary VM")?;
+        state::UpdaterState::update(|state| state.canary = Some(vm_id.clone()));
+        let boot_timeout = self.boot_timeout(Some(params.disk_size));
+        let result = match self.wait_for_vm_running(&vm_id, boot_timeout).await {
+            Ok(()) => canary::verify(canary_config, &vm_id).await,
+            Err(e) => Err(e.context("Canary VM did not come up")),
+        };
+
+        match result {
+            Ok(()) => {
+                Self::audit(
+                    "CanaryPassed",
+                    json!({ "vm_id": vm_id, "compose_hash": desired.hash }),
+                );
+                Ok(vm_id)
+            }
+            Err(e) => {
+                if let Err(e) = self.kill_and_remove_vm(&vm_id).await {
+                    warn!("Failed to remove canary VM {}: {:#}", vm_id, e);
+                }
+                warn!(
+                    "ALERT: canary for compose hash {} failed, keeping the running VM: {:#}",
+                    desired.hash, e
+                );
+                Self::audit(
+                    "CanaryFailed",
+                    json!({ "vm_id": vm_id, "compose_hash": desired.hash, "error": format!("{:#}", e) }),
+                );
+                self.failed_canary = Some(desired.hash.clone());
+                Err(e.context("Canary failed"))
+            }
+        }
+    }
+
+    /// Finish an update that a previous launcher process died in the middle of.
+    /// The old VM being still present rolls the update back (the next check
+    /// plans afresh); otherwise the recorded plan is resumed where it stopped.
+    async fn resume_interrupted_update(&mut self) -> Result<()> {
+        let Some(mut intent) = state::UpdaterState::load()
+            .ok()
+            .and_then(|state| state.pending_update)
+        else {
+            return Ok(());
+        };
+        if self.observe {
+            warn!(
+                "Observe mode: an interrupted update is pending ({}); not resuming it",
+                intent
+            );
+            return Ok(());
+        }
+        warn!("Found interrupted update: {}", intent);
+        self.wait_for_vmm("resume the interrupted update").await?;
+        let vms = self.list_vms().await?;
+
+        if intent.phase == state::UpdatePhase::RemoveOld {
+            if let Some(old_vm_id) = &intent.old_vm_id {
+                if vms.iter().any(|vm| &vm.id == old_vm_id) {
+                    info!(
+                        "Old VM {} was never removed; discarding the interrupted update",
+                        old_vm_id
+                    );
+                    Self::audit(
+                        "UpdateRolledBack",
+                        json!({
+                            "old_vm_id": old_vm_id,
+                            "compose_hash": intent.compose_hash,
+                            "started_at": intent.started_at,
+                        }),
+                    );
+                    state::UpdaterState::update(|state| state.pending_update = None);
+                    // A verified canary is only worth keeping until the old VM goes
+                    if let Some(canary_id) = &intent.new_vm_id {
+                        if vms.iter().any(|vm| &vm.id == canary_id) {
+                            if let Err(e) = self.kill_and_remove_vm(canary_id).await {
+                                warn!("Failed to remove canary VM {}: {:#}", canary_id, e);
+                            }
+                        }
+                    }
+                    return Ok(());
+                }
+            }
+            state::UpdaterState::update(|state| {
+                if let Some(old_vm_id) = &intent.old_vm_id {
+                    state.forget(old_vm_id);
+                }
+            });
+            intent.phase = intent.after_remove_old();
+        }
+        if intent.phase == state::UpdatePhase::Create {
+            // The CreateVm call may have gone through before the launcher died
+            if let Some(vm) = self.find_validator_vm().await? {
+                if Some(&vm.id) != intent.old_vm_id.as_ref() {
+                    info!("VM {} was created before the interruption", vm.id);
+                    intent.new_vm_id = Some(vm.id);
+                    intent.phase = state::UpdatePhase::Boot;
+                }
+            }
+        }
+
+        info!("Resuming interrupted update: {}", intent);
+        Self::audit(
+            "UpdateResumed",
+            json!({
+                "old_vm_id": intent.old_vm_id,
+                "compose_hash": intent.compose_hash,
+                "phase": intent.phase,
+            }),
+        );
+        let platform_config = self.load_platform_config().unwrap_or_default();
+        self.execute_update(intent, &platform_config).await
+    }
+
+    /// Replace the old VM (if any) with one running the intent's compose
+    /// config. Each step is persisted before it starts. The record is
+    /// cleared once the new VM is recorded or the old one is known to be
+    /// still in place; a failure in between leaves it for the next start.
+    async fn execute_update(
+        &mut self,
+        intent: state::UpdateIntent,
+        platform_config: &PlatformConfig,
+    ) -> Result<()> {
+        let _inhibitor = self.inhibit_power(platform_config, "Recreating the validator VM");
+        let (old_vm_id, started_at) = (intent.old_vm_id.clone(), intent.started_at);
+        let old_compose_hash = old_vm_id.as_ref().and(self.current_hash.clone());
+        let mut compose_hash = intent.compose_hash.clone();
+        let api_url = intent.api_url.clone();
+        let result = self.execute_update_steps(intent, platform_config).await;
+        let pending = state::UpdaterState::load()
+            .ok()
+            .and_then(|state| state.pending_update);
+        let mut new_vm_id = None;
+        if let Some(intent) = pending {
+            if result.is_ok() || self.update_settled(&intent).await {
+                state::UpdaterState::update(|state| state.pending_update = None);
+            } else {
+                warn!(
+                    "Keeping the interrupted update ({}) to finish on the next start",
+                    intent
+                );
+            }
+            new_vm_id = intent.new_vm_id;
+            compose_hash = intent.compose_hash;
+        }
+        // The deployment record is written before the new VM boots
+        let image = deployment::DeploymentRecord::load()
+            .ok()
+            .flatten()
+            .filter(|record| new_vm_id.as_ref() == Some(&record.vm_id))
+            .map(|record| record.image);
+        // The end of every deployment, for `history`
+        let mut details = json!({
+            "vm_id": new_vm_id,
+            "old_vm_id": old_vm_id,
+            "old_compose_hash": old_compose_hash,
+            "compose_hash": compose_hash,
+            "image": image,
+            "started_at": started_at,
+            "duration_secs": unix_now().saturating_sub(started_at),
+            "outcome": if result.is_ok() { "succeeded" } else { "failed" },
+            "api_url": api_url,
+        });
+        if let Err(e) = &result {
+            // The first line only: a boot failure carries the VM's log tails
+            let error = format!("{:#}", e);
+            details["error"] = json!(error.lines().next().unwrap_or_default());
+        }
+        Self::audit("DeploymentFinished", details);
+        result
+    }
+
+    /// Whether a failed update left the host with a VM: the new one is
+    /// recorded, or the old one was never removed
+    async fn update_settled(&self, intent: &state::UpdateIntent) -> bool {
+        match intent.phase {
+            state::UpdatePhase::Boot => true,
+            state::UpdatePhase::RemoveOld => match &intent.old_vm_id {
+                Some(old_vm_id) => self
+                    .list_vms()
+                    .await
+                    .is_ok_and(|vms| vms.iter().any(|vm| &vm.id == old_vm_id)),
+                None => true,
+            },
+            state::UpdatePhase::Create | state::UpdatePhase::Promote => false,
+        }
+    }
+
+    async fn execute_update_steps(
+        &mut self,
+        mut intent: state::UpdateIntent,
+        platform_config: &PlatformConfig,
+    ) -> Result<()> {
+        let config = intent.compose_config.clone();
+        let desired = Self::resolve_desired(&config, platform_config)?;
+        if desired.hash != intent.compose_hash {
+            warn!(
+                "Local config changed since the update was planned; compose hash is now {}",
+                desired.hash
+            );
+            intent.compose_hash = desired.hash.clone();
+        }
+        let new_hash = desired.hash.clone();
+        let vm_params = desired.vm_params.clone();
+        let image_digest = self
+            .verify_image_pin(&vm_params.image, platform_config.require_image_digest)
+            .await?;
+        let recovery_policy = platform_config.recovery.clone().unwrap_or_default();
+        let save_intent = |intent: &state::UpdateIntent| {
+            let intent = intent.clone();
+            state::UpdaterState::update(|state| state.pending_update = Some(intent));
+        };
+
+        // Kill and remove existing VM if it exists and needs recreation
+        if intent.phase == state::UpdatePhase::RemoveOld {
+            save_intent(&intent);
+            if let Some(old_vm_id) = intent.old_vm_id.clone() {
+                // The VMM may have restarted since the Status call; only remove
+                // the VM once it is reachable and still reports the VM
+                self.wait_for_vmm("remove the old VM").await?;
+                let still_present = self.list_vms().await?.iter().any(|v| v.id == old_vm_id);
+                if still_present {
+                    info!("Killing and removing existing VM: {}", old_vm_id);
+                    if let Err(e) = self.kill_and_remove_vm(&old_vm_id).await {
+                        error!("Failed to kill/remove VM: {}", e);
+                        return Err(e);
+                    }
+                } else {
+                    warn!(
+                        "VM {} disappeared while the VMM was unavailable, creating its replacement",
+                        old_vm_id
+                    );
+                }
+                state::UpdaterState::update(|state| state.forget(&old_vm_id));
+                self.vm_id = None;
+            }
+            intent.phase = intent.after_remove_old();
+        }
+
+        // A VM created here provisions its disk on first boot
+        let mut new_disk_gb = Some(vm_params.disk_size);
+        if intent.phase == state::UpdatePhase::Promote {
+            save_intent(&intent);
+            let canary_id = intent
+                .new_vm_id
+                .clone()
+                .context("Update intent has no canary to promote")?;
+            self.promote_canary(&canary_id, &desired, platform_config)
+                .await?;
+            new_disk_gb = None;
+            intent.phase = state::UpdatePhase::Boot;
+        }
+
+        // Create new VM with updated compose
+        let mut progress = events::CreateProgress::new(&self.events);
+        if intent.phase == state::UpdatePhase::Create {
+            save_intent(&intent);
+            let new_vm_id = self
+                .create_vm_resilient(&config, &desired, &vm_params, &mut progress)
+                .await?;
+            intent.new_vm_id = Some(new_vm_id);
+            intent.phase = state::UpdatePhase::Boot;
+        }
+        let new_vm_id = intent
+            .new_vm_id
+            .clone()
+            .context("Update intent has no new VM to boot")?;
+
+        // Update state
+        save_intent(&intent);
+        state::UpdaterState::update(|state| {
+            state.set_managed(&new_vm_id, &new_hash, desired.fingerprint());
+            state.pinned_image = image_digest.map(|digest| state::PinnedImage {
+                name: vm_params.image.clone(),
+                digest,
+            });
+        });
+        if let Err(e) = desired
+            .record(&new_vm_id, &config, intent.api_url.clone())
+            .save()
+        {
+            warn!("Failed to record deployment: {:#}", e);
+        }
+        self.vm_id = Some(new_vm_id.clone());
+        self.current_hash = Some(new_hash);
+
+        let boot = progress
+            .run(
+                events::CreatePhase::Boot,
+                self.wait_for_vm_running(&new_vm_id, self.boot_timeout(new_disk_gb)),
+            )
+            .await;
+        if let Err(e) = boot {
+            let logs = self.capture_failure_logs(&new_vm_id).await;
+            Self::audit(
+                "VmBootFailed",
+                json!({ "vm_id": new_vm_id, "error": e.to_string() }),
+            );
+            let window = recovery_policy.crashloop_window_secs;
+            state::UpdaterState::update(|state| {
+                state.record_failure(&new_vm_id, unix_now(), window);
+            });
+            return Err(anyhow::anyhow!(
+                "New VM {} failed to come up: {:#}{}",
+                new_vm_id,
+                e,
+                logs
+            ));
+        }
+
+        info!("VM updated successfully! {}", progress.summary());
+        self.update_applied(&new_vm_id, &desired.hash, config.changelog());
+        self.update_endpoints(platform_config, &new_vm_id, &desired.hash, &vm_params.ports);
+        Ok(())
+    }
+
+    /// Keep a stopped VM with the desired config next to the healthy managed
+    /// VM, replacing one left from an older config, or remove it once
+    /// `warm_standby` is turned off. Failures are logged, never fatal.
+    async fn maintain_standby(
+        &mut self,
+        config: &ComposeConfig,
+        desired: &DesiredDeployment,
+        platform_config: &PlatformConfig,
+    ) {
+        if let Err(e) = self
+            .try_maintain_standby(config, desired, platform_config)
+            .await
+        {
+            if self.failed_standby.as_deref() != Some(desired.hash.as_str()) {
+                warn!(
+                    "Failed to prepare a warm standby VM for compose hash {}, not retrying until the config changes: {:#}",
+                    desired.hash, e
+                );
+                self.failed_standby = Some(desired.hash.clone());
+            }
+        }
+    }
+
+    async fn try_maintain_standby(
+        &mut self,
+        config: &ComposeConfig,
+        desired: &DesiredDeployment,
+        platform_config: &PlatformConfig,
+    ) -> Result<()> {
+        if let Some(standby) = state::UpdaterState::load()?.standby {
+            let listed = self
+                .list_vms()
+                .await?
+                .iter()
+                .any(|vm| vm.id == standby.vm_id);
+            if listed && platform_config.warm_standby && standby.compose_hash == desired.hash {
+                return Ok(());
+            }
+            if listed {
+                info!(
+                    "Removing warm standby VM {} ({})",
+                    standby.vm_id,
+                    if platform_config.warm_standby {
+                        "the config changed"
+                    } else {
+                        "warm standby is off"
+                    }
+                );
+                self.kill_and_remove_vm(&standby.vm_id).await?;
+            } else {
+                warn!("Warm standby VM {} disappeared", standby.vm_id);
+                state::UpdaterState::update(|state| state.forget(&standby.vm_id));
+            }
+        }
+        if !platform_config.warm_standby
+            || self.failed_standby.as_deref() == Some(desired.hash.as_str())
+        {
+            return Ok(());
+        }
+
+        info!("Creating warm standby VM for compose hash {}", desired.hash);
+        let params = VmParameters {
+            stopped: true,
+            ..desired.vm_params.clone()
+        };
+        let mut progress = events::CreateProgress::new(&self.events);
+        let vm_id = self
+            .create_vm(config, desired, &params, None, &mut progress)
+            .await?;
+        state::UpdaterState::update(|state| {
+            state.standby = Some(state::StandbyVm {
+                vm_id: vm_id.clone(),
+                compose_hash: desired.hash.clone(),
+                created_at: unix_now(),
+            })
+        });
+        Self::audit(
+            "StandbyCreated",
+            json!({ "vm_id": vm_id, "compose_hash": desired.hash }),
+        );
+        info!("Warm standby VM {} ready", vm_id);
+        Ok(())
+    }
+
+    /// Replace the failed or missing managed VM by starting the warm
+    /// standby, if there is one for the desired compose hash. Ok(false)
+    /// leaves the replacement to a regular recreation.
+    async fn promote_standby(
+        &mut self,
+        old_vm: Option<&VmInfo>,
+        config: &ComposeConfig,
+        desired: &DesiredDeployment,
+        platform_config: &PlatformConfig,
+    ) -> Result<bool> {
+        let Some(standby) = state::UpdaterState::load()?.standby else {
+            return Ok(false);
+        };
+        if standby.compose_hash != desired.hash {
+            return Ok(false);
+        }
+        if !self
+            .list_vms()
+            .await?
+            .iter()
+            .any(|vm| vm.id == standby.vm_id)
+        {
+            warn!("Warm standby VM {} disappeared", standby.vm_id);
+            state::UpdaterState::update(|state| state.forget(&standby.vm_id));
+            return Ok(false);
+        }
+
+        let _inhibitor = self.inhibit_power(platform_config, "Promoting the warm standby VM");
+        let started_at = unix_now();
+        let vm_id = standby.vm_id;
+        info!("Replacing the validator VM with warm standby VM {}", vm_id);
+        // The standby has the same port bindings, so the old VM goes first
+        if let Some(old_vm) = old_vm {
+            self.kill_and_remove_vm(&old_vm.id).await?;
+            self.vm_id = None;
+        }
+        state::UpdaterState::update(|state| {
+            state.standby = None;
+            state.set_managed(&vm_id, &desired.hash, desired.fingerprint());
+        });
+        if let Err(e) = desired.record(&vm_id, config, self.config_source()).save() {
+            warn!("Failed to record deployment: {:#}", e);
+        }
+        self.vm_id = Some(vm_id.clone());
+        self.current_hash = Some(desired.hash.clone());
+
+        // Now the managed VM: if it does not come up, the next check recreates it
+        let result = match self.start_vm(&vm_id).await {
+            Ok(()) => {
+                self.wait_for_vm_running(&vm_id, self.boot_timeout(None))
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        let mut details = json!({
+            "vm_id": vm_id,
+            "old_vm_id": old_vm.map(|vm| &vm.id),
+            "compose_hash": desired.hash,
+            "image": desired.vm_params.image,
+            "started_at": started_at,
+            "duration_secs": unix_now().saturating_sub(started_at),
+            "outcome": if result.is_ok() { "succeeded" } else { "failed" },
+            "standby": true,
+            "api_url": self.config_source(),
+        });
+        if let Err(e) = &result {
+            details["error"] = json!(format!("{:#}", e));
+        }
+        Self::audit("DeploymentFinished", details);
+        result.context(format!("Warm standby VM {} did not come up", vm_id))?;
+
+        info!("Warm standby VM {} is now the validator VM", vm_id);
+        self.update_applied(&vm_id, &desired.hash, config.changelog());
+        self.update_endpoints(
+            platform_config,
+            &vm_id,
+            &desired.hash,
+            &desired.vm_params.ports,
+        );
+        Ok(true)
+    }
+
+    /// Point the endpoint integrations at `vm_id` running `compose_hash`, in
+    /// the background so a slow DNS or load balancer API never holds up a
+    /// check. Commands that exit right after call [`Self::finish_endpoint_updates`].
+    fn update_endpoints(
+        &mut self,
+        platform_config: &PlatformConfig,
+        vm_id: &str,
+        compose_hash: &str,
+        ports: &[PortMapping],
+    ) {
+        if platform_config.endpoint_integrations.is_empty() {
+            return;
+        }
+        let integrations = platform_config.endpoint_integrations.clone();
+        let binding = endpoints::EndpointBinding::new(
+            vm_id,
+            compose_hash::app_id_from_hash(compose_hash),
+            ports,
+            platform_config.public_ip.as_deref(),
+        );
+        self.endpoint_updates.retain(|task| !task.is_finished());
+        self.endpoint_updates.push(tokio::spawn(async move {
+            endpoints::update_all(&integrations, &binding).await
+        }));
+    }
+
+    /// Wait for the endpoint updates started by this process
+    async fn finish_endpoint_updates(&mut self) {
+        for task in std::mem::take(&mut self.endpoint_updates) {
+            let _ = task.await;
+        }
+    }
+
+    /// Log features the API uses that this build does not implement, once per change
+    fn report_unsupported_features(&mut self, features: &[String]) {
+        let unsupported = api_schema::unsupported_features(features);
+        if unsupported == self.unsupported_features {
+            return;
+        }
+        for feature in &unsupported {
+            match api_schema::impact(feature) {
+                Some(impact) => warn!("API feature '{}' not implemented: {}", feature, impact),
+                None => warn!(
+                    "API advertises feature '{}' which this launcher build does not implement; consider upgrading",
+                    feature
+                ),
+            }
+        }
+        self.unsupported_features = unsupported;
+    }
+
+    fn update_applied(&self, vm_id: &str, compose_hash: &str, changelog: Option<&str>) {
+        self.notifier.update_applied(vm_id, compose_hash, changelog);
+        self.events.emit(events::Event::UpdateApplied {
+            vm_id: vm_id.to_string(),
+            compose_hash: compose_hash.to_string(),
+        });
+    }
+
+    /// Digest or measurement the VMM reports for a guest image, if it reports one
+    async fn image_digest(&self, image: &str) -> Result<Option<String>> {
+        let response = self
+            .rpc_call("ListImages", json!({}))
+            .await
+            .context("Failed to list VMM images")?;
+        let entry = response
+            .get("images")
+            .and_then(|images| images.as_array())
+            .and_then(|images| {
+                images
+                    .iter()
+                    .find(|i| i.get("name").and_then(|n| n.as_str()) == Some(image))
+            })
+            .context(format!("VMM does not list image '{}'", image))?;
+        Ok(["digest", "sha256", "measurement", "hash"]
+            .iter()
+            .find_map(|key| entry.get(*key).and_then(|v| v.as_str()))
+            .filter(|digest| !digest.is_empty())
+            .map(String::from))
+    }
+
+    /// Compare the image's current digest with the one pinned for the managed
+    /// VM. Returns the current digest so new deployments can pin it.
+    async fn verify_image_pin(&self, image: &str, require: bool) -> Result<Option<String>> {
+        let digest = match self.image_digest(image).await {
+            Ok(Some(digest)) => digest,
+            Ok(None) if require => {
+                return Err(error_code::ErrorCode::ImageDigestMismatch.error(format!(
+                    "VMM reports no digest for image '{}' but require_image_digest is set",
+                    image
+                )))
+            }
+            Err(e) if require => return Err(e),
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                warn!("Could not determine digest of image '{}': {:#}", image, e);
+                return Ok(None);
+            }
+        };
+
+        let state = state::UpdaterState::load().unwrap_or_default();
+        match state.pinned_image.as_ref().filter(|pin| pin.name == image) {
+            Some(pin) if pin.digest != digest => {
+                warn!(
+                    "ALERT: image '{}' digest changed on the host: pinned {}, now {}",
+                    image, pin.digest, digest
+                );
+                if require {
+                    return Err(error_code::ErrorCode::ImageDigestMismatch.error(format!(
+                        "Image '{}' no longer matches its pinned digest {}; refusing to trust or redeploy the VM",
+                        image, pin.digest
+                    )));
+                }
+            }
+            Some(_) => {}
+            None if state.vm_id.is_some() && !self.observe => {
+                // First time we see a digest for the managed VM's image
+                let pin = state::PinnedImage {
+                    name: image.to_string(),
+                    digest: digest.clone(),
+                };
+                state::UpdaterState::update(|state| state.pinned_image = Some(pin));
+            }
+            None => {}
+        }
+        Ok(Some(digest))
+    }
+
+    /// Compare the desired resource parameters with those the managed VM was
+    /// provisioned with, returning how to apply a change if there is one
+    fn pending_provisioning_change(
+        &self,
+        vm_id: &str,
+        desired: &DesiredDeployment,
+    ) -> Option<provisioning::UpdatePath> {
+        let state = match state::UpdaterState::load() {
+            Ok(state) => state,
+            Err(e) => {
+                warn!("Failed to load launcher state: {:#}", e);
+                return None;
+            }
+        };
+        let path = provisioning_change(&state, vm_id, desired)?;
+        if let Some(previous) = &state.provisioning {
+            let new = desired.fingerprint();
+            info!(
+                "Provisioning changed ({} -> {}): {}; update path: {:?}",
+                previous.digest(),
+                new.digest(),
+                new.changed_fields(previous).join(", "),
+                path
+            );
+        }
+        Some(path)
+    }
+
+    /// Take the current parameters as the baseline for a VM running the
+    /// desired app with nothing recorded yet (e.g. state from an older launcher)
+    fn record_provisioning_baseline(&self, vm_id: &str, desired: &DesiredDeployment) {
+        if self.observe {
+            return;
+        }
+        let recorded = state::UpdaterState::load().is_ok_and(|state| {
+            state.provisioning.is_some() && state.vm_id.as_deref() == Some(vm_id)
+        });
+        if !recorded {
+            state::UpdaterState::update(|state| {
+                state.set_managed(vm_id, &desired.hash, desired.fingerprint())
+            });
+        }
+    }
+
+    /// Stop and start the validator VM in place, keeping its disk and
+    /// deployment, and wait until it runs again. Returns the VM id.
+    pub async fn restart_vm(&mut self) -> Result<String> {
+        self.ensure_mutations_allowed("restart VM")?;
+        // Held until the VM runs again, so the service's recovery never sees
+        // it stopped halfway
+        let _changes = lock_mutations().await?;
+        let vm = self
+            .find_validator_vm()
+            .await?
+            .context("No validator VM found")?;
+        Self::audit("RestartVm", json!({ "vm_id": vm.id, "status": vm.status }));
+        if recovery::VmCondition::classify(&vm.status) == recovery::VmCondition::Up {
+            self.stop_vm_and_wait(&vm.id).await?;
+        } else {
+            info!("VM {} is already '{}', starting it", vm.id, vm.status);
+        }
+        self.start_vm(&vm.id).await?;
+        Ok(vm.id)
+    }
+
+    /// Kill, remove, and recreate the validator VM from the config the API
+    /// publishes now, for a VM that is wedged. Unless `force`, a VM already
+    /// running that config is left alone. Approval and the canary are
+    /// skipped, as the operator asked for it. Returns the new VM id.
+    pub async fn recreate_vm(&mut self, force: bool) -> Result<String> {
+        self.ensure_mutations_allowed("recreate VM")?;
+        let _changes = lock_mutations().await?;
+        let config = self.fetch_compose_config().await?;
+        api_schema::check_schema_version(config.schema_version)?;
+        let held = self
+            .ensure_required_env(&config.required_env_keys(), &config)
+            .await?;
+        if !held.is_empty() {
+            return Err(error_code::ErrorCode::EnvMissing.error(format!(
+                "Refusing to recreate the VM: no value for {}",
+                held.join(", ")
+            )));
+        }
+        let platform_config = self.load_platform_config().unwrap_or_default();
+        let desired = Self::resolve_desired(&config, &platform_config)?;
+        let vm = self.find_validator_vm().await?;
+        if let Some(vm) = vm.as_ref().filter(|_| !force) {
+            if self
+                .planned_action(vm, &desired, &platform_config)
+                .is_none()
+            {
+                anyhow::bail!(
+                    "VM {} already runs compose hash {}; pass --force to recreate it anyway",
+                    vm.id,
+                    compose_hash::app_id_from_hash(&desired.hash)
+                );
+            }
+        }
+
+        if let Some(vm) = &vm {
+            self.ensure_removable(&vm.id)?;
+        }
+        Self::audit(
+            "RecreateVm",
+            json!({
+                "vm_id": vm.as_ref().map(|vm| vm.id.clone()),
+                "compose_hash": desired.hash,
+                "force": force,
+                "requested_by": "operator",
+            }),
+        );
+        let intent = state::UpdateIntent {
+            old_vm_id: vm.map(|vm| vm.id),
+            compose_hash: desired.hash,
+            phase: state::UpdatePhase::RemoveOld,
+            new_vm_id: None,
+            started_at: unix_now(),
+            compose_config: config,
+            api_url: self.config_source(),
+        };
+        let result = self.execute_update(intent, &platform_config).await;
+        self.finish_endpoint_updates().await;
+        result?;
+        // The new VM runs, so a stop for maintenance is over
+        state::UpdaterState::update(|state| state.stopped_by_operator = None);
+        self.vm_id.clone().context("No VM was created")
+    }
+
+    /// Recreate the validator VM from a kept deployment: the one with
+    /// compose hash (or app id) `to`, or else the newest one other than the
+    /// current deployment. Auto-updates are paused afterwards, so the next
+    /// check does not put the config served by the API back. Returns the
+    /// new VM id and the deployment rolled back to.
+    pub async fn rollback(
+        &mut self,
+        to: Option<&str>,
+    ) -> Result<(String, deployment::DeploymentRecord)> {
+        self.ensure_mutations_allowed("roll back")?;
+        let _changes = lock_mutations().await?;
+        let kept = deployment::DeploymentRecord::kept()?;
+        let current = state::UpdaterState::load()
+            .ok()
+            .and_then(|state| state.current_hash);
+        let target = match to {
+            Some(to) => {
+                let to = to.trim_start_matches("0x");
+                kept.into_iter()
+                    .find(|record| {
+                        record.compose_hash == to
+                            || compose_hash::app_id_from_hash(&record.compose_hash) == to
+                    })
+                    .context(format!(
+                        "No kept deployment with compose hash {}; see `rollback --list`",
+                        to
+                    ))?
+            }
+            None => kept
+                .into_iter()
+                .find(|record| Some(&record.compose_hash) != current.as_ref())
+                .context("No previous deployment is kept to roll back to")?,
+        };
+
+        let config = target.compose_config.clone();
+        let held = self
+            .ensure_required_env(&config.required_env_keys(), &config)
+            .await?;
+        if !held.is_empty() {
+            return Err(error_code::ErrorCode::EnvMissing.error(format!(
+                "Refusing to roll back: no value for {}",
+                held.join(", ")
+            )));
+        }
+        let platform_config = self.load_platform_config().unwrap_or_default();
+        let desired = Self::resolve_desired(&config, &platform_config)?;
+        if desired.hash != target.compose_hash {
+            warn!(
+                "Rolling back to compose hash {} instead of {}: the local config or env changed since",
+                desired.hash, target.compose_hash
+            );
+        }
+        let vm = self.find_validator_vm().await?;
+        if let Some(vm) = &vm {
+            self.ensure_removable(&vm.id)?;
+        }
+        // Paused first, so a running launcher does not undo the rollback
+        state::UpdaterState::update(|state| {
+            state.paused.get_or_insert_with(unix_now);
+        });
+        Self::audit(
+            "Rollback",
+            json!({
+                "vm_id": vm.as_ref().map(|vm| vm.id.clone()),
+                "from_compose_hash": current,
+                "compose_hash": desired.hash,
+                "image": target.image,
+                "deployed_at": target.deployed_at,
+                "requested_by": "operator",
+            }),
+        );
+        let intent = state::UpdateIntent {
+            old_vm_id: vm.map(|vm| vm.id),
+            compose_hash: desired.hash,
+            phase: state::UpdatePhase::RemoveOld,
+            new_vm_id: None,
+            started_at: unix_now(),
+            compose_config: config,
+            api_url: target.api_url.clone(),
+        };
+        let result = self.execute_update(intent, &platform_config).await;
+        self.finish_endpoint_updates().await;
+        result?;
+        state::UpdaterState::update(|state| state.stopped_by_operator = None);
+        let vm_id = self.vm_id.clone().context("No VM was created")?;
+        Ok((vm_id, target))
+    }
+
+    /// Decommission the host: kill and remove the validator VM, and keep a
+    /// running launcher from creating another. With `purge`, the launcher's
+    /// state (but not the audit log or the config) is deleted as well.
+    /// Returns the id of the removed VM, if there was one.
+    pub async fn remove_managed_vm(&mut self, purge: bool) -> Result<Option<String>> {
+        self.ensure_mutations_allowed("remove VM")?;
+        let _changes = lock_mutations().await?;
+        let vm = self.find_validator_vm().await?;
+        if let Some(vm) = &vm {
+            self.ensure_removable(&vm.id)?;
+        }
+        // Recorded first, so a running launcher does not recreate the VM
+        state::UpdaterState::update(|state| {
+            state.stopped_by_operator = Some(unix_now());
+            state.pending_update = None;
+        });
+        Self::audit(
+            "Decommission",
+            json!({
+                "vm_id": vm.as_ref().map(|vm| vm.id.clone()),
+                "purge": purge,
+                "requested_by": "operator",
+            }),
+        );
+        if let Some(vm) = &vm {
+            self.kill_and_remove_vm(&vm.id).await?;
+            state::UpdaterState::update(|state| state.forget(&vm.id));
+            self.vm_id = None;
+            self.current_hash = None;
+        }
+
+        if purge {
+            let keys = [
+                state::STATE_KEY,
+                deployment::RECORD_KEY,
+                deployment::PREVIOUS_KEY,
+                utilization::SAMPLES_KEY,
+                compose_patch::BASE_KEY,
+                env_metadata::CACHE_KEY,
+                alerting::OPEN_KEY,
+            ];
+            store::Store::commit(
+                &store::open()?,
+                keys.iter().map(|key| (key.to_string(), None)).collect(),
+            )?;
+            let captures = capture::captures_dir();
+            if captures.exists() {
+                std::fs::remove_dir_all(&captures)
+                    .context(format!("Failed to remove {}", captures.display()))?;
+            }
+        }
+        Ok(vm.map(|vm| vm.id))
+    }
+
+    /// Stop the validator VM for maintenance and keep the launcher from
+    /// starting, recreating, or updating it until [`Self::start_managed_vm`].
+    /// Returns the VM id.
+    pub async fn stop_managed_vm(&mut self) -> Result<String> {
+        self.ensure_mutations_allowed("stop VM")?;
+        let _changes = lock_mutations().await?;
+        let vm = self
+            .find_validator_vm()
+            .await?
+            .context("No validator VM found")?;
+        // Recorded first, so a running launcher does not bring the VM back while it stops
+        state::UpdaterState::update(|state| state.stopped_by_operator = Some(unix_now()));
+        if recovery::VmCondition::classify(&vm.status) == recovery::VmCondition::Up {
+            Self::audit(
+                "StopVm",
+                json!({
+                    "vm_id": vm.id,
+                    "compose_hash": self.managed_hash(&vm.id),
+                    "requested_by": "operator",
+                }),
+            );
+            self.stop_vm_and_wait(&vm.id).await?;
+        } else {
+            info!("VM {} is already '{}'", vm.id, vm.status);
+        }
+        Ok(vm.id)
+    }
+
+    /// Start a VM stopped with [`Self::stop_managed_vm`], wait until it runs,
+    /// and hand it back to the launcher. Returns the VM id.
+    pub async fn start_managed_vm(&mut self) -> Result<String> {
+        self.ensure_mutations_allowed("start VM")?;
+        let _changes = lock_mutations().await?;
+        let vm = self
+            .find_validator_vm()
+            .await?
+            .context("No validator VM found")?;
+        let result = if recovery::VmCondition::classify(&vm.status) == recovery::VmCondition::Up {
+            info!("VM {} is already '{}'", vm.id, vm.status);
+            Ok(())
+        } else {
+            self.start_vm(&vm.id).await
+        };
+        // Even if the VM did not come up, the launcher's recovery takes over from here
+        state::UpdaterState::update(|state| state.stopped_by_operator = None);
+        result?;
+        // Endpoints may have been pointed elsewhere while it was stopped
+        match (deployment::DeploymentRecord::load(), &vm.app_id) {
+            (Ok(Some(record)), Some(app_id))
+                if compose_hash::app_id_from_hash(&record.compose_hash)
+                    == compose_hash::app_id_from_hash(app_id) =>
+            {
+                let platform_config = self.load_platform_config().unwrap_or_default();
+                self.update_endpoints(
+                    &platform_config,
+                    &vm.id,
+                    &record.compose_hash,
+                    &record.vm_params.ports,
+                );
+                self.finish_endpoint_updates().await;
+            }
+            _ => debug!(
+                "No deployment record for VM {}; endpoints left as they are",
+                vm.id
+            ),
+        }
+        Ok(vm.id)
+    }
+
+    /// Keep the host from shutting down or suspending until the returned
+    /// lock is dropped, unless the power policy allows it
+    fn inhibit_power(
+        &self,
+        platform_config: &PlatformConfig,
+        why: &str,
+    ) -> Option<power::Inhibitor> {
+        let policy = platform_config.power.clone().unwrap_or_default();
+        if self.observe || !policy.inhibit_updates {
+            return None;
+        }
+        power::inhibit("shutdown:sleep", "block", why)
+    }
+
+    /// Called when logind announces a shutdown: audit it and, if the power
+    /// policy asks for it, stop the VM. Not recorded as an operator stop,
+    /// so the VM is started again after the host boots.
+    async fn prepare_for_shutdown(&mut self) {
+        let policy = self
+            .load_platform_config()
+            .unwrap_or_default()
+            .power
+            .unwrap_or_default();
+        let stop_vm = policy.stop_vm_on_shutdown && !self.observe;
+        info!("Host is shutting down; no more update checks");
+        Self::audit(
+            "HostShutdown",
+            json!({ "vm_id": self.vm_id, "stop_vm": stop_vm }),
+        );
+        if let Some(vm_id) = self.vm_id.clone().filter(|_| stop_vm) {
+            if let Err(e) = self.stop_vm_and_wait(&vm_id).await {
+                warn!("Failed to stop VM {} before shutdown: {:#}", vm_id, e);
+            }
+        }
+    }
+
+    async fn stop_vm_and_wait(&self, vm_id: &str) -> Result<()> {
+        self.ensure_mutations_allowed("stop VM")?;
+        info!("Stopping VM {}", vm_id);
+        timeout(
+            VM_KILL_TIMEOUT,
+            self.rpc_call("StopVm", json!({ "id": vm_id })),
+        )
+        .await
+        .context(format!("Timed out stopping VM {}", vm_id))?
+        .context(format!("Failed to stop VM {}", vm_id))?;
+        self.wait_for_vm_stopped(vm_id).await
+    }
+
+    async fn wait_for_vm_stopped(&self, vm_id: &str) -> Result<()> {
+        let deadline = tokio::time::Instant::now() + VM_KILL_TIMEOUT;
+        loop {
+            let vm = self
+                .list_vms()
+                .await?
+                .into_iter()
+                .find(|vm| vm.id == vm_id)
+                .context(format!(
+                    "VM {} disappeared from the VMM while stopping",
+                    vm_id
+                ))?;
+            if recovery::VmCondition::classify(&vm.status) != recovery::VmCondition::Up {
+                info!("VM {} is {}", vm_id, vm.status);
+                return Ok(());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                anyhow::bail!(
+                    "VM {} did not stop within {:?} (status: {})",
+                    vm_id,
+                    VM_KILL_TIMEOUT,
+                    vm.status
+                );
+            }
+            sleep(VMM_RETRY_INTERVAL).await;
+        }
+    }
+
+    async fn start_vm(&self, vm_id: &str) -> Result<()> {
+        self.ensure_mutations_allowed("start VM")?;
+        Self::audit("StartVm", json!({ "vm_id": vm_id }));
+        self.rpc_call("StartVm", json!({ "id": vm_id }))
+            .await
+            .context(format!("Failed to start VM {}", vm_id))?;
+        self.events.emit(events::Event::VmStarted {
+            vm_id: vm_id.to_string(),
+        });
+        self.wait_for_vm_running(vm_id, self.boot_timeout(None))
+            .await
+    }
+
+    /// Capture logs of a failed VM and count it towards crashloop detection.
+    /// Fails, holding off recreation, once the VM has failed too often.
+    async fn record_vm_failure(
+        &mut self,
+        vm_id: &str,
+        reason: &str,
+        policy: &recovery::RecoveryPolicy,
+    ) -> Result<()> {
+        let already_counted = state::UpdaterState::load()
+            .map(|state| state.recent_failures.iter().any(|f| f.vm_id == vm_id))
+            .unwrap_or(false);
+        if !already_counted {
+            let logs = self.capture_failure_logs(vm_id).await;
+            warn!("VM {} failed ({}):{}", vm_id, reason, logs);
+            Self::audit("VmFailed", json!({ "vm_id": vm_id, "reason": reason }));
+        }
+
+        let mut recent_failures = 0;
+        state::UpdaterState::update(|state| {
+            recent_failures = state.record_failure(vm_id, unix_now(), policy.crashloop_window_secs);
+        });
+        if !policy.is_crashlooping(recent_failures) {
+            self.crashloop_alerted = false;
+            return Ok(());
+        }
+        if !std::mem::replace(&mut self.crashloop_alerted, true) {
+            error!(
+                "ALERT: VM crashloop detected: {} failures within {}s",
+                recent_failures, policy.crashloop_window_secs
+            );
+        }
+        Err(anyhow::Error::new(Crashloop(format!(
+            "VM {} is crashlooping ({} failures within {}s), holding off recreation",
+            vm_id, recent_failures, policy.crashloop_window_secs
+        ))))
+    }
+
+    /// Circuit breaker for automatic recreations, whatever their cause: each
+    /// one within the window waits twice as long after the previous one, and
+    /// once too many fall within the window the launcher stops recreating
+    /// and fails as degraded. Ok(false) defers this recreation to a later
+    /// check; Ok(true) counts it and lets it go ahead.
+    fn recreation_allowed(&mut self, policy: &recovery::RecoveryPolicy) -> Result<bool> {
+        let now = unix_now();
+        let window = policy.recreation_window_secs;
+        let recent = state::UpdaterState::load()
+            .map(|state| state.recreations_within(now, window))
+            .unwrap_or_default();
+
+        if policy.is_flapping(recent.len()) {
+            let resumes_in = recent[0] + window - now;
+            let message = format!(
+                "Degraded: VM recreated {} times within {}s, holding off recreation for {}s",
+                recent.len(),
+                window,
+                resumes_in
+            );
+            if !std::mem::replace(&mut self.flapping_alerted, true) {
+                error!("ALERT: {}", message);
+                Self::audit(
+                    "RecreationsHeldOff",
+                    json!({ "recent_recreations": recent.len(), "window_secs": window }),
+                );
+            }
+            return Err(anyhow::Error::new(Flapping(message)));
+        }
+        self.flapping_alerted = false;
+
+        let not_before = recent
+            .last()
+            .map_or(0, |last| last + policy.recreate_backoff(recent.len()));
+        if now < not_before {
+            if self.recreation_deferred_until != Some(not_before) {
+                info!(
+                    "Backing off: {} recreations within {}s, next one in {}s",
+                    recent.len(),
+                    window,
+                    not_before - now
+                );
+                self.recreation_deferred_until = Some(not_before);
+            }
+            return Ok(false);
+        }
+        self.recreation_deferred_until = None;
+        state::UpdaterState::update(|state| state.record_recreation(now, window));
+        Ok(true)
+    }
+
+    /// Apply new vCPU, memory, and disk sizes to an existing VM
+    async fn resize_vm(&self, vm_id: &str, vm_params: &VmParameters) -> Result<()> {
+        self.ensure_mutations_allowed("resize VM")?;
+        let _inhibitor = self.inhibit_power(
+            &self.load_platform_config().unwrap_or_default(),
+            "Resizing the validator VM",
+        );
+        // Resizing a VM that is still running would fail or corrupt it
+        self.stop_vm_and_wait(vm_id)
+            .await
+            .context(format!("Not resizing VM {}", vm_id))?;
+
+        self.rpc_call(
+            "ResizeVm",
+            json!({
+                "id": vm_id,
+                "vcpu": vm_params.vcpu,
+                "memory": vm_params.memory,
+                "disk_size": vm_params.disk_size,
+            }),
+        )
+        .await
+        .context(format!("Failed to resize VM {}", vm_id))?;
+        Self::audit(
+            "ResizeVm",
+            json!({
+                "vm_id": vm_id,
+                "vcpu": vm_params.vcpu,
+                "memory": vm_params.memory,
+                "disk_size": vm_params.disk_size,
+            }),
+        );
+
+        self.rpc_call("StartVm", json!({ "id": vm_id }))
+            .await
+            .context(format!("Failed to start VM {} after resize", vm_id))?;
+        self.wait_for_vm_running(vm_id, self.boot_timeout(None))
+            .await
+    }
+
+    /// Compare the VM's reported parameters with the desired ones, alerting
+    /// on changes; [`plan_update`] decides whether to reconcile them
+    fn report_parameter_drift(
+        &mut self,
+        policy: drift::DriftPolicy,
+        desired: &VmParameters,
+        actual: Option<&Value>,
+    ) {
+        if policy == drift::DriftPolicy::Ignore {
+            return;
+        }
+        let Some(actual) = actual else {
+            return;
+        };
+
+        let drift = drift::detect_drift(desired, actual);
+        if drift != self.last_drift {
+            if drift.is_empty() {
+                info!("VM parameters match desired values again");
+            } else {
+                let details: Vec<String> = drift.iter().map(|d| d.to_string()).collect();
+                warn!(
+                    "ALERT: VM parameter drift detected (policy: {:?}): {}",
+                    policy,
+                    details.join("; ")
+                );
+            }
+            self.last_drift = drift;
+        }
+    }
+
+    /// Log one block describing the effective configuration of this instance
+    fn log_startup_summary(&self, platform_config: &PlatformConfig) {
+        let mut env_keys: Vec<&str> = platform_config
+            .env
+            .iter()
+            .flat_map(|env| env.keys().map(String::as_str))
+            .collect();
+        env_keys.sort_unstable();
+
+        let limits = match &platform_config.resource_limits {
+            Some(limits) => format!(
+                "vcpu<={:?} memory_mb<={:?} disk_gb<={:?} ({:?})",
+                limits.max_vcpu, limits.max_memory_mb, limits.max_disk_gb, limits.policy
+            ),
+            None => "none".to_string(),
+        };
+        let audit_signing = paths::state_dir().join("audit-signing.key").exists();
+
+        let lines = [
+            format!(
+                "API URL:           {}, network {}",
+                resolve_api_url(platform_config),
+                platform_config.network.unwrap_or_default()
+            ),
+            format!(
+                "API mirrors:       {}",
+                if platform_config.api_mirrors.is_empty() {
+                    "none".to_string()
+                } else {
+                    platform_config.api_mirrors.join(", ")
+                }
+            ),
+            format!(
+                "API mTLS:          {}",
+                platform_config
+                    .api_tls
+                    .as_ref()
+                    .map(|tls| tls.client_cert.as_str())
+                    .unwrap_or("disabled")
+            ),
+            format!(
+                "API user agent:    {}",
+                platform_config
+                    .http
+                    .clone()
+                    .unwrap_or_default()
+                    .user_agent()
+            ),
+            format!(
+                "API extra headers: {}",
+                match &platform_config.http {
+                    Some(http) if !http.headers.is_empty() =>
+                        http.headers.keys().cloned().collect::<Vec<_>>().join(", "),
+                    _ => "none".to_string(),
+                }
+            ),
+            format!(
+                "API bandwidth cap: {}",
+                match platform_config
+                    .http
+                    .as_ref()
+                    .and_then(|h| h.max_bytes_per_sec)
+                {
+                    Some(rate) => format!("{} bytes/s", rate),
+                    None => "none".to_string(),
+                }
+            ),
+            format!("VMM RPC URL:       {}", resolve_vmm_url(platform_config)),
+            format!(
+                "Embedded VMM:      {}",
+                platform_config
+                    .embedded_vmm
+                    .as_ref()
+                    .map(|vmm| vmm.to_string())
+                    .unwrap_or_else(|| "none".to_string())
+            ),
+            format!(
+                "Guest VMM URL:     {}",
+                resolve_guest_vmm_url(platform_config)
+            ),
+            format!(
+                "Mode:              {}",
+                if self.observe {
+                    "observe (no destructive actions)"
+                } else {
+                    "managing"
+                }
+            ),
+            format!(
+                "Poll interval:     {} ±{}%",
+                resolve_poll_interval(platform_config, self.poll_interval_flag),
+                platform_config
+                    .poll_jitter_percent
+                    .unwrap_or(defaults::POLL_JITTER_PERCENT)
+            ),
+            format!(
+                "Push updates:      {}",
+                if platform_config.push_updates {
+                    push::stream_url(&resolve_api_url(platform_config).url)
+                } else {
+                    "disabled".to_string()
+                }
+            ),
+            format!(
+                "API transport:     {}",
+                match platform_config.api_transport.unwrap_or_default() {
+                    subscribe::ApiTransport::Http => "http (polling)".to_string(),
+                    subscribe::ApiTransport::Websocket => {
+                        subscribe::subscribe_url(&resolve_api_url(platform_config).url)
+                            .unwrap_or_else(|e| format!("websocket ({:#})", e))
+                    }
+                }
+            ),
+            format!(
+                "VM recheck:        {}s while the API reports no change",
+                platform_config
+                    .vm_recheck_secs
+                    .unwrap_or(defaults::VM_RECHECK_SECS)
+            ),
+            format!(
+                "Disclosure:        {}",
+                platform_config.disclosure.clone().unwrap_or_default()
+            ),
+            format!(
+                "Power:             {}",
+                platform_config.power.clone().unwrap_or_default()
+            ),
+            format!(
+                "Clock check:       {}",
+                platform_config.clock_check.clone().unwrap_or_default()
+            ),
+            format!(
+                "Warm standby:      {}",
+                if platform_config.warm_standby {
+                    "enabled"
+                } else {
+                    "disabled"
+                }
+            ),
+            format!(
+                "Boot timeout:      {}",
+                platform_config.boot_timeout.clone().unwrap_or_default()
+            ),
+            format!(
+                "Drift policy:      {:?}",
+                platform_config.drift_policy.unwrap_or_default()
+            ),
+            format!("Resource limits:   {}", limits),
+            format!(
+                "Missing env:       {}",
+                platform_config.missing_env.clone().unwrap_or_default()
+            ),
+            format!(
+                "Host:              {}",
+                hostinfo::HostInfo::probe().summary()
+            ),
+            format!(
+                "Image digest:      {}",
+                if platform_config.require_image_digest {
+                    "must match pin"
+                } else {
+                    "tracked, alert on change"
+                }
+            ),
+            format!(
+                "Recovery:          {}",
+                platform_config.recovery.clone().unwrap_or_default()
+            ),
+            format!(
+                "Retention:         {}",
+                platform_config.retention.clone().unwrap_or_default()
+            ),
+            format!(
+                "Endpoints:         {}",
+                if platform_config.endpoint_integrations.is_empty() {
+                    "none".to_string()
+                } else {
+                    platform_config
+                        .endpoint_integrations
+                        .iter()
+                        .map(|e| e.describe())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                }
+            ),
+            format!(
+                "Canary:            {}",
+                platform_config
+                    .canary
+                    .as_ref()
+                    .map(|c| c.to_string())
+                    .unwrap_or_else(|| "disabled".to_string())
+            ),
+            format!(
+                "Update approval:   {}",
+                if platform_config.require_update_approval {
+                    "required"
+                } else {
+                    "not required"
+                }
+            ),
+            format!(
+                "Allowed envs:      {}",
+                platform_config
+                    .allowed_envs
+                    .as_ref()
+                    .filter(|o| !o.is_empty())
+                    .map(|o| o.to_string())
+                    .unwrap_or_else(|| "as resolved from the API".to_string())
+            ),
+            format!("Env keys set:      {}", env_keys.join(", ")),
+            format!(
+                "Timezone:          {}",
+                timefmt::DisplayTimezone::from_config(platform_config)
+            ),
+            format!("Config file:       {}", paths::config_path().display()),
+            format!("State dir:         {}", paths::state_dir().display()),
+            format!(
+                "Audit signing:     {}",
+                if audit_signing { "enabled" } else { "disabled" }
+            ),
+        ];
+
+        info!("Effective configuration:\n  {}", lines.join("\n  "));
+    }
+
+    fn record_check_result(&mut self, result: &Result<()>) {
+        self.admin.record_check(result);
+        match result.as_ref().err().and_then(api_failure) {
+            Some((failure, retry_after)) => {
+                self.api_backoff
+                    .failed(failure, retry_after, self.poll_interval);
+            }
+            None => self.api_backoff.succeeded(),
+        }
+        match result {
+            Ok(()) => self.check_errors.clear(),
+            Err(e) => {
+                let message = format!("{:#}", e);
+                let code = classify_error(e).to_string();
+                self.check_errors.error(format!("[{}] {}", code, message));
+                self.events.emit(events::Event::Error { message, code });
+            }
+        }
+    }
+
+    /// Combine the update check with operator-defined health checks and feed
+    /// the overall result to the notifier
+    async fn record_health(&mut self, check_result: Result<()>) {
+        self.health_checks.start();
+        let overall = match check_result {
+            Err(e) => Err(notify::CheckFailure {
+                condition: failure_condition(&e),
+                code: classify_error(&e).as_str(),
+                error: format!("{:#}", e),
+            }),
+            Ok(()) => self
+                .health_checks
+                .status()
+                .map_err(|e| notify::CheckFailure {
+                    condition: notify::conditions::HEALTH_CHECK_FAILING,
+                    code: error_code::ErrorCode::HealthCheckFailing.as_str(),
+                    error: format!("{:#}", e),
+                }),
+        };
+        self.notifier.record_check(overall);
+        self.notifier
+            .publish_status(|| serde_json::to_value(self.admin.status()).unwrap_or_default());
+    }
+
+    /// Re-read the platform config and apply changed settings to the running daemon
+    fn reload_config(&mut self) {
+        let platform_config = match self.load_platform_config() {
+            Ok(config) => {
+                self.reload_errors.clear();
+                config
+            }
+            Err(e) if self.settings.is_some() => {
+                self.reload_errors.warn(format!("{:#}", e));
+                return;
+            }
+            Err(_) => PlatformConfig::default(),
+        };
+        let new_settings =
+            ReloadableSettings::from_config(&platform_config, self.poll_interval_flag);
+
+        if let Some(current) = &self.settings {
+            if *current == new_settings {
+                return;
+            }
+            info!(
+                "Platform config changed, applying: {}",
+                new_settings.changes_from(current).join("; ")
+            );
+            if new_settings.notifications != current.notifications {
+                self.notifier
+                    .reconfigure(new_settings.notifications.as_ref());
+            }
+            if new_settings.health_checks != current.health_checks {
+                self.health_checks.reconfigure(&new_settings.health_checks);
+            }
+            if new_settings.api_url != current.api_url
+                || new_settings.api_mirrors != current.api_mirrors
+            {
+                *self
+                    .api_endpoint
+                    .get_mut()
+                    .unwrap_or_else(|e| e.into_inner()) = 0;
+            }
+            if new_settings.api_tls != current.api_tls || new_settings.http != current.http {
+                match Self::build_api_client(&platform_config) {
+                    Ok(client) => {
+                        self.api_client = client;
+                        // Reopened with the new settings by sync_push
+                        // and sync_subscription
+                        self.push = None;
+                        self.subscription = None;
+                    }
+                    Err(e) => {
+                        error!(
+                            "Failed to apply new api_tls/http settings, keeping previous client: {:#}",
+                            e
+                        );
+                    }
+                }
+            }
+        }
+
+        self.vmm_url = new_settings.vmm_url.clone();
+        self.api_url = new_settings.api_url.clone();
+        self.api_mirrors = new_settings.api_mirrors.clone();
+        self.api_bandwidth = new_settings.http.max_bytes_per_sec;
+        self.poll_interval = new_settings.poll_interval.interval;
+        self.poll_jitter_percent = new_settings.poll_jitter_percent;
+        self.settings = Some(new_settings);
+    }
+
+    /// Run the service. With `once`, stop after the initial check and leave
+    /// scheduling to a supervisor such as a systemd timer or a CronJob.
+    pub async fn run(&mut self, once: bool) -> Result<()> {
+        info!("Starting validator auto-updater");
+        // An observer changes nothing, so it may watch next to the active launcher
+        let _instance = if self.observe {
+            None
+        } else {
+            Some(lock_instance()?)
+        };
+        self.reload_config();
+        let platform_config = self.load_platform_config().unwrap_or_default();
+        self.log_startup_summary(&platform_config);
+        if let (Some(vm_id), Some(hash)) = (&self.vm_id, &self.current_hash) {
+            info!(
+                "Restored state: managing VM {} with compose hash {}",
+                vm_id, hash
+            );
+        }
+        if let Some(admin_api) = platform_config.admin_api.as_ref().filter(|_| !once) {
+            #[cfg(feature = "metrics")]
+            if let Err(e) = admin_server::serve(admin_api, self.admin.clone(), &self.events).await {
+                error!("Admin API disabled: {:#}", e);
+            }
+            #[cfg(not(feature = "metrics"))]
+            warn!(
+                "Admin API configured on {}, but this build lacks the `metrics` feature; not serving it",
+                admin_api.listen
+            );
+        }
+
+        // Supervised for as long as the service runs, so not for `once`
+        if let Some(vmm) = platform_config.embedded_vmm.clone().filter(|_| !once) {
+            let ready_timeout = Duration::from_secs(vmm.ready_timeout_secs);
+            vmm_supervisor::spawn(vmm);
+            if let Err(e) = self
+                .wait_for_vmm_within("run the first update check", ready_timeout)
+                .await
+            {
+                error!("Embedded VMM not ready: {:#}", e);
+            }
+        }
+
+        let changes = self.lock_mutations_unless_observing().await?;
+        if let Err(e) = self.resume_interrupted_update().await {
+            error!("Failed to resume interrupted update: {:#}", e);
+        }
+
+        // Initial check
+        let result = self.scheduled_check().await;
+        let failure = result.as_ref().err().map(|e| format!("{:#}", e));
+        self.record_check_result(&result);
+        if once {
+            // There is no next poll to report them on
+            self.health_checks.start();
+            self.health_checks.settled().await;
+        }
+        self.record_health(result).await;
+        self.collect_guest_metrics().await;
+        self.sample_utilization().await;
+        self.prune_if_due();
+        if !once {
+            self.scan_orphans_if_due().await;
+        }
+        drop(changes);
+        if once {
+            self.finish_endpoint_updates().await;
+            return match failure {
+                Some(e) => Err(anyhow::anyhow!("Update check failed: {}", e)),
+                None => Ok(()),
+            };
+        }
+
+        // Held while idle, so a shutdown or suspend waits for the reaction below
+        const DELAY_REASON: &str = "Stop reconciling the validator VM cleanly";
+        let mut power_events = power::watch();
+        let mut delay_lock = power::inhibit("shutdown:sleep", "delay", DELAY_REASON);
+
+        // Poll loop
+        self.sync_push();
+        self.sync_subscription();
+        loop {
+            // A connected event stream or subscription announces changes;
+            // polling is only a safety net
+            let interval = if self.push.as_ref().is_some_and(|p| p.is_connected())
+                || self.subscription.as_ref().is_some_and(|s| s.is_connected())
+            {
+                self.poll_interval.max(PUSH_POLL_INTERVAL)
+            } else {
+                self.poll_interval
+            };
+            tokio::select! {
+                _ = sleep(jittered(
+                    self.api_backoff.delay().unwrap_or(interval),
+                    self.poll_jitter_percent,
+                )) => {}
+                _ = self.admin.check_requested() => {}
+                _ = push::changed(self.push.as_ref()) => {}
+                _ = subscribe::changed(self.subscription.as_ref()) => {}
+                Some(event) = power_events.recv() => match event {
+                    power::PowerEvent::Shutdown => {
+                        self.prepare_for_shutdown().await;
+                        return Ok(());
+                    }
+                    power::PowerEvent::Sleep => {
+                        info!("Host is suspending; update checks resume after wake-up");
+                        drop(delay_lock.take());
+                        while let Some(event) = power_events.recv().await {
+                            if event == power::PowerEvent::Resume {
+                                break;
+                            }
+                        }
+                        info!("Host resumed from suspend");
+                        delay_lock = power::inhibit("shutdown:sleep", "delay", DELAY_REASON);
+                    }
+                    power::PowerEvent::Resume => {}
+                },
+            }
+            self.reload_config();
+            self.sync_push();
+            self.sync_subscription();
+
+            let _changes = self.lock_mutations_unless_observing().await?;
+            let result = self.scheduled_check().await;
+            self.record_check_result(&result);
+            self.record_health(result).await;
+            self.collect_guest_metrics().await;
+            self.sample_utilization().await;
+            self.prune_if_due();
+            self.scan_orphans_if_due().await;
+        }
+    }
+
+    /// The mutation lock for a check; an observer changes nothing, so takes none
+    async fn lock_mutations_unless_observing(&self) -> Result<Option<std::fs::File>> {
+        if self.observe {
+            return Ok(None);
+        }
+        lock_mutations().await.map(Some)
+    }
+
+    /// Open, reopen, or close the event stream to match `push_updates` and the API URL
+    fn sync_push(&mut self) {
+        // A subscription already announces every change
+        let wanted = self.settings.as_ref().is_some_and(|s| {
+            s.push_updates && s.api_transport != subscribe::ApiTransport::Websocket
+        });
+        let url = push::stream_url(&self.api_url);
+        match &self.push {
+            Some(stream) if wanted && stream.url() == url => {}
+            _ if wanted => {
+                info!("Push updates on, opening event stream {}", url);
+                self.push = Some(push::PushStream::spawn(
+                    self.api_client.clone(),
+                    &self.api_url,
+                ));
+            }
+            Some(_) => {
+                info!("Push updates off, closing the event stream");
+                self.push = None;
+            }
+            None => {}
+        }
+    }
+
+    /// Open, reopen, or close the subscription to match `api_transport` and
+    /// the API URL
+    #[cfg(feature = "websocket")]
+    fn sync_subscription(&mut self) {
+        let wanted = self
+            .settings
+            .as_ref()
+            .is_some_and(|s| s.api_transport == subscribe::ApiTransport::Websocket);
+        match &self.subscription {
+            Some(subscription)
+                if wanted
+                    && subscribe::subscribe_url(&self.api_url).ok().as_deref()
+                        == Some(subscription.url()) => {}
+            _ if wanted => {
+                info!("WebSocket transport on, subscribing to compose config changes");
+                self.subscription = match self.open_subscription() {
+                    Ok(subscription) => Some(subscription),
+                    Err(e) => {
+                        error!("Failed to subscribe, polling over HTTP: {:#}", e);
+                        None
+                    }
+                };
+            }
+            Some(_) => {
+                info!("WebSocket transport off, closing the subscription");
+                self.subscription = None;
+            }
+            None => {}
+        }
+    }
+
+    #[cfg(not(feature = "websocket"))]
+    fn sync_subscription(&mut self) {
+        let wanted = self
+            .settings
+            .as_ref()
+            .is_some_and(|s| s.api_transport == subscribe::ApiTransport::Websocket);
+        if wanted && !self.websocket_unavailable {
+            warn!("WebSocket transport configured, but this build lacks the `websocket` feature; polling over HTTP");
+        }
+        self.websocket_unavailable = wanted;
+    }
+
+    #[cfg(feature = "websocket")]
+    fn open_subscription(&self) -> Result<subscribe::Subscription> {
+        let settings = self.settings.as_ref().context("No settings loaded")?;
+        let mut headers = settings.http.header_map()?;
+        headers.insert(
+            reqwest::header::USER_AGENT,
+            reqwest::header::HeaderValue::from_str(&settings.http.user_agent())?,
+        );
+        headers.insert(
+            compose_hash::SUPPORTED_VERSIONS_HEADER,
+            reqwest::header::HeaderValue::from_str(
+                &compose_hash::HashVersion::supported_header_value(),
+            )?,
+        );
+        headers.insert(
+            api_schema::SCHEMA_VERSION_HEADER,
+            reqwest::header::HeaderValue::from(api_schema::SUPPORTED_SCHEMA_VERSION),
+        );
+        headers.insert(
+            api_schema::FEATURES_HEADER,
+            reqwest::header::HeaderValue::from_str(&api_schema::features_header_value())?,
+        );
+        subscribe::Subscription::spawn(
+            &self.api_url,
+            settings.api_tls.as_ref(),
+            headers,
+            MAX_API_RESPONSE_BYTES,
+        )
+    }
+
+    /// Record the guest's CPU and memory use every
+    /// [`utilization::SAMPLE_INTERVAL_SECS`]. Images without a guest agent
+    /// yield no samples, which only leaves the report empty.
+    async fn sample_utilization(&mut self) {
+        let Some(vm_id) = self.vm_id.clone() else {
+            return;
+        };
+        let now = unix_now();
+        if self
+            .last_utilization_sample
+            .is_some_and(|last| now.saturating_sub(last) < utilization::SAMPLE_INTERVAL_SECS)
+        {
+            return;
+        }
+        self.last_utilization_sample = Some(now);
+        match utilization::collect(&self.http_client, &self.vmm_url, &vm_id, now).await {
+            Ok(sample) => {
+                if let Err(e) = utilization::record(sample) {
+                    warn!("Failed to record resource use: {:#}", e);
+                }
+            }
+            Err(e) => debug!("Resource use unavailable for VM {}: {:#}", vm_id, e),
+        }
+    }
+
+    /// Refresh the app-level view of the managed VM. Not every image runs a
+    /// guest agent, so failures only keep the last sample around.
+    #[cfg(feature = "metrics")]
+    async fn collect_guest_metrics(&self) {
+        let Some(vm_id) = self.vm_id.clone() else {
+            self.admin.record_guest(None);
+            return;
+        };
+        match guest_metrics::collect(&self.http_client, &self.vmm_url, &vm_id, unix_now()).await {
+            Ok(mut metrics) => {
+                let previous = self.admin.guest();
+                metrics.count_restarts(previous.as_ref());
+                if previous.map(|p| p.to_string()) != Some(metrics.to_string()) {
+                    if metrics.all_running() {
+                        info!("Guest: {}", metrics);
+                    } else {
+                        warn!("Guest: {}", metrics);
+                    }
+                }
+                self.admin.record_guest(Some(metrics));
+            }
+            Err(e) => debug!("Guest metrics unavailable for VM {}: {:#}", vm_id, e),
+        }
+    }
+
+    #[cfg(not(feature = "metrics"))]
+    async fn collect_guest_metrics(&self) {}
+
+    /// Look for orphaned VMs at most once per ORPHAN_SCAN_INTERVAL, removing
+    /// the launcher's own with `remove_orphans` and reporting the rest
+    async fn scan_orphans_if_due(&mut self) {
+        if self
+            .last_orphan_scan
+            .is_some_and(|last| last.elapsed() < ORPHAN_SCAN_INTERVAL)
+        {
+            return;
+        }
+        self.last_orphan_scan = Some(std::time::Instant::now());
+        let remove = self
+            .load_platform_config()
+            .unwrap_or_default()
+            .remove_orphans
+            && !self.observe
+            && !self.paused;
+        let orphans = match self.collect_orphans(!remove).await {
+            Ok(orphans) => orphans,
+            Err(e) => {
+                warn!("Failed to look for orphaned VMs: {:#}", e);
+                return;
+            }
+        };
+        for orphan in orphans {
+            match (&orphan.error, remove && orphan.owned) {
+                (None, true) => {}
+                (Some(e), true) => warn!("Failed to remove orphaned VM {}: {}", orphan.id, e),
+                _ if orphan.owned => warn!(
+                    "Orphaned VM {} ({}) is left over from an earlier deployment; `gc` removes it",
+                    orphan.id, orphan.status
+                ),
+                _ => warn!(
+                    "VM {} is named like the validator VM but was not created by this launcher; `gc --force` removes it",
+                    orphan.id
+                ),
+            }
+        }
+    }
+
+    /// VMs that look like the launcher's (owned by it, or named like the
+    /// validator VM) other than the managed VM. VMs an interrupted update or
+    /// a canary check is still working on are not orphans. Owned ids
+    /// the VMM no longer reports are dropped from the state.
+    async fn find_orphans(&mut self) -> Result<Vec<OrphanVm>> {
+        let vms = self.list_vms().await?;
+        let current = self.find_validator_vm().await?.map(|vm| vm.id);
+        let state = state::UpdaterState::load()?;
+        let stale: Vec<String> = state
+            .owned_vm_ids
+            .iter()
+            .filter(|id| !vms.iter().any(|vm| &vm.id == *id))
+            .cloned()
+            .collect();
+        if !stale.is_empty() && !self.observe {
+            state::UpdaterState::update(|state| {
+                state.owned_vm_ids.retain(|id| !stale.contains(id))
+            });
+        }
+        let in_flight: Vec<&str> = state
+            .pending_update
+            .iter()
+            .flat_map(|intent| [intent.old_vm_id.as_deref(), intent.new_vm_id.as_deref()])
+            .flatten()
+            .chain(state.standby_id())
+            .chain(state.canary.as_deref())
+            .collect();
+        Ok(vms
+            .into_iter()
+            .filter(|vm| Some(&vm.id) != current.as_ref() && !in_flight.contains(&vm.id.as_str()))
+            .filter_map(|vm| {
+                let owned = state.owns(&vm.id);
+                let named =
+                    vm.name.as_deref() == Some(VM_NAME) || vm.app_id.as_deref() == Some(VM_NAME);
+                (owned || named).then_some(OrphanVm {
+                    id: vm.id,
+                    name: vm.name,
+                    status: vm.status,
+                    owned,
+                    error: None,
+                })
+            })
+            .collect())
+    }
+
+    /// Find orphaned VMs and, unless `dry_run`, kill and remove them. VMs
+    /// the launcher does not own are only removed with `--force`; the
+    /// others are reported with the reason they were kept.
+    pub async fn remove_orphans(&mut self, dry_run: bool) -> Result<Vec<OrphanVm>> {
+        let _changes = lock_mutations().await?;
+        self.collect_orphans(dry_run).await
+    }
+
+    /// [`Self::remove_orphans`] under a lock the caller holds
+    async fn collect_orphans(&mut self, dry_run: bool) -> Result<Vec<OrphanVm>> {
+        let mut orphans = self.find_orphans().await?;
+        if dry_run || orphans.is_empty() {
+            return Ok(orphans);
+        }
+        self.ensure_mutations_allowed("remove orphaned VMs")?;
+        // The VMM reports no creation times, so a VM an interrupted update
+        // created but did not record yet cannot be told from a leftover
+        let interrupted = state::UpdaterState::load()?.pending_update;
+        let removable: Vec<String> = orphans
+            .iter()
+            .filter(|orphan| interrupted.is_none() && (orphan.owned || self.remove_unowned))
+            .map(|orphan| orphan.id.clone())
+            .collect();
+        if !removable.is_empty() {
+            Self::audit(
+                "RemoveOrphans",
+                json!({ "vm_ids": removable, "force": self.remove_unowned }),
+            );
+        }
+        for orphan in &mut orphans {
+            let result = if removable.contains(&orphan.id) {
+                self.kill_and_remove_vm(&orphan.id).await
+            } else if let Some(intent) = &interrupted {
+                Err(anyhow::anyhow!(
+                    "an interrupted update ({}) may have created it; run the launcher to finish the update first",
+                    intent
+                ))
+            } else {
+                Err(anyhow::anyhow!(
+                    "not created by this launcher; pass --force"
+                ))
+            };
+            if let Err(e) = result {
+                orphan.error = Some(format!("{:#}", e));
+            }
+        }
+        Ok(orphans)
+    }
+
+    /// Platform API endpoint the compose config in use comes from, the API
+    /// URL or the mirror that last served it; None for one applied from a
+    /// payload
+    fn config_source(&self) -> Option<String> {
+        if self.compose_override.is_some() {
+            return None;
+        }
+        let index = *self.api_endpoint.lock().unwrap_or_else(|e| e.into_inner());
+        Some(
+            index
+                .checked_sub(1)
+                .and_then(|i| self.api_mirrors.get(i))
+                .unwrap_or(&self.api_url)
+                .clone(),
+        )
+    }
+
+    /// Apply the retention policy to local data at most once per PRUNE_INTERVAL
+    fn prune_if_due(&mut self) {
+        if self
+            .last_prune
+            .is_some_and(|last| last.elapsed() < PRUNE_INTERVAL)
+        {
+            return;
+        }
+        self.last_prune = Some(std::time::Instant::now());
+        let policy = self
+            .settings
+            .as_ref()
+            .map(|settings| settings.retention.clone())
+            .unwrap_or_default();
+        match retention::prune(&policy, false) {
+            Ok(report) if !report.is_empty() => info!("Pruned local data: {}", report),
+            Ok(_) => {}
+            Err(e) => warn!("Failed to prune local data: {:#}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "1111111111111111111111111111111111111111aaaa";
+
+    fn vm(status: &str, app_id: &str) -> VmInfo {
+        VmInfo {
+            id: "vm-1".to_string(),
+            name: Some(VM_NAME.to_string()),
+            status: status.to_string(),
+            app_id: Some(app_id.to_string()),
+            configuration: None,
+        }
+    }
+
+    fn desired() -> DesiredDeployment {
+        DesiredDeployment {
+            allowed_envs: Vec::new(),
+            manifest_defaults: ManifestDefaults::default(),
+            vm_params: VmParameters::default(),
+            vm_name: VM_NAME.to_string(),
+            compose_file: String::new(),
+            hash_version: compose_hash::HashVersion::default(),
+            hash: HASH.to_string(),
+        }
+    }
+
+    fn plan(vm: &VmInfo, config: &PlatformConfig) -> Option<String> {
+        plan_update(vm, &desired(), config, None).map(|plan| plan.to_string())
+    }
+
+    #[test]
+    fn up_to_date_vm_is_left_alone() {
+        let config = PlatformConfig::default();
+        assert_eq!(plan(&vm("running", &HASH[..40]), &config), None);
+    }
+
+    #[test]
+    fn other_app_is_replaced_before_anything_else() {
+        let config = PlatformConfig {
+            drift_policy: Some(drift::DriftPolicy::Reconcile),
+            ..Default::default()
+        };
+        let mut vm = vm("running", "2222222222222222222222222222222222222222");
+        vm.configuration = Some(json!({ "vcpu": 1 }));
+        let planned = plan_update(
+            &vm,
+            &desired(),
+            &config,
+            Some(provisioning::UpdatePath::Resize),
+        );
+        assert!(matches!(
+            planned,
+            Some(UpdatePlan::Reconcile(reconcile::Action::Replace {
+                reason: reconcile::ReplaceReason::AppIdChanged { .. },
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn drift_is_only_reconciled_when_the_policy_says_so() {
+        let mut vm = vm("running", HASH);
+        vm.configuration = Some(json!({ "vcpu": 1 }));
+        let mut config = PlatformConfig::default();
+        assert_eq!(plan(&vm, &config), None);
+        config.drift_policy = Some(drift::DriftPolicy::Reconcile);
+        assert_eq!(
+            plan(&vm, &config).as_deref(),
+            Some("recreate VM vm-1 to reconcile drift")
+        );
+    }
+
+    #[test]
+    fn provisioning_change_comes_before_a_restart() {
+        let config = PlatformConfig::default();
+        let vm = vm("stopped", HASH);
+        let planned = plan_update(
+            &vm,
+            &desired(),
+            &config,
+            Some(provisioning::UpdatePath::Resize),
+        );
+        assert_eq!(
+            planned.map(|plan| plan.to_string()).as_deref(),
+            Some("resize VM vm-1 in place")
+        );
+        assert!(matches!(
+            plan_update(&vm, &desired(), &config, None),
+            Some(UpdatePlan::Reconcile(_))
+        ));
+    }
+
+    #[test]
+    fn provisioning_change_needs_a_baseline_for_the_vm() {
+        let desired = desired();
+        let mut state = state::UpdaterState::default();
+        assert_eq!(provisioning_change(&state, "vm-1", &desired), None);
+
+        state.set_managed("vm-1", HASH, desired.fingerprint());
+        assert_eq!(provisioning_change(&state, "vm-1", &desired), None);
+        assert_eq!(provisioning_change(&state, "vm-2", &desired), None);
+
+        let mut bigger = desired.clone();
+        bigger.vm_params.memory *= 2;
+        assert_eq!(
+            provisioning_change(&state, "vm-1", &bigger),
+            Some(provisioning::UpdatePath::Resize)
+        );
+        bigger.vm_params.hugepages = true;
+        assert_eq!(
+            provisioning_change(&state, "vm-1", &bigger),
+            Some(provisioning::UpdatePath::Recreate)
+        );
+    }
+}
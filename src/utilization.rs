@@ -0,0 +1,274 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! CPU and memory use of the managed VM over time, sampled from the guest
+//! agent, and a right-sizing report comparing it with what the platform API
+//! asks for. Samples are absolute (vCPUs busy, MB used), so they stay
+//! comparable across VMs with different allocations.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::store;
+
+pub(crate) const SAMPLES_KEY: &str = "utilization.json";
+/// Seconds between samples
+pub const SAMPLE_INTERVAL_SECS: u64 = 300;
+/// Two weeks of samples at [`SAMPLE_INTERVAL_SECS`]
+const MAX_SAMPLES: usize = 4032;
+/// A day of samples; fewer give no suggestion
+const MIN_SAMPLES_FOR_SUGGESTION: usize = 288;
+/// p95 below this share of the allocation is over-provisioned
+const OVER_PROVISIONED: f64 = 0.25;
+/// p95 above this share of the allocation is under-provisioned
+const UNDER_PROVISIONED: f64 = 0.9;
+/// Suggested allocations leave this much room above p95
+const HEADROOM: f64 = 1.5;
+const MEMORY_STEP_MB: u32 = 1024;
+
+/// One reading of the guest's resource use
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub at: u64,
+    pub vm_id: String,
+    /// One-minute load average: roughly the number of busy vCPUs
+    pub cpu_load: f64,
+    pub memory_used_mb: u64,
+}
+
+/// Ask the guest agent (via the VMM's guest API) for the VM's load and memory
+pub async fn collect(
+    client: &reqwest::Client,
+    vmm_url: &str,
+    vm_id: &str,
+    now: u64,
+) -> Result<Sample> {
+    let url = format!("{}/prpc/Guest.SysInfo?json", vmm_url);
+    let response = client
+        .post(&url)
+        .json(&json!({ "id": vm_id }))
+        .send()
+        .await
+        .context("Guest agent unreachable")?;
+    let status = response.status();
+    if !status.is_success() {
+        anyhow::bail!("Guest agent returned {}", status);
+    }
+    let body: Value = response
+        .json()
+        .await
+        .context("Invalid guest agent response")?;
+    let cpu_load = body
+        .get("loadavg_one")
+        .and_then(|v| v.as_f64())
+        .context("Guest agent reported no load average")?;
+    let total = body.get("total_memory").and_then(|v| v.as_u64());
+    let available = body.get("available_memory").and_then(|v| v.as_u64());
+    let used = match (total, available) {
+        (Some(total), Some(available)) => total.saturating_sub(available),
+        _ => body
+            .get("used_memory")
+            .and_then(|v| v.as_u64())
+            .context("Guest agent reported no memory use")?,
+    };
+    Ok(Sample {
+        at: now,
+        vm_id: vm_id.to_string(),
+        cpu_load,
+        memory_used_mb: used / (1024 * 1024),
+    })
+}
+
+/// Append a sample, dropping the oldest beyond two weeks' worth
+pub fn record(sample: Sample) -> Result<()> {
+    store::update_json(
+        &store::open()?,
+        SAMPLES_KEY,
+        |samples: &mut VecDeque<Sample>| {
+            samples.push_back(sample);
+            while samples.len() > MAX_SAMPLES {
+                samples.pop_front();
+            }
+        },
+    )
+}
+
+/// Serialized size of each kept sample in bytes, oldest first
+pub(crate) fn sample_sizes() -> Result<Vec<u64>> {
+    Ok(load()?
+        .iter()
+        .map(|sample| serde_json::to_vec(sample).map_or(0, |json| json.len() as u64 + 1))
+        .collect())
+}
+
+/// Drop the `count` oldest samples
+pub(crate) fn drop_oldest(count: usize) -> Result<()> {
+    store::update_json(
+        &store::open()?,
+        SAMPLES_KEY,
+        |samples: &mut VecDeque<Sample>| {
+            samples.drain(..count.min(samples.len()));
+        },
+    )
+}
+
+pub fn load() -> Result<Vec<Sample>> {
+    let samples: Option<VecDeque<Sample>> = store::get_json(&store::open()?, SAMPLES_KEY)?;
+    Ok(samples.map(Vec::from).unwrap_or_default())
+}
+
+/// Median, 95th percentile, and peak of a series
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p95: f64,
+    pub max: f64,
+}
+
+impl Percentiles {
+    fn of(mut values: Vec<f64>) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+        // Nearest rank
+        let rank = |p: f64| values[((p * values.len() as f64).ceil() as usize).max(1) - 1];
+        Some(Self {
+            p50: rank(0.5),
+            p95: rank(0.95),
+            max: values[values.len() - 1],
+        })
+    }
+}
+
+/// Utilization over the kept samples against an allocation
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub samples: usize,
+    pub first_at: Option<u64>,
+    pub last_at: Option<u64>,
+    pub vcpu: u32,
+    pub memory_mb: u32,
+    /// Busy vCPUs
+    pub cpu: Option<Percentiles>,
+    pub memory_mb_used: Option<Percentiles>,
+    pub suggestions: Vec<String>,
+}
+
+impl Report {
+    /// Compare `samples` with an allocation of `vcpu` vCPUs and `memory_mb` MB
+    pub fn new(samples: &[Sample], vcpu: u32, memory_mb: u32) -> Self {
+        let cpu = Percentiles::of(samples.iter().map(|s| s.cpu_load).collect());
+        let memory = Percentiles::of(samples.iter().map(|s| s.memory_used_mb as f64).collect());
+        let mut suggestions = Vec::new();
+        if samples.len() >= MIN_SAMPLES_FOR_SUGGESTION {
+            if let Some(cpu) = cpu {
+                if let Some(suggested) = suggest(cpu.p95, vcpu as f64, 1.0) {
+                    suggestions.push(format!(
+                        "vCPU: p95 load is {:.1} of {} allocated; ask for {}",
+                        cpu.p95, vcpu, suggested
+                    ));
+                }
+            }
+            if let Some(memory) = memory {
+                if let Some(suggested) =
+                    suggest(memory.p95, memory_mb as f64, MEMORY_STEP_MB as f64)
+                {
+                    suggestions.push(format!(
+                        "memory: p95 use is {:.0} MB of {} MB allocated; ask for {} MB",
+                        memory.p95, memory_mb, suggested
+                    ));
+                }
+            }
+        }
+        Self {
+            samples: samples.len(),
+            first_at: samples.first().map(|s| s.at),
+            last_at: samples.last().map(|s| s.at),
+            vcpu,
+            memory_mb,
+            cpu,
+            memory_mb_used: memory,
+            suggestions,
+        }
+    }
+
+    /// Whether enough samples were kept to judge the allocation
+    pub fn conclusive(&self) -> bool {
+        self.samples >= MIN_SAMPLES_FOR_SUGGESTION
+    }
+}
+
+/// A better allocation when `p95` is far from `allocated`, rounded up to `step`
+fn suggest(p95: f64, allocated: f64, step: f64) -> Option<u64> {
+    if allocated <= 0.0 {
+        return None;
+    }
+    let share = p95 / allocated;
+    if (OVER_PROVISIONED..=UNDER_PROVISIONED).contains(&share) {
+        return None;
+    }
+    let suggested = ((p95 * HEADROOM / step).ceil() * step).max(step) as u64;
+    (suggested != allocated as u64).then_some(suggested)
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Allocation requested by the API: {} vCPU, {} MB",
+            self.vcpu, self.memory_mb
+        )?;
+        writeln!(f, "Samples: {}", self.samples)?;
+        if let Some(cpu) = self.cpu {
+            writeln!(
+                f,
+                "CPU (busy vCPUs): p50 {:.2}, p95 {:.2}, max {:.2}",
+                cpu.p50, cpu.p95, cpu.max
+            )?;
+        }
+        if let Some(memory) = self.memory_mb_used {
+            writeln!(
+                f,
+                "Memory (MB used): p50 {:.0}, p95 {:.0}, max {:.0}",
+                memory.p50, memory.p95, memory.max
+            )?;
+        }
+        if !self.conclusive() {
+            write!(
+                f,
+                "Not enough samples for a suggestion yet (need {}, one every {}s)",
+                MIN_SAMPLES_FOR_SUGGESTION, SAMPLE_INTERVAL_SECS
+            )
+        } else if self.suggestions.is_empty() {
+            write!(f, "The allocation fits the observed use")
+        } else {
+            for (i, suggestion) in self.suggestions.iter().enumerate() {
+                if i > 0 {
+                    writeln!(f)?;
+                }
+                write!(f, "Suggestion: {}", suggestion)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Print the report for the current deployment, or its JSON
+pub fn run_utilization_command(json: bool) -> Result<()> {
+    let record = crate::deployment::DeploymentRecord::load()?
+        .context("No deployment recorded yet; the report needs the API's requested allocation")?;
+    let requested = &record.compose_config.provisioning.vm_parameters;
+    let report = Report::new(&load()?, requested.vcpu, requested.memory);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("{}", report);
+    }
+    Ok(())
+}
@@ -0,0 +1,237 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::error_code::ErrorCode;
+use crate::{defaults, ComposeConfig, ManifestDefaults, PlatformConfig, VmParameters};
+
+const KNOWN_PROTOCOLS: &[&str] = &["tcp", "udp"];
+const MAX_VCPU: u32 = 512;
+const MAX_MEMORY_MB: u32 = 4 * 1024 * 1024;
+const MAX_DISK_GB: u32 = 64 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct ValidationIssue {
+    pub field: String,
+    pub message: String,
+}
+
+/// All problems found in a compose config, collected in a single pass
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn push(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.issues.push(ValidationIssue {
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Turn the report into an error listing every issue found
+    pub fn into_result(self) -> Result<()> {
+        if self.is_ok() {
+            return Ok(());
+        }
+        Err(ErrorCode::ConfigInvalid.error(self.to_string()))
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Validator VM configuration has {} problem(s):",
+            self.issues.len()
+        )?;
+        for issue in &self.issues {
+            write!(f, "\n  - {}: {}", issue.field, issue.message)?;
+        }
+        Ok(())
+    }
+}
+
+fn default_runners() -> Vec<String> {
+    defaults::IMAGE_RUNNERS
+        .iter()
+        .map(|r| r.to_string())
+        .collect()
+}
+
+fn default_max_manifest_version() -> u32 {
+    defaults::MAX_MANIFEST_VERSION
+}
+
+/// What a dstack guest image can run; configured per image in the platform config
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageSupport {
+    #[serde(default = "default_runners")]
+    pub runners: Vec<String>,
+    #[serde(default = "default_max_manifest_version")]
+    pub max_manifest_version: u32,
+}
+
+impl Default for ImageSupport {
+    fn default() -> Self {
+        Self {
+            runners: default_runners(),
+            max_manifest_version: defaults::MAX_MANIFEST_VERSION,
+        }
+    }
+}
+
+impl ImageSupport {
+    /// Support configured for `image`, or the built-in defaults
+    pub fn for_image(platform_config: &PlatformConfig, image: &str) -> Self {
+        platform_config
+            .image_support
+            .get(image)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// Validate a compose config fetched from the platform API against what the
+/// target image supports
+pub fn validate_compose_config(config: &ComposeConfig, support: &ImageSupport) -> ValidationReport {
+    let mut report = ValidationReport::default();
+
+    if config.vm_type.trim().is_empty() {
+        report.push("vm_type", "must not be empty");
+    }
+    if config.compose_content.trim().is_empty() {
+        report.push("compose_content", "must not be empty");
+    }
+
+    validate_manifest_defaults(
+        &config.provisioning.manifest_defaults,
+        support,
+        &config.provisioning.vm_parameters.image,
+        &mut report,
+    );
+    validate_vm_parameters(&config.provisioning.vm_parameters, &mut report);
+
+    report
+}
+
+pub fn validate_manifest_defaults(
+    defaults: &ManifestDefaults,
+    support: &ImageSupport,
+    image: &str,
+    report: &mut ValidationReport,
+) {
+    if defaults.manifest_version == 0 {
+        report.push(
+            "provisioning.manifest_defaults.manifest_version",
+            "must be > 0",
+        );
+    } else if defaults.manifest_version > support.max_manifest_version {
+        report.push(
+            "provisioning.manifest_defaults.manifest_version",
+            format!(
+                "version {} cannot be parsed by image '{}' (supports up to {})",
+                defaults.manifest_version, image, support.max_manifest_version
+            ),
+        );
+    }
+    if !support.runners.contains(&defaults.runner) {
+        report.push(
+            "provisioning.manifest_defaults.runner",
+            format!(
+                "runner '{}' is not supported by image '{}' (supported: {})",
+                defaults.runner,
+                image,
+                support.runners.join(", ")
+            ),
+        );
+    }
+}
+
+pub fn validate_vm_parameters(params: &VmParameters, report: &mut ValidationReport) {
+    const PREFIX: &str = "provisioning.vm_parameters";
+
+    if !is_recognized_image_name(&params.image) {
+        report.push(
+            format!("{}.image", PREFIX),
+            format!(
+                "unrecognized image name '{}' (expected e.g. dstack-0.5.2 or dstack-nvidia-0.5.2)",
+                params.image
+            ),
+        );
+    }
+    if params.vcpu == 0 || params.vcpu > MAX_VCPU {
+        report.push(
+            format!("{}.vcpu", PREFIX),
+            format!("must be between 1 and {} (got {})", MAX_VCPU, params.vcpu),
+        );
+    }
+    if params.memory == 0 || params.memory > MAX_MEMORY_MB {
+        report.push(
+            format!("{}.memory", PREFIX),
+            format!(
+                "must be between 1 and {} MB (got {})",
+                MAX_MEMORY_MB, params.memory
+            ),
+        );
+    }
+    if params.disk_size == 0 || params.disk_size > MAX_DISK_GB {
+        report.push(
+            format!("{}.disk_size", PREFIX),
+            format!(
+                "must be between 1 and {} GB (got {})",
+                MAX_DISK_GB, params.disk_size
+            ),
+        );
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    for (i, port) in params.ports.iter().enumerate() {
+        let field = format!("{}.ports[{}]", PREFIX, i);
+        if !KNOWN_PROTOCOLS.contains(&port.protocol.as_str()) {
+            report.push(
+                &field,
+                format!("unknown protocol '{}' (expected tcp or udp)", port.protocol),
+            );
+        }
+        if port.host_port == 0 {
+            report.push(&field, "host_port must be between 1 and 65535");
+        }
+        if port.vm_port == 0 {
+            report.push(&field, "vm_port must be between 1 and 65535");
+        }
+        let key = (
+            port.protocol.clone(),
+            port.host_address.clone().unwrap_or_default(),
+            port.host_port,
+        );
+        if port.host_port != 0 && !seen.insert(key) {
+            report.push(
+                &field,
+                format!("duplicate host port {}/{}", port.host_port, port.protocol),
+            );
+        }
+    }
+}
+
+/// dstack images are named `dstack[-<flavor>]-<major>.<minor>.<patch>`
+fn is_recognized_image_name(image: &str) -> bool {
+    let Some(rest) = image.strip_prefix("dstack-") else {
+        return false;
+    };
+    let version = rest.rsplit('-').next().unwrap_or_default();
+    !version.is_empty()
+        && version
+            .split('.')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+}
@@ -0,0 +1,84 @@
+// SPDX-FileCopyrightText: © 2024-2025 Phala Network <dstack@phala.network>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! All-in-one mode: the launcher starts the dstack VMM itself and restarts it
+//! when it exits, so a single-box host needs no separate VMM service.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+use crate::defaults;
+
+fn default_ready_timeout_secs() -> u64 {
+    defaults::EMBEDDED_VMM_READY_TIMEOUT_SECS
+}
+
+/// The VMM process the launcher supervises
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddedVmmConfig {
+    /// Path of the VMM binary (not interpreted by a shell)
+    pub binary: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// How long the first update check waits for the VMM to answer RPCs
+    #[serde(default = "default_ready_timeout_secs")]
+    pub ready_timeout_secs: u64,
+}
+
+impl fmt::Display for EmbeddedVmmConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.binary)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        Ok(())
+    }
+}
+
+/// Delay before the first restart; doubled on each quick exit
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+/// A VMM that ran at least this long restarts after [`MIN_RESTART_DELAY`] again
+const STABLE_RUN: Duration = Duration::from_secs(300);
+
+/// Run the VMM and restart it whenever it exits or fails to start, backing
+/// off while it keeps exiting quickly. The VMM is killed when the returned
+/// task is aborted or the launcher exits.
+pub fn spawn(config: EmbeddedVmmConfig) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut delay = MIN_RESTART_DELAY;
+        loop {
+            let started = Instant::now();
+            let mut command = tokio::process::Command::new(&config.binary);
+            command
+                .args(&config.args)
+                .stdin(std::process::Stdio::null())
+                .kill_on_drop(true);
+            match command.spawn() {
+                Ok(mut child) => {
+                    info!(
+                        "Started embedded VMM (pid {}): {}",
+                        child.id().unwrap_or_default(),
+                        config
+                    );
+                    match child.wait().await {
+                        Ok(status) => warn!("Embedded VMM exited with {}", status),
+                        Err(e) => error!("Failed to wait for the embedded VMM: {}", e),
+                    }
+                }
+                Err(e) => error!("Failed to start embedded VMM {}: {}", config.binary, e),
+            }
+            if started.elapsed() >= STABLE_RUN {
+                delay = MIN_RESTART_DELAY;
+            }
+            warn!("Restarting the embedded VMM in {:?}", delay);
+            sleep(delay).await;
+            delay = (delay * 2).min(MAX_RESTART_DELAY);
+        }
+    })
+}